@@ -0,0 +1,179 @@
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use tracing_appender::{
+    non_blocking::{NonBlocking, WorkerGuard},
+    rolling::{RollingFileAppender, Rotation},
+};
+
+use crate::auth::Principal;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Destination {
+    // Along with the panel's other logs.
+    #[default]
+    Log,
+    // Files in `dir`, a new one every `rotation`.
+    File,
+    // The journal, with each field as a journal field.
+    Journald,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RotateEvery {
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AccessLogConfig {
+    destination: Option<Destination>,
+    // For files, "access-log" if unset.
+    dir: Option<String>,
+    rotation: Option<RotateEvery>,
+    // How many files to keep, all of them if unset.
+    keep: Option<usize>,
+}
+
+#[derive(Clone)]
+enum Sink {
+    Log,
+    // The guard flushes what's left when the last clone is dropped.
+    File(NonBlocking, Arc<WorkerGuard>),
+    Journald,
+}
+
+/// Records every request with who made it, how it went and how long it
+/// took.
+#[derive(Clone)]
+pub struct AccessLog {
+    sink: Sink,
+}
+
+struct Entry<'a> {
+    remote: Option<SocketAddr>,
+    user: Option<&'a str>,
+    method: &'a str,
+    path: &'a str,
+    status: u16,
+    latency_ms: f64,
+}
+
+impl Entry<'_> {
+    /// Like the common log format, with the latency on the end.
+    fn line(&self) -> String {
+        let remote = match self.remote {
+            Some(r) => r.ip().to_string(),
+            None => String::from("-"),
+        };
+        format!(
+            "{} {} \"{} {}\" {} {:.1}ms",
+            remote,
+            self.user.unwrap_or("-"),
+            self.method,
+            self.path,
+            self.status,
+            self.latency_ms
+        )
+    }
+}
+
+pub fn init(config: Option<AccessLogConfig>) -> Result<AccessLog, String> {
+    let config = match config {
+        Some(c) => c,
+        None => return Ok(AccessLog { sink: Sink::Log }),
+    };
+    let sink = match config.destination.unwrap_or_default() {
+        Destination::Log => Sink::Log,
+        Destination::Journald => Sink::Journald,
+        Destination::File => {
+            let rotation = match config.rotation.unwrap_or_default() {
+                RotateEvery::Hourly => Rotation::HOURLY,
+                RotateEvery::Daily => Rotation::DAILY,
+                RotateEvery::Never => Rotation::NEVER,
+            };
+            let mut builder = RollingFileAppender::builder()
+                .rotation(rotation)
+                .filename_prefix("access")
+                .filename_suffix("log");
+            if let Some(keep) = config.keep {
+                builder = builder.max_log_files(keep);
+            }
+            let dir = config.dir.unwrap_or(String::from("access-log"));
+            let appender = match builder.build(&dir) {
+                Ok(a) => a,
+                Err(e) => return Err(format!("could not open the access log in {}: {}", dir, e)),
+            };
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            Sink::File(writer, Arc::new(guard))
+        }
+    };
+    Ok(AccessLog { sink })
+}
+
+impl AccessLog {
+    fn write(&self, entry: &Entry) {
+        match &self.sink {
+            Sink::Log => tracing::info!(target: "access", "{}", entry.line()),
+            Sink::File(writer, _) => {
+                let mut writer = writer.clone();
+                let _ = writeln!(writer, "{}", entry.line());
+            }
+            Sink::Journald => {
+                let remote = entry.remote.map(|r| r.ip().to_string()).unwrap_or_default();
+                let fields = [
+                    format!("MESSAGE={}", entry.line()),
+                    String::from("PRIORITY=6"),
+                    String::from("SYSLOG_IDENTIFIER=minecraft-control-access"),
+                    format!("HTTP_METHOD={}", entry.method),
+                    format!("HTTP_PATH={}", entry.path),
+                    format!("HTTP_STATUS={}", entry.status),
+                    format!("HTTP_LATENCY_MS={:.1}", entry.latency_ms),
+                    format!("HTTP_REMOTE={}", remote),
+                    format!("HTTP_USER={}", entry.user.unwrap_or("")),
+                ];
+                let fields: Vec<&str> = fields.iter().map(|f| f.as_str()).collect();
+                ::systemd::journal::send(&fields);
+            }
+        }
+    }
+}
+
+/// Logs each request once it's been answered. The user is whoever
+/// `require_auth` let through, which it leaves on the response.
+pub async fn middleware(State(log): State<AccessLog>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    // The query is left out, since tickets and tokens can be in it.
+    let path = request.uri().path().to_owned();
+    let remote = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|c| c.0);
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let user = response
+        .extensions()
+        .get::<Principal>()
+        .map(|p| p.name.as_str());
+    log.write(&Entry {
+        remote,
+        user,
+        method: &method,
+        path: &path,
+        status: response.status().as_u16(),
+        latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+    });
+    response
+}
@@ -0,0 +1,1027 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use axum::{
+    extract::{ConnectInfo, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{AppendHeaders, Html, IntoResponse, Redirect, Response},
+    Extension, Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use tokio::fs;
+
+use crate::error::ApiError;
+use crate::oidc::{OidcClient, OidcConfig};
+use crate::policy::CommandPolicy;
+use crate::totp::{self, TwoFactorStore};
+use crate::util::now;
+
+const SESSION_COOKIE: &str = "mcctl_session";
+const PENDING_COOKIE: &str = "mcctl_2fa";
+const PENDING_TTL: u64 = 300;
+const OIDC_COOKIE: &str = "mcctl_oidc";
+const ISSUER: &str = "Minecraft Control";
+// WebSocket tickets only have to last until the socket is opened.
+const TICKET_TTL: u64 = 30;
+// Prefix of a `Sec-WebSocket-Protocol` value carrying an API token.
+const TOKEN_PROTOCOL: &str = "bearer.";
+
+// Pages that have to stay reachable so a logged out user can log in.
+const PUBLIC_PAGES: [&str; 2] = ["/login.html", "/styles.css"];
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PrincipalKind {
+    Anonymous,
+    Token,
+    User,
+    Oidc,
+}
+
+/// The authenticated caller, stored in the request extensions by
+/// `require_auth`.
+#[derive(Serialize, Debug, Clone)]
+pub struct Principal {
+    pub name: String,
+    pub kind: PrincipalKind,
+    pub role: Role,
+    #[serde(skip_serializing_if = "CommandPolicy::is_empty")]
+    pub policy: CommandPolicy,
+    // Who rate limits count against. Unnamed tokens all share a name, so
+    // for tokens it's a hash of the token instead.
+    #[serde(skip)]
+    pub id: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum TokenConfig {
+    Plain(String),
+    Named {
+        name: String,
+        token: String,
+        role: Option<Role>,
+        allow: Option<Vec<String>>,
+        deny: Option<Vec<String>>,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AuthConfig {
+    tokens: Option<Vec<TokenConfig>>,
+    tokens_file: Option<String>,
+    users: Option<Vec<UserConfig>>,
+    users_file: Option<String>,
+    session_secret: Option<String>,
+    session_ttl_hours: Option<u64>,
+    public_assets: Option<bool>,
+    default_role: Option<Role>,
+    two_factor_path: Option<String>,
+    oidc: Option<OidcConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UserConfig {
+    username: String,
+    password_hash: String,
+    role: Option<Role>,
+}
+
+struct User {
+    password_hash: String,
+    role: Role,
+}
+
+/// The tokens and users from the config, which can be swapped for new
+/// ones when it's reloaded.
+struct Credentials {
+    tokens: Vec<(String, Principal)>,
+    users: HashMap<String, User>,
+}
+
+#[derive(Clone)]
+pub struct Auth {
+    credentials: Arc<RwLock<Arc<Credentials>>>,
+    session_key: Arc<Vec<u8>>,
+    session_ttl: u64,
+    secure_cookies: bool,
+    public_assets: bool,
+    // Nonces of sessions that were logged out, mapped to their expiry.
+    revoked: Arc<Mutex<HashMap<String, u64>>>,
+    // Unused WebSocket tickets and who they were issued to.
+    tickets: Arc<Mutex<HashMap<String, (Principal, u64)>>>,
+    two_factor: TwoFactorStore,
+    oidc: Option<Arc<OidcClient>>,
+}
+
+#[derive(Debug)]
+pub enum ReloadError {
+    Io(std::io::Error),
+    // The new config has no tokens, users or OIDC, while the current one
+    // has some.
+    Disables,
+}
+
+impl From<std::io::Error> for ReloadError {
+    fn from(e: std::io::Error) -> Self {
+        ReloadError::Io(e)
+    }
+}
+
+impl std::fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReloadError::Io(e) => write!(f, "{}", e),
+            ReloadError::Disables => write!(
+                f,
+                "no API tokens or users are configured, which would open the control routes to anyone"
+            ),
+        }
+    }
+}
+
+struct Session {
+    username: String,
+    expires: u64,
+    nonce: String,
+    // `local` for users from the config, `oidc:<role>` for users that came
+    // through the identity provider.
+    origin: String,
+}
+
+#[derive(Deserialize)]
+pub struct LoginRequest {
+    username: String,
+    password: String,
+}
+
+fn empty_config() -> AuthConfig {
+    AuthConfig {
+        tokens: None,
+        tokens_file: None,
+        users: None,
+        users_file: None,
+        session_secret: None,
+        session_ttl_hours: None,
+        public_assets: None,
+        default_role: None,
+        two_factor_path: None,
+        oidc: None,
+    }
+}
+
+/// Reads the tokens and users from the config and the files it names.
+async fn credentials(c: &AuthConfig) -> Result<Credentials, std::io::Error> {
+    // Anyone without an explicit role keeps full access, which is what every
+    // caller had before roles existed.
+    let default_role = c.default_role.unwrap_or(Role::Admin);
+
+    let mut tokens: Vec<(String, Principal)> = Vec::new();
+    for t in c.tokens.clone().unwrap_or_default() {
+        let (token, principal) = match t {
+            TokenConfig::Plain(token) => (
+                token.clone(),
+                Principal {
+                    name: String::from("token"),
+                    kind: PrincipalKind::Token,
+                    role: default_role,
+                    policy: CommandPolicy::default(),
+                    id: token_id(&token),
+                },
+            ),
+            TokenConfig::Named {
+                name,
+                token,
+                role,
+                allow,
+                deny,
+            } => (
+                token.clone(),
+                Principal {
+                    name,
+                    kind: PrincipalKind::Token,
+                    role: role.unwrap_or(default_role),
+                    policy: CommandPolicy::new(allow.unwrap_or_default(), deny.unwrap_or_default()),
+                    id: token_id(&token),
+                },
+            ),
+        };
+        tokens.push((token, principal));
+    }
+    // One token per line, blank lines and # comments are ignored.
+    if let Some(path) = &c.tokens_file {
+        let file = fs::read_to_string(path).await?;
+        for line in config_lines(&file) {
+            let principal = Principal {
+                name: String::from("token"),
+                kind: PrincipalKind::Token,
+                role: default_role,
+                policy: CommandPolicy::default(),
+                id: token_id(line),
+            };
+            tokens.push((line.to_owned(), principal));
+        }
+    }
+
+    let mut users: HashMap<String, User> = HashMap::new();
+    if let Some(u) = &c.users {
+        for user in u {
+            let role = user.role.unwrap_or(default_role);
+            users.insert(
+                user.username.clone(),
+                User {
+                    password_hash: user.password_hash.clone(),
+                    role,
+                },
+            );
+        }
+    }
+    // `username:$argon2id$...[:role]` per line, like an htpasswd file.
+    if let Some(path) = &c.users_file {
+        let file = fs::read_to_string(path).await?;
+        for line in config_lines(&file) {
+            let mut parts = line.splitn(3, ':');
+            let (username, hash) = match (parts.next(), parts.next()) {
+                (Some(u), Some(h)) => (u, h),
+                _ => continue,
+            };
+            let role = match parts.next() {
+                Some(r) => match parse_role(r) {
+                    Some(role) => role,
+                    None => {
+                        tracing::warn!(%username, role = %r, "ignoring a user with an unknown role");
+                        continue;
+                    }
+                },
+                None => default_role,
+            };
+            users.insert(
+                username.to_owned(),
+                User {
+                    password_hash: hash.to_owned(),
+                    role,
+                },
+            );
+        }
+    }
+    Ok(Credentials { tokens, users })
+}
+
+/// Whether the tokens and users files can be read, for `--check`.
+pub async fn check(config: Option<AuthConfig>) -> Result<(), std::io::Error> {
+    let c = config.unwrap_or_else(empty_config);
+    credentials(&c).await?;
+    TwoFactorStore::load(two_factor_path(&c)).await?;
+    Ok(())
+}
+
+fn two_factor_path(c: &AuthConfig) -> PathBuf {
+    PathBuf::from(
+        c.two_factor_path
+            .clone()
+            .unwrap_or_else(|| String::from("two_factor.json")),
+    )
+}
+
+pub async fn init(
+    config: Option<AuthConfig>,
+    secure_cookies: bool,
+) -> Result<Auth, std::io::Error> {
+    let c = config.unwrap_or_else(empty_config);
+    let credentials = credentials(&c).await?;
+
+    let two_factor_path = two_factor_path(&c);
+
+    let session_key = match c.session_secret {
+        Some(s) => s.into_bytes(),
+        None => {
+            if !credentials.users.is_empty() {
+                tracing::warn!("no session_secret configured, sessions won't survive a restart");
+            }
+            let mut key = vec![0u8; 32];
+            rand::thread_rng().fill_bytes(&mut key);
+            key
+        }
+    };
+
+    let oidc = c.oidc.map(|o| Arc::new(OidcClient::new(o)));
+
+    if credentials.tokens.is_empty() && credentials.users.is_empty() && oidc.is_none() {
+        tracing::warn!("no API tokens or users configured, control routes are open to anyone");
+    }
+
+    Ok(Auth {
+        credentials: Arc::new(RwLock::new(Arc::new(credentials))),
+        session_key: Arc::new(session_key),
+        session_ttl: c.session_ttl_hours.unwrap_or(24 * 7) * 3600,
+        secure_cookies,
+        public_assets: c.public_assets.unwrap_or(true),
+        revoked: Arc::new(Mutex::new(HashMap::new())),
+        tickets: Arc::new(Mutex::new(HashMap::new())),
+        two_factor: TwoFactorStore::load(two_factor_path).await?,
+        oidc,
+    })
+}
+
+fn role_name(role: Role) -> &'static str {
+    match role {
+        Role::Viewer => "viewer",
+        Role::Operator => "operator",
+        Role::Admin => "admin",
+    }
+}
+
+fn parse_role(role: &str) -> Option<Role> {
+    match role.trim() {
+        "viewer" => Some(Role::Viewer),
+        "operator" => Some(Role::Operator),
+        "admin" => Some(Role::Admin),
+        _ => None,
+    }
+}
+
+fn config_lines(file: &str) -> impl Iterator<Item = &str> {
+    file.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+}
+
+impl Auth {
+    fn credentials(&self) -> Arc<Credentials> {
+        self.credentials.read().unwrap().clone()
+    }
+
+    /// Swaps in the tokens and users from a reloaded config. Everything
+    /// else in [auth] needs a restart. A config that would leave the routes
+    /// open to anyone while they're protected now is refused, since it's
+    /// more likely a file caught halfway through being written than a
+    /// change anyone meant to make.
+    pub async fn reload(&self, config: Option<AuthConfig>) -> Result<(), ReloadError> {
+        let credentials = credentials(&config.unwrap_or_else(empty_config)).await?;
+        let open =
+            credentials.tokens.is_empty() && credentials.users.is_empty() && self.oidc.is_none();
+        if open && self.enabled() {
+            tracing::error!(
+                "the reloaded config has no API tokens or users, keeping the current ones"
+            );
+            return Err(ReloadError::Disables);
+        }
+        *self.credentials.write().unwrap() = Arc::new(credentials);
+        Ok(())
+    }
+
+    fn enabled(&self) -> bool {
+        let credentials = self.credentials();
+        !credentials.tokens.is_empty() || !credentials.users.is_empty() || self.oidc.is_some()
+    }
+
+    fn validate_token(&self, token: &str) -> Option<Principal> {
+        self.credentials()
+            .tokens
+            .iter()
+            .find(|(t, _)| constant_time_eq(t.as_bytes(), token.as_bytes()))
+            .map(|(_, principal)| principal.clone())
+    }
+
+    fn authenticate(&self, request: &Request) -> Option<Principal> {
+        if let Some(token) = request_token(request) {
+            return self.validate_token(token);
+        }
+        if let Some(ticket) = request_ticket(request) {
+            return self.redeem_ticket(ticket);
+        }
+        let session = self.session(request.headers())?;
+        if let Some(role) = session.origin.strip_prefix("oidc:") {
+            return Some(Principal {
+                id: format!("oidc:{}", session.username),
+                name: session.username,
+                kind: PrincipalKind::Oidc,
+                role: parse_role(role)?,
+                policy: CommandPolicy::default(),
+            });
+        }
+        let role = self.credentials().users.get(&session.username)?.role;
+        Some(Principal {
+            id: format!("user:{}", session.username),
+            name: session.username,
+            kind: PrincipalKind::User,
+            role,
+            policy: CommandPolicy::default(),
+        })
+    }
+
+    fn sign(&self, payload: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.session_key).unwrap();
+        mac.update(payload);
+        mac
+    }
+
+    /// Signs newline separated fields into a cookie value.
+    fn seal(&self, fields: &[&str]) -> String {
+        let payload = fields.join("\n");
+        let signature = self.sign(payload.as_bytes()).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// Checks the signature of a sealed value and returns its fields if the
+    /// first one matches `purpose`.
+    fn unseal(&self, value: &str, purpose: &str) -> Option<Vec<String>> {
+        let (payload, signature) = value.split_once('.')?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        self.sign(&payload).verify_slice(&signature).ok()?;
+
+        let payload = String::from_utf8(payload).ok()?;
+        let mut fields = payload.split('\n').map(|f| f.to_owned());
+        if fields.next()? != purpose {
+            return None;
+        }
+        Some(fields.collect())
+    }
+
+    fn issue_ticket(&self, principal: Principal) -> String {
+        let mut ticket = [0u8; 24];
+        rand::thread_rng().fill_bytes(&mut ticket);
+        let ticket = URL_SAFE_NO_PAD.encode(ticket);
+        let now = now();
+        let mut tickets = self.tickets.lock().unwrap();
+        tickets.retain(|_, (_, expires)| *expires > now);
+        tickets.insert(ticket.clone(), (principal, now + TICKET_TTL));
+        ticket
+    }
+
+    /// Tickets are single use, so this also removes it.
+    fn redeem_ticket(&self, ticket: &str) -> Option<Principal> {
+        let (principal, expires) = self.tickets.lock().unwrap().remove(ticket)?;
+        if expires <= now() {
+            return None;
+        }
+        Some(principal)
+    }
+
+    fn issue_session(&self, username: &str, origin: &str) -> String {
+        let mut nonce = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let expires = (now() + self.session_ttl).to_string();
+        let nonce = URL_SAFE_NO_PAD.encode(nonce);
+        self.seal(&["session", username, &expires, &nonce, origin])
+    }
+
+    fn session(&self, headers: &HeaderMap) -> Option<Session> {
+        let value = cookie_value(headers, SESSION_COOKIE)?;
+        let mut fields = self.unseal(value, "session")?.into_iter();
+        let session = Session {
+            username: fields.next()?,
+            expires: fields.next()?.parse().ok()?,
+            nonce: fields.next()?,
+            origin: fields.next()?,
+        };
+        if session.expires <= now() || self.revoked.lock().unwrap().contains_key(&session.nonce) {
+            return None;
+        }
+        // Users removed from the config lose their sessions too.
+        if session.origin == "local" && !self.credentials().users.contains_key(&session.username) {
+            return None;
+        }
+        Some(session)
+    }
+
+    /// Returns the user that passed the password step of a login and still
+    /// has to provide their second factor.
+    fn pending_two_factor(&self, headers: &HeaderMap) -> Option<String> {
+        let value = cookie_value(headers, PENDING_COOKIE)?;
+        let mut fields = self.unseal(value, "2fa")?.into_iter();
+        let username = fields.next()?;
+        let expires: u64 = fields.next()?.parse().ok()?;
+        if expires <= now() {
+            return None;
+        }
+        Some(username)
+    }
+
+    fn cookie(&self, name: &str, value: &str, max_age: u64) -> String {
+        // The OIDC state cookie has to come along on the redirect back from
+        // the identity provider, which Strict would prevent.
+        let same_site = if name == OIDC_COOKIE { "Lax" } else { "Strict" };
+        let mut cookie = format!(
+            "{}={}; Path=/; HttpOnly; SameSite={}; Max-Age={}",
+            name, value, same_site, max_age
+        );
+        if self.secure_cookies {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+
+    fn session_response(&self, username: &str) -> Response {
+        tracing::info!(%username, "logged in");
+        let session = self.issue_session(username, "local");
+        (
+            StatusCode::NO_CONTENT,
+            AppendHeaders([
+                (
+                    header::SET_COOKIE,
+                    self.cookie(SESSION_COOKIE, &session, self.session_ttl),
+                ),
+                (header::SET_COOKIE, self.cookie(PENDING_COOKIE, "", 0)),
+            ]),
+        )
+            .into_response()
+    }
+}
+
+/// A short hash of the token, to tell tokens apart by.
+fn token_id(token: &str) -> String {
+    let digest = <Sha256 as sha2::Digest>::digest(token.as_bytes());
+    let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("token:{}", hex)
+}
+
+/// Gates API routes behind either a bearer token or a session cookie and
+/// records who the caller is for `require_role`.
+pub async fn require_auth(State(auth): State<Auth>, mut request: Request, next: Next) -> Response {
+    let principal = if auth.enabled() {
+        auth.authenticate(&request)
+    } else {
+        // Told apart by address where it's known.
+        let id = match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(addr)) => format!("anonymous:{}", addr.ip()),
+            None => String::from("anonymous"),
+        };
+        Some(Principal {
+            name: String::from("anonymous"),
+            kind: PrincipalKind::Anonymous,
+            role: Role::Admin,
+            policy: CommandPolicy::default(),
+            id,
+        })
+    };
+    if let Some(principal) = principal {
+        request.extensions_mut().insert(principal.clone());
+        let mut response = next.run(request).await;
+        // For the access log, which only sees the response.
+        response.extensions_mut().insert(principal);
+        return response;
+    }
+
+    (
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        ApiError::new(StatusCode::UNAUTHORIZED, "authentication required"),
+    )
+        .into_response()
+}
+
+/// Gates the static frontend when `public_assets` is disabled, sending the
+/// browser to the login page instead of returning a bare 401.
+pub async fn require_page_auth(State(auth): State<Auth>, request: Request, next: Next) -> Response {
+    if auth.public_assets
+        || !auth.enabled()
+        || PUBLIC_PAGES.contains(&request.uri().path())
+        || auth.authenticate(&request).is_some()
+    {
+        return next.run(request).await;
+    }
+
+    Redirect::to("/login.html").into_response()
+}
+
+/// Rejects callers whose role is below the one given as the layer's state.
+/// Must be layered inside `require_auth`.
+pub async fn require_role(State(role): State<Role>, request: Request, next: Next) -> Response {
+    let allowed = match request.extensions().get::<Principal>() {
+        Some(principal) => principal.role >= role,
+        None => false,
+    };
+    if !allowed {
+        return ApiError::new(StatusCode::FORBIDDEN, "insufficient role").into_response();
+    }
+    next.run(request).await
+}
+
+pub async fn me(Extension(principal): Extension<Principal>) -> Json<Principal> {
+    Json(principal)
+}
+
+/// Issues a short-lived ticket for opening `/ws` as the caller, so browsers
+/// don't have to put a token in the URL.
+pub async fn ws_ticket(
+    State(auth): State<Auth>,
+    Extension(principal): Extension<Principal>,
+) -> Json<serde_json::Value> {
+    let ticket = auth.issue_ticket(principal);
+    Json(json!({ "ticket": ticket, "expires_in": TICKET_TTL }))
+}
+
+pub async fn login(State(auth): State<Auth>, Json(login): Json<LoginRequest>) -> Response {
+    // Unknown usernames are checked against a dummy hash, so they take as
+    // long as a wrong password and don't give away who has an account.
+    let (hash, known) = match auth.credentials().users.get(&login.username) {
+        Some(u) => (u.password_hash.clone(), true),
+        None => (dummy_hash().to_owned(), false),
+    };
+
+    // Argon2 is deliberately slow, keep it off the async workers.
+    let password = login.password;
+    let verified = tokio::task::spawn_blocking(move || verify_password(&hash, &password))
+        .await
+        .unwrap_or(false)
+        && known;
+    if !verified {
+        tracing::warn!(username = %login.username, "failed login");
+        return ApiError::new(StatusCode::UNAUTHORIZED, "invalid username or password")
+            .into_response();
+    }
+
+    if auth.two_factor.enabled(&login.username).await {
+        let expires = (now() + PENDING_TTL).to_string();
+        let pending = auth.seal(&["2fa", &login.username, &expires]);
+        return (
+            StatusCode::OK,
+            [(
+                header::SET_COOKIE,
+                auth.cookie(PENDING_COOKIE, &pending, PENDING_TTL),
+            )],
+            Json(json!({ "two_factor_required": true })),
+        )
+            .into_response();
+    }
+
+    auth.session_response(&login.username)
+}
+
+pub async fn logout(State(auth): State<Auth>, headers: HeaderMap) -> Response {
+    if let Some(session) = auth.session(&headers) {
+        let mut revoked = auth.revoked.lock().unwrap();
+        let now = now();
+        revoked.retain(|_, expires| *expires > now);
+        revoked.insert(session.nonce, session.expires);
+        tracing::info!(username = %session.username, "logged out");
+    }
+
+    (
+        StatusCode::NO_CONTENT,
+        [(header::SET_COOKIE, auth.cookie(SESSION_COOKIE, "", 0))],
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+pub struct CodeRequest {
+    code: String,
+}
+
+/// Second step of a login for users with two-factor enabled. Accepts either
+/// a TOTP code or one of the recovery codes.
+pub async fn two_factor_verify(
+    State(auth): State<Auth>,
+    headers: HeaderMap,
+    Json(request): Json<CodeRequest>,
+) -> Response {
+    let username = match auth.pending_two_factor(&headers) {
+        Some(u) => u,
+        None => {
+            return ApiError::new(StatusCode::UNAUTHORIZED, "log in with a password first")
+                .into_response()
+        }
+    };
+
+    match auth.two_factor.verify(&username, &request.code).await {
+        Ok(true) => auth.session_response(&username),
+        Ok(false) => {
+            tracing::warn!(%username, "failed two-factor code");
+            ApiError::new(StatusCode::UNAUTHORIZED, "invalid code").into_response()
+        }
+        Err(e) => two_factor_error(e),
+    }
+}
+
+pub async fn two_factor_status(
+    State(auth): State<Auth>,
+    Extension(principal): Extension<Principal>,
+) -> Response {
+    if principal.kind != PrincipalKind::User {
+        return ApiError::new(StatusCode::BAD_REQUEST, "two-factor is only for users")
+            .into_response();
+    }
+    let enabled = auth.two_factor.enabled(&principal.name).await;
+    Json(json!({ "enabled": enabled })).into_response()
+}
+
+pub async fn two_factor_enroll(
+    State(auth): State<Auth>,
+    Extension(principal): Extension<Principal>,
+) -> Response {
+    if principal.kind != PrincipalKind::User {
+        return ApiError::new(StatusCode::BAD_REQUEST, "two-factor is only for users")
+            .into_response();
+    }
+    match auth.two_factor.begin(&principal.name).await {
+        Ok(Some(secret)) => Json(json!({
+            "secret": secret,
+            "otpauth_uri": totp::otpauth_uri(ISSUER, &principal.name, &secret),
+        }))
+        .into_response(),
+        Ok(None) => {
+            ApiError::new(StatusCode::CONFLICT, "two-factor is already enabled").into_response()
+        }
+        Err(e) => two_factor_error(e),
+    }
+}
+
+pub async fn two_factor_confirm(
+    State(auth): State<Auth>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<CodeRequest>,
+) -> Response {
+    if principal.kind != PrincipalKind::User {
+        return ApiError::new(StatusCode::BAD_REQUEST, "two-factor is only for users")
+            .into_response();
+    }
+    match auth
+        .two_factor
+        .confirm(&principal.name, &request.code)
+        .await
+    {
+        Ok(Some(codes)) => {
+            tracing::info!(user = %principal.name, "enabled two-factor");
+            Json(json!({ "recovery_codes": codes })).into_response()
+        }
+        Ok(None) => ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid code or no pending enrollment",
+        )
+        .into_response(),
+        Err(e) => two_factor_error(e),
+    }
+}
+
+pub async fn two_factor_disable(
+    State(auth): State<Auth>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<CodeRequest>,
+) -> Response {
+    if principal.kind != PrincipalKind::User {
+        return ApiError::new(StatusCode::BAD_REQUEST, "two-factor is only for users")
+            .into_response();
+    }
+    match auth
+        .two_factor
+        .disable(&principal.name, &request.code)
+        .await
+    {
+        Ok(true) => {
+            tracing::info!(user = %principal.name, "disabled two-factor");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => ApiError::new(StatusCode::UNAUTHORIZED, "invalid code").into_response(),
+        Err(e) => two_factor_error(e),
+    }
+}
+
+fn two_factor_error(e: std::io::Error) -> Response {
+    tracing::error!(error = %e, "could not save two-factor state");
+    ApiError::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "could not save two-factor state",
+    )
+    .into_response()
+}
+
+/// Tells the login page which ways of logging in are available.
+pub async fn providers(State(auth): State<Auth>) -> Json<serde_json::Value> {
+    Json(json!({
+        "password": !auth.credentials().users.is_empty(),
+        "oidc": auth.oidc.is_some(),
+    }))
+}
+
+pub async fn oidc_login(State(auth): State<Auth>) -> Response {
+    let client = match &auth.oidc {
+        Some(c) => c,
+        None => return ApiError::new(StatusCode::NOT_FOUND, "OIDC isn't set up").into_response(),
+    };
+    let request = match client.authorize().await {
+        Ok(r) => r,
+        Err(e) => {
+            tracing::warn!(error = %e, "could not start an OIDC login");
+            return ApiError::new(StatusCode::BAD_GATEWAY, e.to_string()).into_response();
+        }
+    };
+
+    let expires = (now() + PENDING_TTL).to_string();
+    let sealed = auth.seal(&[
+        "oidc",
+        &request.state,
+        &request.nonce,
+        &request.verifier,
+        &expires,
+    ]);
+    (
+        [(
+            header::SET_COOKIE,
+            auth.cookie(OIDC_COOKIE, &sealed, PENDING_TTL),
+        )],
+        Redirect::to(&request.url),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+pub struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+pub async fn oidc_callback(
+    State(auth): State<Auth>,
+    headers: HeaderMap,
+    Query(params): Query<CallbackParams>,
+) -> Response {
+    let client = match &auth.oidc {
+        Some(c) => c,
+        None => return ApiError::new(StatusCode::NOT_FOUND, "OIDC isn't set up").into_response(),
+    };
+    if let Some(e) = params.error {
+        return ApiError::new(StatusCode::UNAUTHORIZED, format!("login failed: {}", e))
+            .into_response();
+    }
+
+    let fields = cookie_value(&headers, OIDC_COOKIE).and_then(|v| auth.unseal(v, "oidc"));
+    let (state, nonce, verifier) = match fields.as_deref() {
+        Some([state, nonce, verifier, expires])
+            if expires.parse::<u64>().map(|e| e > now()).unwrap_or(false) =>
+        {
+            (state, nonce, verifier)
+        }
+        _ => {
+            return ApiError::new(StatusCode::BAD_REQUEST, "login expired, try again")
+                .into_response()
+        }
+    };
+    let (code, returned_state) = match (params.code, params.state) {
+        (Some(c), Some(s)) => (c, s),
+        _ => {
+            return ApiError::new(StatusCode::BAD_REQUEST, "missing code or state").into_response()
+        }
+    };
+    if !constant_time_eq(state.as_bytes(), returned_state.as_bytes()) {
+        return ApiError::new(StatusCode::BAD_REQUEST, "state mismatch").into_response();
+    }
+
+    let identity = match client.exchange(&code, verifier, nonce).await {
+        Ok(i) => i,
+        Err(e) => {
+            tracing::warn!(error = %e, "OIDC login failed");
+            return ApiError::new(StatusCode::UNAUTHORIZED, e.to_string()).into_response();
+        }
+    };
+
+    tracing::info!(username = %identity.username, "logged in through OIDC");
+    let origin = format!("oidc:{}", role_name(identity.role));
+    let session = auth.issue_session(&identity.username, &origin);
+    // A Strict cookie isn't sent on a redirect chain that started at the
+    // identity provider, so go back to the panel from a page of its own.
+    (
+        AppendHeaders([
+            (
+                header::SET_COOKIE,
+                auth.cookie(SESSION_COOKIE, &session, auth.session_ttl),
+            ),
+            (header::SET_COOKIE, auth.cookie(OIDC_COOKIE, "", 0)),
+        ]),
+        Html(r#"<!doctype html><meta http-equiv="refresh" content="0;url=/">"#),
+    )
+        .into_response()
+}
+
+/// A hash made with the same parameters as real ones, so checking against
+/// it costs the same.
+fn dummy_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| {
+        let hashed = SaltString::encode_b64(&[0; 16]).and_then(|salt| {
+            Argon2::default()
+                .hash_password(b"", &salt)
+                .map(|h| h.to_string())
+        });
+        match hashed {
+            Ok(h) => h,
+            Err(_) => String::new(),
+        }
+    })
+}
+
+fn verify_password(hash: &str, password: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(e) => {
+            tracing::error!(error = %e, "invalid password hash in the config");
+            false
+        }
+    }
+}
+
+fn request_token(request: &Request) -> Option<&str> {
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if bearer.is_some() {
+        return bearer;
+    }
+
+    // Browsers can't set headers on WebSocket upgrades, so the token may
+    // come as a subprotocol or in the query string there instead.
+    if !request.headers().contains_key(header::UPGRADE) {
+        return None;
+    }
+    let protocol = request
+        .headers()
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .find_map(|p| p.trim().strip_prefix(TOKEN_PROTOCOL));
+    if protocol.is_some() {
+        return protocol;
+    }
+    query_param(request, "token")
+}
+
+fn request_ticket(request: &Request) -> Option<&str> {
+    if !request.headers().contains_key(header::UPGRADE) {
+        return None;
+    }
+    query_param(request, "ticket")
+}
+
+fn query_param<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request.uri().query()?.split('&').find_map(|pair| {
+        pair.strip_prefix(name)
+            .and_then(|pair| pair.strip_prefix('='))
+    })
+}
+
+fn cookie_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|c| {
+            c.trim()
+                .strip_prefix(name)
+                .and_then(|c| c.strip_prefix('='))
+        })
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dummy_hash_costs_the_same() {
+        let hash = PasswordHash::new(dummy_hash()).unwrap();
+        let params = argon2::Params::try_from(&hash).unwrap();
+        let default = argon2::Params::default();
+        assert_eq!(hash.algorithm, argon2::Algorithm::default().ident());
+        assert_eq!(
+            (params.m_cost(), params.t_cost(), params.p_cost()),
+            (default.m_cost(), default.t_cost(), default.p_cost())
+        );
+        assert!(!verify_password(dummy_hash(), "password"));
+    }
+}
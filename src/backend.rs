@@ -0,0 +1,109 @@
+use std::fmt;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::lifecycle::LifecycleError;
+use crate::logsource::LogSource;
+
+pub mod docker;
+pub mod kubernetes;
+pub mod podman;
+pub mod process;
+pub mod screen;
+pub mod systemd;
+
+pub type LogStream = BoxStream<'static, Result<Bytes, std::io::Error>>;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Systemd,
+    Docker,
+    Podman,
+    Process,
+    Tmux,
+    Screen,
+    Kubernetes,
+}
+
+#[derive(Debug)]
+pub enum BackendError {
+    PermissionDenied(String),
+    NotFound(String),
+    Io(std::io::Error),
+    // Writing to the console at this path failed.
+    Console(String, std::io::Error),
+    Other(String),
+}
+
+impl std::error::Error for BackendError {}
+
+impl From<std::io::Error> for BackendError {
+    fn from(e: std::io::Error) -> Self {
+        BackendError::Io(e)
+    }
+}
+
+impl From<LifecycleError> for BackendError {
+    fn from(e: LifecycleError) -> Self {
+        match e {
+            LifecycleError::PermissionDenied(s) => BackendError::PermissionDenied(s),
+            LifecycleError::NoSuchUnit(s) => BackendError::NotFound(s),
+            LifecycleError::Dbus(e) => BackendError::Other(e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::PermissionDenied(s) => write!(f, "permission denied: {}", s),
+            BackendError::NotFound(s) => write!(f, "not found: {}", s),
+            BackendError::Io(e) => write!(f, "io error: {}", e),
+            BackendError::Console(path, e) => write!(f, "could not write to {}: {}", path, e),
+            BackendError::Other(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct ServerStatus {
+    #[schema(value_type = String)]
+    pub backend: &'static str,
+    // The unit, container or session the server runs in.
+    pub unit: String,
+    pub active_state: String,
+    pub sub_state: String,
+    pub pid: Option<u32>,
+    pub uptime_seconds: Option<u64>,
+}
+
+/// Everything the HTTP layer needs from whatever is running the server.
+#[async_trait]
+pub trait ServerBackend: Send + Sync {
+    fn kind(&self) -> &'static str;
+
+    fn target(&self) -> String;
+
+    /// Where live console lines come from.
+    fn log_source(&self) -> Box<dyn LogSource>;
+
+    /// The log of the current run from the beginning.
+    async fn log(&self) -> Result<LogStream, BackendError>;
+
+    /// Writes a single line to the server console.
+    async fn send_command(&self, command: &str) -> Result<(), BackendError>;
+
+    async fn start(&self) -> Result<String, BackendError>;
+
+    async fn stop(&self) -> Result<String, BackendError>;
+
+    async fn restart(&self) -> Result<String, BackendError>;
+
+    async fn status(&self) -> Result<ServerStatus, BackendError>;
+}
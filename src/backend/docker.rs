@@ -0,0 +1,295 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bollard::{
+    container::{
+        AttachContainerOptions, AttachContainerResults, InspectContainerOptions, LogOutput,
+        LogsOptions, RestartContainerOptions, StartContainerOptions, StopContainerOptions,
+    },
+    errors::Error as DockerError,
+    exec::{CreateExecOptions, StartExecResults},
+    Docker, API_DEFAULT_VERSION,
+};
+use futures::StreamExt;
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+
+use super::{BackendError, LogStream, ServerBackend, ServerStatus};
+use crate::logsource::{channel_lines, LogEntry, LogLines, LogSource};
+
+/// A server running in a container, controlled through the Docker Engine
+/// API.
+pub struct DockerBackend {
+    kind: &'static str,
+    docker: Docker,
+    container: String,
+    // Run through `exec` for each command, e.g. `rcon-cli`, instead of
+    // writing to the container's stdin.
+    exec_command: Option<Vec<String>>,
+}
+
+impl From<DockerError> for BackendError {
+    fn from(e: DockerError) -> Self {
+        match e {
+            DockerError::DockerResponseServerError {
+                status_code: 403,
+                message,
+            } => BackendError::PermissionDenied(message),
+            DockerError::DockerResponseServerError {
+                status_code: 404,
+                message,
+            } => BackendError::NotFound(message),
+            DockerError::IOError { err } => BackendError::Io(err),
+            e => BackendError::Other(e.to_string()),
+        }
+    }
+}
+
+impl DockerBackend {
+    pub fn new(
+        socket: Option<String>,
+        container: Option<String>,
+        exec_command: Option<Vec<String>>,
+    ) -> Result<DockerBackend, String> {
+        let docker = match socket {
+            Some(s) => Docker::connect_with_unix(&s, 120, API_DEFAULT_VERSION),
+            None => Docker::connect_with_local_defaults(),
+        };
+        match docker {
+            Ok(d) => Ok(DockerBackend::with_client(
+                "docker",
+                d,
+                container,
+                exec_command,
+            )),
+            Err(e) => Err(format!("could not connect to docker: {}", e)),
+        }
+    }
+
+    pub fn with_client(
+        kind: &'static str,
+        docker: Docker,
+        container: Option<String>,
+        exec_command: Option<Vec<String>>,
+    ) -> DockerBackend {
+        DockerBackend {
+            kind,
+            docker,
+            container: match container {
+                Some(c) => c,
+                None => String::from("minecraft"),
+            },
+            exec_command,
+        }
+    }
+
+    async fn exec(&self, program: &[String], command: &str) -> Result<(), BackendError> {
+        let mut cmd = program.to_vec();
+        cmd.push(command.to_owned());
+        let exec = self
+            .docker
+            .create_exec(
+                &self.container,
+                CreateExecOptions {
+                    cmd: Some(cmd),
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        // Wait for the command to finish so its effects are visible when
+        // this returns. Anything it prints shows up in the server log anyway.
+        if let StartExecResults::Attached { mut output, .. } =
+            self.docker.start_exec(&exec.id, None).await?
+        {
+            while let Some(chunk) = output.next().await {
+                chunk?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Output of a container as reported by `docker logs --follow`.
+pub struct DockerLogSource {
+    docker: Docker,
+    container: String,
+}
+
+impl LogSource for DockerLogSource {
+    fn name(&self) -> &'static str {
+        "docker"
+    }
+
+    fn lines(&self) -> LogLines {
+        let (tx, rx) = mpsc::channel(256);
+        let docker = self.docker.clone();
+        let container = self.container.clone();
+        tokio::spawn(async move {
+            loop {
+                let options = LogsOptions::<String> {
+                    follow: true,
+                    stdout: true,
+                    stderr: true,
+                    tail: String::from("0"),
+                    ..Default::default()
+                };
+                let mut logs = docker.logs(&container, Some(options));
+                let mut partial = String::new();
+                while let Some(chunk) = logs.next().await {
+                    let chunk = match chunk {
+                        Ok(c) => c,
+                        Err(e) => {
+                            tracing::warn!(%container, error = %e, "could not read container logs");
+                            break;
+                        }
+                    };
+                    partial.push_str(&String::from_utf8_lossy(&chunk.into_bytes()));
+                    while let Some(i) = partial.find('\n') {
+                        let line: String = partial.drain(..=i).collect();
+                        let entry = LogEntry::new(&container, line.trim_end().to_owned());
+                        if tx.send(entry).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                // The stream ends when the container stops, so pick it back
+                // up once it's running again.
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        });
+        channel_lines(rx)
+    }
+}
+
+#[async_trait]
+impl ServerBackend for DockerBackend {
+    fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    fn target(&self) -> String {
+        self.container.clone()
+    }
+
+    fn log_source(&self) -> Box<dyn LogSource> {
+        Box::new(DockerLogSource {
+            docker: self.docker.clone(),
+            container: self.container.clone(),
+        })
+    }
+
+    async fn log(&self) -> Result<LogStream, BackendError> {
+        // Only the current run, like latest.log.
+        let since = match self
+            .docker
+            .inspect_container(&self.container, None::<InspectContainerOptions>)
+            .await?
+            .state
+            .and_then(|s| s.started_at)
+            .and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok())
+        {
+            Some(t) => t.timestamp(),
+            None => 0,
+        };
+        let options = LogsOptions::<String> {
+            stdout: true,
+            stderr: true,
+            since,
+            ..Default::default()
+        };
+        let logs = self
+            .docker
+            .logs(&self.container, Some(options))
+            .map(|chunk| match chunk {
+                Ok(c) => Ok(LogOutput::into_bytes(c)),
+                Err(e) => Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
+            });
+        Ok(logs.boxed())
+    }
+
+    async fn send_command(&self, command: &str) -> Result<(), BackendError> {
+        let command = command.trim_end_matches('\n');
+        if let Some(program) = &self.exec_command {
+            return self.exec(program, command).await;
+        }
+
+        // Needs the container to be created with stdin open (`-i`).
+        let AttachContainerResults { mut input, .. } = self
+            .docker
+            .attach_container(
+                &self.container,
+                Some(AttachContainerOptions::<String> {
+                    stdin: Some(true),
+                    stream: Some(true),
+                    ..Default::default()
+                }),
+            )
+            .await?;
+        input.write_all(format!("{}\n", command).as_bytes()).await?;
+        input.flush().await?;
+        Ok(())
+    }
+
+    async fn start(&self) -> Result<String, BackendError> {
+        self.docker
+            .start_container(&self.container, None::<StartContainerOptions<String>>)
+            .await?;
+        tracing::info!(container = %self.container, "started the container");
+        Ok(self.container.clone())
+    }
+
+    async fn stop(&self) -> Result<String, BackendError> {
+        self.docker
+            .stop_container(&self.container, None::<StopContainerOptions>)
+            .await?;
+        tracing::info!(container = %self.container, "stopped the container");
+        Ok(self.container.clone())
+    }
+
+    async fn restart(&self) -> Result<String, BackendError> {
+        self.docker
+            .restart_container(&self.container, None::<RestartContainerOptions>)
+            .await?;
+        tracing::info!(container = %self.container, "restarted the container");
+        Ok(self.container.clone())
+    }
+
+    async fn status(&self) -> Result<ServerStatus, BackendError> {
+        let info = self
+            .docker
+            .inspect_container(&self.container, None::<InspectContainerOptions>)
+            .await?;
+        let state = info.state.unwrap_or_default();
+        let running = state.running.unwrap_or(false);
+
+        let sub_state = match state.status {
+            Some(s) => s.to_string(),
+            None => String::from("unknown"),
+        };
+        let pid = match state.pid {
+            Some(pid) if running && pid > 0 => Some(pid as u32),
+            _ => None,
+        };
+        let uptime_seconds = match state
+            .started_at
+            .and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok())
+        {
+            Some(t) if running => {
+                let seconds = chrono::Utc::now().timestamp() - t.timestamp();
+                Some(seconds.max(0) as u64)
+            }
+            _ => None,
+        };
+
+        Ok(ServerStatus {
+            backend: self.kind,
+            unit: self.container.clone(),
+            // Mirrors systemd's states so the UI can treat both alike.
+            active_state: String::from(if running { "active" } else { "inactive" }),
+            sub_state,
+            pid,
+            uptime_seconds,
+        })
+    }
+}
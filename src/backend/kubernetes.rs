@@ -0,0 +1,315 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{AsyncBufReadExt, StreamExt};
+use k8s_openapi::api::{apps::v1::StatefulSet, core::v1::Pod};
+use kube::{
+    api::{AttachParams, DeleteParams, ListParams, LogParams, Patch, PatchParams},
+    Api, Client,
+};
+use tokio::{io::AsyncWriteExt, sync::mpsc, sync::OnceCell};
+
+use super::{BackendError, LogStream, ServerBackend, ServerStatus};
+use crate::logsource::{channel_lines, LogEntry, LogLines, LogSource};
+
+/// A server running in a Kubernetes pod, found by label selector. Start and
+/// stop scale the StatefulSet that owns it, if one is configured.
+pub struct KubernetesBackend {
+    namespace: String,
+    selector: String,
+    container: Option<String>,
+    statefulset: Option<String>,
+    exec_command: Option<Vec<String>>,
+    client: OnceCell<Client>,
+}
+
+impl From<kube::Error> for BackendError {
+    fn from(e: kube::Error) -> Self {
+        match e {
+            kube::Error::Api(r) if r.code == 403 => BackendError::PermissionDenied(r.message),
+            kube::Error::Api(r) if r.code == 404 => BackendError::NotFound(r.message),
+            e => BackendError::Other(e.to_string()),
+        }
+    }
+}
+
+impl KubernetesBackend {
+    pub fn new(
+        namespace: Option<String>,
+        selector: Option<String>,
+        container: Option<String>,
+        statefulset: Option<String>,
+        exec_command: Option<Vec<String>>,
+    ) -> KubernetesBackend {
+        KubernetesBackend {
+            namespace: match namespace {
+                Some(n) => n,
+                None => String::from("default"),
+            },
+            selector: match selector {
+                Some(s) => s,
+                None => String::from("app=minecraft"),
+            },
+            container,
+            statefulset,
+            exec_command,
+            client: OnceCell::new(),
+        }
+    }
+
+    /// Uses the in-cluster service account, or the local kubeconfig.
+    async fn client(&self) -> Result<Client, BackendError> {
+        let client = self
+            .client
+            .get_or_try_init(|| async { Client::try_default().await })
+            .await?;
+        Ok(client.clone())
+    }
+
+    async fn pods(&self) -> Result<Api<Pod>, BackendError> {
+        Ok(Api::namespaced(self.client().await?, &self.namespace))
+    }
+
+    /// The matching pod, preferring one that is running.
+    async fn pod(&self) -> Result<Pod, BackendError> {
+        let mut pods = self
+            .pods()
+            .await?
+            .list(&ListParams::default().labels(&self.selector))
+            .await?
+            .items;
+        pods.sort_by_key(|p| phase(p) != "Running");
+        match pods.into_iter().next() {
+            Some(p) => Ok(p),
+            None => Err(BackendError::NotFound(format!(
+                "no pod matches {} in {}",
+                self.selector, self.namespace
+            ))),
+        }
+    }
+
+    async fn pod_name(&self) -> Result<String, BackendError> {
+        match self.pod().await?.metadata.name {
+            Some(n) => Ok(n),
+            None => Err(BackendError::Other(String::from("pod has no name"))),
+        }
+    }
+
+    fn attach_params(&self) -> AttachParams {
+        let params = AttachParams::default();
+        match &self.container {
+            Some(c) => params.container(c.as_str()),
+            None => params,
+        }
+    }
+
+    async fn scale(&self, replicas: i32) -> Result<String, BackendError> {
+        let name = match &self.statefulset {
+            Some(s) => s,
+            None => {
+                return Err(BackendError::Other(String::from(
+                    "no statefulset is configured to scale",
+                )))
+            }
+        };
+        let sets: Api<StatefulSet> = Api::namespaced(self.client().await?, &self.namespace);
+        sets.patch_scale(
+            name,
+            &PatchParams::default(),
+            &Patch::Merge(serde_json::json!({ "spec": { "replicas": replicas } })),
+        )
+        .await?;
+        tracing::info!(statefulset = %name, replicas, "scaled the statefulset");
+        Ok(name.clone())
+    }
+}
+
+fn phase(pod: &Pod) -> String {
+    match pod.status.as_ref().and_then(|s| s.phase.clone()) {
+        Some(p) => p,
+        None => String::from("Unknown"),
+    }
+}
+
+/// Output of whichever pod currently matches the selector.
+pub struct PodLogSource {
+    backend: Arc<KubernetesBackend>,
+}
+
+impl LogSource for PodLogSource {
+    fn name(&self) -> &'static str {
+        "kubernetes"
+    }
+
+    fn lines(&self) -> LogLines {
+        let (tx, rx) = mpsc::channel(256);
+        let backend = self.backend.clone();
+        tokio::spawn(async move {
+            loop {
+                // Pods come and go, so find the current one each time the
+                // stream ends.
+                let stream = async {
+                    let name = backend.pod_name().await?;
+                    let params = LogParams {
+                        follow: true,
+                        container: backend.container.clone(),
+                        tail_lines: Some(0),
+                        ..Default::default()
+                    };
+                    let stream = backend.pods().await?.log_stream(&name, &params).await?;
+                    Ok::<_, BackendError>((name, stream))
+                };
+                match stream.await {
+                    Ok((name, stream)) => {
+                        let mut lines = stream.lines();
+                        while let Some(Ok(line)) = lines.next().await {
+                            if tx.send(LogEntry::new(&name, line)).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(e) => tracing::warn!(error = %e, "could not follow pod logs"),
+                }
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+        channel_lines(rx)
+    }
+}
+
+#[async_trait]
+impl ServerBackend for KubernetesBackend {
+    fn kind(&self) -> &'static str {
+        "kubernetes"
+    }
+
+    fn target(&self) -> String {
+        format!("{}/{}", self.namespace, self.selector)
+    }
+
+    fn log_source(&self) -> Box<dyn LogSource> {
+        Box::new(PodLogSource {
+            backend: Arc::new(KubernetesBackend::new(
+                Some(self.namespace.clone()),
+                Some(self.selector.clone()),
+                self.container.clone(),
+                self.statefulset.clone(),
+                None,
+            )),
+        })
+    }
+
+    async fn log(&self) -> Result<LogStream, BackendError> {
+        let name = self.pod_name().await?;
+        let params = LogParams {
+            container: self.container.clone(),
+            ..Default::default()
+        };
+        let log = self.pods().await?.logs(&name, &params).await?;
+        Ok(futures::stream::once(async move { Ok(Bytes::from(log)) }).boxed())
+    }
+
+    async fn send_command(&self, command: &str) -> Result<(), BackendError> {
+        let command = command.trim_end_matches('\n');
+        let name = self.pod_name().await?;
+        let pods = self.pods().await?;
+
+        if let Some(program) = &self.exec_command {
+            let mut cmd = program.clone();
+            cmd.push(command.to_owned());
+            let process = pods
+                .exec(&name, cmd, &self.attach_params().stdout(true).stderr(true))
+                .await?;
+            process
+                .join()
+                .await
+                .map_err(|e| BackendError::Other(e.to_string()))?;
+            return Ok(());
+        }
+
+        // Needs `stdin: true` on the server container.
+        let params = self.attach_params().stdin(true).stdout(false).stderr(false);
+        let mut process = pods.attach(&name, &params).await?;
+        let mut stdin = match process.stdin() {
+            Some(s) => s,
+            None => return Err(BackendError::Other(String::from("pod has no stdin"))),
+        };
+        stdin.write_all(format!("{}\n", command).as_bytes()).await?;
+        stdin.flush().await?;
+        Ok(())
+    }
+
+    async fn start(&self) -> Result<String, BackendError> {
+        self.scale(1).await
+    }
+
+    async fn stop(&self) -> Result<String, BackendError> {
+        self.scale(0).await
+    }
+
+    /// Deletes the pod so its controller replaces it.
+    async fn restart(&self) -> Result<String, BackendError> {
+        let name = self.pod_name().await?;
+        self.pods()
+            .await?
+            .delete(&name, &DeleteParams::default())
+            .await?;
+        tracing::info!(pod = %name, "deleted the pod");
+        Ok(name)
+    }
+
+    async fn status(&self) -> Result<ServerStatus, BackendError> {
+        let pod = match self.pod().await {
+            Ok(p) => p,
+            // Scaled down to nothing.
+            Err(BackendError::NotFound(_)) => {
+                return Ok(ServerStatus {
+                    backend: self.kind(),
+                    unit: self.target(),
+                    active_state: String::from("inactive"),
+                    sub_state: String::from("dead"),
+                    pid: None,
+                    uptime_seconds: None,
+                })
+            }
+            Err(e) => return Err(e),
+        };
+
+        let phase = phase(&pod);
+        let running = phase == "Running";
+        let started = pod
+            .status
+            .as_ref()
+            .and_then(|s| s.container_statuses.as_ref())
+            .and_then(|statuses| {
+                statuses
+                    .iter()
+                    .find(|c| match &self.container {
+                        Some(name) => &c.name == name,
+                        None => true,
+                    })
+                    .cloned()
+            })
+            .and_then(|c| c.state)
+            .and_then(|s| s.running)
+            .and_then(|r| r.started_at);
+        let uptime_seconds = match started {
+            Some(t) if running => Some((chrono::Utc::now() - t.0).num_seconds().max(0) as u64),
+            _ => None,
+        };
+
+        Ok(ServerStatus {
+            backend: self.kind(),
+            unit: match pod.metadata.name {
+                Some(n) => format!("{}/{}", self.namespace, n),
+                None => self.target(),
+            },
+            active_state: String::from(if running { "active" } else { "inactive" }),
+            sub_state: phase.to_lowercase(),
+            pid: None,
+            uptime_seconds,
+        })
+    }
+}
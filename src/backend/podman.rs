@@ -0,0 +1,38 @@
+use bollard::{Docker, API_DEFAULT_VERSION};
+
+use super::docker::DockerBackend;
+
+/// Podman serves a Docker compatible API on its own socket, so a Podman
+/// server is driven by the Docker backend pointed at that socket.
+pub fn new(
+    socket: Option<String>,
+    container: Option<String>,
+    exec_command: Option<Vec<String>>,
+) -> Result<DockerBackend, String> {
+    let socket = match socket {
+        Some(s) => s,
+        None => default_socket(),
+    };
+    tracing::info!(%socket, "connecting to podman");
+    match Docker::connect_with_unix(&socket, 120, API_DEFAULT_VERSION) {
+        Ok(d) => Ok(DockerBackend::with_client(
+            "podman",
+            d,
+            container,
+            exec_command,
+        )),
+        Err(e) => Err(format!("could not connect to podman at {}: {}", socket, e)),
+    }
+}
+
+/// The rootless socket of the current user if there is one, otherwise the
+/// system socket.
+fn default_socket() -> String {
+    if let Ok(dir) = std::env::var("XDG_RUNTIME_DIR") {
+        let path = format!("{}/podman/podman.sock", dir);
+        if std::path::Path::new(&path).exists() {
+            return path;
+        }
+    }
+    String::from("/run/podman/podman.sock")
+}
@@ -0,0 +1,263 @@
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    process::{ChildStdin, Command},
+    sync::{broadcast, Mutex, Notify},
+};
+use tokio_util::io::ReaderStream;
+
+use super::{BackendError, LogStream, ServerBackend, ServerStatus};
+use crate::logsource::{LogEntry, LogSource, PipeSource};
+
+// How long a server gets to shut down after `stop` before it's killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(60);
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Runs the server JVM as a child process of the panel, restarting it if it
+/// exits on its own.
+pub struct ProcessBackend {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    command: Vec<String>,
+    working_dir: PathBuf,
+    restart_on_exit: bool,
+    // Everything the server prints, read by the log source.
+    output: broadcast::Sender<LogEntry>,
+    state: Mutex<ProcessState>,
+    kill: Notify,
+}
+
+#[derive(Default)]
+struct ProcessState {
+    stdin: Option<ChildStdin>,
+    pid: Option<u32>,
+    started: Option<Instant>,
+    // Whether the server should be running, as opposed to stopped on
+    // request.
+    wanted: bool,
+    restart_requested: bool,
+    // Bumped for every spawn so a pending kill doesn't hit a later process.
+    run: u64,
+}
+
+impl ProcessBackend {
+    pub fn new(
+        command: Option<Vec<String>>,
+        working_dir: Option<String>,
+        restart_on_exit: Option<bool>,
+    ) -> ProcessBackend {
+        let command = match command {
+            Some(c) if !c.is_empty() => c,
+            _ => ["java", "-Xmx2G", "-jar", "server.jar", "nogui"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        };
+        let inner = Arc::new(Inner {
+            command,
+            working_dir: match working_dir {
+                Some(d) => PathBuf::from(d),
+                None => PathBuf::from("/var/lib/minecraft"),
+            },
+            restart_on_exit: restart_on_exit.unwrap_or(true),
+            output: broadcast::channel(256).0,
+            state: Mutex::new(ProcessState {
+                wanted: true,
+                ..Default::default()
+            }),
+            kill: Notify::new(),
+        });
+
+        // Nothing else is going to start the server.
+        tokio::spawn(inner.clone().supervise());
+        ProcessBackend { inner }
+    }
+}
+
+impl Inner {
+    async fn write_stdin(&self, line: &str) -> Result<(), BackendError> {
+        let mut state = self.state.lock().await;
+        let stdin = match state.stdin.as_mut() {
+            Some(s) => s,
+            None => return Err(BackendError::Other(String::from("server is not running"))),
+        };
+        stdin.write_all(format!("{}\n", line).as_bytes()).await?;
+        stdin.flush().await?;
+        Ok(())
+    }
+
+    /// Asks the server to stop, killing it if it hasn't after STOP_TIMEOUT.
+    async fn request_stop(self: &Arc<Self>) -> Result<(), BackendError> {
+        let run = self.state.lock().await.run;
+        self.write_stdin("stop").await?;
+
+        let inner = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(STOP_TIMEOUT).await;
+            let state = inner.state.lock().await;
+            if state.run == run && state.pid.is_some() {
+                tracing::warn!("server did not stop in time, killing it");
+                inner.kill.notify_one();
+            }
+        });
+        Ok(())
+    }
+
+    /// Runs the server until it's stopped for good.
+    async fn supervise(self: Arc<Self>) {
+        loop {
+            let mut child = match Command::new(&self.command[0])
+                .args(&self.command[1..])
+                .current_dir(&self.working_dir)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()
+            {
+                Ok(c) => c,
+                Err(e) => {
+                    tracing::error!(command = %self.command[0], error = %e, "could not start the server process");
+                    self.state.lock().await.wanted = false;
+                    return;
+                }
+            };
+            tracing::info!(pid = ?child.id(), "started the server process");
+
+            {
+                let mut state = self.state.lock().await;
+                state.stdin = child.stdin.take();
+                state.pid = child.id();
+                state.started = Some(Instant::now());
+                state.run += 1;
+            }
+            if let Some(stdout) = child.stdout.take() {
+                tokio::spawn(forward_lines(self.clone(), "stdout", stdout));
+            }
+            if let Some(stderr) = child.stderr.take() {
+                tokio::spawn(forward_lines(self.clone(), "stderr", stderr));
+            }
+
+            let status = tokio::select! {
+                status = child.wait() => status,
+                _ = self.kill.notified() => {
+                    let _ = child.start_kill();
+                    child.wait().await
+                }
+            };
+            match status {
+                Ok(s) => tracing::info!(status = %s, "server process exited"),
+                Err(e) => tracing::warn!(error = %e, "could not wait for the server process"),
+            }
+
+            let mut state = self.state.lock().await;
+            state.stdin = None;
+            state.pid = None;
+            state.started = None;
+            if state.restart_requested {
+                state.restart_requested = false;
+                continue;
+            }
+            if !state.wanted || !self.restart_on_exit {
+                state.wanted = false;
+                return;
+            }
+            drop(state);
+            tracing::warn!("server exited unexpectedly, restarting it");
+            tokio::time::sleep(RESTART_DELAY).await;
+            if !self.state.lock().await.wanted {
+                return;
+            }
+        }
+    }
+}
+
+async fn forward_lines<R: AsyncRead + Unpin>(inner: Arc<Inner>, source: &'static str, reader: R) {
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        // Fails only while nobody is subscribed.
+        let _ = inner.output.send(LogEntry::new(source, line));
+    }
+}
+
+#[async_trait]
+impl ServerBackend for ProcessBackend {
+    fn kind(&self) -> &'static str {
+        "process"
+    }
+
+    fn target(&self) -> String {
+        self.inner.command.join(" ")
+    }
+
+    fn log_source(&self) -> Box<dyn LogSource> {
+        Box::new(PipeSource::new(self.inner.output.clone()))
+    }
+
+    async fn log(&self) -> Result<LogStream, BackendError> {
+        let path = self.inner.working_dir.join("logs").join("latest.log");
+        let file = tokio::fs::File::open(path).await?;
+        Ok(ReaderStream::new(file).boxed())
+    }
+
+    async fn send_command(&self, command: &str) -> Result<(), BackendError> {
+        self.inner.write_stdin(command.trim_end_matches('\n')).await
+    }
+
+    async fn start(&self) -> Result<String, BackendError> {
+        let mut state = self.inner.state.lock().await;
+        if state.wanted {
+            return Ok(String::from("already running"));
+        }
+        state.wanted = true;
+        drop(state);
+        tokio::spawn(self.inner.clone().supervise());
+        Ok(String::from("starting"))
+    }
+
+    async fn stop(&self) -> Result<String, BackendError> {
+        self.inner.state.lock().await.wanted = false;
+        self.inner.request_stop().await?;
+        Ok(String::from("stopping"))
+    }
+
+    async fn restart(&self) -> Result<String, BackendError> {
+        {
+            let mut state = self.inner.state.lock().await;
+            if state.pid.is_none() {
+                drop(state);
+                return self.start().await;
+            }
+            state.wanted = true;
+            state.restart_requested = true;
+        }
+        self.inner.request_stop().await?;
+        Ok(String::from("restarting"))
+    }
+
+    async fn status(&self) -> Result<ServerStatus, BackendError> {
+        let state = self.inner.state.lock().await;
+        let (active_state, sub_state) = match (state.pid, state.wanted) {
+            (Some(_), true) => ("active", "running"),
+            (Some(_), false) => ("deactivating", "stop-sigterm"),
+            (None, true) => ("activating", "auto-restart"),
+            (None, false) => ("inactive", "dead"),
+        };
+        Ok(ServerStatus {
+            backend: self.kind(),
+            unit: self.target(),
+            active_state: active_state.to_owned(),
+            sub_state: sub_state.to_owned(),
+            pid: state.pid,
+            uptime_seconds: state.started.map(|s| s.elapsed().as_secs()),
+        })
+    }
+}
@@ -0,0 +1,238 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use tokio::process::Command;
+use tokio_util::io::ReaderStream;
+
+use super::{BackendError, LogStream, ServerBackend, ServerStatus};
+use crate::logsource::{file::FileSource, LogSource};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplexer {
+    Tmux,
+    Screen,
+}
+
+/// A server running inside a tmux or screen session. Commands are typed
+/// into the session and the log file is tailed for output.
+pub struct ScreenBackend {
+    multiplexer: Multiplexer,
+    session: String,
+    log_path: PathBuf,
+    // Used to create the session on start, if set.
+    command: Option<Vec<String>>,
+    working_dir: Option<String>,
+}
+
+impl ScreenBackend {
+    pub fn new(
+        multiplexer: Multiplexer,
+        session: Option<String>,
+        log_path: Option<String>,
+        command: Option<Vec<String>>,
+        working_dir: Option<String>,
+    ) -> ScreenBackend {
+        ScreenBackend {
+            multiplexer,
+            session: match session {
+                Some(s) => s,
+                None => String::from("minecraft"),
+            },
+            log_path: match log_path {
+                Some(p) => PathBuf::from(p),
+                None => PathBuf::from("/var/lib/minecraft/logs/latest.log"),
+            },
+            command,
+            working_dir,
+        }
+    }
+
+    fn program(&self) -> &'static str {
+        match self.multiplexer {
+            Multiplexer::Tmux => "tmux",
+            Multiplexer::Screen => "screen",
+        }
+    }
+
+    async fn run(&self, args: &[&str]) -> Result<String, BackendError> {
+        let output = Command::new(self.program()).args(args).output().await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+            if stderr.contains("can't find session")
+                || stderr.contains("no server running")
+                || stderr.contains("No screen session found")
+            {
+                return Err(BackendError::NotFound(self.session.clone()));
+            }
+            return Err(BackendError::Other(format!(
+                "{} failed: {}",
+                self.program(),
+                stderr
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    async fn running(&self) -> bool {
+        match self.multiplexer {
+            Multiplexer::Tmux => self
+                .run(&["has-session", "-t", &self.session])
+                .await
+                .is_ok(),
+            Multiplexer::Screen => self.screen_pid().await.is_some(),
+        }
+    }
+
+    /// screen -ls lists sessions as `<pid>.<name>`.
+    async fn screen_pid(&self) -> Option<u32> {
+        // screen -ls exits non-zero even when it finds sessions.
+        let output = Command::new("screen").arg("-ls").output().await.ok()?;
+        let listing = String::from_utf8_lossy(&output.stdout).into_owned();
+        listing.lines().find_map(|line| {
+            let (pid, name) = line.split_whitespace().next()?.split_once('.')?;
+            if name == self.session {
+                pid.parse().ok()
+            } else {
+                None
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl ServerBackend for ScreenBackend {
+    fn kind(&self) -> &'static str {
+        self.program()
+    }
+
+    fn target(&self) -> String {
+        self.session.clone()
+    }
+
+    fn log_source(&self) -> Box<dyn LogSource> {
+        Box::new(FileSource::new(self.log_path.clone()))
+    }
+
+    async fn log(&self) -> Result<LogStream, BackendError> {
+        let file = tokio::fs::File::open(&self.log_path).await?;
+        Ok(ReaderStream::new(file).boxed())
+    }
+
+    async fn send_command(&self, command: &str) -> Result<(), BackendError> {
+        let command = command.trim_end_matches('\n');
+        match self.multiplexer {
+            Multiplexer::Tmux => {
+                // -l sends the command literally rather than as key names.
+                self.run(&["send-keys", "-t", &self.session, "-l", command])
+                    .await?;
+                self.run(&["send-keys", "-t", &self.session, "Enter"])
+                    .await?;
+            }
+            Multiplexer::Screen => {
+                let line = format!("{}\r", command);
+                self.run(&["-S", &self.session, "-p", "0", "-X", "stuff", &line])
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn start(&self) -> Result<String, BackendError> {
+        if self.running().await {
+            return Ok(String::from("already running"));
+        }
+        let command = match &self.command {
+            Some(c) if !c.is_empty() => c,
+            _ => {
+                return Err(BackendError::Other(String::from(
+                    "no command is configured to start the session with",
+                )))
+            }
+        };
+
+        let dir = match &self.working_dir {
+            Some(d) => d.as_str(),
+            None => ".",
+        };
+        let mut args: Vec<&str> = match self.multiplexer {
+            Multiplexer::Tmux => vec!["new-session", "-d", "-s", &self.session, "-c", dir],
+            Multiplexer::Screen => vec!["-dmS", &self.session],
+        };
+        args.extend(command.iter().map(|s| s.as_str()));
+        let mut process = Command::new(self.program());
+        process.args(&args).current_dir(dir);
+        let output = process.output().await?;
+        if !output.status.success() {
+            return Err(BackendError::Other(
+                String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            ));
+        }
+        tracing::info!(program = self.program(), session = %self.session, "started the session");
+        Ok(self.session.clone())
+    }
+
+    async fn stop(&self) -> Result<String, BackendError> {
+        // The session ends by itself once the server has shut down.
+        self.send_command("stop").await?;
+        Ok(self.session.clone())
+    }
+
+    async fn restart(&self) -> Result<String, BackendError> {
+        self.stop().await?;
+        for _ in 0..120 {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            if !self.running().await {
+                return self.start().await;
+            }
+        }
+        Err(BackendError::Other(format!(
+            "session {} did not stop in time",
+            self.session
+        )))
+    }
+
+    async fn status(&self) -> Result<ServerStatus, BackendError> {
+        let (pid, uptime_seconds) = match self.multiplexer {
+            Multiplexer::Tmux => {
+                match self
+                    .run(&[
+                        "list-panes",
+                        "-t",
+                        &self.session,
+                        "-F",
+                        "#{pane_pid} #{session_created}",
+                    ])
+                    .await
+                {
+                    Ok(out) => {
+                        let mut fields = out.split_whitespace();
+                        let pid = fields.next().and_then(|p| p.parse().ok());
+                        let uptime =
+                            fields
+                                .next()
+                                .and_then(|c| c.parse::<i64>().ok())
+                                .map(|created| {
+                                    (chrono::Utc::now().timestamp() - created).max(0) as u64
+                                });
+                        (pid, uptime)
+                    }
+                    Err(BackendError::NotFound(_)) => (None, None),
+                    Err(e) => return Err(e),
+                }
+            }
+            Multiplexer::Screen => (self.screen_pid().await, None),
+        };
+
+        let running = pid.is_some();
+        Ok(ServerStatus {
+            backend: self.kind(),
+            unit: self.session.clone(),
+            active_state: String::from(if running { "active" } else { "inactive" }),
+            sub_state: String::from(if running { "running" } else { "dead" }),
+            pid,
+            uptime_seconds,
+        })
+    }
+}
@@ -0,0 +1,111 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+use super::{BackendError, LogStream, ServerBackend, ServerStatus};
+use crate::lifecycle::Lifecycle;
+use crate::logsource::{
+    journal::{JournalFilter, JournalSource},
+    LogSource,
+};
+
+/// A server run by a systemd unit, with its console on a FIFO and its output
+/// in the journal.
+pub struct SystemdBackend {
+    log_path: String,
+    socket_path: String,
+    journal: JournalFilter,
+    lifecycle: Lifecycle,
+}
+
+impl SystemdBackend {
+    pub fn new(
+        unit: Option<String>,
+        log_path: Option<String>,
+        socket_path: Option<String>,
+        journal: JournalFilter,
+    ) -> SystemdBackend {
+        let unit = match unit {
+            Some(s) => s,
+            None => String::from("minecraft-server.service"),
+        };
+        SystemdBackend {
+            log_path: match log_path {
+                Some(f) => f,
+                None => String::from("/var/lib/minecraft/logs/latest.log"),
+            },
+            socket_path: match socket_path {
+                Some(f) => f,
+                None => String::from("/run/minecraft-server.stdin"),
+            },
+            journal,
+            lifecycle: Lifecycle::new(unit),
+        }
+    }
+}
+
+#[async_trait]
+impl ServerBackend for SystemdBackend {
+    fn kind(&self) -> &'static str {
+        "systemd"
+    }
+
+    fn target(&self) -> String {
+        self.lifecycle.unit().to_owned()
+    }
+
+    fn log_source(&self) -> Box<dyn LogSource> {
+        Box::new(JournalSource::new(self.journal.clone()))
+    }
+
+    async fn log(&self) -> Result<LogStream, BackendError> {
+        let file = tokio::fs::File::open(&self.log_path).await?;
+        Ok(ReaderStream::new(file).boxed())
+    }
+
+    async fn send_command(&self, command: &str) -> Result<(), BackendError> {
+        let console = |e| BackendError::Console(self.socket_path.clone(), e);
+        let mut file = match OpenOptions::new()
+            .read(false)
+            .write(true)
+            .open(&self.socket_path)
+            .await
+        {
+            Ok(f) => f,
+            Err(e) => return Err(console(e)),
+        };
+        let line = format!("{}\n", command.trim_end_matches('\n'));
+        if let Err(e) = file.write_all(line.as_bytes()).await {
+            return Err(console(e));
+        }
+        if let Err(e) = file.flush().await {
+            return Err(console(e));
+        }
+        Ok(())
+    }
+
+    async fn start(&self) -> Result<String, BackendError> {
+        Ok(self.lifecycle.start().await?)
+    }
+
+    async fn stop(&self) -> Result<String, BackendError> {
+        Ok(self.lifecycle.stop().await?)
+    }
+
+    async fn restart(&self) -> Result<String, BackendError> {
+        Ok(self.lifecycle.restart().await?)
+    }
+
+    async fn status(&self) -> Result<ServerStatus, BackendError> {
+        let status = self.lifecycle.status().await?;
+        Ok(ServerStatus {
+            backend: self.kind(),
+            unit: status.unit,
+            active_state: status.active_state,
+            sub_state: status.sub_state,
+            pid: status.pid,
+            uptime_seconds: status.uptime_seconds,
+        })
+    }
+}
@@ -0,0 +1,658 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+use chrono::Local;
+use data_encoding::HEXLOWER;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::events::ServerEvent;
+use crate::minecraft::MinecraftControl;
+use crate::util::now;
+use crate::worlds;
+
+pub mod repository;
+pub mod restore;
+pub mod retention;
+pub mod s3;
+
+// How long a restore confirmation token lasts.
+const CONFIRM_SECONDS: u64 = 300;
+// How long restoring waits for the server to stop.
+const STOP_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Backed up along with the worlds when `include` isn't set.
+const CONFIG_FILES: &[&str] = &[
+    "server.properties",
+    "ops.json",
+    "whitelist.json",
+    "banned-players.json",
+    "banned-ips.json",
+    "eula.txt",
+    "server-icon.png",
+];
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct BackupConfig {
+    // Where archives and the list of backups are kept.
+    destination: String,
+    // When to take backups, as a cron expression with seconds, e.g.
+    // "0 0 4 * * *" for 4am every day. Only on request if unset.
+    schedule: Option<String>,
+    format: Option<BackupFormat>,
+    // For restic and borg: the repository to back up to, the program if
+    // it isn't on PATH, and a file with the repository's password.
+    repository: Option<String>,
+    binary: Option<String>,
+    password_file: Option<String>,
+    // Paths in the server directory to back up. Every world and the
+    // server's config files if unset.
+    include: Option<Vec<String>>,
+    // A bucket every archive is copied to once it's written.
+    s3: Option<s3::S3Config>,
+    // Backups left out are pruned after each new one. All of them are kept
+    // if unset.
+    retention: Option<retention::Retention>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackupFormat {
+    #[default]
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    #[serde(rename = "tar.zst")]
+    TarZst,
+    #[serde(rename = "restic")]
+    Restic,
+    #[serde(rename = "borg")]
+    Borg,
+}
+
+impl BackupFormat {
+    /// None for formats that go to a repository instead of an archive.
+    fn extension(&self) -> Option<&'static str> {
+        match self {
+            BackupFormat::TarGz => Some("tar.gz"),
+            BackupFormat::TarZst => Some("tar.zst"),
+            BackupFormat::Restic | BackupFormat::Borg => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupRecord {
+    pub id: String,
+    // The archive's file name in the destination, or the restic snapshot or
+    // borg archive.
+    pub file: String,
+    // Seconds since the epoch.
+    pub created: u64,
+    pub size: u64,
+    pub format: BackupFormat,
+    // "schedule", or the name of whoever asked for it.
+    pub trigger: String,
+    // The archive's key in the S3 bucket, once it's been uploaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum BackupError {
+    Io(std::io::Error),
+    // Another backup of this server hasn't finished.
+    Busy,
+    NoServerDir,
+    // The server didn't confirm it had flushed the world.
+    NotSaved,
+    // The archive was written but couldn't be copied off the server.
+    Upload(s3::S3Error),
+    // An archive couldn't be removed from the bucket.
+    Remote(s3::S3Error),
+    // restic or borg failed.
+    Tool(String),
+    NotFound,
+    // A restore was asked for without a valid confirmation token.
+    Unconfirmed,
+    StillRunning,
+}
+
+impl From<std::io::Error> for BackupError {
+    fn from(e: std::io::Error) -> Self {
+        BackupError::Io(e)
+    }
+}
+
+impl std::fmt::Display for BackupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackupError::Io(e) => write!(f, "{}", e),
+            BackupError::Busy => write!(f, "a backup is already running"),
+            BackupError::NoServerDir => {
+                write!(f, "this server's files aren't available to the panel")
+            }
+            BackupError::NotSaved => write!(f, "the server didn't confirm it had saved"),
+            BackupError::Upload(e) => write!(f, "the archive was kept but not uploaded: {}", e),
+            BackupError::Remote(e) => write!(f, "could not remove the archive from s3: {}", e),
+            BackupError::Tool(message) => write!(f, "{}", message),
+            BackupError::NotFound => write!(f, "there's no backup with that id"),
+            BackupError::Unconfirmed => {
+                write!(f, "the confirmation token is wrong or has expired")
+            }
+            BackupError::StillRunning => write!(f, "the server didn't stop"),
+        }
+    }
+}
+
+/// A server's backups: taking them on a schedule or on request, and the
+/// list of those taken, persisted as JSON next to the archives.
+#[derive(Clone)]
+pub struct Backups {
+    config: BackupConfig,
+    destination: PathBuf,
+    schedule: Option<cron::Schedule>,
+    s3: Option<s3::S3Target>,
+    repository: Option<repository::Repository>,
+    records: Arc<Mutex<Vec<BackupRecord>>>,
+    // Held for as long as a backup or restore runs.
+    running: Arc<Mutex<()>>,
+    // Restore confirmation tokens, with the backup each is for and when it
+    // expires.
+    confirmations: Arc<Mutex<HashMap<String, (String, u64)>>>,
+}
+
+/// Include paths have to stay inside the server directory.
+fn valid_include(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+}
+
+impl Backups {
+    /// Reads what's been made so far, or says what's wrong with the config.
+    pub fn load(config: BackupConfig) -> Result<Backups, String> {
+        let schedule = match &config.schedule {
+            Some(s) => match cron::Schedule::from_str(s) {
+                Ok(s) => Some(s),
+                Err(e) => return Err(format!("backup schedule {:?} is not valid: {}", s, e)),
+            },
+            None => None,
+        };
+        if let Some(include) = &config.include {
+            if let Some(bad) = include.iter().find(|p| !valid_include(p)) {
+                return Err(format!(
+                    "backup include {:?} has to be inside the server directory",
+                    bad
+                ));
+            }
+        }
+        let format = config.format.unwrap_or_default();
+        let repository = match (format.extension(), &config.repository) {
+            (Some(_), _) => None,
+            (None, Some(r)) => Some(repository::Repository::new(
+                format,
+                config.binary.clone(),
+                r.clone(),
+                config.password_file.clone(),
+            )),
+            (None, None) => return Err(String::from("restic and borg backups need a repository")),
+        };
+        if repository.is_some() && config.s3.is_some() {
+            return Err(String::from(
+                "backups to a restic or borg repository can't be uploaded to s3",
+            ));
+        }
+        let s3 = match config.s3.clone() {
+            Some(c) => Some(s3::S3Target::new(c)?),
+            None => None,
+        };
+        let destination = PathBuf::from(&config.destination);
+        let path = destination.join("backups.json");
+        let records = match std::fs::read_to_string(&path) {
+            Ok(file) => match serde_json::from_str(&file) {
+                Ok(r) => r,
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            },
+            Err(_) => Vec::new(),
+        };
+        Ok(Backups {
+            config,
+            destination,
+            schedule,
+            s3,
+            repository,
+            records: Arc::new(Mutex::new(records)),
+            running: Arc::new(Mutex::new(())),
+            confirmations: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    async fn save(&self, records: &[BackupRecord]) -> Result<(), std::io::Error> {
+        let path = self.destination.join("backups.json");
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(records)?).await?;
+        fs::rename(&tmp, &path).await
+    }
+
+    /// Newest first.
+    pub async fn list(&self) -> Vec<BackupRecord> {
+        let mut records = self.records.lock().await.clone();
+        records.reverse();
+        records
+    }
+
+    /// Where archives and the list of backups are kept.
+    pub fn destination(&self) -> &Path {
+        &self.destination
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.try_lock().is_err()
+    }
+
+    pub fn retention(&self) -> Option<&retention::Retention> {
+        self.config.retention.as_ref()
+    }
+
+    /// Seconds since the epoch of the next scheduled backup.
+    pub fn next_run(&self) -> Option<u64> {
+        let next = self.schedule.as_ref()?.upcoming(Local).next()?;
+        Some(next.timestamp() as u64)
+    }
+
+    /// Starts a backup in the background, which publishes BackupFinished
+    /// or BackupFailed when it's done.
+    pub fn start(&self, control: MinecraftControl, trigger: String) -> Result<(), BackupError> {
+        let guard = match self.running.clone().try_lock_owned() {
+            Ok(g) => g,
+            Err(_) => return Err(BackupError::Busy),
+        };
+        let backups = self.clone();
+        tokio::spawn(async move {
+            let event = match backups.run(&control, trigger).await {
+                Ok(record) => {
+                    tracing::info!(server = %control.name(), file = %record.file, "backed up the server");
+                    if let Err(e) = backups.prune().await {
+                        tracing::warn!(server = %control.name(), error = %e, "could not prune backups");
+                    }
+                    ServerEvent::BackupFinished {
+                        id: record.id,
+                        size: record.size,
+                    }
+                }
+                Err(e) => {
+                    tracing::error!(server = %control.name(), error = %e, "backup failed");
+                    ServerEvent::BackupFailed {
+                        message: e.to_string(),
+                    }
+                }
+            };
+            drop(guard);
+            control.publish(event);
+        });
+        Ok(())
+    }
+
+    async fn run(
+        &self,
+        control: &MinecraftControl,
+        trigger: String,
+    ) -> Result<BackupRecord, BackupError> {
+        let server_dir = match control.server_dir() {
+            Some(d) => d,
+            None => return Err(BackupError::NoServerDir),
+        };
+        let include = match &self.config.include {
+            Some(i) => i.clone(),
+            None => default_include(&server_dir).await?,
+        };
+        let format = self.config.format.unwrap_or_default();
+        let id = format!(
+            "{}-{}",
+            control.name(),
+            Local::now().format("%Y%m%d-%H%M%S")
+        );
+
+        let paused = match control.pause_saving().await {
+            Ok(p) => Some(p),
+            // A server that isn't running isn't writing either.
+            Err(_) if !control.is_running().await => None,
+            Err(_) => return Err(BackupError::NotSaved),
+        };
+        let written = match (&self.repository, format.extension()) {
+            (Some(r), _) => r.create(&id, &server_dir, &include).await,
+            (None, Some(extension)) => {
+                self.write(&id, extension, format, &server_dir, &include)
+                    .await
+            }
+            // load makes sure there's a repository for formats without one.
+            (None, None) => Err(BackupError::Tool(String::from(
+                "restic and borg backups need a repository",
+            ))),
+        };
+        if let Some(p) = paused {
+            if p.resume().await.is_err() {
+                tracing::warn!(server = %control.name(), "could not turn saving back on");
+            }
+        }
+        let (file, size) = written?;
+
+        let mut record = BackupRecord {
+            id,
+            file,
+            created: now(),
+            size,
+            format,
+            trigger,
+            remote: None,
+        };
+        // Kept whether or not the upload works, since the archive is there.
+        let uploaded = match &self.s3 {
+            Some(s3) => match s3
+                .upload(&self.destination.join(&record.file), &record.file)
+                .await
+            {
+                Ok(key) => {
+                    record.remote = Some(key);
+                    Ok(())
+                }
+                Err(e) => Err(BackupError::Upload(e)),
+            },
+            None => Ok(()),
+        };
+        let mut records = self.records.lock().await;
+        records.push(record.clone());
+        self.save(&records).await?;
+        uploaded.map(|()| record)
+    }
+
+    /// A token that has to be sent back to restore the backup `id`, and
+    /// when it expires, since restoring replaces the world.
+    pub async fn confirmation(&self, id: &str) -> Result<(String, u64), BackupError> {
+        if !self.records.lock().await.iter().any(|r| r.id == id) {
+            return Err(BackupError::NotFound);
+        }
+        let mut bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token = HEXLOWER.encode(&bytes);
+        let expires = now() + CONFIRM_SECONDS;
+        let mut confirmations = self.confirmations.lock().await;
+        confirmations.retain(|_, (_, e)| *e > now());
+        confirmations.insert(token.clone(), (id.to_owned(), expires));
+        Ok((token, expires))
+    }
+
+    /// Starts restoring the backup `id` in the background: the server is
+    /// stopped, the backup put in place of what's there, and the server
+    /// started again if it was running. Each step is published as
+    /// RestoreProgress, then RestoreFinished or RestoreFailed.
+    pub async fn restore(
+        &self,
+        control: MinecraftControl,
+        id: &str,
+        token: &str,
+    ) -> Result<(), BackupError> {
+        match self.confirmations.lock().await.remove(token) {
+            Some((for_id, expires)) if for_id == id && expires > now() => {}
+            _ => return Err(BackupError::Unconfirmed),
+        }
+        let record = match self.records.lock().await.iter().find(|r| r.id == id) {
+            Some(r) => r.clone(),
+            None => return Err(BackupError::NotFound),
+        };
+        let guard = match self.running.clone().try_lock_owned() {
+            Ok(g) => g,
+            Err(_) => return Err(BackupError::Busy),
+        };
+        let backups = self.clone();
+        tokio::spawn(async move {
+            let event = match backups.run_restore(&control, &record).await {
+                Ok(moved) => {
+                    tracing::info!(server = %control.name(), backup = %record.id, "restored a backup");
+                    for old in moved {
+                        tracing::info!(path = %old.display(), "moved the old world aside");
+                    }
+                    ServerEvent::RestoreFinished { id: record.id }
+                }
+                Err(e) => {
+                    tracing::error!(server = %control.name(), error = %e, "restoring a backup failed");
+                    ServerEvent::RestoreFailed {
+                        id: record.id,
+                        message: e.to_string(),
+                    }
+                }
+            };
+            drop(guard);
+            control.publish(event);
+        });
+        Ok(())
+    }
+
+    async fn run_restore(
+        &self,
+        control: &MinecraftControl,
+        record: &BackupRecord,
+    ) -> Result<Vec<PathBuf>, BackupError> {
+        let server_dir = match control.server_dir() {
+            Some(d) => d,
+            None => return Err(BackupError::NoServerDir),
+        };
+        let step = |step| {
+            control.publish(ServerEvent::RestoreProgress {
+                id: record.id.clone(),
+                step,
+            })
+        };
+
+        step("stopping");
+        let was_running = control.is_running().await;
+        if was_running {
+            if let Err(e) = control.stop().await {
+                return Err(BackupError::Tool(format!(
+                    "could not stop the server: {}",
+                    e
+                )));
+            }
+            let deadline = tokio::time::Instant::now() + STOP_TIMEOUT;
+            while control.is_running().await {
+                if tokio::time::Instant::now() > deadline {
+                    return Err(BackupError::StillRunning);
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+
+        step("extracting");
+        let staging = server_dir.join(format!(".restore-{}", record.id));
+        let _ = fs::remove_dir_all(&staging).await;
+        fs::create_dir_all(&staging).await?;
+        let extracted = match &self.repository {
+            Some(r) => r.extract(&record.file, &server_dir, &staging).await,
+            None => {
+                let path = self.destination.join(&record.file);
+                restore::extract_archive(&path, record.format, &staging)
+                    .await
+                    .map_err(BackupError::from)
+            }
+        };
+        let moved = match extracted {
+            Ok(()) => {
+                step("swapping");
+                restore::swap_in(&staging, &server_dir).await
+            }
+            Err(e) => {
+                let _ = fs::remove_dir_all(&staging).await;
+                return Err(e);
+            }
+        };
+        let _ = fs::remove_dir_all(&staging).await;
+        let moved = moved?;
+
+        if was_running {
+            step("starting");
+            if let Err(e) = control.start().await {
+                return Err(BackupError::Tool(format!(
+                    "restored, but could not start the server: {}",
+                    e
+                )));
+            }
+        }
+        Ok(moved)
+    }
+
+    /// Removes the backups the retention policy doesn't keep, from
+    /// wherever they're stored. Ones that fail to go are tried again next
+    /// time.
+    async fn prune(&self) -> Result<(), BackupError> {
+        let retention = match &self.config.retention {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+        let mut records = self.records.lock().await;
+        let expired = retention.expired(&records);
+        let mut failed = None;
+        for id in &expired {
+            let record = match records.iter().find(|r| r.id == *id) {
+                Some(r) => r.clone(),
+                None => continue,
+            };
+            match self.delete(&record).await {
+                Ok(()) => {
+                    tracing::info!(backup = %record.id, "pruned a backup");
+                    records.retain(|r| r.id != record.id);
+                }
+                Err(e) => failed = Some(e),
+            }
+        }
+        if !expired.is_empty() {
+            self.save(&records).await?;
+        }
+        match failed {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    async fn delete(&self, record: &BackupRecord) -> Result<(), BackupError> {
+        if let Some(r) = &self.repository {
+            return r.delete(&record.file).await;
+        }
+        if let (Some(s3), Some(key)) = (&self.s3, &record.remote) {
+            s3.delete(key).await.map_err(BackupError::Remote)?;
+        }
+        match fs::remove_file(self.destination.join(&record.file)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes an archive to the destination and returns its name and size.
+    async fn write(
+        &self,
+        id: &str,
+        extension: &str,
+        format: BackupFormat,
+        server_dir: &Path,
+        include: &[String],
+    ) -> Result<(String, u64), BackupError> {
+        let file = format!("{}.{}", id, extension);
+        fs::create_dir_all(&self.destination).await?;
+        let path = self.destination.join(&file);
+        let tmp = self.destination.join(format!(".{}.tmp", file));
+        if let Err(e) = write_archive(&tmp, format, server_dir, include).await {
+            let _ = fs::remove_file(&tmp).await;
+            return Err(e.into());
+        }
+        fs::rename(&tmp, &path).await?;
+        let size = fs::metadata(&path).await?.len();
+        Ok((file, size))
+    }
+
+    /// Takes backups on the schedule, if there is one, until `shutdown` is
+    /// cancelled.
+    pub fn schedule(&self, control: MinecraftControl, shutdown: CancellationToken) {
+        let schedule = match &self.schedule {
+            Some(s) => s.clone(),
+            None => return,
+        };
+        let backups = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let next = match schedule.upcoming(Local).next() {
+                    Some(n) => n,
+                    None => return,
+                };
+                let wait = (next - Local::now()).to_std().unwrap_or_default();
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {},
+                    _ = shutdown.cancelled() => return,
+                }
+                if let Err(e) = backups.start(control.clone(), String::from("schedule")) {
+                    tracing::warn!(server = %control.name(), error = %e, "skipped a scheduled backup");
+                }
+            }
+        });
+    }
+}
+
+async fn default_include(server_dir: &Path) -> Result<Vec<String>, std::io::Error> {
+    let mut include: Vec<String> = worlds::list(server_dir)
+        .await?
+        .into_iter()
+        .map(|w| w.name)
+        .collect();
+    for file in CONFIG_FILES {
+        if fs::try_exists(server_dir.join(file)).await? {
+            include.push(String::from(*file));
+        }
+    }
+    Ok(include)
+}
+
+async fn write_archive(
+    path: &Path,
+    format: BackupFormat,
+    server_dir: &Path,
+    include: &[String],
+) -> Result<(), std::io::Error> {
+    let file = fs::File::create(path).await?;
+    let mut compressed: Box<dyn AsyncWrite + Unpin + Send> = match format {
+        BackupFormat::TarZst => {
+            Box::new(append(ZstdEncoder::new(file), server_dir, include).await?)
+        }
+        _ => Box::new(append(GzipEncoder::new(file), server_dir, include).await?),
+    };
+    // Finishes the compressed stream as well as the file.
+    compressed.shutdown().await
+}
+
+async fn append<W: AsyncWrite + Unpin + Send + 'static>(
+    writer: W,
+    server_dir: &Path,
+    include: &[String],
+) -> Result<W, std::io::Error> {
+    let mut tar = tokio_tar::Builder::new(writer);
+    tar.follow_symlinks(false);
+    for name in include {
+        let path = server_dir.join(name);
+        let metadata = match fs::metadata(&path).await {
+            Ok(m) => m,
+            // Something configured that the server hasn't written yet.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        if metadata.is_dir() {
+            tar.append_dir_all(name, &path).await?;
+        } else {
+            tar.append_path_with_name(&path, name).await?;
+        }
+    }
+    tar.into_inner().await
+}
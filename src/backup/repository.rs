@@ -0,0 +1,198 @@
+use std::path::Path;
+
+use serde::Deserialize;
+use tokio::process::Command;
+
+use super::{BackupError, BackupFormat};
+
+/// A restic or borg repository backups are handed to, for when
+/// deduplication is wanted.
+#[derive(Clone)]
+pub struct Repository {
+    format: BackupFormat,
+    binary: String,
+    repository: String,
+    password_file: Option<String>,
+}
+
+// The last line restic prints with --json.
+#[derive(Deserialize)]
+struct ResticSummary {
+    message_type: String,
+    snapshot_id: Option<String>,
+    #[serde(default)]
+    data_added: u64,
+}
+
+#[derive(Deserialize)]
+struct BorgCreate {
+    archive: BorgArchive,
+}
+
+#[derive(Deserialize)]
+struct BorgArchive {
+    name: String,
+    stats: BorgStats,
+}
+
+#[derive(Deserialize)]
+struct BorgStats {
+    deduplicated_size: u64,
+}
+
+fn failed(program: &str, stderr: &[u8]) -> BackupError {
+    let stderr = String::from_utf8_lossy(stderr);
+    let last = stderr.lines().rev().find(|l| !l.trim().is_empty());
+    BackupError::Tool(format!(
+        "{} failed: {}",
+        program,
+        last.unwrap_or("no output")
+    ))
+}
+
+impl Repository {
+    pub fn new(
+        format: BackupFormat,
+        binary: Option<String>,
+        repository: String,
+        password_file: Option<String>,
+    ) -> Repository {
+        let binary = binary.unwrap_or(String::from(match format {
+            BackupFormat::Borg => "borg",
+            _ => "restic",
+        }));
+        Repository {
+            format,
+            binary,
+            repository,
+            password_file,
+        }
+    }
+
+    /// The program, pointed at the repository.
+    async fn command(&self) -> Result<Command, std::io::Error> {
+        let mut command = Command::new(&self.binary);
+        match self.format {
+            BackupFormat::Borg => {
+                command.env("BORG_REPO", &self.repository);
+                if let Some(f) = &self.password_file {
+                    let passphrase = tokio::fs::read_to_string(f).await?;
+                    command.env("BORG_PASSPHRASE", passphrase.trim());
+                }
+            }
+            _ => {
+                command.env("RESTIC_REPOSITORY", &self.repository);
+                if let Some(f) = &self.password_file {
+                    command.env("RESTIC_PASSWORD_FILE", f);
+                }
+            }
+        }
+        Ok(command)
+    }
+
+    /// Backs up `include` from the server directory and returns the
+    /// snapshot or archive made, with how much it added to the repository.
+    pub async fn create(
+        &self,
+        id: &str,
+        server_dir: &Path,
+        include: &[String],
+    ) -> Result<(String, u64), BackupError> {
+        // Both of them treat a missing path as a failure.
+        let mut paths = Vec::new();
+        for path in include {
+            if tokio::fs::try_exists(server_dir.join(path)).await? {
+                paths.push(path.as_str());
+            }
+        }
+        let mut command = self.command().await?;
+        command.current_dir(server_dir);
+        match self.format {
+            BackupFormat::Borg => command
+                .args(["create", "--json"])
+                .arg(format!("::{}", id))
+                .args(&paths),
+            _ => command.args(["backup", "--json", "--tag", id]).args(&paths),
+        };
+
+        let output = command.output().await?;
+        if !output.status.success() {
+            return Err(failed(&self.binary, &output.stderr));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        match self.format {
+            BackupFormat::Borg => match serde_json::from_str::<BorgCreate>(&stdout) {
+                Ok(c) => Ok((c.archive.name, c.archive.stats.deduplicated_size)),
+                Err(e) => Err(BackupError::Tool(format!(
+                    "could not read what borg made: {}",
+                    e
+                ))),
+            },
+            _ => {
+                // Progress comes first, a line of JSON at a time.
+                let summary = stdout
+                    .lines()
+                    .filter_map(|l| serde_json::from_str::<ResticSummary>(l).ok())
+                    .find(|s| s.message_type == "summary");
+                match summary {
+                    Some(ResticSummary {
+                        snapshot_id: Some(snapshot),
+                        data_added,
+                        ..
+                    }) => Ok((snapshot, data_added)),
+                    _ => Err(BackupError::Tool(String::from(
+                        "restic didn't say which snapshot it made",
+                    ))),
+                }
+            }
+        }
+    }
+
+    /// Restores a snapshot or archive into `staging`, laid out the way the
+    /// server directory was.
+    pub async fn extract(
+        &self,
+        name: &str,
+        server_dir: &Path,
+        staging: &Path,
+    ) -> Result<(), BackupError> {
+        let mut command = self.command().await?;
+        match self.format {
+            // borg keeps the relative paths it was given.
+            BackupFormat::Borg => command
+                .current_dir(staging)
+                .arg("extract")
+                .arg(format!("::{}", name)),
+            // restic makes them absolute, so only the server directory's
+            // contents are taken.
+            _ => {
+                let server_dir = tokio::fs::canonicalize(server_dir).await?;
+                command
+                    .arg("restore")
+                    .arg(format!("{}:{}", name, server_dir.display()))
+                    .arg("--target")
+                    .arg(staging)
+            }
+        };
+        let output = command.output().await?;
+        if !output.status.success() {
+            return Err(failed(&self.binary, &output.stderr));
+        }
+        Ok(())
+    }
+
+    /// Removes a snapshot or archive `create` made, along with whatever
+    /// data only it used.
+    pub async fn delete(&self, name: &str) -> Result<(), BackupError> {
+        let mut command = self.command().await?;
+        match self.format {
+            BackupFormat::Borg => command.arg("delete").arg(format!("::{}", name)),
+            _ => command.args(["forget", "--prune", name]),
+        };
+        let output = command.output().await?;
+        if !output.status.success() {
+            return Err(failed(&self.binary, &output.stderr));
+        }
+        Ok(())
+    }
+}
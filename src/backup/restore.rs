@@ -0,0 +1,43 @@
+use std::path::{Path, PathBuf};
+
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use tokio::fs;
+use tokio::io::{AsyncRead, BufReader};
+
+use super::BackupFormat;
+use crate::util::now;
+
+/// Unpacks one of the panel's own archives into `staging`. tokio-tar
+/// refuses entries that would land outside it.
+pub async fn extract_archive(
+    path: &Path,
+    format: BackupFormat,
+    staging: &Path,
+) -> Result<(), std::io::Error> {
+    let file = BufReader::new(fs::File::open(path).await?);
+    let decoded: Box<dyn AsyncRead + Unpin + Send> = match format {
+        BackupFormat::TarZst => Box::new(ZstdDecoder::new(file)),
+        _ => Box::new(GzipDecoder::new(file)),
+    };
+    tokio_tar::Archive::new(decoded).unpack(staging).await
+}
+
+/// Moves everything in `staging` into the server directory, renaming
+/// whatever is already there rather than deleting it. Returns where the
+/// old copies went.
+pub async fn swap_in(staging: &Path, server_dir: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let stamp = now();
+    let mut moved = Vec::new();
+    let mut entries = fs::read_dir(staging).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        let target = server_dir.join(&name);
+        if fs::try_exists(&target).await? {
+            let aside = server_dir.join(format!("{}.old-{}", name.to_string_lossy(), stamp));
+            fs::rename(&target, &aside).await?;
+            moved.push(aside);
+        }
+        fs::rename(entry.path(), &target).await?;
+    }
+    Ok(moved)
+}
@@ -0,0 +1,60 @@
+use std::collections::HashSet;
+
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use super::BackupRecord;
+
+/// Which backups to keep, the way restic and borg count them: the newest
+/// `keep_last`, then the newest of each of the last `keep_daily` days,
+/// `keep_weekly` weeks and `keep_monthly` months that have one. A backup
+/// kept by any rule stays and everything else is pruned.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Retention {
+    keep_last: Option<usize>,
+    keep_daily: Option<usize>,
+    keep_weekly: Option<usize>,
+    keep_monthly: Option<usize>,
+}
+
+impl Retention {
+    /// The ids of the backups the policy doesn't keep.
+    pub fn expired(&self, records: &[BackupRecord]) -> Vec<String> {
+        let mut newest: Vec<&BackupRecord> = records.iter().collect();
+        newest.sort_by(|a, b| b.created.cmp(&a.created));
+
+        let mut kept: HashSet<&str> = HashSet::new();
+        for r in newest.iter().take(self.keep_last.unwrap_or(0)) {
+            kept.insert(&r.id);
+        }
+        for (keep, period) in [
+            (self.keep_daily, "%Y-%m-%d"),
+            (self.keep_weekly, "%G-%V"),
+            (self.keep_monthly, "%Y-%m"),
+        ] {
+            let keep = match keep {
+                Some(k) => k,
+                None => continue,
+            };
+            let mut periods = HashSet::new();
+            for r in &newest {
+                if periods.len() == keep {
+                    break;
+                }
+                let created = match Local.timestamp_opt(r.created as i64, 0).single() {
+                    Some(c) => c,
+                    None => continue,
+                };
+                if periods.insert(created.format(period).to_string()) {
+                    kept.insert(&r.id);
+                }
+            }
+        }
+
+        newest
+            .iter()
+            .filter(|r| !kept.contains(r.id.as_str()))
+            .map(|r| r.id.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,378 @@
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::Utc;
+use data_encoding::HEXLOWER;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, StatusCode, Url};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+
+// S3 won't take parts smaller than this, other than the last, or more
+// than MAX_PARTS of them.
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+const MAX_PARTS: u64 = 10000;
+// Each request is tried this many times, waiting twice as long after each
+// failure, starting at a second.
+const ATTEMPTS: u32 = 3;
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct S3Config {
+    // e.g. "https://s3.eu-west-1.amazonaws.com", "http://minio:9000" or
+    // "https://<account>.r2.cloudflarestorage.com".
+    endpoint: String,
+    bucket: String,
+    // "us-east-1" if unset. R2 takes "auto".
+    region: Option<String>,
+    // Put in front of each archive's name, e.g. "survival/".
+    prefix: Option<String>,
+    // Puts the bucket in the host name instead of the path. Off by default,
+    // which is what MinIO and most other stores expect.
+    virtual_hosted: Option<bool>,
+    // Either given here, read from a file, or taken from AWS_ACCESS_KEY_ID
+    // and AWS_SECRET_ACCESS_KEY.
+    access_key_id: Option<String>,
+    access_key_id_file: Option<String>,
+    secret_access_key: Option<String>,
+    secret_access_key_file: Option<String>,
+    // Archives bigger than this are uploaded in parts of this size.
+    part_size_mb: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum S3Error {
+    Io(std::io::Error),
+    Http(reqwest::Error),
+    Status(StatusCode, String),
+}
+
+impl From<std::io::Error> for S3Error {
+    fn from(e: std::io::Error) -> Self {
+        S3Error::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for S3Error {
+    fn from(e: reqwest::Error) -> Self {
+        S3Error::Http(e)
+    }
+}
+
+impl std::fmt::Display for S3Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            S3Error::Io(e) => write!(f, "{}", e),
+            S3Error::Http(e) => write!(f, "{}", e),
+            S3Error::Status(status, body) => write!(f, "{}: {}", status, body),
+        }
+    }
+}
+
+/// A bucket backups are copied to. Requests are signed with AWS SigV4,
+/// which MinIO, B2 and R2 all take as well.
+#[derive(Clone)]
+pub struct S3Target {
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    prefix: String,
+    virtual_hosted: bool,
+    access_key_id: String,
+    secret_access_key: String,
+    part_size: u64,
+    http: reqwest::Client,
+}
+
+fn credential(value: Option<String>, file: Option<String>, env: &str) -> Result<String, String> {
+    if let Some(v) = value {
+        return Ok(v);
+    }
+    if let Some(path) = file {
+        return match std::fs::read_to_string(&path) {
+            Ok(v) => Ok(v.trim().to_owned()),
+            Err(e) => Err(format!("could not read {}: {}", path, e)),
+        };
+    }
+    match std::env::var(env) {
+        Ok(v) => Ok(v),
+        Err(_) => Err(format!(
+            "backup s3 credentials are missing, and {} isn't set",
+            env
+        )),
+    }
+}
+
+/// Percent-encodes everything but unreserved characters, and '/' too
+/// unless it's a path.
+fn uri_encode(value: &str, path: bool) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            b'/' if path => out.push('/'),
+            b => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// The text of the first `<tag>` in an XML reply.
+fn xml_value(body: &str, tag: &str) -> Option<String> {
+    let start = body.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = body[start..].find(&format!("</{}>", tag))? + start;
+    Some(body[start..end].to_owned())
+}
+
+impl S3Target {
+    pub fn new(config: S3Config) -> Result<S3Target, String> {
+        let endpoint = match Url::parse(&config.endpoint) {
+            Ok(u) => u,
+            Err(e) => {
+                return Err(format!(
+                    "backup s3 endpoint {:?} is not valid: {}",
+                    config.endpoint, e
+                ))
+            }
+        };
+        let part_size = config.part_size_mb.unwrap_or(64) * 1024 * 1024;
+        Ok(S3Target {
+            endpoint,
+            bucket: config.bucket,
+            region: config.region.unwrap_or(String::from("us-east-1")),
+            prefix: config.prefix.unwrap_or_default(),
+            virtual_hosted: config.virtual_hosted.unwrap_or(false),
+            access_key_id: credential(
+                config.access_key_id,
+                config.access_key_id_file,
+                "AWS_ACCESS_KEY_ID",
+            )?,
+            secret_access_key: credential(
+                config.secret_access_key,
+                config.secret_access_key_file,
+                "AWS_SECRET_ACCESS_KEY",
+            )?,
+            part_size: part_size.max(MIN_PART_SIZE),
+            http: reqwest::Client::new(),
+        })
+    }
+
+    fn url(&self, key: &str, query: &str) -> Url {
+        let mut url = self.endpoint.clone();
+        let base = url.path().trim_end_matches('/').to_owned();
+        if self.virtual_hosted {
+            let host = format!("{}.{}", self.bucket, url.host_str().unwrap_or_default());
+            // Only fails for URLs that can't have a host, which parse rejects.
+            let _ = url.set_host(Some(&host));
+            url.set_path(&format!("{}/{}", base, uri_encode(key, true)));
+        } else {
+            url.set_path(&format!(
+                "{}/{}/{}",
+                base,
+                uri_encode(&self.bucket, false),
+                uri_encode(key, true)
+            ));
+        }
+        url.set_query(if query.is_empty() { None } else { Some(query) });
+        url
+    }
+
+    /// Sends a signed request. `query` has to be in canonical form: sorted
+    /// by name with names and values encoded.
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &str,
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response, S3Error> {
+        let url = self.url(key, query);
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = HEXLOWER.encode(&Sha256::digest(&body));
+        let host = match url.port() {
+            Some(p) => format!("{}:{}", url.host_str().unwrap_or_default(), p),
+            None => url.host_str().unwrap_or_default().to_owned(),
+        };
+
+        let canonical = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method,
+            url.path(),
+            query,
+            host,
+            payload_hash,
+            amz_date,
+            SIGNED_HEADERS,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            HEXLOWER.encode(&Sha256::digest(canonical.as_bytes()))
+        );
+        let mut signing_key = hmac(
+            format!("AWS4{}", self.secret_access_key).as_bytes(),
+            date.as_bytes(),
+        );
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes());
+        }
+        let signature = HEXLOWER.encode(&hmac(&signing_key, to_sign.as_bytes()));
+
+        let response = self
+            .http
+            .request(method, url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", amz_date)
+            .header(
+                "Authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    self.access_key_id, scope, SIGNED_HEADERS, signature
+                ),
+            )
+            .body(body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(S3Error::Status(status, response.text().await?));
+        }
+        Ok(response)
+    }
+
+    /// `send`, tried again after server errors and failed connections.
+    async fn send_retrying(
+        &self,
+        method: Method,
+        key: &str,
+        query: &str,
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response, S3Error> {
+        let mut delay = Duration::from_secs(1);
+        let mut attempt = 1;
+        loop {
+            match self.send(method.clone(), key, query, body.clone()).await {
+                Err(S3Error::Status(s, _)) if s.is_server_error() && attempt < ATTEMPTS => {}
+                Err(S3Error::Http(_)) if attempt < ATTEMPTS => {}
+                result => return result,
+            }
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+
+    /// Copies an archive to the bucket and returns its key there. Anything
+    /// bigger than a part goes up as a multipart upload.
+    pub async fn upload(&self, path: &Path, name: &str) -> Result<String, S3Error> {
+        let key = format!("{}{}", self.prefix, name);
+        let size = fs::metadata(path).await?.len();
+        if size <= self.part_size {
+            let body = fs::read(path).await?;
+            self.send_retrying(Method::PUT, &key, "", body).await?;
+            return Ok(key);
+        }
+
+        let reply = self
+            .send_retrying(Method::POST, &key, "uploads=", Vec::new())
+            .await?
+            .text()
+            .await?;
+        let upload_id = match xml_value(&reply, "UploadId") {
+            Some(id) => id,
+            None => {
+                return Err(S3Error::Status(
+                    StatusCode::OK,
+                    String::from("no UploadId in the reply"),
+                ))
+            }
+        };
+        let result = self.upload_parts(path, &key, &upload_id, size).await;
+        if result.is_err() {
+            // Otherwise the parts already sent are kept, and paid for.
+            let query = format!("uploadId={}", uri_encode(&upload_id, false));
+            if let Err(e) = self.send(Method::DELETE, &key, &query, Vec::new()).await {
+                tracing::warn!(%key, error = %e, "could not abort the upload");
+            }
+        }
+        result.map(|()| key)
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<(), S3Error> {
+        self.send_retrying(Method::DELETE, key, "", Vec::new())
+            .await?;
+        Ok(())
+    }
+
+    async fn upload_parts(
+        &self,
+        path: &Path,
+        key: &str,
+        upload_id: &str,
+        size: u64,
+    ) -> Result<(), S3Error> {
+        // Parts grow for archives that would need more than S3 allows.
+        let part_size = self.part_size.max(size.div_ceil(MAX_PARTS));
+        let mut file = fs::File::open(path).await?;
+        let mut complete = String::from("<CompleteMultipartUpload>");
+        let mut number = 1;
+        loop {
+            let mut part = Vec::with_capacity(part_size as usize);
+            (&mut file).take(part_size).read_to_end(&mut part).await?;
+            if part.is_empty() {
+                break;
+            }
+            let query = format!(
+                "partNumber={}&uploadId={}",
+                number,
+                uri_encode(upload_id, false)
+            );
+            let response = self.send_retrying(Method::PUT, key, &query, part).await?;
+            let etag = match response.headers().get("ETag").and_then(|e| e.to_str().ok()) {
+                Some(e) => e.to_owned(),
+                None => {
+                    return Err(S3Error::Status(
+                        response.status(),
+                        format!("no ETag for part {}", number),
+                    ))
+                }
+            };
+            complete.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                number, etag
+            ));
+            number += 1;
+        }
+        complete.push_str("</CompleteMultipartUpload>");
+
+        let query = format!("uploadId={}", uri_encode(upload_id, false));
+        let response = self
+            .send_retrying(Method::POST, key, &query, complete.into_bytes())
+            .await?;
+        let status = response.status();
+        let reply = response.text().await?;
+        // Completing can fail after the 200 has been sent.
+        if reply.contains("<Error>") {
+            return Err(S3Error::Status(
+                status,
+                xml_value(&reply, "Message").unwrap_or(reply),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::commands::Command;
+use crate::minecraft::MinecraftControl;
+use crate::util::now;
+
+/// An entry in banned-players.json or banned-ips.json, as the server
+/// writes it.
+#[derive(Deserialize, Debug, Clone)]
+struct BanFile {
+    uuid: Option<String>,
+    name: Option<String>,
+    ip: Option<String>,
+    created: Option<String>,
+    source: Option<String>,
+    reason: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Ban {
+    // The player's name or the IP address.
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    pub reason: Option<String>,
+    pub created: Option<String>,
+    // Who banned them, as the server recorded it.
+    pub source: Option<String>,
+    // Seconds since the epoch when the panel will lift the ban.
+    pub expires: Option<u64>,
+    // The panel user who banned them, if it was done here.
+    pub banned_by: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BanList {
+    pub players: Vec<Ban>,
+    pub ips: Vec<Ban>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum BanKind {
+    Player,
+    Ip,
+}
+
+impl BanKind {
+    pub fn pardon(&self, target: &str) -> Command {
+        match self {
+            BanKind::Player => Command::Pardon {
+                player: target.to_owned(),
+            },
+            BanKind::Ip => Command::PardonIp {
+                ip: target.to_owned(),
+            },
+        }
+    }
+}
+
+/// What the panel knows about a ban that the server doesn't keep.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct BanMeta {
+    kind: BanKind,
+    target: String,
+    expires: Option<u64>,
+    banned_by: String,
+}
+
+/// Expiry times and who banned whom, persisted as JSON. The server has no
+/// temporary bans, so the panel pardons players itself once they're up.
+#[derive(Clone)]
+pub struct BanStore {
+    path: PathBuf,
+    bans: Arc<Mutex<HashMap<String, BanMeta>>>,
+}
+
+fn key(kind: BanKind, target: &str) -> String {
+    match kind {
+        BanKind::Player => format!("player:{}", target.to_lowercase()),
+        BanKind::Ip => format!("ip:{}", target),
+    }
+}
+
+impl BanStore {
+    pub fn load(path: PathBuf) -> Result<BanStore, String> {
+        let bans = match std::fs::read_to_string(&path) {
+            Ok(file) => match serde_json::from_str(&file) {
+                Ok(b) => b,
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            },
+            Err(_) => HashMap::new(),
+        };
+        Ok(BanStore {
+            path,
+            bans: Arc::new(Mutex::new(bans)),
+        })
+    }
+
+    async fn save(&self, bans: &HashMap<String, BanMeta>) -> Result<(), std::io::Error> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(bans)?).await?;
+        fs::rename(&tmp, &self.path).await
+    }
+
+    pub async fn banned(
+        &self,
+        kind: BanKind,
+        target: &str,
+        expires: Option<u64>,
+        banned_by: &str,
+    ) -> Result<(), std::io::Error> {
+        let mut bans = self.bans.lock().await;
+        bans.insert(
+            key(kind, target),
+            BanMeta {
+                kind,
+                target: target.to_owned(),
+                expires,
+                banned_by: banned_by.to_owned(),
+            },
+        );
+        self.save(&bans).await
+    }
+
+    pub async fn pardoned(&self, kind: BanKind, target: &str) -> Result<(), std::io::Error> {
+        let mut bans = self.bans.lock().await;
+        if bans.remove(&key(kind, target)).is_some() {
+            self.save(&bans).await?;
+        }
+        Ok(())
+    }
+
+    /// The server's ban lists with what the panel knows added in.
+    pub async fn list(&self, server_dir: &Path) -> Result<BanList, std::io::Error> {
+        let bans = self.bans.lock().await;
+        let merge = |kind: BanKind, entries: Vec<BanFile>| -> Vec<Ban> {
+            entries
+                .into_iter()
+                .filter_map(|e| {
+                    let target = match kind {
+                        BanKind::Player => e.name.clone()?,
+                        BanKind::Ip => e.ip.clone()?,
+                    };
+                    let meta = bans.get(&key(kind, &target));
+                    Some(Ban {
+                        uuid: e.uuid,
+                        reason: e.reason,
+                        created: e.created,
+                        source: e.source,
+                        expires: meta.and_then(|m| m.expires),
+                        banned_by: meta.map(|m| m.banned_by.clone()),
+                        target,
+                    })
+                })
+                .collect()
+        };
+        Ok(BanList {
+            players: merge(
+                BanKind::Player,
+                read_bans(&server_dir.join("banned-players.json")).await?,
+            ),
+            ips: merge(
+                BanKind::Ip,
+                read_bans(&server_dir.join("banned-ips.json")).await?,
+            ),
+        })
+    }
+
+    /// Lifts bans once they expire, until `shutdown` is cancelled.
+    pub fn expire(&self, control: MinecraftControl, shutdown: CancellationToken) {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {},
+                    _ = shutdown.cancelled() => return,
+                }
+                let now = now();
+                let expired: Vec<BanMeta> = store
+                    .bans
+                    .lock()
+                    .await
+                    .values()
+                    .filter(|b| b.expires.map_or(false, |e| e <= now))
+                    .cloned()
+                    .collect();
+                for ban in expired {
+                    let command = ban.kind.pardon(&ban.target).render();
+                    match control.command(command).await {
+                        Ok(_) => {
+                            tracing::info!(ban = %ban.target, "ban expired");
+                            if let Err(e) = store.pardoned(ban.kind, &ban.target).await {
+                                tracing::warn!(error = %e, "could not save bans");
+                            }
+                        }
+                        // Tried again next time round.
+                        Err(_) => {
+                            tracing::warn!(ban = %ban.target, "could not lift an expired ban")
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+async fn read_bans(path: &Path) -> Result<Vec<BanFile>, std::io::Error> {
+    match fs::read_to_string(path).await {
+        Ok(file) => Ok(serde_json::from_str(&file)?),
+        // The server only writes these once someone is banned.
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,198 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tokio::net::UdpSocket;
+use tokio::time::Instant;
+
+use crate::util::invalid;
+
+const UNCONNECTED_PING: u8 = 0x01;
+const UNCONNECTED_PONG: u8 = 0x1c;
+// Marks RakNet's offline messages.
+const MAGIC: [u8; 16] = [
+    0x00, 0xff, 0xff, 0x00, 0xfe, 0xfe, 0xfe, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78,
+];
+// Who the panel is to RakNet.
+const GUID: i64 = 0x6d63_6374_726c;
+
+/// What a Bedrock server, or Geyser, shows in the server list.
+#[derive(Serialize)]
+pub struct BedrockStatus {
+    // Both lines, without the `§` codes Geyser passes through.
+    pub motd: String,
+    pub sub_motd: String,
+    // e.g. "1.21.50".
+    pub version: String,
+    pub protocol: i32,
+    pub online: u32,
+    pub max: u32,
+    pub game_mode: Option<String>,
+    pub latency_ms: u64,
+}
+
+fn strip_codes(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            chars.next();
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// The pong's server id string, e.g.
+/// `MCPE;Geyser;712;1.21.50;3;100;<id>;Another line;Survival;1;19132;19133;`.
+fn parse(id: &str, latency: Duration) -> Option<BedrockStatus> {
+    let fields: Vec<&str> = id.split(';').collect();
+    if !matches!(fields.first(), Some(&"MCPE") | Some(&"MCEE")) || fields.len() < 6 {
+        return None;
+    }
+    Some(BedrockStatus {
+        motd: strip_codes(fields[1]),
+        sub_motd: strip_codes(fields.get(7).unwrap_or(&"")),
+        version: fields[3].to_owned(),
+        protocol: fields[2].parse().ok()?,
+        online: fields[4].parse().ok()?,
+        max: fields[5].parse().ok()?,
+        game_mode: fields
+            .get(8)
+            .filter(|m| !m.is_empty())
+            .map(|m| m.to_string()),
+        latency_ms: latency.as_millis() as u64,
+    })
+}
+
+async fn exchange(address: &str) -> Result<BedrockStatus, std::io::Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(address).await?;
+    let millis = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as i64,
+        Err(_) => 0,
+    };
+    let mut ping = vec![UNCONNECTED_PING];
+    ping.extend_from_slice(&millis.to_be_bytes());
+    ping.extend_from_slice(&MAGIC);
+    ping.extend_from_slice(&GUID.to_be_bytes());
+    let sent = Instant::now();
+    socket.send(&ping).await?;
+
+    let mut buf = vec![0; 2048];
+    let n = socket.recv(&mut buf).await?;
+    parse_pong(&buf[..n], sent.elapsed())
+}
+
+fn parse_pong(pong: &[u8], latency: Duration) -> Result<BedrockStatus, std::io::Error> {
+    // The id, time, server GUID and magic, then the length of the id string.
+    if pong.len() < 35 || pong[0] != UNCONNECTED_PONG || pong[17..33] != MAGIC {
+        return Err(invalid("expected an unconnected pong"));
+    }
+    let length = u16::from_be_bytes([pong[33], pong[34]]) as usize;
+    let id = match pong.get(35..35 + length) {
+        Some(id) => String::from_utf8_lossy(id),
+        None => return Err(invalid("the pong is cut short")),
+    };
+    match parse(&id, latency) {
+        Some(s) => Ok(s),
+        None => Err(invalid("the pong isn't from a Bedrock server")),
+    }
+}
+
+/// Does an unconnected ping the way Bedrock clients do for their server
+/// list.
+pub async fn ping(address: &str, timeout: Duration) -> Result<BedrockStatus, std::io::Error> {
+    match tokio::time::timeout(timeout, exchange(address)).await {
+        Ok(r) => r,
+        Err(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "the Bedrock port didn't answer in time",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pong(id: &str) -> Vec<u8> {
+        let mut pong = vec![UNCONNECTED_PONG];
+        pong.extend_from_slice(&0i64.to_be_bytes());
+        pong.extend_from_slice(&1i64.to_be_bytes());
+        pong.extend_from_slice(&MAGIC);
+        pong.extend_from_slice(&(id.len() as u16).to_be_bytes());
+        pong.extend_from_slice(id.as_bytes());
+        pong
+    }
+
+    const GEYSER: &str = "MCPE;Geyser;712;1.21.50;3;100;1234;Another line;Survival;1;19132;19133;";
+
+    #[test]
+    fn geyser() {
+        let status = parse_pong(&pong(GEYSER), Duration::from_millis(12)).unwrap();
+        assert_eq!(status.motd, "Geyser");
+        assert_eq!(status.sub_motd, "Another line");
+        assert_eq!(status.version, "1.21.50");
+        assert_eq!(status.protocol, 712);
+        assert_eq!((status.online, status.max), (3, 100));
+        assert_eq!(status.game_mode.as_deref(), Some("Survival"));
+        assert_eq!(status.latency_ms, 12);
+    }
+
+    #[test]
+    fn rejects_other_packets() {
+        let at = Duration::ZERO;
+        assert!(parse_pong(&pong(GEYSER)[..34], at).is_err());
+        assert!(parse_pong(&[], at).is_err());
+
+        let mut other = pong(GEYSER);
+        other[0] = UNCONNECTED_PING;
+        assert!(parse_pong(&other, at).is_err());
+
+        let mut magic = pong(GEYSER);
+        magic[20] ^= 0xff;
+        assert!(parse_pong(&magic, at).is_err());
+
+        // A length past the end of the packet.
+        let mut long = pong(GEYSER);
+        long[33..35].copy_from_slice(&u16::MAX.to_be_bytes());
+        assert!(parse_pong(&long, at).is_err());
+        let full = pong(GEYSER);
+        assert!(parse_pong(&full[..full.len() - 1], at).is_err());
+    }
+
+    #[test]
+    fn short_and_odd_fields() {
+        let at = Duration::ZERO;
+        assert!(parse_pong(&pong(""), at).is_err());
+        assert!(parse_pong(&pong("MCPE;Geyser;712;1.21.50;3"), at).is_err());
+        assert!(parse_pong(&pong("MCJE;Geyser;712;1.21.50;3;100"), at).is_err());
+        assert!(parse_pong(&pong("MCPE;Geyser;712;1.21.50;three;100"), at).is_err());
+        assert!(parse_pong(&pong("MCPE;Geyser;new;1.21.50;3;100"), at).is_err());
+        assert!(parse_pong(&pong("MCPE;Geyser;712;1.21.50;3;-1"), at).is_err());
+
+        // Only the fields every server sends.
+        let status = parse_pong(&pong("MCPE;Geyser;712;1.21.50;3;100"), at).unwrap();
+        assert_eq!(status.sub_motd, "");
+        assert_eq!(status.game_mode, None);
+
+        // An empty game mode is left out too.
+        let status = parse_pong(&pong("MCPE;Geyser;712;1.21.50;3;100;1;Line;"), at).unwrap();
+        assert_eq!(status.sub_motd, "Line");
+        assert_eq!(status.game_mode, None);
+
+        let status = parse_pong(&pong("MCEE;Classroom;712;1.21.50;0;30;1;x;Creative"), at);
+        assert_eq!(status.unwrap().game_mode.as_deref(), Some("Creative"));
+    }
+
+    #[test]
+    fn strips_codes() {
+        let id =
+            "MCPE;\u{a7}aGeyser \u{a7}lnow;712;1.21.50;3;100;1;\u{a7}7Another line\u{a7};Survival";
+        let status = parse_pong(&pong(id), Duration::ZERO).unwrap();
+        assert_eq!(status.motd, "Geyser now");
+        assert_eq!(status.sub_motd, "Another line");
+        assert_eq!(strip_codes("plain"), "plain");
+    }
+}
@@ -0,0 +1,136 @@
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+
+use crate::minecraft::MinecraftConfig;
+use crate::{
+    auth, cli, history, logging, modrinth, mojang, notifications, policy, scheduler, telemetry,
+};
+use crate::{AppConfig, WebserverConfig};
+
+/// Reads one section on its own, so a mistake in one doesn't hide those in
+/// the others.
+fn section<T: DeserializeOwned>(raw: &toml::Table, key: &str, problems: &mut Vec<String>) {
+    if let Some(v) = raw.get(key) {
+        if let Err(e) = cli::deserialize::<T>(v.clone()) {
+            problems.push(format!("{}: {}", key, e));
+        }
+    }
+}
+
+/// Server names end up in URLs, so they're kept to what's safe there.
+fn names(servers: &[MinecraftConfig], problems: &mut Vec<String>) {
+    for (i, server) in servers.iter().enumerate() {
+        let name = server.name();
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            problems.push(format!(
+                "server name {:?} may only contain letters, digits, - and _",
+                name
+            ));
+        }
+        if servers[..i].iter().any(|s| s.name() == name) {
+            problems.push(format!("server name {:?} is used more than once", name));
+        }
+    }
+}
+
+/// The config, or everything wrong with it rather than just the first
+/// mistake.
+pub fn parse(raw: &toml::Table) -> Result<AppConfig, Vec<String>> {
+    let mut problems = Vec::new();
+    match raw.get("minecraft") {
+        Some(toml::Value::Array(servers)) => {
+            for (i, server) in servers.iter().enumerate() {
+                let name = match server.get("name").and_then(|n| n.as_str()) {
+                    Some(n) => n.to_owned(),
+                    None => i.to_string(),
+                };
+                if let Err(e) = cli::deserialize::<MinecraftConfig>(server.clone()) {
+                    problems.push(format!("minecraft.{}: {}", name, e));
+                }
+            }
+        }
+        _ => section::<MinecraftConfig>(raw, "minecraft", &mut problems),
+    }
+    section::<WebserverConfig>(raw, "webserver", &mut problems);
+    section::<auth::AuthConfig>(raw, "auth", &mut problems);
+    section::<history::HistoryConfig>(raw, "history", &mut problems);
+    section::<notifications::NotificationsConfig>(raw, "notifications", &mut problems);
+    section::<mojang::MojangConfig>(raw, "mojang", &mut problems);
+    section::<modrinth::ModrinthConfig>(raw, "modrinth", &mut problems);
+    section::<telemetry::TelemetryConfig>(raw, "telemetry", &mut problems);
+    section::<logging::LoggingConfig>(raw, "logging", &mut problems);
+    section::<policy::CommandsConfig>(raw, "commands", &mut problems);
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+
+    let config: AppConfig = match cli::deserialize(toml::Value::Table(raw.clone())) {
+        Ok(c) => c,
+        Err(e) => return Err(vec![e.to_string()]),
+    };
+    names(&config.servers(), &mut problems);
+    for server in config.servers() {
+        if let Err(e) = scheduler::check_configured(server.schedules()) {
+            problems.push(format!("minecraft.{}.schedules: {}", server.name(), e));
+        }
+    }
+    if let Err(e) = policy::CommandRules::new(config.commands.as_ref()) {
+        problems.push(format!("commands: {}", e));
+    }
+    if let Err(e) = logging::check(config.logging.as_ref()) {
+        problems.push(format!("logging: {}", e));
+    }
+    if let Err(e) = telemetry::check(config.telemetry.as_ref()) {
+        problems.push(format!("telemetry: {}", e));
+    }
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    Ok(config)
+}
+
+fn directory(problems: &mut Vec<String>, key: &str, path: &Path) {
+    if !path.is_dir() {
+        problems.push(format!("{}: {} isn't a directory", key, path.display()));
+    }
+}
+
+/// What `--check` looks at once the config parses: the paths it names,
+/// every server's backend, the auth files and the certificate.
+pub async fn run(config: &AppConfig, no_tls: bool) -> Vec<String> {
+    let mut problems = Vec::new();
+    for server in config.servers() {
+        for p in server.check().await {
+            problems.push(format!("minecraft.{}: {}", server.name(), p));
+        }
+    }
+    if let Err(e) = auth::check(config.auth.clone()).await {
+        problems.push(format!(
+            "auth: could not read the tokens, users or two-factor enrollments: {}",
+            e
+        ));
+    }
+    if let Some(w) = &config.webserver {
+        match &w.cert_path {
+            Some(p) if !no_tls => {
+                if let Err(e) = crate::load_certificate(p).await {
+                    problems.push(format!("webserver.cert_path: {}", e));
+                }
+            }
+            _ => {}
+        }
+        if let Some(p) = &w.bluemaps_path {
+            directory(
+                &mut problems,
+                "webserver.bluemaps_path",
+                &Path::new("/").join(p),
+            );
+        }
+    }
+    directory(&mut problems, "assets", Path::new("assets"));
+    problems
+}
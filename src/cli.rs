@@ -0,0 +1,443 @@
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+
+// Taken by the flags below rather than by the config.
+const FLAG_VARS: &[&str] = &["MCCTL_CONFIG", "MCCTL_BIND", "MCCTL_PORT", "MCCTL_NO_TLS"];
+// The only config file that's allowed not to exist.
+const DEFAULT_CONFIG: &str = "config.toml";
+
+/// A web panel for running Minecraft servers.
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Cli {
+    /// The config file. It's fine for the default one not to exist when
+    /// everything is set with MCCTL_ variables instead.
+    #[arg(long, env = "MCCTL_CONFIG", default_value = DEFAULT_CONFIG)]
+    pub config: PathBuf,
+    /// The address to listen on.
+    #[arg(long, env = "MCCTL_BIND", default_value = "0.0.0.0")]
+    pub bind: IpAddr,
+    /// The port to listen on, 443 with TLS and 3000 without by default.
+    #[arg(long, env = "MCCTL_PORT")]
+    pub port: Option<u16>,
+    /// Serve plain HTTP even when the config has a cert_path, for running
+    /// behind a proxy that handles TLS.
+    #[arg(long, env = "MCCTL_NO_TLS")]
+    pub no_tls: bool,
+    /// Check the config and what it points at, print every problem found
+    /// and exit.
+    #[arg(long)]
+    pub check: bool,
+}
+
+/// A variable's value as a string, or as TOML when it's a list, a table or
+/// a quoted string. Numbers and booleans are left as strings for
+/// `deserialize` to read, since only the field knows whether `123456` is a
+/// port or a token.
+fn env_value(raw: &str) -> toml::Value {
+    let string = toml::Value::String(raw.to_owned());
+    match toml::from_str::<toml::Table>(&format!("value = {}", raw)) {
+        Ok(mut t) => match t.remove("value") {
+            Some(v @ (toml::Value::Array(_) | toml::Value::Table(_) | toml::Value::String(_))) => v,
+            _ => string,
+        },
+        Err(_) => string,
+    }
+}
+
+/// Sets `value` at `path` in `table`, making tables on the way. Numbers
+/// pick out an entry in a list, like a server in `[[minecraft]]`.
+fn set(table: &mut toml::Table, path: &[String], value: toml::Value) -> Result<(), String> {
+    let (key, rest) = match path.split_first() {
+        Some(p) => p,
+        None => return Err(String::from("the name is empty")),
+    };
+    if rest.is_empty() {
+        table.insert(key.clone(), value);
+        return Ok(());
+    }
+    let next = table
+        .entry(key.clone())
+        .or_insert_with(|| match rest[0].parse::<usize>() {
+            Ok(_) => toml::Value::Array(Vec::new()),
+            Err(_) => toml::Value::Table(toml::Table::new()),
+        });
+    set_in(next, rest, value)
+}
+
+fn set_in(target: &mut toml::Value, path: &[String], value: toml::Value) -> Result<(), String> {
+    match target {
+        toml::Value::Table(t) => set(t, path, value),
+        toml::Value::Array(a) => {
+            let index: usize = match path[0].parse() {
+                Ok(i) => i,
+                Err(_) => return Err(format!("{} isn't a position in a list", path[0])),
+            };
+            // Lists grow by one entry at a time, so a typo can't make a
+            // huge one.
+            if index > a.len() {
+                return Err(format!(
+                    "{} is past the end of a list of {}",
+                    index,
+                    a.len()
+                ));
+            }
+            if index == a.len() {
+                a.push(toml::Value::Table(toml::Table::new()));
+            }
+            if path.len() == 1 {
+                a[index] = value;
+                return Ok(());
+            }
+            set_in(&mut a[index], &path[1..], value)
+        }
+        _ => Err(format!("{} isn't a table", path[0])),
+    }
+}
+
+/// Reads the config file, if there is one, and puts MCCTL_ variables over
+/// it. `MCCTL_AUTH__SESSION_SECRET` sets `session_secret` in `[auth]`, and
+/// `MCCTL_MINECRAFT__0__RCON_PASSWORD` that of the first `[[minecraft]]`.
+pub async fn read_config(path: &Path) -> Result<toml::Table, String> {
+    let file = match tokio::fs::read_to_string(path).await {
+        Ok(file) => file,
+        // A --config that isn't there is more likely a typo than a choice
+        // to start without auth.
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && path == Path::new(DEFAULT_CONFIG) => {
+            String::new()
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut config: toml::Table = match toml::from_str(&file) {
+        Ok(c) => c,
+        Err(e) => return Err(e.to_string()),
+    };
+    overlay(&mut config, std::env::vars())?;
+    Ok(config)
+}
+
+/// Puts the MCCTL_ variables among `vars` over `config`, in order, so
+/// `__1__` comes after `__0__` and before `__10__`.
+fn overlay(
+    config: &mut toml::Table,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<(), String> {
+    let mut vars: Vec<(Vec<String>, String, String)> = vars
+        .filter(|(k, _)| k.starts_with("MCCTL_") && !FLAG_VARS.contains(&k.as_str()))
+        .map(|(name, raw)| {
+            let path = name["MCCTL_".len()..]
+                .split("__")
+                .map(|k| k.to_lowercase())
+                .collect();
+            (path, name, raw)
+        })
+        .collect();
+    vars.sort_by_cached_key(|(path, _, _)| {
+        path.iter()
+            .map(|k| match k.parse::<usize>() {
+                Ok(i) => (i, String::new()),
+                Err(_) => (0, k.clone()),
+            })
+            .collect::<Vec<_>>()
+    });
+    for (path, name, raw) in vars {
+        if let Err(e) = set(config, &path, env_value(&raw)) {
+            return Err(format!("{}: {}", name, e));
+        }
+    }
+    Ok(())
+}
+
+/// Reads part of the config the way TOML would, except that a string is
+/// also taken where a number or a boolean is wanted, which is how
+/// MCCTL_ variables are set.
+pub fn deserialize<T: DeserializeOwned>(value: toml::Value) -> Result<T, toml::de::Error> {
+    T::deserialize(Lenient(value))
+}
+
+struct Lenient(toml::Value);
+
+// Parses a string for a field of type `$ty`, and leaves anything else to
+// TOML.
+macro_rules! parsed {
+    ($($method:ident: $ty:ty => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self.0 {
+                    toml::Value::String(s) => match s.parse::<$ty>() {
+                        Ok(v) => visitor.$visit(v),
+                        Err(_) => visitor.visit_string(s),
+                    },
+                    v => v.$method(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Lenient {
+    type Error = toml::de::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            toml::Value::Table(t) => visitor.visit_map(Entries {
+                entries: t.into_iter(),
+                value: None,
+            }),
+            toml::Value::Array(a) => visitor.visit_seq(Items(a.into_iter())),
+            v => v.deserialize_any(visitor),
+        }
+    }
+
+    parsed! {
+        deserialize_bool: bool => visit_bool,
+        deserialize_i8: i64 => visit_i64,
+        deserialize_i16: i64 => visit_i64,
+        deserialize_i32: i64 => visit_i64,
+        deserialize_i64: i64 => visit_i64,
+        deserialize_u8: u64 => visit_u64,
+        deserialize_u16: u64 => visit_u64,
+        deserialize_u32: u64 => visit_u64,
+        deserialize_u64: u64 => visit_u64,
+        deserialize_f32: f64 => visit_f64,
+        deserialize_f64: f64 => visit_f64,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0.deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct seq tuple tuple_struct
+        map struct identifier ignored_any
+    }
+}
+
+struct Entries {
+    entries: toml::map::IntoIter,
+    value: Option<toml::Value>,
+}
+
+impl<'de> MapAccess<'de> for Entries {
+    type Error = toml::de::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(toml::Value::String(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.value.take() {
+            Some(v) => seed.deserialize(Lenient(v)),
+            None => Err(de::Error::custom("a value was asked for before its key")),
+        }
+    }
+}
+
+struct Items(std::vec::IntoIter<toml::Value>);
+
+impl<'de> SeqAccess<'de> for Items {
+    type Error = toml::de::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        match self.0.next() {
+            Some(v) => seed.deserialize(Lenient(v)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn values_stay_strings() {
+        let string = |s: &str| toml::Value::String(s.to_owned());
+        assert_eq!(env_value("123456"), string("123456"));
+        assert_eq!(env_value("true"), string("true"));
+        assert_eq!(env_value("1.5"), string("1.5"));
+        assert_eq!(env_value("2024-01-01"), string("2024-01-01"));
+        assert_eq!(env_value("not toml ["), string("not toml ["));
+        assert_eq!(env_value(r#""quoted""#), string("quoted"));
+        assert_eq!(env_value("'123456'"), string("123456"));
+        assert_eq!(
+            env_value(r#"["a", "b"]"#),
+            toml::Value::Array(vec![string("a"), string("b")])
+        );
+        assert!(env_value("{ per_minute = 30 }").is_table());
+    }
+
+    #[test]
+    fn sets_nested_keys() {
+        let mut config: toml::Table = toml::from_str("[auth]\nsession_ttl_hours = 24").unwrap();
+        overlay(
+            &mut config,
+            vars(&[
+                ("MCCTL_AUTH__SESSION_SECRET", "secret"),
+                ("MCCTL_MINECRAFT__0__NAME", "survival"),
+                ("MCCTL_MINECRAFT__1__NAME", "creative"),
+                ("MCCTL_CONFIG", "ignored.toml"),
+                ("PATH", "/usr/bin"),
+            ]),
+        )
+        .unwrap();
+        let expected: toml::Table = toml::from_str(
+            r#"
+            [auth]
+            session_ttl_hours = 24
+            session_secret = "secret"
+            [[minecraft]]
+            name = "survival"
+            [[minecraft]]
+            name = "creative"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn list_positions() {
+        // Sorted by number, so __10__ comes after __2__.
+        let mut servers: Vec<(String, String)> = (0..11)
+            .map(|i| (format!("MCCTL_MINECRAFT__{}__NAME", i), i.to_string()))
+            .collect();
+        servers.reverse();
+        let mut config = toml::Table::new();
+        overlay(&mut config, servers.into_iter()).unwrap();
+        assert_eq!(config["minecraft"].as_array().unwrap().len(), 11);
+        assert_eq!(config["minecraft"][10]["name"].as_str(), Some("10"));
+
+        let mut config = toml::Table::new();
+        let e = overlay(
+            &mut config,
+            vars(&[("MCCTL_MINECRAFT__4000000000__NAME", "x")]),
+        );
+        assert_eq!(
+            e.unwrap_err(),
+            "MCCTL_MINECRAFT__4000000000__NAME: 4000000000 is past the end of a list of 0"
+        );
+        let mut config = toml::Table::new();
+        overlay(&mut config, vars(&[("MCCTL_MINECRAFT__0__NAME", "x")])).unwrap();
+        let e = overlay(&mut config, vars(&[("MCCTL_MINECRAFT__NAME", "x")]));
+        assert_eq!(
+            e.unwrap_err(),
+            "MCCTL_MINECRAFT__NAME: name isn't a position in a list"
+        );
+        let e = overlay(
+            &mut config,
+            vars(&[("MCCTL_MINECRAFT__0__NAME__FIRST", "x")]),
+        );
+        assert_eq!(
+            e.unwrap_err(),
+            "MCCTL_MINECRAFT__0__NAME__FIRST: first isn't a table"
+        );
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Section {
+        token: String,
+        port: u16,
+        enabled: bool,
+        ratio: Option<f64>,
+        ports: Vec<u16>,
+    }
+
+    #[test]
+    fn strings_where_numbers_are_wanted() {
+        let mut config = toml::Table::new();
+        overlay(
+            &mut config,
+            vars(&[
+                ("MCCTL_SECTION__TOKEN", "123456"),
+                ("MCCTL_SECTION__PORT", "25575"),
+                ("MCCTL_SECTION__ENABLED", "true"),
+                ("MCCTL_SECTION__RATIO", "0.5"),
+                ("MCCTL_SECTION__PORTS", r#"["25565", 25566]"#),
+            ]),
+        )
+        .unwrap();
+        let section: Section = deserialize(config["section"].clone()).unwrap();
+        assert_eq!(
+            section,
+            Section {
+                token: String::from("123456"),
+                port: 25575,
+                enabled: true,
+                ratio: Some(0.5),
+                ports: vec![25565, 25566],
+            }
+        );
+
+        // What's in the file is read as it always was.
+        let file: toml::Value =
+            toml::from_str("token = \"abc\"\nport = 25575\nenabled = false\nports = []").unwrap();
+        let section: Section = deserialize(file).unwrap();
+        assert_eq!(
+            (section.port, section.enabled, section.ratio),
+            (25575, false, None)
+        );
+
+        let bad: toml::Value =
+            toml::from_str("token = \"abc\"\nport = \"many\"\nenabled = true\nports = []").unwrap();
+        assert!(deserialize::<Section>(bad).is_err());
+        let bad: toml::Value =
+            toml::from_str("token = \"abc\"\nport = \"70000\"\nenabled = true\nports = []")
+                .unwrap();
+        assert!(deserialize::<Section>(bad).is_err());
+    }
+
+    #[tokio::test]
+    async fn only_the_default_file_may_be_missing() {
+        let missing = std::env::temp_dir()
+            .join("mcctl-missing")
+            .join("config.toml");
+        assert!(read_config(&missing).await.is_err());
+    }
+}
@@ -0,0 +1,383 @@
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::auth::Role;
+use crate::gamerules;
+use crate::motd;
+use crate::parser::is_player_name;
+
+// The most `give` hands out at once, 100 stacks of 64.
+const MAX_GIVE: u32 = 6400;
+
+/// A command the panel builds itself, so its arguments are checked and
+/// escaped rather than pasted into a string. As JSON it's tagged with its
+/// `kind`, e.g. `{"kind":"ban_ip","target":"10.0.0.1"}`.
+#[derive(Deserialize, ToSchema, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Command {
+    Kick {
+        player: String,
+        reason: Option<String>,
+    },
+    Ban {
+        player: String,
+        reason: Option<String>,
+    },
+    // A player name here bans whatever address they're connected from.
+    BanIp {
+        target: String,
+        reason: Option<String>,
+    },
+    Pardon {
+        player: String,
+    },
+    PardonIp {
+        ip: String,
+    },
+    Op {
+        player: String,
+    },
+    Deop {
+        player: String,
+    },
+    Gamerule {
+        rule: String,
+        #[schema(value_type = Object)]
+        value: serde_json::Value,
+    },
+    Give {
+        player: String,
+        // e.g. "minecraft:diamond". Components and NBT aren't taken.
+        item: String,
+        // 1 if unset.
+        count: Option<u32>,
+    },
+    Gamemode {
+        player: String,
+        mode: GameMode,
+    },
+    // A message to everyone in game.
+    Say {
+        message: String,
+    },
+    // Shown in the middle of everyone's screen.
+    Title {
+        message: String,
+    },
+    // A message to everyone with `&` codes and links, see motd::to_json.
+    Tellraw {
+        message: String,
+    },
+}
+
+#[derive(Deserialize, ToSchema, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum GameMode {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
+
+impl GameMode {
+    fn name(self) -> &'static str {
+        match self {
+            GameMode::Survival => "survival",
+            GameMode::Creative => "creative",
+            GameMode::Adventure => "adventure",
+            GameMode::Spectator => "spectator",
+        }
+    }
+}
+
+/// Why the server turned a command down, going by its reply.
+#[derive(Debug)]
+pub enum Refusal {
+    NoPlayer(String),
+    NothingChanged(String),
+    Invalid(String),
+}
+
+// The longest reason kept. The vanilla client cuts off long kick screens
+// anyway.
+const MAX_REASON: usize = 256;
+
+/// Makes free text safe to put at the end of a command: a line break
+/// would start another command on the console, so control characters
+/// become spaces.
+pub fn escape_text(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(MAX_REASON)
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
+
+fn with_reason(command: String, reason: &Option<String>) -> String {
+    match reason.as_deref().map(escape_text) {
+        Some(r) if !r.is_empty() => format!("{} {}", command, r),
+        _ => command,
+    }
+}
+
+impl Command {
+    /// Checks the arguments that can't be escaped.
+    pub fn validate(&self) -> Result<(), String> {
+        let (name, valid) = match self {
+            Command::Kick { player, .. }
+            | Command::Ban { player, .. }
+            | Command::Pardon { player }
+            | Command::Op { player }
+            | Command::Deop { player }
+            | Command::Gamemode { player, .. } => (player, is_player_name(player)),
+            Command::BanIp { target, .. } => (target, valid_ip(target) || is_player_name(target)),
+            Command::PardonIp { ip } => (ip, valid_ip(ip)),
+            Command::Gamerule { rule, value } => return gamerules::check(rule, value).map(|_| ()),
+            Command::Give {
+                player,
+                item,
+                count,
+            } => {
+                if !is_player_name(player) {
+                    return Err(format!("{:?} is not a player name", player));
+                }
+                if !valid_item(item) {
+                    return Err(format!("{:?} is not an item ID", item));
+                }
+                return match count {
+                    Some(c) if *c == 0 || *c > MAX_GIVE => {
+                        Err(format!("count has to be from 1 to {}", MAX_GIVE))
+                    }
+                    _ => Ok(()),
+                };
+            }
+            Command::Say { .. } | Command::Title { .. } | Command::Tellraw { .. } => return Ok(()),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("{:?} is not a player name or address", name))
+        }
+    }
+
+    /// The command as it's typed on the console.
+    pub fn render(&self) -> String {
+        match self {
+            Command::Kick { player, reason } => with_reason(format!("kick {}", player), reason),
+            Command::Ban { player, reason } => with_reason(format!("ban {}", player), reason),
+            Command::BanIp { target, reason } => with_reason(format!("ban-ip {}", target), reason),
+            Command::Pardon { player } => format!("pardon {}", player),
+            Command::PardonIp { ip } => format!("pardon-ip {}", ip),
+            Command::Op { player } => format!("op {}", player),
+            Command::Deop { player } => format!("deop {}", player),
+            Command::Gamerule { rule, value } => match gamerules::check(rule, value) {
+                Ok(v) => format!("gamerule {} {}", rule, v),
+                // Caught by validate.
+                Err(_) => format!("gamerule {}", rule),
+            },
+            Command::Give {
+                player,
+                item,
+                count,
+            } => format!("give {} {} {}", player, item, count.unwrap_or(1)),
+            Command::Gamemode { player, mode } => format!("gamemode {} {}", mode.name(), player),
+            Command::Say { message } => format!("say {}", escape_text(message)),
+            Command::Title { message } => format!(
+                "title @a title {}",
+                serde_json::json!({ "text": escape_text(message) })
+            ),
+            Command::Tellraw { message } => format!("tellraw @a {}", motd::to_json(message)),
+        }
+    }
+
+    /// Who can run it from `POST /api/commands`. Making someone an op is
+    /// kept to admins, as it is through `/api/ops`.
+    pub fn role(&self) -> Role {
+        match self {
+            Command::Op { .. } | Command::Deop { .. } => Role::Admin,
+            _ => Role::Operator,
+        }
+    }
+
+    /// Reads the server's reply for the ways vanilla says a command didn't
+    /// work. Anything else, including no reply at all, is taken as done.
+    pub fn check_reply(&self, reply: &str) -> Result<(), Refusal> {
+        for line in reply.lines() {
+            let line = crate::parser::strip_log_prefix(line).trim();
+            if line.starts_with("No player was found")
+                || line.starts_with("That player does not exist")
+            {
+                return Err(Refusal::NoPlayer(line.to_owned()));
+            }
+            if line.starts_with("Nothing changed") {
+                return Err(Refusal::NothingChanged(line.to_owned()));
+            }
+            if line.starts_with("Invalid IP address")
+                || line.starts_with("Unknown item")
+                || line.starts_with("Unknown or incomplete command")
+                || line.starts_with("Incorrect argument for command")
+            {
+                return Err(Refusal::Invalid(line.to_owned()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that an address is an address, so it can't carry anything else
+/// into the command.
+pub fn valid_ip(ip: &str) -> bool {
+    ip.parse::<std::net::IpAddr>().is_ok()
+}
+
+/// An item ID like `diamond` or `minecraft:diamond`, with nothing after it
+/// that could change what the command does.
+fn valid_item(item: &str) -> bool {
+    let (namespace, path) = match item.split_once(':') {
+        Some(p) => p,
+        None => ("minecraft", item),
+    };
+    let allowed = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "_-.".contains(c);
+    !namespace.is_empty()
+        && !path.is_empty()
+        && namespace.chars().all(allowed)
+        && path.chars().all(|c| allowed(c) || c == '/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kick(player: &str, reason: &str) -> Command {
+        Command::Kick {
+            player: player.to_owned(),
+            reason: Some(reason.to_owned()),
+        }
+    }
+
+    #[test]
+    fn line_breaks_stay_on_one_line() {
+        assert_eq!(escape_text("bye\nop Steve"), "bye op Steve");
+        assert_eq!(escape_text("bye\r\nop Steve\t"), "bye  op Steve");
+        assert_eq!(
+            kick("Steve", "griefing\nop Alex").render(),
+            "kick Steve griefing op Alex"
+        );
+        let say = Command::Say {
+            message: String::from("hi\nstop"),
+        };
+        assert_eq!(say.render(), "say hi stop");
+        let tellraw = Command::Tellraw {
+            message: String::from("hi\nstop"),
+        };
+        assert!(!tellraw.render().contains('\n'));
+    }
+
+    #[test]
+    fn reasons_are_cut_and_dropped_when_empty() {
+        assert_eq!(escape_text(&"a".repeat(1000)).len(), MAX_REASON);
+        assert_eq!(kick("Steve", " \n ").render(), "kick Steve");
+        let ban = Command::Ban {
+            player: String::from("Steve"),
+            reason: None,
+        };
+        assert_eq!(ban.render(), "ban Steve");
+    }
+
+    #[test]
+    fn quotes_stay_inside_the_json() {
+        let title = Command::Title {
+            message: String::from("say \"hi\"} {"),
+        };
+        let rendered = title.render();
+        let json = rendered.strip_prefix("title @a title ").unwrap();
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(value["text"], "say \"hi\"} {");
+
+        let tellraw = Command::Tellraw {
+            message: String::from("\"}]"),
+        };
+        let rendered = tellraw.render();
+        let json = rendered.strip_prefix("tellraw @a ").unwrap();
+        assert!(serde_json::from_str::<serde_json::Value>(json).is_ok());
+    }
+
+    #[test]
+    fn selectors_are_not_names() {
+        for player in ["@a", "@p", "@e[type=player]", "Steve @a", "Steve\nop Alex"] {
+            assert!(kick(player, "").validate().is_err(), "{}", player);
+            let op = Command::Op {
+                player: player.to_owned(),
+            };
+            assert!(op.validate().is_err(), "{}", player);
+        }
+        assert!(kick("Steve", "@a").validate().is_ok());
+        assert!(kick(".BedrockSteve", "").validate().is_ok());
+    }
+
+    #[test]
+    fn addresses() {
+        let ban = |target: &str| Command::BanIp {
+            target: target.to_owned(),
+            reason: None,
+        };
+        assert!(ban("10.0.0.1").validate().is_ok());
+        assert!(ban("::1").validate().is_ok());
+        assert!(ban("Steve").validate().is_ok());
+        assert!(ban("@a").validate().is_err());
+        assert!(ban("10.0.0.1 op").validate().is_err());
+        let pardon = Command::PardonIp {
+            ip: String::from("Steve"),
+        };
+        assert!(pardon.validate().is_err());
+    }
+
+    #[test]
+    fn give() {
+        let give = |item: &str, count: Option<u32>| Command::Give {
+            player: String::from("Steve"),
+            item: item.to_owned(),
+            count,
+        };
+        assert!(give("diamond", None).validate().is_ok());
+        assert!(give("minecraft:diamond", Some(MAX_GIVE)).validate().is_ok());
+        assert!(give("mod:tools/pick", Some(1)).validate().is_ok());
+        assert!(give("diamond{x:1}", None).validate().is_err());
+        assert!(give("diamond[damage=1]", None).validate().is_err());
+        assert!(give("diamond 64", None).validate().is_err());
+        assert!(give(":diamond", None).validate().is_err());
+        assert!(give("diamond", Some(0)).validate().is_err());
+        assert!(give("diamond", Some(MAX_GIVE + 1)).validate().is_err());
+        assert_eq!(give("diamond", None).render(), "give Steve diamond 1");
+    }
+
+    #[test]
+    fn gamemode() {
+        let gamemode = |player: &str| Command::Gamemode {
+            player: player.to_owned(),
+            mode: GameMode::Creative,
+        };
+        assert_eq!(gamemode("Steve").render(), "gamemode creative Steve");
+        assert!(gamemode("@a").validate().is_err());
+    }
+
+    #[test]
+    fn replies() {
+        let c = kick("Steve", "");
+        assert!(c.check_reply("Kicked Steve: Kicked by an operator").is_ok());
+        assert!(c.check_reply("").is_ok());
+        assert!(matches!(
+            c.check_reply("No player was found"),
+            Err(Refusal::NoPlayer(_))
+        ));
+        assert!(matches!(
+            c.check_reply("Nothing changed. The player is already banned"),
+            Err(Refusal::NothingChanged(_))
+        ));
+        assert!(matches!(
+            c.check_reply("Unknown or incomplete command, see below for error"),
+            Err(Refusal::Invalid(_))
+        ));
+    }
+}
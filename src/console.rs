@@ -0,0 +1,325 @@
+use std::convert::Infallible;
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket},
+        Query, State, WebSocketUpgrade,
+    },
+    http::{StatusCode, Version},
+    response::{
+        sse::{self, Event, Sse},
+        IntoResponse,
+    },
+    Extension,
+};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{broadcast, broadcast::Receiver, mpsc};
+
+use crate::auth::{Principal, Role};
+use crate::error::ApiError;
+use crate::logsource::{LogEntry, LogFilter};
+use crate::minecraft::MinecraftControl;
+use crate::server;
+
+/// Clients that ask for this subprotocol get JSON frames and can send
+/// commands. Everyone else just gets each log message as plain text.
+const PROTOCOL: &str = "mcctl.v1";
+
+/// Messages a console client can send.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientMessage {
+    // `id` is echoed back in the ack so replies can be matched up.
+    Command {
+        id: Option<Value>,
+        body: String,
+    },
+    // Replaces the connection's filter; leaving both out clears it.
+    // `level` is the least severe level to send, e.g. "warning".
+    Filter {
+        id: Option<Value>,
+        pattern: Option<String>,
+        level: Option<String>,
+    },
+}
+
+/// Everything sent to a client that speaks PROTOCOL is one of these, as
+/// JSON.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ServerMessage {
+    Log(LogEntry),
+    Ack {
+        id: Option<Value>,
+        ok: bool,
+        reply: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// How often clients are pinged, and how long they can stay silent (pongs
+/// included) before they're dropped.
+#[derive(Clone, Copy)]
+pub struct KeepAlive {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+/// Options shared by the WebSocket and the event stream.
+#[derive(Deserialize)]
+pub struct ConsoleQuery {
+    // Set to false by clients that have loaded the log some other way.
+    backlog: Option<bool>,
+    // The initial filter, for clients that can't send one.
+    pattern: Option<String>,
+    level: Option<String>,
+}
+
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    version: Version,
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Extension(keepalive): Extension<KeepAlive>,
+    Query(query): Query<ConsoleQuery>,
+) -> impl IntoResponse {
+    let filter = match LogFilter::new(query.pattern.as_deref(), query.level.as_deref()) {
+        Ok(f) => f,
+        Err(e) => return Err(ApiError::new(StatusCode::BAD_REQUEST, e)),
+    };
+    tracing::info!(?version, "accepted a console WebSocket");
+    let (mut backlog, rx) = control.subscribe();
+    if !query.backlog.unwrap_or(true) {
+        backlog.clear();
+    }
+    Ok(ws.protocols([PROTOCOL]).on_upgrade(move |socket| {
+        handle_socket(socket, control, principal, keepalive, filter, backlog, rx)
+    }))
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    control: MinecraftControl,
+    principal: Principal,
+    keepalive: KeepAlive,
+    mut filter: LogFilter,
+    backlog: Vec<LogEntry>,
+    mut rx: Receiver<LogEntry>,
+) {
+    let json = socket.protocol().is_some();
+    let (mut sender, mut receiver) = socket.split();
+    // Commands run on their own tasks so the log keeps flowing while they
+    // wait for a reply.
+    let (replies_tx, mut replies) = mpsc::channel::<ServerMessage>(16);
+
+    for entry in backlog.into_iter().filter(|e| filter.matches(e)) {
+        if send(&mut sender, json, ServerMessage::Log(entry))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+
+    let mut ping = tokio::time::interval(keepalive.interval);
+    ping.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut last_seen = Instant::now();
+    let shutdown = control.shutdown();
+
+    loop {
+        let message = tokio::select! {
+            _ = shutdown.cancelled() => {
+                let _ = sender.send(Message::Close(Some(going_away()))).await;
+                break;
+            },
+
+            _ = ping.tick() => {
+                if last_seen.elapsed() >= keepalive.timeout {
+                    tracing::info!("console WebSocket idle for too long, closing it");
+                    let close = CloseFrame {
+                        code: close_code::NORMAL,
+                        reason: "idle timeout".into(),
+                    };
+                    let _ = sender.send(Message::Close(Some(close))).await;
+                    break;
+                }
+                if let Err(e) = sender.send(Message::Ping(Vec::new())).await {
+                    tracing::info!(error = %e, "could not ping the console WebSocket, closing it");
+                    break;
+                }
+                continue;
+            },
+
+            // Wait for the next message from the broadcast channel
+            msg = rx.recv() => match msg {
+                Ok(entry) if filter.matches(&entry) => ServerMessage::Log(entry),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+
+            Some(reply) = replies.recv() => reply,
+
+            // Handle client messages and WebSocket close
+            result = receiver.next() => {
+                last_seen = Instant::now();
+                match result {
+                Some(Ok(Message::Text(text))) if json => {
+                    match handle_message(&control, &principal, &mut filter, &text, &replies_tx) {
+                        Some(m) => m,
+                        None => continue,
+                    }
+                }
+                Some(Ok(Message::Close(_))) | None => {
+                    tracing::info!("console WebSocket closed by the client");
+                    break;
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => {
+                    tracing::info!(error = %e, "console WebSocket failed, closing it");
+                    break;
+                }
+                }
+            }
+        };
+
+        if let Err(e) = send(&mut sender, json, message).await {
+            tracing::info!(error = %e, "could not send to the console WebSocket, closing it");
+            break;
+        }
+    }
+}
+
+/// Tells a client the panel is shutting down, so it knows to reconnect
+/// rather than treating it as an error.
+pub fn going_away() -> CloseFrame<'static> {
+    CloseFrame {
+        code: close_code::AWAY,
+        reason: "the panel is shutting down".into(),
+    }
+}
+
+/// Starts whatever a client message asks for, returning a message to send
+/// straight away if there is one.
+fn handle_message(
+    control: &MinecraftControl,
+    principal: &Principal,
+    filter: &mut LogFilter,
+    text: &str,
+    replies: &mpsc::Sender<ServerMessage>,
+) -> Option<ServerMessage> {
+    let message: ClientMessage = match serde_json::from_str(text) {
+        Ok(m) => m,
+        Err(e) => {
+            return Some(ServerMessage::Error {
+                message: format!("invalid message: {}", e),
+            })
+        }
+    };
+
+    match message {
+        ClientMessage::Command { id, body } => {
+            if principal.role < Role::Operator {
+                return Some(ServerMessage::Ack {
+                    id,
+                    ok: false,
+                    reply: String::from("viewers can't send commands"),
+                });
+            }
+            let control = control.clone();
+            let principal = principal.clone();
+            let replies = replies.clone();
+            tokio::spawn(async move {
+                let ack = match server::run_command(&control, &principal, body).await {
+                    Ok(reply) => ServerMessage::Ack {
+                        id,
+                        ok: true,
+                        reply,
+                    },
+                    Err(e) => ServerMessage::Ack {
+                        id,
+                        ok: false,
+                        reply: e.message().to_owned(),
+                    },
+                };
+                let _ = replies.send(ack).await;
+            });
+            None
+        }
+        ClientMessage::Filter { id, pattern, level } => {
+            match LogFilter::new(pattern.as_deref(), level.as_deref()) {
+                Ok(f) => {
+                    *filter = f;
+                    Some(ServerMessage::Ack {
+                        id,
+                        ok: true,
+                        reply: String::new(),
+                    })
+                }
+                Err(e) => Some(ServerMessage::Ack {
+                    id,
+                    ok: false,
+                    reply: e,
+                }),
+            }
+        }
+    }
+}
+
+async fn send<S>(sender: &mut S, json: bool, message: ServerMessage) -> Result<(), axum::Error>
+where
+    S: Sink<Message, Error = axum::Error> + Unpin,
+{
+    let text = match message {
+        _ if json => serde_json::to_string(&message).unwrap(),
+        ServerMessage::Log(entry) => entry.message,
+        // Plain text clients can't send anything to get a reply to.
+        _ => return Ok(()),
+    };
+    sender.send(Message::Text(text)).await
+}
+
+/// The same log as the WebSocket, as server-sent events with each entry as
+/// JSON. Read-only, but works through proxies that don't pass WebSockets.
+pub async fn events_handler(
+    State(control): State<MinecraftControl>,
+    Extension(keepalive): Extension<KeepAlive>,
+    Query(query): Query<ConsoleQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let filter = match LogFilter::new(query.pattern.as_deref(), query.level.as_deref()) {
+        Ok(f) => f,
+        Err(e) => return Err(ApiError::new(StatusCode::BAD_REQUEST, e)),
+    };
+    let (mut backlog, rx) = control.subscribe();
+    if !query.backlog.unwrap_or(true) {
+        backlog.clear();
+    }
+
+    let live = futures::stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(entry) => return Some((entry, rx)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    let events = futures::stream::iter(backlog)
+        .chain(live)
+        .take_until(control.shutdown().cancelled_owned())
+        .filter(move |entry| futures::future::ready(filter.matches(entry)))
+        .map(|entry| {
+            let event = match Event::default().event("log").json_data(&entry) {
+                Ok(e) => e,
+                Err(_) => Event::default().event("log").data(entry.message),
+            };
+            Ok(event)
+        });
+
+    Ok(Sse::new(events).keep_alive(sse::KeepAlive::new().interval(keepalive.interval)))
+}
@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::events::ServerEvent;
+use crate::minecraft::MinecraftControl;
+
+// How often the unit is checked for having died.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+// Crashes noticed this soon after a restart are the same crash, still
+// being logged, or the restart not having taken yet.
+const GRACE: Duration = Duration::from_secs(60);
+const PATTERNS: &[&str] = &[
+    "Exception in server tick loop",
+    "java.lang.OutOfMemoryError",
+    "Preparing crash report",
+];
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CrashConfig {
+    // Log lines containing any of these mean the server has crashed, as
+    // well as the unit failing or exiting when nobody stopped it.
+    patterns: Option<Vec<String>>,
+    // How long to wait before restarting, doubled for every restart
+    // already made within window_minutes. 10 seconds by default.
+    backoff_secs: Option<u64>,
+    // The longest the wait gets, 5 minutes by default.
+    max_backoff_secs: Option<u64>,
+    // Give up after this many restarts within window_minutes, 5 and 30 by
+    // default. Starting the server through the panel tries again.
+    max_restarts: Option<usize>,
+    window_minutes: Option<u64>,
+}
+
+/// Why the server looks to have crashed, if it does.
+fn crashed_in(line: &str, patterns: &[String]) -> Option<String> {
+    patterns
+        .iter()
+        .find(|p| line.contains(p.as_str()))
+        .map(|_| line.trim().to_owned())
+}
+
+/// Restarts the server when it crashes, waiting longer each time, until it
+/// has crashed `max_restarts` times in the window.
+pub fn watch(config: CrashConfig, control: MinecraftControl, shutdown: CancellationToken) {
+    let patterns: Vec<String> = match config.patterns {
+        Some(p) => p,
+        None => PATTERNS.iter().map(|p| p.to_string()).collect(),
+    };
+    let backoff = Duration::from_secs(config.backoff_secs.unwrap_or(10));
+    let max_backoff = Duration::from_secs(config.max_backoff_secs.unwrap_or(300));
+    let max_restarts = config.max_restarts.unwrap_or(5);
+    let window_minutes = config.window_minutes.unwrap_or(30);
+    let window = Duration::from_secs(window_minutes * 60);
+    let (_, mut logs) = control.subscribe();
+    let mut rx = control.events().subscribe();
+    tokio::spawn(async move {
+        let mut restarts: VecDeque<Instant> = VecDeque::new();
+        let mut last_restart: Option<Instant> = None;
+        let mut was_running = control.is_running().await;
+        // Stopped through the panel, so not a crash.
+        let mut stopping = false;
+        let mut gave_up = false;
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            let reason = tokio::select! {
+                entry = logs.recv() => match entry {
+                    Ok(e) => crashed_in(&e.message, &patterns),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+                event = rx.recv() => {
+                    let event = match event {
+                        Ok(e) => e,
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return,
+                    };
+                    if event.server != control.name() {
+                        continue;
+                    }
+                    match event.event {
+                        ServerEvent::LifecycleRequested { action: "stop" }
+                        | ServerEvent::IdleShutdown { .. } => stopping = true,
+                        ServerEvent::LifecycleRequested { .. } => {
+                            stopping = false;
+                            // Started again by hand, so it gets another go.
+                            if gave_up {
+                                gave_up = false;
+                                restarts.clear();
+                            }
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                _ = interval.tick() => {
+                    let status = control.status().await;
+                    let running = control.is_running().await;
+                    let exited = was_running && !running && !stopping;
+                    was_running = running;
+                    match status {
+                        Ok(s) if s.active_state == "failed" => {
+                            Some(format!("{} failed", s.unit))
+                        }
+                        _ if exited => Some(String::from("the server exited by itself")),
+                        _ => None,
+                    }
+                }
+                _ = shutdown.cancelled() => return,
+            };
+            let reason = match reason {
+                Some(r) if !gave_up && !stopping => r,
+                _ => continue,
+            };
+            if last_restart.is_some_and(|r| r.elapsed() < GRACE) {
+                continue;
+            }
+
+            while restarts.front().is_some_and(|r| r.elapsed() > window) {
+                restarts.pop_front();
+            }
+            if restarts.len() >= max_restarts {
+                gave_up = true;
+                tracing::error!(server = %control.name(), crashes = restarts.len(), minutes = window_minutes, "not restarting the server again");
+                control.publish(ServerEvent::CrashLoop {
+                    restarts: restarts.len(),
+                    minutes: window_minutes,
+                });
+                continue;
+            }
+            let delay = backoff
+                .saturating_mul(1u32 << restarts.len().min(16))
+                .min(max_backoff);
+            tracing::warn!(server = %control.name(), %reason, delay_secs = delay.as_secs(), "server crashed, restarting it");
+            control.publish(ServerEvent::CrashRestart {
+                attempt: restarts.len() + 1,
+                delay_secs: delay.as_secs(),
+                reason,
+            });
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {},
+                _ = shutdown.cancelled() => return,
+            }
+            if let Err(e) = control.restart().await {
+                tracing::error!(server = %control.name(), error = %e, "could not restart the server");
+            }
+            let now = Instant::now();
+            restarts.push_back(now);
+            last_restart = Some(now);
+            was_running = true;
+        }
+    });
+}
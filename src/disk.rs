@@ -0,0 +1,180 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::events::ServerEvent;
+use crate::minecraft::MinecraftControl;
+use crate::properties;
+use crate::worlds;
+
+const MB: u64 = 1024 * 1024;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DiskConfig {
+    // How often to check, 5 minutes by default.
+    interval_secs: Option<u64>,
+    // A filesystem with less free than this, as a percentage, is reported.
+    // 10 by default.
+    min_free_percent: Option<f64>,
+    // Directories bigger than these are reported. No limit if unset.
+    max_world_mb: Option<u64>,
+    max_logs_mb: Option<u64>,
+    max_backups_mb: Option<u64>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct Filesystem {
+    // Bytes.
+    pub total: u64,
+    // What's free to the panel, which leaves out what's kept for root.
+    pub available: u64,
+    // Directories on the same filesystem share it.
+    #[serde(skip)]
+    id: u64,
+}
+
+impl Filesystem {
+    fn free_percent(&self) -> f64 {
+        if self.total == 0 {
+            return 100.0;
+        }
+        self.available as f64 / self.total as f64 * 100.0
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct DirectoryUsage {
+    pub path: String,
+    // Bytes on disk of everything under it.
+    pub size: u64,
+    // None when it doesn't exist.
+    pub filesystem: Option<Filesystem>,
+}
+
+#[derive(Serialize)]
+pub struct DiskUsage {
+    pub world: DirectoryUsage,
+    pub logs: DirectoryUsage,
+    // Where backups are kept, when there are any.
+    pub backups: Option<DirectoryUsage>,
+}
+
+fn filesystem(path: &Path) -> Option<Filesystem> {
+    let stat = nix::sys::statvfs::statvfs(path).ok()?;
+    let block = stat.fragment_size() as u64;
+    Some(Filesystem {
+        total: stat.blocks() as u64 * block,
+        available: stat.blocks_available() as u64 * block,
+        id: stat.filesystem_id() as u64,
+    })
+}
+
+/// This blocks, so call it from `spawn_blocking`.
+fn directory_usage(path: PathBuf) -> DirectoryUsage {
+    let (size, _) = worlds::disk_usage(&path);
+    DirectoryUsage {
+        path: path.to_string_lossy().into_owned(),
+        size,
+        filesystem: filesystem(&path),
+    }
+}
+
+/// How much the server's world, logs and backups take up, and how much
+/// room is left for them.
+pub async fn usage(
+    control: &MinecraftControl,
+    server_dir: &Path,
+) -> Result<DiskUsage, std::io::Error> {
+    let world = properties::world_dir(server_dir).await;
+    let logs = server_dir.join("logs");
+    let backups = control.backups().map(|b| b.destination().to_path_buf());
+    let measured = tokio::task::spawn_blocking(move || DiskUsage {
+        world: directory_usage(world),
+        logs: directory_usage(logs),
+        backups: backups.map(directory_usage),
+    })
+    .await;
+    match measured {
+        Ok(u) => Ok(u),
+        Err(e) => Err(std::io::Error::other(e)),
+    }
+}
+
+/// Checks disk usage every so often and publishes an event when a
+/// filesystem gets low on space or a directory grows past its limit. Each
+/// is only reported again once it's gone back under.
+pub fn watch(config: DiskConfig, control: MinecraftControl, shutdown: CancellationToken) {
+    let server_dir = match control.server_dir() {
+        Some(d) => d,
+        None => {
+            tracing::info!(
+                "not watching disk usage for {}, it has no server directory",
+                control.name()
+            );
+            return;
+        }
+    };
+    let interval = Duration::from_secs(config.interval_secs.unwrap_or(300));
+    let min_free = config.min_free_percent.unwrap_or(10.0);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        let mut low: HashSet<u64> = HashSet::new();
+        let mut over: HashSet<&'static str> = HashSet::new();
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = shutdown.cancelled() => return,
+            }
+            let usage = match usage(&control, &server_dir).await {
+                Ok(u) => u,
+                Err(e) => {
+                    tracing::warn!("could not measure disk usage for {}: {}", control.name(), e);
+                    continue;
+                }
+            };
+            let directories = [
+                ("world", Some(&usage.world), config.max_world_mb),
+                ("logs", Some(&usage.logs), config.max_logs_mb),
+                ("backups", usage.backups.as_ref(), config.max_backups_mb),
+            ];
+            let mut still_low = HashSet::new();
+            for (directory, dir, limit) in directories {
+                let dir = match dir {
+                    Some(d) => d,
+                    None => continue,
+                };
+                if let Some(fs) = dir
+                    .filesystem
+                    .as_ref()
+                    .filter(|f| f.free_percent() < min_free)
+                {
+                    if still_low.insert(fs.id) && !low.contains(&fs.id) {
+                        control.publish(ServerEvent::DiskSpaceLow {
+                            directory,
+                            available: fs.available,
+                            percent: fs.free_percent(),
+                        });
+                    }
+                }
+                match limit.map(|l| l * MB) {
+                    Some(limit) if dir.size > limit => {
+                        if over.insert(directory) {
+                            control.publish(ServerEvent::DirectoryOverLimit {
+                                directory,
+                                size: dir.size,
+                                limit,
+                            });
+                        }
+                    }
+                    _ => {
+                        over.remove(directory);
+                    }
+                }
+            }
+            low = still_low;
+        }
+    });
+}
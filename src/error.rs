@@ -0,0 +1,161 @@
+use axum::{
+    body::{self, Body},
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rand::RngCore;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+// Error bodies bigger than this are cut down to their status.
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct ErrorBody {
+    // e.g. "not_found", or something more specific like "command_denied".
+    code: String,
+    message: String,
+    #[schema(value_type = Option<Object>)]
+    details: Option<serde_json::Value>,
+    request_id: Option<String>,
+}
+
+/// An error as the API sends it, as JSON with a code the frontend can
+/// match on rather than just a status.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    body: ErrorBody,
+}
+
+/// The status's reason as a code, e.g. "service_unavailable".
+fn status_code(status: StatusCode) -> String {
+    match status.canonical_reason() {
+        Some(r) => r.to_lowercase().replace([' ', '-'], "_"),
+        None => String::from("error"),
+    }
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> ApiError {
+        let mut message = message.into();
+        if message.is_empty() {
+            message = status.canonical_reason().unwrap_or("error").to_owned();
+        }
+        ApiError {
+            status,
+            body: ErrorBody {
+                code: status_code(status),
+                message,
+                details: None,
+                request_id: None,
+            },
+        }
+    }
+
+    pub fn code(mut self, code: &str) -> ApiError {
+        self.body.code = code.to_owned();
+        self
+    }
+
+    pub fn details(mut self, details: serde_json::Value) -> ApiError {
+        self.body.details = Some(details);
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn message(&self) -> &str {
+        &self.body.message
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = match serde_json::to_vec(&self.body) {
+            Ok(b) => b,
+            Err(_) => return self.status.into_response(),
+        };
+        let mut response = (
+            self.status,
+            [(header::CONTENT_TYPE, "application/json")],
+            body,
+        )
+            .into_response();
+        // Kept for the middleware to add the request ID to.
+        response.extensions_mut().insert(self.body);
+        response
+    }
+}
+
+/// From X-Request-Id if the caller sent a usable one, so a failure can be
+/// followed through a proxy's logs as well.
+fn request_id(request: &Request) -> String {
+    let given = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 64 && v.chars().all(|c| c.is_ascii_graphic()));
+    match given {
+        Some(id) => id.to_owned(),
+        None => {
+            let mut bytes = [0u8; 8];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            bytes.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+    }
+}
+
+fn is_json(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"))
+}
+
+/// Gives every request an ID, sent back in X-Request-Id, and turns error
+/// responses that aren't JSON already, like axum's own rejections, into the
+/// same JSON as `ApiError`. Server errors are logged with the ID.
+pub async fn middleware(request: Request, next: Next) -> Response {
+    let id = request_id(&request);
+    let mut response = next.run(request).await;
+
+    let status = response.status();
+    let body = match response.extensions_mut().remove::<ErrorBody>() {
+        Some(b) => Some(b),
+        None if (status.is_client_error() || status.is_server_error()) && !is_json(&response) => {
+            let (parts, body) = response.into_parts();
+            let message = match body::to_bytes(body, MAX_MESSAGE_BYTES).await {
+                Ok(b) => String::from_utf8_lossy(&b).trim().to_owned(),
+                Err(_) => String::new(),
+            };
+            response = Response::from_parts(parts, Body::empty());
+            Some(ApiError::new(status, message).body)
+        }
+        None => None,
+    };
+    if let Some(mut body) = body {
+        if status.is_server_error() {
+            tracing::warn!(request_id = %id, status = status.as_u16(), "{}", body.message);
+        }
+        body.request_id = Some(id.clone());
+        if let Ok(json) = serde_json::to_vec(&body) {
+            let (mut parts, _) = response.into_parts();
+            parts.headers.remove(header::CONTENT_LENGTH);
+            parts.headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            response = Response::from_parts(parts, Body::from(json));
+        }
+    }
+    if let Ok(v) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert("x-request-id", v);
+    }
+    response
+}
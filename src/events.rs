@@ -0,0 +1,258 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+use tokio_util::sync::CancellationToken;
+
+use crate::logsource::LogEntry;
+use crate::parser::LogEvent;
+
+/// Something that happened to a server, for anything that wants to react
+/// to it rather than read the log.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    ServerStarted {
+        seconds: f64,
+    },
+    ServerStopping,
+    Crash {
+        message: String,
+    },
+    // Someone asked the panel to start, stop or restart the server.
+    LifecycleRequested {
+        action: &'static str,
+    },
+    PlayerJoined {
+        player: String,
+    },
+    PlayerLeft {
+        player: String,
+    },
+    Chat {
+        player: String,
+        message: String,
+    },
+    Death {
+        player: String,
+        message: String,
+    },
+    Advancement {
+        player: String,
+        advancement: String,
+        kind: &'static str,
+    },
+    Error {
+        message: String,
+    },
+    BackupFinished {
+        id: String,
+        size: u64,
+    },
+    BackupFailed {
+        message: String,
+    },
+    // A restore moving on to "stopping", "extracting", "swapping" or
+    // "starting".
+    RestoreProgress {
+        id: String,
+        step: &'static str,
+    },
+    RestoreFinished {
+        id: String,
+    },
+    RestoreFailed {
+        id: String,
+        message: String,
+    },
+    // Players were warned of a scheduled restart, this many minutes
+    // before it. `at` is when it'll happen, in seconds since the epoch.
+    RestartWarning {
+        minutes: u64,
+        at: u64,
+    },
+    // The server was stopped after this long without players.
+    IdleShutdown {
+        minutes: u64,
+    },
+    // A new server jar was put in place.
+    ServerUpdated {
+        version: String,
+        build: String,
+    },
+    // The server crashed and will be restarted after `delay_secs`.
+    CrashRestart {
+        attempt: usize,
+        delay_secs: u64,
+        reason: String,
+    },
+    // The server crashed again after this many restarts in `minutes`, and
+    // was left down.
+    CrashLoop {
+        restarts: usize,
+        minutes: u64,
+    },
+    // The server was up but didn't answer this many pings, and was
+    // restarted.
+    Unresponsive {
+        failures: u32,
+    },
+    // Maintenance mode was turned on or off.
+    MaintenanceChanged {
+        enabled: bool,
+    },
+    // The filesystem a directory is on, "world", "logs" or "backups", has
+    // only this much of it free.
+    DiskSpaceLow {
+        directory: &'static str,
+        available: u64,
+        percent: f64,
+    },
+    // A directory grew past the limit set for it, in bytes.
+    DirectoryOverLimit {
+        directory: &'static str,
+        size: u64,
+        limit: u64,
+    },
+    // A garbage collection paused the server for longer than the
+    // threshold set for it.
+    LongGcPause {
+        kind: String,
+        duration_ms: f64,
+    },
+    // The heap was still this full, as a percentage, after several
+    // collections in a row.
+    MemoryPressure {
+        percent: f64,
+    },
+}
+
+impl ServerEvent {
+    /// The `type` it's serialized with.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ServerEvent::ServerStarted { .. } => "server_started",
+            ServerEvent::ServerStopping => "server_stopping",
+            ServerEvent::Crash { .. } => "crash",
+            ServerEvent::LifecycleRequested { .. } => "lifecycle_requested",
+            ServerEvent::PlayerJoined { .. } => "player_joined",
+            ServerEvent::PlayerLeft { .. } => "player_left",
+            ServerEvent::Chat { .. } => "chat",
+            ServerEvent::Death { .. } => "death",
+            ServerEvent::Advancement { .. } => "advancement",
+            ServerEvent::Error { .. } => "error",
+            ServerEvent::BackupFinished { .. } => "backup_finished",
+            ServerEvent::BackupFailed { .. } => "backup_failed",
+            ServerEvent::RestoreProgress { .. } => "restore_progress",
+            ServerEvent::RestoreFinished { .. } => "restore_finished",
+            ServerEvent::RestoreFailed { .. } => "restore_failed",
+            ServerEvent::RestartWarning { .. } => "restart_warning",
+            ServerEvent::IdleShutdown { .. } => "idle_shutdown",
+            ServerEvent::ServerUpdated { .. } => "server_updated",
+            ServerEvent::CrashRestart { .. } => "crash_restart",
+            ServerEvent::CrashLoop { .. } => "crash_loop",
+            ServerEvent::Unresponsive { .. } => "unresponsive",
+            ServerEvent::MaintenanceChanged { .. } => "maintenance_changed",
+            ServerEvent::DiskSpaceLow { .. } => "disk_space_low",
+            ServerEvent::DirectoryOverLimit { .. } => "directory_over_limit",
+            ServerEvent::LongGcPause { .. } => "long_gc_pause",
+            ServerEvent::MemoryPressure { .. } => "memory_pressure",
+        }
+    }
+}
+
+impl From<LogEvent> for ServerEvent {
+    fn from(e: LogEvent) -> Self {
+        match e {
+            LogEvent::ServerReady { seconds } => ServerEvent::ServerStarted { seconds },
+            LogEvent::ServerStopping => ServerEvent::ServerStopping,
+            LogEvent::Crash { message } => ServerEvent::Crash { message },
+            LogEvent::PlayerJoined { player } => ServerEvent::PlayerJoined { player },
+            LogEvent::PlayerLeft { player } => ServerEvent::PlayerLeft { player },
+            LogEvent::Chat { player, message } => ServerEvent::Chat { player, message },
+            LogEvent::Death { player, message } => ServerEvent::Death { player, message },
+            LogEvent::Advancement {
+                player,
+                advancement,
+                kind,
+            } => ServerEvent::Advancement {
+                player,
+                advancement,
+                kind,
+            },
+            LogEvent::Error { message } => ServerEvent::Error { message },
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Event {
+    pub server: String,
+    // Milliseconds since the epoch.
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub event: ServerEvent,
+}
+
+/// Where every server's events are published. Shared by all servers, so
+/// subscribers that only care about one check `server`.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: Sender<Event>,
+}
+
+pub fn init() -> EventBus {
+    EventBus {
+        tx: broadcast::channel(256).0,
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, server: &str, event: ServerEvent) {
+        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_millis() as u64,
+            Err(_) => 0,
+        };
+        // Fails only while nobody is subscribed.
+        let _ = self.tx.send(Event {
+            server: server.to_owned(),
+            timestamp,
+            event,
+        });
+    }
+
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.tx.subscribe()
+    }
+
+    /// Events sent that the slowest subscriber hasn't had yet.
+    pub fn queued(&self) -> usize {
+        self.tx.len()
+    }
+
+    /// Publishes the events the parser found in a server's log until
+    /// `shutdown` is cancelled.
+    pub fn publish_log_events(
+        &self,
+        server: String,
+        mut rx: Receiver<LogEntry>,
+        shutdown: CancellationToken,
+    ) {
+        let bus = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let entry = tokio::select! {
+                    entry = rx.recv() => entry,
+                    _ = shutdown.cancelled() => return,
+                };
+                match entry {
+                    Ok(LogEntry {
+                        event: Some(event), ..
+                    }) => bus.publish(&server, event.into()),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+}
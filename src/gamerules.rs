@@ -0,0 +1,102 @@
+use serde_json::Value;
+
+#[derive(Clone, Copy)]
+enum RuleKind {
+    Bool,
+    Int,
+}
+
+// Vanilla's rules as of 1.21. Older servers don't have all of them.
+const RULES: &[(&str, RuleKind)] = &[
+    ("announceAdvancements", RuleKind::Bool),
+    ("blockExplosionDropDecay", RuleKind::Bool),
+    ("commandBlockOutput", RuleKind::Bool),
+    ("commandModificationLimit", RuleKind::Int),
+    ("disableElytraMovementCheck", RuleKind::Bool),
+    ("disablePlayerMovementCheck", RuleKind::Bool),
+    ("disableRaids", RuleKind::Bool),
+    ("doDaylightCycle", RuleKind::Bool),
+    ("doEntityDrops", RuleKind::Bool),
+    ("doFireTick", RuleKind::Bool),
+    ("doImmediateRespawn", RuleKind::Bool),
+    ("doInsomnia", RuleKind::Bool),
+    ("doLimitedCrafting", RuleKind::Bool),
+    ("doMobLoot", RuleKind::Bool),
+    ("doMobSpawning", RuleKind::Bool),
+    ("doPatrolSpawning", RuleKind::Bool),
+    ("doTileDrops", RuleKind::Bool),
+    ("doTraderSpawning", RuleKind::Bool),
+    ("doVinesSpread", RuleKind::Bool),
+    ("doWardenSpawning", RuleKind::Bool),
+    ("doWeatherCycle", RuleKind::Bool),
+    ("drowningDamage", RuleKind::Bool),
+    ("enderPearlsVanishOnDeath", RuleKind::Bool),
+    ("fallDamage", RuleKind::Bool),
+    ("fireDamage", RuleKind::Bool),
+    ("forgiveDeadPlayers", RuleKind::Bool),
+    ("freezeDamage", RuleKind::Bool),
+    ("globalSoundEvents", RuleKind::Bool),
+    ("keepInventory", RuleKind::Bool),
+    ("lavaSourceConversion", RuleKind::Bool),
+    ("logAdminCommands", RuleKind::Bool),
+    ("maxCommandChainLength", RuleKind::Int),
+    ("maxCommandForkCount", RuleKind::Int),
+    ("maxEntityCramming", RuleKind::Int),
+    ("mobExplosionDropDecay", RuleKind::Bool),
+    ("mobGriefing", RuleKind::Bool),
+    ("naturalRegeneration", RuleKind::Bool),
+    ("playersNetherPortalCreativeDelay", RuleKind::Int),
+    ("playersNetherPortalDefaultDelay", RuleKind::Int),
+    ("playersSleepingPercentage", RuleKind::Int),
+    ("projectilesCanBreakBlocks", RuleKind::Bool),
+    ("randomTickSpeed", RuleKind::Int),
+    ("reducedDebugInfo", RuleKind::Bool),
+    ("sendCommandFeedback", RuleKind::Bool),
+    ("showDeathMessages", RuleKind::Bool),
+    ("snowAccumulationHeight", RuleKind::Int),
+    ("spawnChunkRadius", RuleKind::Int),
+    ("spawnRadius", RuleKind::Int),
+    ("spectatorsGenerateChunks", RuleKind::Bool),
+    ("tntExplosionDropDecay", RuleKind::Bool),
+    ("universalAnger", RuleKind::Bool),
+    ("waterSourceConversion", RuleKind::Bool),
+];
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    RULES.iter().map(|(name, _)| *name)
+}
+
+fn kind(rule: &str) -> Option<RuleKind> {
+    RULES.iter().find(|(r, _)| *r == rule).map(|(_, k)| *k)
+}
+
+/// Checks a value against the rule's type and turns it into the text the
+/// command takes.
+pub fn check(rule: &str, value: &Value) -> Result<String, String> {
+    match (kind(rule), value) {
+        (None, _) => Err(format!("{} isn't a game rule", rule)),
+        (Some(RuleKind::Bool), Value::Bool(b)) => Ok(b.to_string()),
+        (Some(RuleKind::Int), Value::Number(n))
+            if n.as_i64().is_some_and(|n| n as i32 as i64 == n) =>
+        {
+            Ok(n.to_string())
+        }
+        (Some(RuleKind::Bool), _) => Err(format!("{} has to be true or false", rule)),
+        (Some(RuleKind::Int), _) => Err(format!("{} has to be a whole number", rule)),
+    }
+}
+
+/// Reads `Gamerule keepInventory is currently set to: false`.
+pub fn parse_reply(rule: &str, reply: &str) -> Option<Value> {
+    let kind = kind(rule)?;
+    let prefix = format!("Gamerule {} is currently set to: ", rule);
+    let value = reply
+        .lines()
+        .map(crate::parser::strip_log_prefix)
+        .find_map(|l| l.strip_prefix(prefix.as_str()))?
+        .trim();
+    match kind {
+        RuleKind::Bool => value.parse::<bool>().ok().map(Value::Bool),
+        RuleKind::Int => value.parse::<i64>().ok().map(Value::from),
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::events::ServerEvent;
+use crate::logsource::{file::FileSource, LogSource};
+use crate::minecraft::MinecraftControl;
+use crate::util::now;
+
+// Collections in a row with the heap over heap_threshold_percent before
+// it's reported, so one full heap just before a collection isn't.
+const PRESSURE_COLLECTIONS: u32 = 5;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GcConfig {
+    // The JVM's unified GC log, as written with -Xlog:gc:file=logs/gc.log.
+    // Relative to the server directory, logs/gc.log by default.
+    path: Option<String>,
+    // How many pauses to keep, 1000 by default.
+    window: Option<usize>,
+    // Pauses longer than this are reported. Not reported if unset.
+    pause_threshold_ms: Option<f64>,
+    // A heap still this full after collections is reported. Not reported
+    // if unset.
+    heap_threshold_percent: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Pause {
+    // Seconds since the epoch it was read.
+    pub at: u64,
+    pub gc_id: u64,
+    // e.g. "Pause Young (Normal) (G1 Evacuation Pause)".
+    pub kind: String,
+    pub duration_ms: f64,
+    // In bytes. Collectors that log their pauses separately from the
+    // collection, like ZGC, don't give these.
+    pub heap_before: Option<u64>,
+    pub heap_after: Option<u64>,
+    pub heap_total: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct GcSummary {
+    // How many pauses these are worked out from.
+    pub pauses: usize,
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    // How fast the heap left after collections is growing, in bytes an
+    // hour, which should stay near 0 for a server that isn't leaking.
+    pub heap_after_growth_per_hour: Option<f64>,
+    // Oldest first.
+    pub recent: Vec<Pause>,
+}
+
+/// e.g. "512M".
+fn size(text: &str) -> Option<u64> {
+    let unit = match text.chars().last()? {
+        'B' => 1,
+        'K' => 1024,
+        'M' => 1024 * 1024,
+        'G' => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    let number: u64 = text[..text.len() - 1].parse().ok()?;
+    Some(number * unit)
+}
+
+/// e.g. "512M->128M(2048M)".
+fn heap(token: &str) -> Option<(u64, u64, u64)> {
+    let (before, rest) = token.split_once("->")?;
+    let (after, total) = rest.strip_suffix(')')?.split_once('(')?;
+    Some((size(before)?, size(after)?, size(total)?))
+}
+
+/// Reads a pause from a line of the unified GC log, such as
+/// `[12.345s][info][gc] GC(12) Pause Young (Normal) (G1 Evacuation Pause) 512M->128M(2048M) 12.345ms`.
+fn parse(line: &str) -> Option<Pause> {
+    let rest = &line[line.find("GC(")? + 3..];
+    let (id, rest) = rest.split_once(')')?;
+    let rest = rest.trim_start();
+    if !rest.starts_with("Pause") {
+        return None;
+    }
+    let mut tokens: Vec<&str> = rest.split_whitespace().collect();
+    let duration_ms: f64 = tokens.pop()?.strip_suffix("ms")?.parse().ok()?;
+    let heap = match tokens.last().and_then(|t| heap(t)) {
+        Some(h) => {
+            tokens.pop();
+            Some(h)
+        }
+        None => None,
+    };
+    Some(Pause {
+        at: now(),
+        gc_id: id.parse().ok()?,
+        kind: tokens.join(" "),
+        duration_ms,
+        heap_before: heap.map(|h| h.0),
+        heap_after: heap.map(|h| h.1),
+        heap_total: heap.map(|h| h.2),
+    })
+}
+
+/// By nearest rank, of durations already sorted.
+fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// The slope of a least squares fit of heap after collection against
+/// time, in bytes a second.
+fn growth(pauses: &[Pause]) -> Option<f64> {
+    let points: Vec<(f64, f64)> = pauses
+        .iter()
+        .filter_map(|p| Some((p.at as f64, p.heap_after? as f64)))
+        .collect();
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_t = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_h = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let covariance: f64 = points.iter().map(|p| (p.0 - mean_t) * (p.1 - mean_h)).sum();
+    let variance: f64 = points.iter().map(|p| (p.0 - mean_t).powi(2)).sum();
+    if variance == 0.0 {
+        return None;
+    }
+    Some(covariance / variance)
+}
+
+/// Follows the server's GC log and keeps its latest pauses, reporting
+/// long ones and a heap that stays full.
+#[derive(Clone)]
+pub struct Gc {
+    config: GcConfig,
+    pauses: Arc<Mutex<VecDeque<Pause>>>,
+}
+
+impl Gc {
+    pub fn new(config: GcConfig) -> Gc {
+        Gc {
+            config,
+            pauses: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    pub async fn summary(&self) -> GcSummary {
+        let pauses: Vec<Pause> = self.pauses.lock().await.iter().cloned().collect();
+        let mut durations: Vec<f64> = pauses.iter().map(|p| p.duration_ms).collect();
+        durations.sort_by(|a, b| a.total_cmp(b));
+        GcSummary {
+            pauses: pauses.len(),
+            p50_ms: percentile(&durations, 50.0),
+            p95_ms: percentile(&durations, 95.0),
+            p99_ms: percentile(&durations, 99.0),
+            max_ms: durations.last().copied(),
+            heap_after_growth_per_hour: growth(&pauses).map(|g| g * 3600.0),
+            recent: pauses[pauses.len().saturating_sub(100)..].to_vec(),
+        }
+    }
+
+    /// Reads pauses from the end of the GC log as they're written, until
+    /// shutdown.
+    pub fn start(&self, control: MinecraftControl, shutdown: CancellationToken) {
+        let path = self.config.path.as_deref().unwrap_or("logs/gc.log");
+        let path = match control.server_dir() {
+            Some(d) => d.join(path),
+            None => PathBuf::from(path),
+        };
+        let gc = self.clone();
+        let window = self.config.window.unwrap_or(1000);
+        tokio::spawn(async move {
+            let mut lines = FileSource::new(path).lines();
+            // Collections in a row with the heap over the threshold.
+            let mut full = 0;
+            loop {
+                let entry = tokio::select! {
+                    entry = lines.next() => match entry {
+                        Some(e) => e,
+                        None => return,
+                    },
+                    _ = shutdown.cancelled() => return,
+                };
+                let pause = match parse(&entry.message) {
+                    Some(p) => p,
+                    None => continue,
+                };
+                if let Some(threshold) = gc.config.pause_threshold_ms {
+                    if pause.duration_ms > threshold {
+                        control.publish(ServerEvent::LongGcPause {
+                            kind: pause.kind.clone(),
+                            duration_ms: pause.duration_ms,
+                        });
+                    }
+                }
+                if let (Some(threshold), Some(after), Some(total)) = (
+                    gc.config.heap_threshold_percent,
+                    pause.heap_after,
+                    pause.heap_total,
+                ) {
+                    let percent = after as f64 / total.max(1) as f64 * 100.0;
+                    if percent < threshold {
+                        full = 0;
+                    } else {
+                        full += 1;
+                        if full == PRESSURE_COLLECTIONS {
+                            control.publish(ServerEvent::MemoryPressure { percent });
+                        }
+                    }
+                }
+                let mut pauses = gc.pauses.lock().await;
+                pauses.push_back(pause);
+                while pauses.len() > window {
+                    pauses.pop_front();
+                }
+            }
+        });
+    }
+}
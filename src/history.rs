@@ -0,0 +1,258 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection};
+use serde::Deserialize;
+use tokio::sync::broadcast::{self, Receiver};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+use crate::logsource::journal::{JournalEntry, JournalPage};
+use crate::logsource::search::{SearchMatch, SearchTerms};
+use crate::logsource::LogEntry;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct HistoryConfig {
+    // The SQLite database every server's log is written to.
+    path: Option<String>,
+    // Lines older than this are deleted...
+    retention_days: Option<u64>,
+    // ...and so are the oldest once a server has more than this many.
+    max_lines: Option<u64>,
+}
+
+/// Every line that was broadcast, kept in SQLite so it outlives the panel.
+#[derive(Clone)]
+pub struct History {
+    db: Arc<Mutex<Connection>>,
+    retention: Option<Duration>,
+    max_lines: Option<u64>,
+    // The recorders, which write what they have left when they stop.
+    tasks: TaskTracker,
+}
+
+pub fn open(config: HistoryConfig) -> Result<History, rusqlite::Error> {
+    let path = match config.path {
+        Some(p) => p,
+        None => String::from("history.db"),
+    };
+    let db = Connection::open(&path)?;
+    db.execute_batch(
+        "PRAGMA journal_mode = WAL;
+        CREATE TABLE IF NOT EXISTS log_lines (
+            id INTEGER PRIMARY KEY,
+            server TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            source TEXT NOT NULL,
+            severity TEXT NOT NULL,
+            message TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS log_lines_server_timestamp
+            ON log_lines (server, timestamp);",
+    )?;
+    tracing::info!(%path, "keeping log history");
+    Ok(History {
+        db: Arc::new(Mutex::new(db)),
+        retention: config
+            .retention_days
+            .map(|d| Duration::from_secs(d * 24 * 60 * 60)),
+        max_lines: config.max_lines,
+        tasks: TaskTracker::new(),
+    })
+}
+
+impl History {
+    /// Writes everything `rx` receives under `server` until `shutdown` is
+    /// cancelled, pruning old lines every hour.
+    pub fn record(&self, server: String, mut rx: Receiver<LogEntry>, shutdown: CancellationToken) {
+        let history = self.clone();
+        self.tasks.spawn(async move {
+            let mut prune = tokio::time::interval(Duration::from_secs(60 * 60));
+            let mut batch: Vec<LogEntry> = Vec::new();
+            loop {
+                // Lines come in bursts, so they're written a batch at a
+                // time rather than one transaction each.
+                let flush = tokio::select! {
+                    entry = rx.recv() => match entry {
+                        Ok(entry) => {
+                            batch.push(entry);
+                            batch.len() >= 256 || rx.is_empty()
+                        }
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            tracing::warn!(%server, skipped = n, "log history fell behind and skipped lines");
+                            false
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = prune.tick() => {
+                        let h = history.clone();
+                        let s = server.clone();
+                        if let Ok(Err(e)) = tokio::task::spawn_blocking(move || h.prune(&s)).await {
+                            tracing::warn!(error = %e, "could not prune log history");
+                        }
+                        false
+                    },
+                    _ = shutdown.cancelled() => break,
+                };
+                if flush {
+                    history.write(&server, std::mem::take(&mut batch)).await;
+                }
+            }
+            history.write(&server, batch).await;
+        });
+    }
+
+    /// Waits for every recorder to write what it has left, once
+    /// `shutdown` has been cancelled.
+    pub async fn close(&self) {
+        self.tasks.close();
+        self.tasks.wait().await;
+    }
+
+    async fn write(&self, server: &str, batch: Vec<LogEntry>) {
+        if batch.is_empty() {
+            return;
+        }
+        let history = self.clone();
+        let server = server.to_owned();
+        let written = tokio::task::spawn_blocking(move || history.insert(&server, &batch)).await;
+        if let Ok(Err(e)) = written {
+            tracing::warn!(error = %e, "could not write log history");
+        }
+    }
+
+    fn insert(&self, server: &str, batch: &[LogEntry]) -> Result<(), rusqlite::Error> {
+        let mut db = self.db.lock().unwrap();
+        let tx = db.transaction()?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO log_lines (server, timestamp, source, severity, message)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for entry in batch {
+                insert.execute(params![
+                    server,
+                    entry.timestamp as i64,
+                    entry.source,
+                    entry.severity,
+                    entry.message
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+
+    fn prune(&self, server: &str) -> Result<(), rusqlite::Error> {
+        let db = self.db.lock().unwrap();
+        if let Some(retention) = self.retention {
+            let cutoff = match SystemTime::now().checked_sub(retention) {
+                Some(t) => match t.duration_since(UNIX_EPOCH) {
+                    Ok(d) => d.as_millis() as i64,
+                    Err(_) => 0,
+                },
+                None => 0,
+            };
+            db.execute(
+                "DELETE FROM log_lines WHERE server = ?1 AND timestamp < ?2",
+                params![server, cutoff],
+            )?;
+        }
+        if let Some(max) = self.max_lines {
+            db.execute(
+                "DELETE FROM log_lines WHERE server = ?1 AND id <= (
+                    SELECT id FROM log_lines WHERE server = ?1
+                    ORDER BY id DESC LIMIT 1 OFFSET ?2
+                )",
+                params![server, max as i64],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Up to `limit` lines from before `cursor`, or the latest ones, in the
+    /// same shape as journal history. Cursors are row ids. This blocks, so
+    /// call it from `spawn_blocking`.
+    pub fn page(
+        &self,
+        server: &str,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<JournalPage, rusqlite::Error> {
+        let before = match cursor.and_then(|c| c.parse::<i64>().ok()) {
+            Some(id) => id,
+            None => i64::MAX,
+        };
+        let db = self.db.lock().unwrap();
+        let mut query = db.prepare_cached(
+            "SELECT id, timestamp, message FROM log_lines
+            WHERE server = ?1 AND id < ?2 ORDER BY id DESC LIMIT ?3",
+        )?;
+        let mut entries = query
+            .query_map(params![server, before, limit as i64], |row| {
+                Ok(JournalEntry {
+                    cursor: row.get::<_, i64>(0)?.to_string(),
+                    timestamp: row.get::<_, i64>(1)? as u64,
+                    message: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<JournalEntry>, rusqlite::Error>>()?;
+
+        let next = if entries.len() == limit {
+            entries.last().map(|e| e.cursor.clone())
+        } else {
+            None
+        };
+        entries.reverse();
+        Ok(JournalPage { entries, next })
+    }
+
+    /// Up to `limit` matching lines, oldest first. This blocks, so call it
+    /// from `spawn_blocking`.
+    pub fn search(
+        &self,
+        server: &str,
+        terms: &SearchTerms,
+        limit: usize,
+    ) -> Result<Vec<SearchMatch>, rusqlite::Error> {
+        let from = terms.from.map_or(0, |f| f as i64);
+        let to = terms.to.map_or(i64::MAX, |t| t as i64);
+        // LIKE narrows things down for plain text; the terms decide.
+        let like = match terms.text() {
+            Some(t) => format!(
+                "%{}%",
+                t.replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            ),
+            None => String::from("%"),
+        };
+        let db = self.db.lock().unwrap();
+        let mut query = db.prepare_cached(
+            "SELECT id, timestamp, source, message FROM log_lines
+            WHERE server = ?1 AND timestamp BETWEEN ?2 AND ?3
+                AND message LIKE ?4 ESCAPE '\\'
+            ORDER BY id",
+        )?;
+        let mut rows = query.query(params![server, from, to, like])?;
+
+        let mut matches = Vec::new();
+        while matches.len() < limit {
+            let row = match rows.next()? {
+                Some(r) => r,
+                None => break,
+            };
+            let message: String = row.get(3)?;
+            if !terms.is_match(&message) {
+                continue;
+            }
+            matches.push(SearchMatch {
+                source: row.get(2)?,
+                offset: None,
+                cursor: Some(row.get::<_, i64>(0)?.to_string()),
+                timestamp: Some(row.get::<_, i64>(1)? as u64),
+                line: message,
+            });
+        }
+        Ok(matches)
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::events::ServerEvent;
+use crate::minecraft::MinecraftControl;
+use crate::players::parse_list;
+
+// How often the server is checked for having been empty long enough.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StopWith {
+    // Through the backend, which stops the systemd unit, container or pod.
+    #[default]
+    Unit,
+    // A stop command on the console, for setups where something else
+    // brings the server back.
+    Command,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct IdleConfig {
+    // How long the server can go without players before it's stopped.
+    after_minutes: u64,
+    stop_with: Option<StopWith>,
+}
+
+async fn stop(control: &MinecraftControl, stop_with: StopWith) -> Result<(), String> {
+    match stop_with {
+        StopWith::Unit => match control.stop().await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        },
+        StopWith::Command => match control.command(String::from("stop")).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(String::from("could not send stop to the server")),
+        },
+    }
+}
+
+/// Stops the server once nobody has been on it for `after_minutes`,
+/// following join and leave events to know who's online.
+pub fn watch(config: IdleConfig, control: MinecraftControl, shutdown: CancellationToken) {
+    let mut rx = control.events().subscribe();
+    let after = Duration::from_secs(config.after_minutes * 60);
+    let stop_with = config.stop_with.unwrap_or_default();
+    tokio::spawn(async move {
+        // Whoever was already on when the panel started.
+        let mut online: HashSet<String> = match control.execute(String::from("list")).await {
+            Ok(Some(reply)) => parse_list(&reply).map(|l| l.players).unwrap_or_default(),
+            _ => Vec::new(),
+        }
+        .into_iter()
+        .collect();
+        let mut empty_since: Option<Instant> = None;
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            tokio::select! {
+                event = rx.recv() => {
+                    let event = match event {
+                        Ok(e) => e,
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return,
+                    };
+                    if event.server != control.name() {
+                        continue;
+                    }
+                    match event.event {
+                        ServerEvent::PlayerJoined { player } => {
+                            online.insert(player);
+                        }
+                        ServerEvent::PlayerLeft { player } => {
+                            online.remove(&player);
+                        }
+                        // Nobody gets a leave message when the server goes
+                        // down.
+                        ServerEvent::ServerStarted { .. }
+                        | ServerEvent::ServerStopping
+                        | ServerEvent::Crash { .. } => online.clear(),
+                        _ => {}
+                    }
+                    continue;
+                }
+                _ = interval.tick() => {},
+                _ = shutdown.cancelled() => return,
+            }
+
+            if !online.is_empty() || !control.is_running().await {
+                empty_since = None;
+                continue;
+            }
+            let since = *empty_since.get_or_insert_with(Instant::now);
+            if since.elapsed() < after {
+                continue;
+            }
+            empty_since = None;
+            tracing::info!(server = %control.name(), minutes = config.after_minutes, "stopping the server after minutes without players");
+            match stop(&control, stop_with).await {
+                Ok(()) => control.publish(ServerEvent::IdleShutdown {
+                    minutes: config.after_minutes,
+                }),
+                Err(e) => {
+                    tracing::warn!(server = %control.name(), error = %e, "could not stop the idle server")
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,128 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct JvmConfig {
+    // The programs, if they aren't on PATH. They have to come from a JDK
+    // close to the server's and run as the same user it does.
+    jcmd: Option<String>,
+    jstack: Option<String>,
+    // How long either gets, 30 seconds by default.
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum JvmError {
+    Io(std::io::Error),
+    // That the backend knows the PID of.
+    NotRunning,
+    Timeout,
+    Failed(String),
+}
+
+impl From<std::io::Error> for JvmError {
+    fn from(e: std::io::Error) -> Self {
+        JvmError::Io(e)
+    }
+}
+
+impl std::fmt::Display for JvmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JvmError::Io(e) => write!(f, "could not run the JDK's tools: {}", e),
+            JvmError::NotRunning => write!(f, "there's no server process to look at"),
+            JvmError::Timeout => write!(f, "the JVM didn't answer in time"),
+            JvmError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Diagnostic {
+    pub pid: u32,
+    // What was run, e.g. "jcmd Thread.print".
+    pub tool: String,
+    pub output: String,
+}
+
+/// Asks the JDK's tools about a running JVM.
+#[derive(Clone)]
+pub struct Jvm {
+    config: Option<JvmConfig>,
+}
+
+impl Jvm {
+    pub fn new(config: Option<JvmConfig>) -> Jvm {
+        Jvm { config }
+    }
+
+    fn timeout(&self) -> Duration {
+        let timeout = self.config.as_ref().and_then(|c| c.timeout_secs);
+        Duration::from_secs(timeout.unwrap_or(30))
+    }
+
+    async fn run(&self, program: &str, args: &[String]) -> Result<String, JvmError> {
+        let child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let output = match tokio::time::timeout(self.timeout(), child).await {
+            Ok(o) => o?,
+            Err(_) => return Err(JvmError::Timeout),
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            // jcmd puts why it couldn't attach on stdout.
+            let why = stderr
+                .lines()
+                .chain(stdout.lines())
+                .rev()
+                .find(|l| !l.trim().is_empty())
+                .unwrap_or("no output")
+                .to_owned();
+            return Err(JvmError::Failed(format!("{} failed: {}", program, why)));
+        }
+        Ok(stdout)
+    }
+
+    fn jcmd(&self) -> &str {
+        let jcmd = self.config.as_ref().and_then(|c| c.jcmd.as_deref());
+        jcmd.unwrap_or("jcmd")
+    }
+
+    /// The stack of every thread, with the locks they hold. Falls back to
+    /// jstack for JDKs without jcmd.
+    pub async fn thread_dump(&self, pid: u32) -> Result<Diagnostic, JvmError> {
+        let args = [
+            pid.to_string(),
+            String::from("Thread.print"),
+            String::from("-l"),
+        ];
+        let (tool, output) = match self.run(self.jcmd(), &args).await {
+            Err(JvmError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                let jstack = self.config.as_ref().and_then(|c| c.jstack.as_deref());
+                let jstack = jstack.unwrap_or("jstack");
+                let args = [String::from("-l"), pid.to_string()];
+                (String::from("jstack -l"), self.run(jstack, &args).await?)
+            }
+            result => (String::from("jcmd Thread.print"), result?),
+        };
+        Ok(Diagnostic { pid, tool, output })
+    }
+
+    /// How big the heap is and how much of it is used, per generation or
+    /// region depending on the collector.
+    pub async fn heap_summary(&self, pid: u32) -> Result<Diagnostic, JvmError> {
+        let args = [pid.to_string(), String::from("GC.heap_info")];
+        Ok(Diagnostic {
+            pid,
+            tool: String::from("jcmd GC.heap_info"),
+            output: self.run(self.jcmd(), &args).await?,
+        })
+    }
+}
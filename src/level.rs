@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::nbt::{self, NbtError};
+
+#[derive(Deserialize)]
+struct LevelFile {
+    #[serde(rename = "Data")]
+    data: LevelData,
+}
+
+#[derive(Deserialize)]
+struct LevelData {
+    #[serde(rename = "LevelName")]
+    level_name: Option<String>,
+    #[serde(rename = "Version")]
+    version: Option<Version>,
+    // Where the seed is kept since 1.16...
+    #[serde(rename = "WorldGenSettings")]
+    world_gen_settings: Option<WorldGenSettings>,
+    // ...and before.
+    #[serde(rename = "RandomSeed")]
+    random_seed: Option<i64>,
+    #[serde(rename = "SpawnX")]
+    spawn_x: Option<i32>,
+    #[serde(rename = "SpawnY")]
+    spawn_y: Option<i32>,
+    #[serde(rename = "SpawnZ")]
+    spawn_z: Option<i32>,
+    #[serde(rename = "Time")]
+    time: Option<i64>,
+    #[serde(rename = "DayTime")]
+    day_time: Option<i64>,
+    #[serde(default)]
+    raining: i8,
+    #[serde(rename = "rainTime", default)]
+    rain_time: i32,
+    #[serde(default)]
+    thundering: i8,
+    #[serde(rename = "thunderTime", default)]
+    thunder_time: i32,
+    #[serde(rename = "clearWeatherTime", default)]
+    clear_weather_time: i32,
+    // Only written since 1.13.
+    #[serde(rename = "DataPacks", default)]
+    data_packs: Datapacks,
+}
+
+#[derive(Deserialize)]
+struct Version {
+    #[serde(rename = "Name")]
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct WorldGenSettings {
+    seed: i64,
+}
+
+#[derive(Serialize)]
+pub struct Weather {
+    pub raining: bool,
+    pub thundering: bool,
+    // Ticks until each changes. The clear time is what /weather clear set,
+    // and while it's counting down the others don't.
+    pub rain_time: i32,
+    pub thunder_time: i32,
+    pub clear_weather_time: i32,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Datapacks {
+    #[serde(rename(deserialize = "Enabled"), default)]
+    pub enabled: Vec<String>,
+    #[serde(rename(deserialize = "Disabled"), default)]
+    pub disabled: Vec<String>,
+}
+
+/// The settings a world was saved with, from its level.dat.
+#[derive(Serialize)]
+pub struct Level {
+    pub name: Option<String>,
+    // A string since seeds don't fit in a JavaScript number.
+    pub seed: Option<String>,
+    // The game version that last saved it. Only recorded since 1.9.
+    pub version: Option<String>,
+    pub spawn: Option<[i32; 3]>,
+    // Ticks since the world was created, and the time of day, which keeps
+    // counting past 24000 as the days go by.
+    pub time: Option<i64>,
+    pub day_time: Option<i64>,
+    pub weather: Weather,
+    pub datapacks: Datapacks,
+}
+
+pub async fn read(world_dir: &Path) -> Result<Option<Level>, NbtError> {
+    let file: LevelFile = match nbt::read_gzipped(&world_dir.join("level.dat")).await? {
+        Some(f) => f,
+        None => return Ok(None),
+    };
+    let data = file.data;
+    let spawn = match (data.spawn_x, data.spawn_y, data.spawn_z) {
+        (Some(x), Some(y), Some(z)) => Some([x, y, z]),
+        _ => None,
+    };
+    Ok(Some(Level {
+        name: data.level_name,
+        seed: data
+            .world_gen_settings
+            .map(|w| w.seed)
+            .or(data.random_seed)
+            .map(|s| s.to_string()),
+        version: data.version.map(|v| v.name),
+        spawn,
+        time: data.time,
+        day_time: data.day_time,
+        weather: Weather {
+            raining: data.raining != 0,
+            thundering: data.thundering != 0,
+            rain_time: data.rain_time,
+            thunder_time: data.thunder_time,
+            clear_weather_time: data.clear_weather_time,
+        },
+        datapacks: data.data_packs,
+    }))
+}
@@ -0,0 +1,211 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tokio::sync::OnceCell;
+use zbus::{proxy, proxy::CacheProperties, zvariant::OwnedObjectPath, Connection};
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Manager",
+    default_service = "org.freedesktop.systemd1",
+    default_path = "/org/freedesktop/systemd1"
+)]
+trait Manager {
+    fn start_unit(&self, name: &str, mode: &str) -> zbus::Result<OwnedObjectPath>;
+    fn stop_unit(&self, name: &str, mode: &str) -> zbus::Result<OwnedObjectPath>;
+    fn restart_unit(&self, name: &str, mode: &str) -> zbus::Result<OwnedObjectPath>;
+    fn load_unit(&self, name: &str) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Unit",
+    default_service = "org.freedesktop.systemd1"
+)]
+trait Unit {
+    #[zbus(property)]
+    fn load_state(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn active_state(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn sub_state(&self) -> zbus::Result<String>;
+    #[zbus(property)]
+    fn active_enter_timestamp(&self) -> zbus::Result<u64>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Service",
+    default_service = "org.freedesktop.systemd1"
+)]
+trait Service {
+    #[zbus(property, name = "MainPID")]
+    fn main_pid(&self) -> zbus::Result<u32>;
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UnitStatus {
+    pub unit: String,
+    pub active_state: String,
+    pub sub_state: String,
+    pub pid: Option<u32>,
+    pub uptime_seconds: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum LifecycleError {
+    PermissionDenied(String),
+    NoSuchUnit(String),
+    Dbus(zbus::Error),
+}
+
+impl From<zbus::Error> for LifecycleError {
+    fn from(e: zbus::Error) -> Self {
+        if let zbus::Error::MethodError(name, message, _) = &e {
+            let message = message.clone().unwrap_or_default();
+            match name.as_str() {
+                "org.freedesktop.DBus.Error.AccessDenied"
+                | "org.freedesktop.DBus.Error.InteractiveAuthorizationRequired" => {
+                    return LifecycleError::PermissionDenied(message)
+                }
+                "org.freedesktop.systemd1.NoSuchUnit" => {
+                    return LifecycleError::NoSuchUnit(message)
+                }
+                _ => {}
+            }
+        }
+        LifecycleError::Dbus(e)
+    }
+}
+
+impl fmt::Display for LifecycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LifecycleError::PermissionDenied(s) => write!(f, "permission denied: {}", s),
+            LifecycleError::NoSuchUnit(s) => write!(f, "no such unit: {}", s),
+            LifecycleError::Dbus(e) => write!(f, "d-bus error: {}", e),
+        }
+    }
+}
+
+/// Controls the server's systemd unit through the system bus.
+#[derive(Clone)]
+pub struct Lifecycle {
+    unit: String,
+    connection: Arc<OnceCell<Connection>>,
+}
+
+impl Lifecycle {
+    pub fn new(unit: String) -> Lifecycle {
+        Lifecycle {
+            unit,
+            connection: Arc::new(OnceCell::new()),
+        }
+    }
+
+    pub fn unit(&self) -> &str {
+        &self.unit
+    }
+
+    async fn connection(&self) -> Result<&Connection, LifecycleError> {
+        let connection = self
+            .connection
+            .get_or_try_init(|| async { Connection::system().await })
+            .await?;
+        Ok(connection)
+    }
+
+    async fn manager(&self) -> Result<ManagerProxy<'_>, LifecycleError> {
+        let connection = self.connection().await?;
+        Ok(ManagerProxy::new(connection).await?)
+    }
+
+    /// Queues a start job for the unit and returns the job's object path.
+    pub async fn start(&self) -> Result<String, LifecycleError> {
+        let job = self
+            .manager()
+            .await?
+            .start_unit(&self.unit, "replace")
+            .await?;
+        tracing::info!(unit = %self.unit, "queued a start");
+        Ok(job.as_str().to_owned())
+    }
+
+    pub async fn stop(&self) -> Result<String, LifecycleError> {
+        let job = self
+            .manager()
+            .await?
+            .stop_unit(&self.unit, "replace")
+            .await?;
+        tracing::info!(unit = %self.unit, "queued a stop");
+        Ok(job.as_str().to_owned())
+    }
+
+    pub async fn restart(&self) -> Result<String, LifecycleError> {
+        let job = self
+            .manager()
+            .await?
+            .restart_unit(&self.unit, "replace")
+            .await?;
+        tracing::info!(unit = %self.unit, "queued a restart");
+        Ok(job.as_str().to_owned())
+    }
+
+    /// Fails if systemd doesn't know of the unit. Loading one that doesn't
+    /// exist still succeeds, so this goes by its load state.
+    pub async fn check(&self) -> Result<(), LifecycleError> {
+        let connection = self.connection().await?;
+        let path = self.manager().await?.load_unit(&self.unit).await?;
+        let unit = UnitProxy::builder(connection)
+            .path(path)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        match unit.load_state().await?.as_str() {
+            "not-found" => Err(LifecycleError::NoSuchUnit(self.unit.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn status(&self) -> Result<UnitStatus, LifecycleError> {
+        let connection = self.connection().await?;
+        let path = self.manager().await?.load_unit(&self.unit).await?;
+
+        let unit = UnitProxy::builder(connection)
+            .path(path.clone())?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        let active_state = unit.active_state().await?;
+        let sub_state = unit.sub_state().await?;
+
+        // Both are zero while the unit isn't running.
+        let service = ServiceProxy::builder(connection)
+            .path(path)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        let pid = match service.main_pid().await? {
+            0 => None,
+            pid => Some(pid),
+        };
+        let uptime_seconds = match unit.active_enter_timestamp().await? {
+            0 => None,
+            _ if active_state != "active" => None,
+            usec => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_micros() as u64;
+                Some(now.saturating_sub(usec) / 1_000_000)
+            }
+        };
+
+        Ok(UnitStatus {
+            unit: self.unit.clone(),
+            active_state,
+            sub_state,
+            pid,
+            uptime_seconds,
+        })
+    }
+}
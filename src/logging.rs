@@ -0,0 +1,76 @@
+use serde::Deserialize;
+use tracing_subscriber::{
+    layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
+
+/// Swaps the filter when the config is reloaded.
+pub type FilterHandle = reload::Handle<EnvFilter, Registry>;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    // One JSON object per line, for log shippers.
+    Json,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LoggingConfig {
+    format: Option<LogFormat>,
+    // Levels in RUST_LOG's syntax, e.g. "info,minecraft_control::backend=debug".
+    // RUST_LOG takes precedence when it's set. "info" by default.
+    filter: Option<String>,
+}
+
+fn config_filter(filter: Option<&str>) -> Result<EnvFilter, String> {
+    match EnvFilter::try_new(filter.unwrap_or("info")) {
+        Ok(f) => Ok(f),
+        Err(e) => Err(format!("logging filter {:?} is not valid: {}", filter, e)),
+    }
+}
+
+/// Whether the filter in the config is one `init` can use, for
+/// `check::parse`.
+pub fn check(config: Option<&LoggingConfig>) -> Result<(), String> {
+    config_filter(config.and_then(|c| c.filter.as_deref())).map(|_| ())
+}
+
+/// Sets up the panel's own logs on stderr, along with `extra`, which is
+/// for the OTLP exporter.
+pub fn init(
+    config: Option<LoggingConfig>,
+    extra: Option<Box<dyn Layer<Registry> + Send + Sync>>,
+) -> Result<FilterHandle, String> {
+    let (format, filter) = match config {
+        Some(c) => (c.format.unwrap_or_default(), c.filter),
+        None => (LogFormat::default(), None),
+    };
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(f) => f,
+        Err(_) => config_filter(filter.as_deref())?,
+    };
+    let (filter, handle) = reload::Layer::new(filter);
+    let output = tracing_subscriber::fmt::layer().with_writer(std::io::stderr);
+    let output = match format {
+        LogFormat::Text => output.with_filter(filter).boxed(),
+        LogFormat::Json => output.json().with_filter(filter).boxed(),
+    };
+    let mut layers = vec![output];
+    layers.extend(extra);
+    tracing_subscriber::registry().with(layers).init();
+    Ok(handle)
+}
+
+/// Puts in the filter from a reloaded config. Returns false if RUST_LOG is
+/// set, since that takes precedence.
+pub fn reload(handle: &FilterHandle, config: Option<&LoggingConfig>) -> Result<bool, String> {
+    if std::env::var_os("RUST_LOG").is_some() {
+        return Ok(false);
+    }
+    let filter = config_filter(config.and_then(|c| c.filter.as_deref()))?;
+    match handle.reload(filter) {
+        Ok(()) => Ok(true),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::{stream::BoxStream, StreamExt};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::sync::CancellationToken;
+
+use crate::parser::{self, LogEvent};
+
+pub mod archive;
+pub mod file;
+pub mod journal;
+pub mod search;
+
+pub type LogLines = BoxStream<'static, LogEntry>;
+
+/// Syslog severities, most severe first, so a priority indexes into it.
+pub const SEVERITIES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+/// A single line of console output and what is known about it.
+#[derive(Serialize, Debug, Clone)]
+pub struct LogEntry {
+    // Milliseconds since the epoch.
+    pub timestamp: u64,
+    // Where the line came from: the unit or identifier in the journal, the
+    // file name, the container or the output stream.
+    pub source: String,
+    // Syslog severity names, from "emerg" down to "debug".
+    pub severity: &'static str,
+    pub message: String,
+    // What the line means, if the parser recognises it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<LogEvent>,
+}
+
+fn now_ms() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as u64,
+        Err(_) => 0,
+    }
+}
+
+impl LogEntry {
+    /// An entry logged now, with its severity taken from the log4j level
+    /// in the message if it has one and its event parsed out.
+    pub fn new(source: &str, message: String) -> LogEntry {
+        LogEntry {
+            timestamp: now_ms(),
+            source: source.to_owned(),
+            severity: message_severity(&message),
+            event: parser::parse(&message),
+            message,
+        }
+    }
+}
+
+/// Reads the level out of `[12:00:00] [Server thread/WARN]: ...`.
+fn message_severity(message: &str) -> &'static str {
+    let level = match message.find("]: ") {
+        Some(end) => match message[..end].rfind('/') {
+            Some(start) => &message[start + 1..end],
+            None => "",
+        },
+        None => "",
+    };
+    match level {
+        "FATAL" => "crit",
+        "ERROR" => "err",
+        "WARN" => "warning",
+        "DEBUG" | "TRACE" => "debug",
+        _ => "info",
+    }
+}
+
+/// Which entries a subscriber wants: those matching a pattern, at least as
+/// severe as a level, or both.
+#[derive(Clone, Default)]
+pub struct LogFilter {
+    pattern: Option<Regex>,
+    // Index into SEVERITIES.
+    max_severity: Option<usize>,
+}
+
+impl LogFilter {
+    pub fn new(pattern: Option<&str>, level: Option<&str>) -> Result<LogFilter, String> {
+        let pattern = match pattern {
+            Some(p) if !p.is_empty() => Some(Regex::new(p).map_err(|e| e.to_string())?),
+            _ => None,
+        };
+        let max_severity = match level {
+            Some(l) if !l.is_empty() => match SEVERITIES.iter().position(|s| *s == l) {
+                Some(i) => Some(i),
+                None => return Err(format!("unknown level {:?}", l)),
+            },
+            _ => None,
+        };
+        Ok(LogFilter {
+            pattern,
+            max_severity,
+        })
+    }
+
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        if let Some(max) = self.max_severity {
+            match SEVERITIES.iter().position(|s| *s == entry.severity) {
+                Some(i) if i <= max => {}
+                _ => return false,
+            }
+        }
+        match &self.pattern {
+            Some(p) => p.is_match(&entry.message),
+            None => true,
+        }
+    }
+}
+
+/// Overrides where a backend's live log comes from.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSourceKind {
+    Journal,
+    File,
+}
+
+/// Somewhere live console lines come from. Every backend has one, and
+/// whatever it produces ends up on the same broadcast channel.
+pub trait LogSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Entries from now on. The stream only ends if the source can't be
+    /// read any longer.
+    fn lines(&self) -> LogLines;
+}
+
+/// The last lines that were broadcast, so new subscribers can catch up.
+#[derive(Clone)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<VecDeque<LogEntry>>>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> LogBuffer {
+        LogBuffer {
+            lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Remembers an entry and broadcasts it. Both happen under the lock so
+    /// a subscriber sees every entry exactly once.
+    fn publish(
+        &self,
+        tx: &broadcast::Sender<LogEntry>,
+        line: LogEntry,
+    ) -> Result<usize, broadcast::error::SendError<LogEntry>> {
+        let mut lines = self.lines.lock().unwrap();
+        if self.capacity > 0 {
+            if lines.len() == self.capacity {
+                lines.pop_front();
+            }
+            lines.push_back(line.clone());
+        }
+        tx.send(line)
+    }
+
+    /// The buffered entries, oldest first, and a receiver for what follows.
+    pub fn subscribe(
+        &self,
+        tx: &broadcast::Sender<LogEntry>,
+    ) -> (Vec<LogEntry>, broadcast::Receiver<LogEntry>) {
+        let lines = self.lines.lock().unwrap();
+        (lines.iter().cloned().collect(), tx.subscribe())
+    }
+}
+
+/// Feeds a source into the channel the WebSocket handlers subscribe to
+/// until `shutdown` is cancelled. Dropping the stream then stops whatever
+/// task or thread the source reads from. The flag returned stays true
+/// while the source is being read.
+pub fn forward(
+    source: Box<dyn LogSource>,
+    tx: broadcast::Sender<LogEntry>,
+    buffer: LogBuffer,
+    shutdown: CancellationToken,
+) -> Arc<AtomicBool> {
+    let reading = Arc::new(AtomicBool::new(true));
+    let running = reading.clone();
+    tokio::spawn(async move {
+        tracing::info!(source = %source.name(), "reading logs");
+        let _ = buffer.publish(&tx, LogEntry::new("panel", "starting up".to_owned()));
+        let mut lines = source.lines();
+        loop {
+            let line = tokio::select! {
+                line = lines.next() => line,
+                _ = shutdown.cancelled() => break,
+            };
+            match line {
+                Some(line) => {
+                    // How far behind the source the panel is, mostly for
+                    // the journal.
+                    let lag_ms = now_ms().saturating_sub(line.timestamp);
+                    let span = tracing::debug_span!("log_entry", source = %source.name(), lag_ms);
+                    if let Err(e) = span.in_scope(|| buffer.publish(&tx, line)) {
+                        tracing::warn!(error = %e, "could not publish a log line");
+                    }
+                }
+                None => break,
+            }
+        }
+        tracing::info!(source = %source.name(), "log source ended");
+        running.store(false, Ordering::Relaxed);
+    });
+    reading
+}
+
+/// Turns the receiving end of a channel into a stream, for sources that
+/// produce entries from a task or thread of their own.
+pub fn channel_lines(rx: mpsc::Receiver<LogEntry>) -> LogLines {
+    futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|line| (line, rx))
+    })
+    .boxed()
+}
+
+/// Entries that something else publishes on a broadcast channel, like the
+/// output of a child process.
+pub struct PipeSource {
+    tx: broadcast::Sender<LogEntry>,
+}
+
+impl PipeSource {
+    pub fn new(tx: broadcast::Sender<LogEntry>) -> PipeSource {
+        PipeSource { tx }
+    }
+}
+
+impl LogSource for PipeSource {
+    fn name(&self) -> &'static str {
+        "pipe"
+    }
+
+    fn lines(&self) -> LogLines {
+        futures::stream::unfold(self.tx.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(line) => return Some((line, rx)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+}
@@ -0,0 +1,73 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use async_compression::tokio::bufread::GzipDecoder;
+use futures::StreamExt;
+use serde::Serialize;
+use tokio::io::BufReader;
+use tokio_util::io::ReaderStream;
+
+use crate::backend::LogStream;
+
+/// A log the server rotated out and compressed, like
+/// `logs/2024-10-01-1.log.gz`.
+#[derive(Serialize)]
+pub struct ArchivedLog {
+    pub name: String,
+    // Compressed size in bytes.
+    pub size: u64,
+    // Seconds since the epoch.
+    pub modified: u64,
+}
+
+/// The archived logs in `dir`, newest first.
+pub async fn list(dir: &Path) -> Result<Vec<ArchivedLog>, io::Error> {
+    let mut logs = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !is_archive_name(&name) {
+            continue;
+        }
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified = match metadata.modified().map(|m| m.duration_since(UNIX_EPOCH)) {
+            Ok(Ok(d)) => d.as_secs(),
+            _ => 0,
+        };
+        logs.push(ArchivedLog {
+            name,
+            size: metadata.len(),
+            modified,
+        });
+    }
+    logs.sort_by(|a, b| b.modified.cmp(&a.modified).then(b.name.cmp(&a.name)));
+    Ok(logs)
+}
+
+/// Where an archived log is, if `name` is one and doesn't try to leave
+/// `dir`.
+pub fn path(dir: &Path, name: &str) -> Option<PathBuf> {
+    if !is_archive_name(name) || name.contains('/') || name.contains('\\') || name.contains("..") {
+        return None;
+    }
+    Some(dir.join(name))
+}
+
+/// An archived log, decompressed as it's read.
+pub async fn open(dir: &Path, name: &str) -> Result<LogStream, io::Error> {
+    let path = match path(dir, name) {
+        Some(p) => p,
+        None => return Err(io::Error::new(io::ErrorKind::NotFound, name.to_owned())),
+    };
+    let file = tokio::fs::File::open(path).await?;
+    let decoder = GzipDecoder::new(BufReader::new(file));
+    Ok(ReaderStream::new(decoder).boxed())
+}
+
+fn is_archive_name(name: &str) -> bool {
+    name.ends_with(".log.gz")
+}
@@ -0,0 +1,178 @@
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify::{event::ModifyKind, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncSeekExt, BufReader},
+    sync::mpsc::{self, Sender, UnboundedReceiver},
+};
+
+use super::{channel_lines, LogEntry, LogLines, LogSource};
+
+/// A log file such as latest.log, followed like `tail -F`.
+pub struct FileSource {
+    path: PathBuf,
+}
+
+impl FileSource {
+    pub fn new(path: PathBuf) -> FileSource {
+        FileSource { path }
+    }
+}
+
+impl LogSource for FileSource {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn lines(&self) -> LogLines {
+        let (tx, rx) = mpsc::channel(256);
+        tokio::spawn(tail(self.path.clone(), tx));
+        channel_lines(rx)
+    }
+}
+
+enum Change {
+    Written,
+    // The file was removed, renamed or created anew.
+    Replaced,
+    Closed,
+}
+
+/// Watches the directory rather than the file so a new latest.log is
+/// noticed after rotation.
+fn watch(
+    path: &Path,
+) -> Result<(RecommendedWatcher, UnboundedReceiver<notify::Result<Event>>), notify::Error> {
+    let (events_tx, events) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = events_tx.send(event);
+    })?;
+    let dir = match path.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    Ok((watcher, events))
+}
+
+/// Waits for the next change to the file. If watching failed this falls
+/// back to checking every second.
+async fn next_change(
+    path: &Path,
+    events: &mut Option<UnboundedReceiver<notify::Result<Event>>>,
+) -> Change {
+    let events = match events {
+        Some(e) => e,
+        None => {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            return Change::Written;
+        }
+    };
+    loop {
+        // Also wake up now and then in case an event was missed.
+        let event = match tokio::time::timeout(Duration::from_secs(5), events.recv()).await {
+            Ok(Some(Ok(e))) => e,
+            Ok(Some(Err(e))) => {
+                tracing::warn!(path = %path.display(), error = %e, "error watching the log file");
+                continue;
+            }
+            Ok(None) => return Change::Closed,
+            Err(_) => return Change::Written,
+        };
+        if !event
+            .paths
+            .iter()
+            .any(|p| p.file_name() == path.file_name())
+        {
+            continue;
+        }
+        return match event.kind {
+            EventKind::Create(_)
+            | EventKind::Remove(_)
+            | EventKind::Modify(ModifyKind::Name(_)) => Change::Replaced,
+            EventKind::Modify(_) => Change::Written,
+            _ => continue,
+        };
+    }
+}
+
+/// Follows a log file from its current end, starting over when it's
+/// truncated or replaced, as the server does with latest.log on startup.
+async fn tail(path: PathBuf, tx: Sender<LogEntry>) {
+    let source = match path.file_name() {
+        Some(n) => n.to_string_lossy().into_owned(),
+        None => String::from("file"),
+    };
+    // The watcher stops when dropped, so it's kept for as long as this runs.
+    let (_watcher, mut events) = match watch(&path) {
+        Ok((w, e)) => (Some(w), Some(e)),
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "could not watch the log file, polling instead");
+            (None, None)
+        }
+    };
+
+    // Only skip what's already there the first time; a new file is read
+    // from the start.
+    let mut from_end = true;
+    loop {
+        let file = match tokio::fs::File::open(&path).await {
+            Ok(f) => f,
+            Err(_) => {
+                from_end = false;
+                if let Change::Closed = next_change(&path, &mut events).await {
+                    return;
+                }
+                continue;
+            }
+        };
+        let mut reader = BufReader::new(file);
+        let start = if from_end {
+            SeekFrom::End(0)
+        } else {
+            SeekFrom::Start(0)
+        };
+        let mut position = match reader.seek(start).await {
+            Ok(p) => p,
+            Err(_) => continue,
+        };
+        from_end = false;
+        let mut line = String::new();
+        loop {
+            match reader.read_line(&mut line).await {
+                Ok(0) => match next_change(&path, &mut events).await {
+                    Change::Replaced => break,
+                    Change::Closed => return,
+                    Change::Written => {
+                        let len = match tokio::fs::metadata(&path).await {
+                            Ok(m) => m.len(),
+                            Err(_) => 0,
+                        };
+                        // A shorter file means it was truncated.
+                        if len < position {
+                            break;
+                        }
+                    }
+                },
+                Ok(n) => {
+                    position += n as u64;
+                    // Wait for the rest of a partially written line.
+                    if !line.ends_with('\n') {
+                        continue;
+                    }
+                    let entry = LogEntry::new(&source, line.trim_end().to_owned());
+                    if tx.send(entry).await.is_err() {
+                        return;
+                    }
+                    line.clear();
+                }
+                Err(e) => {
+                    tracing::warn!(path = %path.display(), error = %e, "could not read the log file");
+                    break;
+                }
+            }
+        }
+    }
+}
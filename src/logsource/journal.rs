@@ -0,0 +1,237 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use ::systemd::{journal, Journal};
+use serde::Serialize;
+use tokio::sync::mpsc::{self, Sender};
+
+use super::search::{SearchMatch, SearchTerms};
+use super::{channel_lines, LogEntry, LogLines, LogSource, SEVERITIES};
+
+/// Which journal entries belong to a server. Each kind of match is OR'd
+/// within itself and AND'd with the others, as journalctl does.
+#[derive(Debug, Clone)]
+pub struct JournalFilter {
+    pub units: Vec<String>,
+    pub identifiers: Vec<String>,
+    // Only entries at least this important, 0 (emerg) to 7 (debug).
+    pub max_priority: Option<u8>,
+}
+
+impl JournalFilter {
+    /// Adds the filter as journal matches so sd-journal skips everything
+    /// else itself.
+    fn apply(&self, j: &mut Journal) -> Result<(), std::io::Error> {
+        for unit in &self.units {
+            j.match_add("_SYSTEMD_UNIT", unit.as_str())?;
+        }
+        for identifier in &self.identifiers {
+            j.match_add("SYSLOG_IDENTIFIER", identifier.as_str())?;
+        }
+        if let Some(max) = self.max_priority {
+            for priority in 0..=max.min(7) {
+                j.match_add("PRIORITY", priority.to_string())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Messages logged to the journal by a server's units.
+pub struct JournalSource {
+    filter: JournalFilter,
+}
+
+impl JournalSource {
+    pub fn new(filter: JournalFilter) -> JournalSource {
+        JournalSource { filter }
+    }
+}
+
+impl LogSource for JournalSource {
+    fn name(&self) -> &'static str {
+        "journal"
+    }
+
+    fn lines(&self) -> LogLines {
+        let (tx, rx) = mpsc::channel(256);
+        let filter = self.filter.clone();
+        let _ = tokio::task::spawn_blocking(move || read_journal(tx, filter));
+        channel_lines(rx)
+    }
+}
+
+#[derive(Serialize)]
+pub struct JournalEntry {
+    pub cursor: String,
+    // Milliseconds since the epoch.
+    pub timestamp: u64,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct JournalPage {
+    // Oldest first.
+    pub entries: Vec<JournalEntry>,
+    // Pass as `cursor` to get the entries before these, if there are any.
+    pub next: Option<String>,
+}
+
+/// Reads up to `limit` matching entries that come before `cursor`, or the
+/// latest ones without it. This blocks, so call it from `spawn_blocking`.
+pub fn history(
+    filter: &JournalFilter,
+    cursor: Option<&str>,
+    limit: usize,
+) -> Result<JournalPage, std::io::Error> {
+    let mut j: Journal = journal::OpenOptions::default().open()?;
+    filter.apply(&mut j)?;
+    match cursor {
+        Some(c) => j.seek_cursor(c)?,
+        None => j.seek_tail()?,
+    };
+
+    let mut entries = Vec::new();
+    while entries.len() < limit {
+        let entry = match j.previous_entry()? {
+            Some(e) => e,
+            None => break,
+        };
+        let entry_cursor = j.cursor()?;
+        // Seeking to a cursor lands on that entry, which the caller has
+        // already seen.
+        if Some(entry_cursor.as_str()) == cursor {
+            continue;
+        }
+        let timestamp = match j.timestamp()?.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_millis() as u64,
+            Err(_) => 0,
+        };
+        entries.push(JournalEntry {
+            cursor: entry_cursor,
+            timestamp,
+            message: match entry.get("MESSAGE") {
+                Some(m) => m.to_owned(),
+                None => String::new(),
+            },
+        });
+    }
+
+    let next = if entries.len() == limit {
+        entries.last().map(|e| e.cursor.clone())
+    } else {
+        None
+    };
+    entries.reverse();
+    Ok(JournalPage { entries, next })
+}
+
+/// Reads up to `limit` entries that match, oldest first, starting at
+/// `terms.from`. This blocks, so call it from `spawn_blocking`.
+pub fn search(
+    filter: &JournalFilter,
+    terms: &SearchTerms,
+    limit: usize,
+) -> Result<Vec<SearchMatch>, std::io::Error> {
+    let mut j: Journal = journal::OpenOptions::default().open()?;
+    filter.apply(&mut j)?;
+    match terms.from {
+        Some(from) => j.seek(journal::JournalSeek::ClockRealtime { usec: from * 1000 })?,
+        None => j.seek(journal::JournalSeek::Head)?,
+    };
+
+    let mut matches = Vec::new();
+    while matches.len() < limit {
+        let entry = match j.next_entry()? {
+            Some(e) => e,
+            None => break,
+        };
+        let timestamp = match j.timestamp()?.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_millis() as u64,
+            Err(_) => 0,
+        };
+        if terms.to.map_or(false, |to| timestamp > to) {
+            break;
+        }
+        let message = match entry.get("MESSAGE") {
+            Some(m) => m.to_owned(),
+            None => continue,
+        };
+        if !terms.is_match(&message) {
+            continue;
+        }
+        matches.push(SearchMatch {
+            source: String::from("journal"),
+            offset: None,
+            cursor: Some(j.cursor()?),
+            timestamp: Some(timestamp),
+            line: message,
+        });
+    }
+    Ok(matches)
+}
+
+/// Follows the journal on a thread of its own, since sd-journal is blocking.
+/// sd_journal_wait wakes it as soon as something is logged, and it stops
+/// once the receiving end is dropped.
+fn read_journal(tx: Sender<LogEntry>, filter: JournalFilter) {
+    tracing::info!("opening the journal");
+    let mut j: Journal = match journal::OpenOptions::default().open() {
+        Ok(j) => j,
+        Err(e) => {
+            tracing::error!(error = %e, "could not open the journal");
+            return;
+        }
+    };
+    if let Err(e) = filter.apply(&mut j) {
+        tracing::error!(error = %e, "could not filter the journal");
+        return;
+    }
+    let _ = j.seek_tail();
+    let _ = j.previous();
+
+    while !tx.is_closed() {
+        match j.next_entry() {
+            Ok(Some(entry)) => {
+                let message = match entry.get("MESSAGE") {
+                    Some(value) => value,
+                    None => &"".to_owned(),
+                };
+                let source = match entry
+                    .get("SYSLOG_IDENTIFIER")
+                    .or_else(|| entry.get("_SYSTEMD_UNIT"))
+                {
+                    Some(value) => value.as_str(),
+                    None => "journal",
+                };
+                let mut log_entry = LogEntry::new(source, message.to_owned());
+                if let Ok(t) = j.timestamp() {
+                    if let Ok(d) = t.duration_since(UNIX_EPOCH) {
+                        log_entry.timestamp = d.as_millis() as u64;
+                    }
+                }
+                // The journal's own priority beats guessing from the text.
+                if let Some(p) = entry.get("PRIORITY").and_then(|p| p.parse::<usize>().ok()) {
+                    if let Some(severity) = SEVERITIES.get(p) {
+                        log_entry.severity = severity;
+                    }
+                }
+
+                // Nobody is reading any more.
+                if tx.blocking_send(log_entry).is_err() {
+                    return;
+                }
+            }
+            Ok(None) => {
+                // Bounded so a closed channel is noticed while it's quiet.
+                if let Err(e) = j.wait(Some(Duration::from_millis(500))) {
+                    tracing::error!(error = %e, "could not wait for the journal");
+                    return;
+                }
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "could not read the journal");
+                return;
+            }
+        }
+    }
+}
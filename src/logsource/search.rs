@@ -0,0 +1,212 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use async_compression::tokio::bufread::GzipDecoder;
+use chrono::{Local, NaiveDate, NaiveTime, TimeZone};
+use regex::Regex;
+use serde::Serialize;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+
+use super::archive;
+
+/// What to look for. A line has to match both the plain text, which is
+/// case-insensitive, and the regex if both are given.
+pub struct SearchTerms {
+    text: Option<(String, Regex)>,
+    regex: Option<Regex>,
+    // Milliseconds since the epoch, inclusive.
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+impl SearchTerms {
+    pub fn new(
+        text: Option<&str>,
+        regex: Option<&str>,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> Result<SearchTerms, String> {
+        let text = match text {
+            Some(t) if !t.is_empty() => {
+                Some(Regex::new(&format!("(?i){}", regex::escape(t))).map_err(|e| e.to_string())?)
+            }
+            _ => None,
+        };
+        let regex = match regex {
+            Some(r) if !r.is_empty() => Some(Regex::new(r).map_err(|e| e.to_string())?),
+            _ => None,
+        };
+        if text.is_none() && regex.is_none() {
+            return Err(String::from("either q or regex is required"));
+        }
+        Ok(SearchTerms {
+            text,
+            regex,
+            from,
+            to,
+        })
+    }
+
+    /// The plain text being looked for, if any.
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_ref().map(|(t, _)| t.as_str())
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        let text = match &self.text {
+            Some((_, t)) => t.is_match(line),
+            None => true,
+        };
+        let regex = match &self.regex {
+            Some(r) => r.is_match(line),
+            None => true,
+        };
+        text && regex
+    }
+
+    /// Whether a line at `timestamp` is in range. Lines without one are
+    /// only kept when no range was asked for.
+    pub fn in_range(&self, timestamp: Option<u64>) -> bool {
+        match timestamp {
+            Some(t) => self.from.map_or(true, |f| t >= f) && self.to.map_or(true, |to| t <= to),
+            None => self.from.is_none() && self.to.is_none(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SearchResults {
+    // Oldest first.
+    pub matches: Vec<SearchMatch>,
+    // There were more matches than the limit.
+    pub truncated: bool,
+}
+
+#[derive(Serialize)]
+pub struct SearchMatch {
+    // The file name, or "journal".
+    pub source: String,
+    // Where the line starts in the file, decompressed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+    // The journal cursor, for journal entries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    // Milliseconds since the epoch, if the line has a time on it.
+    pub timestamp: Option<u64>,
+    pub line: String,
+}
+
+/// The files worth searching in `dir`, oldest first: the archived logs
+/// and then latest.log.
+pub async fn log_files(dir: &Path, terms: &SearchTerms) -> Result<Vec<PathBuf>, io::Error> {
+    let mut archived = archive::list(dir).await?;
+    archived.reverse();
+    let mut files: Vec<PathBuf> = archived
+        .into_iter()
+        .filter(|log| match file_date(&log.name) {
+            // Rotated logs are named after the day they were written.
+            Some(date) => {
+                let (start, end) = day_range(date);
+                terms.from.map_or(true, |f| end >= f) && terms.to.map_or(true, |t| start <= t)
+            }
+            None => true,
+        })
+        .filter_map(|log| archive::path(dir, &log.name))
+        .collect();
+    let latest = dir.join("latest.log");
+    if tokio::fs::try_exists(&latest).await? {
+        files.push(latest);
+    }
+    Ok(files)
+}
+
+/// Adds the lines in `path` that match to `matches`, up to `limit` of them
+/// in total.
+pub async fn search_file(
+    path: &Path,
+    terms: &SearchTerms,
+    matches: &mut Vec<SearchMatch>,
+    limit: usize,
+) -> Result<(), io::Error> {
+    let name = match path.file_name() {
+        Some(n) => n.to_string_lossy().into_owned(),
+        None => return Ok(()),
+    };
+    let file = tokio::fs::File::open(path).await?;
+    let date = match file_date(&name) {
+        Some(d) => Some(d),
+        // latest.log is from whenever it was last written to.
+        None => match file.metadata().await.and_then(|m| m.modified()) {
+            Ok(t) => Some(chrono::DateTime::<Local>::from(t).date_naive()),
+            Err(_) => None,
+        },
+    };
+    let reader: Box<dyn AsyncBufRead + Unpin + Send> = if name.ends_with(".gz") {
+        Box::new(BufReader::new(GzipDecoder::new(BufReader::new(file))))
+    } else {
+        Box::new(BufReader::new(file))
+    };
+    search_lines(reader, &name, date, terms, matches, limit).await
+}
+
+async fn search_lines(
+    mut reader: Box<dyn AsyncBufRead + Unpin + Send>,
+    name: &str,
+    date: Option<NaiveDate>,
+    terms: &SearchTerms,
+    matches: &mut Vec<SearchMatch>,
+    limit: usize,
+) -> Result<(), io::Error> {
+    let mut offset = 0;
+    let mut buf = Vec::new();
+    while matches.len() < limit {
+        buf.clear();
+        let n = reader.read_until(b'\n', &mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        let start = offset;
+        offset += n as u64;
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end();
+        if !terms.is_match(line) {
+            continue;
+        }
+        let timestamp = date.and_then(|d| line_timestamp(d, line));
+        if !terms.in_range(timestamp) {
+            continue;
+        }
+        matches.push(SearchMatch {
+            source: name.to_owned(),
+            offset: Some(start),
+            cursor: None,
+            timestamp,
+            line: line.to_owned(),
+        });
+    }
+    Ok(())
+}
+
+/// The day in a rotated log's name, `2024-10-01-1.log.gz`.
+fn file_date(name: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(name.get(..10)?, "%Y-%m-%d").ok()
+}
+
+/// The first and last millisecond of a day in local time.
+fn day_range(date: NaiveDate) -> (u64, u64) {
+    let start = local_millis(date, NaiveTime::MIN).unwrap_or(0);
+    (start, start + 24 * 60 * 60 * 1000 - 1)
+}
+
+/// Combines the `[12:00:00]` at the start of a line with the day it was
+/// logged on. The server writes it in local time.
+fn line_timestamp(date: NaiveDate, line: &str) -> Option<u64> {
+    let time = NaiveTime::parse_from_str(line.strip_prefix('[')?.get(..8)?, "%H:%M:%S").ok()?;
+    local_millis(date, time)
+}
+
+fn local_millis(date: NaiveDate, time: NaiveTime) -> Option<u64> {
+    let t = Local.from_local_datetime(&date.and_time(time)).earliest()?;
+    u64::try_from(t.timestamp_millis()).ok()
+}
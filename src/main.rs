@@ -1,85 +1,315 @@
-use std::io::Error as IoError;
+use std::io::{Error as IoError, Write};
 use std::net::SocketAddr;
 use std::path::Path;
 
+use std::sync::Arc;
+use std::time::Duration;
+
+use auth::Auth;
 use axum::{
-    body::Body,
-    extract::{
-        ws::{Message, WebSocket},
-        ConnectInfo, Request, State, WebSocketUpgrade,
-    },
-    http::{header, HeaderMap, StatusCode, Version},
+    extract::{FromRef, Request, State},
+    http::{header, HeaderValue, StatusCode},
     middleware::Next,
-    response::{IntoResponse, Redirect, Response},
-    routing::{any, get, get_service, post},
-    Router,
+    response::{Redirect, Response},
+    routing::{get, post},
+    Extension, Json, Router,
 };
-use axum_extra::{headers, TypedHeader};
 use axum_server::tls_rustls::RustlsConfig;
-use futures::{SinkExt, StreamExt};
-use minecraft::MinecraftControl;
-use serde::Deserialize;
-use tokio::{fs, sync::broadcast::Receiver};
+use clap::Parser;
+use minecraft::{MinecraftConfig, MinecraftControl};
+use serde::{Deserialize, Serialize};
+use server::Layout;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio_tungstenite::tungstenite::Result;
+use tokio_util::sync::CancellationToken;
 use tower_http::{
     compression::CompressionLayer, decompression::RequestDecompressionLayer, services::ServeDir,
+    trace::TraceLayer,
 };
+use utoipa::ToSchema;
 
+mod access_log;
+mod auth;
+mod backend;
+mod backup;
+mod bans;
+mod bedrock;
+mod check;
+mod cli;
+mod commands;
+mod console;
+mod crash;
+mod disk;
+mod error;
+mod events;
+mod gamerules;
+mod gc;
+mod history;
+mod idle;
+mod jvm;
+mod level;
+mod lifecycle;
+mod logging;
+mod logsource;
+mod maintenance;
+mod metrics;
 mod minecraft;
+mod modrinth;
+mod mods;
+mod mojang;
+mod motd;
+mod nbt;
+mod notifications;
+mod oidc;
+mod openapi;
+mod parser;
+mod ping;
+mod playerdata;
+mod players;
+mod policy;
+mod properties;
+mod protocol;
+mod query;
+mod queue;
+mod rcon;
+mod reload;
+mod resources;
+mod scheduler;
+mod server;
+mod sessions;
+mod stats;
+mod telemetry;
+mod totp;
+mod tps;
+mod updater;
+mod util;
+mod wake;
+mod watchdog;
+mod worlds;
+
+// How long requests still in flight get to finish once the panel is told
+// to stop, and then how long the log history gets to be written.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
 
 #[derive(Deserialize, Debug, Clone)]
 struct AppConfig {
-    minecraft: Option<minecraft::MinecraftConfig>,
+    minecraft: Option<ServerConfigs>,
     webserver: Option<WebserverConfig>,
+    auth: Option<auth::AuthConfig>,
+    history: Option<history::HistoryConfig>,
+    notifications: Option<notifications::NotificationsConfig>,
+    mojang: Option<mojang::MojangConfig>,
+    modrinth: Option<modrinth::ModrinthConfig>,
+    telemetry: Option<telemetry::TelemetryConfig>,
+    logging: Option<logging::LoggingConfig>,
+    commands: Option<policy::CommandsConfig>,
+}
+
+// Either a single `[minecraft]` table or a list of `[[minecraft]]` tables.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum ServerConfigs {
+    One(MinecraftConfig),
+    Many(Vec<MinecraftConfig>),
+}
+
+impl AppConfig {
+    fn servers(&self) -> Vec<MinecraftConfig> {
+        match &self.minecraft {
+            Some(ServerConfigs::One(c)) => vec![c.clone()],
+            Some(ServerConfigs::Many(c)) => c.clone(),
+            None => vec![MinecraftConfig::default()],
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
 struct WebserverConfig {
     bluemaps_path: Option<String>,
     cert_path: Option<String>,
+    ws_ping_interval_secs: Option<u64>,
+    ws_idle_timeout_secs: Option<u64>,
+    access_log: Option<access_log::AccessLogConfig>,
+    // Serves Swagger UI at /api/v1/docs. Off by default.
+    swagger_ui: Option<bool>,
 }
 
 #[derive(Clone)]
 struct AppState {
     config: WebserverConfig,
-    control: MinecraftControl,
+    servers: Arc<Vec<MinecraftControl>>,
+    auth: Auth,
+    metrics: metrics::Metrics,
+    reloader: reload::Reloader,
+}
+
+#[derive(Serialize)]
+struct ServerReadiness {
+    // Left out of /readyz, like the errors, since that's open to anyone.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    // Whether the log source is still being read.
+    log: bool,
+    // Whether the backend answers.
+    backend: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backend_error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    ready: bool,
+    // Whether the config would load if the panel were started again.
+    config: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config_error: Option<String>,
+    servers: Vec<ServerReadiness>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ServerSummary {
+    name: String,
+    #[schema(value_type = String)]
+    backend: &'static str,
+    unit: String,
+}
+
+impl FromRef<AppState> for Auth {
+    fn from_ref(state: &AppState) -> Auth {
+        state.auth.clone()
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), IoError> {
-    let file = fs::read_to_string("config.toml").await.unwrap();
-    let config: AppConfig = toml::from_str(&file).unwrap();
+    let cli = cli::Cli::parse();
+    let raw = match cli::read_config(&cli.config).await {
+        Ok(r) => r,
+        Err(e) => fail(&[format!("could not read {}: {}", cli.config.display(), e)]),
+    };
+    let config = match check::parse(&raw) {
+        Ok(c) => c,
+        Err(problems) => fail(&problems),
+    };
+    if cli.check {
+        let problems = check::run(&config, cli.no_tls).await;
+        if !problems.is_empty() {
+            fail(&problems);
+        }
+        let _ = writeln!(
+            std::io::stdout(),
+            "{}: no problems found",
+            cli.config.display()
+        );
+        return Ok(());
+    }
+    let (otlp, tracer_provider) = match telemetry::init(config.telemetry.clone()) {
+        Ok(Some((layer, provider))) => (Some(layer), Some(provider)),
+        Ok(None) => (None, None),
+        Err(e) => fail(&[format!("telemetry: {}", e)]),
+    };
+    let filter = match logging::init(config.logging.clone(), otlp) {
+        Ok(f) => f,
+        Err(e) => fail(&[format!("logging: {}", e)]),
+    };
 
-    let control = minecraft::init(config.minecraft);
+    let history = match config.history.clone() {
+        Some(c) => match history::open(c) {
+            Ok(h) => Some(h),
+            Err(e) => fail(&[format!("could not open the log history: {}", e)]),
+        },
+        None => None,
+    };
+    // Checked by check::parse already.
+    let commands = match policy::CommandRules::new(config.commands.as_ref()) {
+        Ok(r) => r,
+        Err(e) => fail(&[format!("commands: {}", e)]),
+    };
+    commands.warn_own_commands();
+    let rate_limit = config.commands.as_ref().and_then(|c| c.rate_limit.clone());
+    let events = events::init();
+    // Stops the background tasks of every server.
+    let shutdown = CancellationToken::new();
+    let mut servers: Vec<MinecraftControl> = Vec::new();
+    for c in config.servers() {
+        let name = c.name().to_owned();
+        match minecraft::init(
+            c,
+            history.clone(),
+            commands.clone(),
+            rate_limit.clone(),
+            events.clone(),
+            shutdown.clone(),
+        ) {
+            Ok(s) => servers.push(s),
+            Err(e) => fail(&[format!("minecraft.{}: {}", name, e)]),
+        }
+    }
 
     let webconfig: WebserverConfig = match config.webserver {
         Some(c) => c,
         None => WebserverConfig {
             bluemaps_path: None,
             cert_path: None,
+            ws_ping_interval_secs: None,
+            ws_idle_timeout_secs: None,
+            access_log: None,
+            swagger_ui: None,
         },
     };
+    let keepalive = console::KeepAlive {
+        interval: Duration::from_secs(webconfig.ws_ping_interval_secs.unwrap_or(30)),
+        timeout: Duration::from_secs(webconfig.ws_idle_timeout_secs.unwrap_or(90)),
+    };
+    let mojang = mojang::init(config.mojang);
+    let modrinth = modrinth::init(config.modrinth);
+    let auth = match auth::init(config.auth, webconfig.cert_path.is_some()).await {
+        Ok(a) => a,
+        Err(e) => fail(&[format!(
+            "could not read the auth tokens, users or two-factor enrollments: {}",
+            e
+        )]),
+    };
+    let metrics = metrics::Metrics::default();
+    let access_log = match access_log::init(webconfig.access_log.clone()) {
+        Ok(a) => a,
+        Err(e) => fail(&[format!("webserver.access_log: {}", e)]),
+    };
+    let servers = Arc::new(servers);
+    let reloader = reload::Reloader::new(
+        cli.config.clone(),
+        raw,
+        config.notifications,
+        auth.clone(),
+        events.clone(),
+        servers.clone(),
+        filter,
+        shutdown.clone(),
+    );
+    reloader.watch();
     let state = AppState {
         config: webconfig,
-        control,
+        servers,
+        auth: auth.clone(),
+        metrics: metrics.clone(),
+        reloader,
     };
 
     let ssl_config: Option<RustlsConfig> = match &state.config.cert_path {
-        Some(p) => {
-            let cert = Path::new(p).join("fullchain.pem");
-            let key = Path::new(p).join("key.pem");
-            Some(RustlsConfig::from_pem_file(cert, key).await.unwrap())
-        }
-        None => None,
+        Some(p) if !cli.no_tls => match load_certificate(p).await {
+            Ok(c) => Some(c),
+            Err(e) => fail(&[e]),
+        },
+        _ => None,
     };
 
     let assets_dir = Path::new(".").join("assets");
-    println!("assets directory: {}", assets_dir.display());
+    tracing::info!("assets directory: {}", assets_dir.display());
 
-    let map_routes = match &state.config.bluemaps_path {
+    let map_routes: Router<AppState> = match &state.config.bluemaps_path {
         Some(p) => {
             let path = Path::new("/").join(p);
-            println!("{}", path.display());
+            tracing::info!("serving BlueMap from {}", path.display());
             Router::new()
                 .route("/map", get(|| async { Redirect::permanent("/map/") }))
                 .nest_service(
@@ -92,105 +322,302 @@ async fn main() -> Result<(), IoError> {
         None => Router::new(),
     };
 
-    let app = Router::new()
+    let account_routes: Router<AppState> = Router::new()
+        .route("/servers", get(servers_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/auth/me", get(auth::me))
+        .route("/auth/ws-ticket", post(auth::ws_ticket))
+        .route("/auth/2fa/status", get(auth::two_factor_status))
+        .route("/auth/2fa/enroll", post(auth::two_factor_enroll))
+        .route("/auth/2fa/confirm", post(auth::two_factor_confirm))
+        .route("/auth/2fa/disable", post(auth::two_factor_disable))
+        .route("/api/v1/servers", get(servers_handler))
+        .merge(
+            Router::new()
+                .route("/api/config/reload-status", get(reload_status_handler))
+                .route("/api/v1/config/reload-status", get(reload_status_handler))
+                .route("/api/v1/readiness", get(readiness_handler))
+                .route_layer(axum::middleware::from_fn_with_state(
+                    auth::Role::Admin,
+                    auth::require_role,
+                )),
+        );
+
+    // The first server also keeps the original unprefixed routes.
+    let mut legacy_routes: Router<AppState> =
+        server::routes(Layout::Legacy).with_state(state.servers[0].clone());
+    let mut server_routes: Router<AppState> = Router::new();
+    for control in state.servers.iter() {
+        legacy_routes = legacy_routes.nest(
+            &format!("/servers/{}", control.name()),
+            server::routes(Layout::Legacy).with_state(control.clone()),
+        );
+        server_routes = server_routes.nest(
+            &format!("/api/v1/servers/{}", control.name()),
+            server::routes(Layout::V1).with_state(control.clone()),
+        );
+    }
+    let server_routes = server_routes.merge(legacy_routes.layer(
+        axum::middleware::from_fn_with_state(state.servers[0].name().to_owned(), deprecated),
+    ));
+
+    let control_routes: Router<AppState> = Router::new()
+        .merge(account_routes)
+        .merge(server_routes)
+        .route_layer(axum::middleware::from_fn_with_state(
+            auth.clone(),
+            auth::require_auth,
+        ));
+
+    let auth_routes: Router<AppState> = Router::new()
+        .route("/healthz", get(|| async { "ok" }))
+        .route("/readyz", get(ready_handler))
+        .route("/auth/login", post(auth::login))
+        .route("/auth/logout", post(auth::logout))
+        .route("/auth/2fa/verify", post(auth::two_factor_verify))
+        .route("/auth/providers", get(auth::providers))
+        .route("/auth/oidc/login", get(auth::oidc_login))
+        .route("/auth/oidc/callback", get(auth::oidc_callback))
+        .route("/api/v1/openapi.json", get(openapi::openapi_handler));
+    let auth_routes = if state.config.swagger_ui.unwrap_or(false) {
+        auth_routes.route("/api/v1/docs", get(openapi::docs_handler))
+    } else {
+        auth_routes
+    };
+
+    let page_routes: Router<AppState> = Router::new()
         .merge(map_routes)
         .fallback_service(ServeDir::new(assets_dir).append_index_html_on_directories(true))
-        .route("/ws", any(ws_handler))
-        .route("/log", get(log_handler))
-        .route("/command", post(command_writer))
+        .layer(axum::middleware::from_fn_with_state(
+            auth,
+            auth::require_page_auth,
+        ));
+
+    // Pages keep their plain errors, everything else answers with JSON.
+    let api_routes: Router<AppState> = Router::new()
+        .merge(auth_routes)
+        .merge(control_routes)
+        .layer(axum::middleware::from_fn(error::middleware));
+
+    let app = Router::new()
+        .merge(page_routes)
+        .merge(api_routes)
         .layer(RequestDecompressionLayer::new())
         .layer(CompressionLayer::new())
-        .layer(axum::middleware::from_fn(logging_middleware))
+        .layer(axum::middleware::from_fn_with_state(
+            access_log,
+            access_log::middleware,
+        ))
+        .layer(TraceLayer::new_for_http())
+        .layer(axum::middleware::from_fn_with_state(
+            metrics,
+            metrics::track,
+        ))
+        .layer(Extension(keepalive))
+        .layer(Extension(mojang))
+        .layer(Extension(modrinth))
         .with_state(state);
 
+    tokio::spawn(wait_for_signal(shutdown.clone()));
     if ssl_config.is_some() {
-        let addr = SocketAddr::from(([0, 0, 0, 0], 443));
+        let addr = SocketAddr::new(cli.bind, cli.port.unwrap_or(443));
 
-        let mut server = axum_server::bind_rustls(addr, ssl_config.unwrap());
+        let handle = axum_server::Handle::new();
+        let stopping = handle.clone();
+        let signalled = shutdown.clone();
+        tokio::spawn(async move {
+            signalled.cancelled().await;
+            stopping.graceful_shutdown(Some(SHUTDOWN_GRACE));
+        });
+        let mut server = axum_server::bind_rustls(addr, ssl_config.unwrap()).handle(handle);
         server.http_builder().http2().enable_connect_protocol();
-        server.serve(app.into_make_service()).await.unwrap();
+        if let Err(e) = server.serve(app.into_make_service()).await {
+            fail(&[format!("could not listen on {}: {}", addr, e)]);
+        }
     } else {
-        let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+        let addr = SocketAddr::new(cli.bind, cli.port.unwrap_or(3000));
+        let listener = match tokio::net::TcpListener::bind(addr).await {
+            Ok(l) => l,
+            Err(e) => fail(&[format!("could not listen on {}: {}", addr, e)]),
+        };
 
-        println!("listening on {}", listener.local_addr().unwrap());
+        tracing::info!("listening on {}", listener.local_addr().unwrap());
         axum::serve(
             listener,
             app.into_make_service_with_connect_info::<SocketAddr>(),
         )
+        .with_graceful_shutdown(shutdown.clone().cancelled_owned())
         .await
         .unwrap();
     }
 
+    // Servers' background tasks stopped with the web server, and the log
+    // history still has its last lines to write.
+    if let Some(h) = &history {
+        if tokio::time::timeout(SHUTDOWN_GRACE, h.close())
+            .await
+            .is_err()
+        {
+            tracing::warn!("gave up waiting for the log history to be written");
+        }
+    }
+
+    if let Some(p) = tracer_provider {
+        let _ = p.shutdown();
+    }
     Ok(())
 }
 
-async fn logging_middleware(request: Request, next: Next) -> Response {
-    println!("{}", request.uri());
-    let response = next.run(request).await;
+/// Marks the routes from before `/api/v1` as deprecated, with a link to
+/// where they are now.
+async fn deprecated(State(first): State<String>, request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_owned();
+    let mut response = next.run(request).await;
+    let (name, rest) = match path
+        .strip_prefix("/servers/")
+        .and_then(|p| p.split_once('/'))
+    {
+        Some((name, rest)) => (name.to_owned(), format!("/{}", rest)),
+        None => (first, path),
+    };
+    let successor = format!(
+        "</api/v1/servers/{}{}>; rel=\"successor-version\"",
+        name,
+        Layout::V1.path(&rest)
+    );
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    if let Ok(v) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, v);
+    }
     response
 }
 
-async fn log_handler(State(state): State<AppState>) -> impl IntoResponse {
-    let logstream = match state.control.log().await {
+/// Prints what's wrong and exits, for problems the panel can't start with.
+/// Logs the problems once logging is set up, and writes them to stderr
+/// when it isn't yet, since that's only done once the config is read.
+fn fail(problems: &[String]) -> ! {
+    let logging = tracing::dispatcher::has_been_set();
+    for p in problems {
+        if logging {
+            tracing::error!("{}", p);
+        } else {
+            let _ = writeln!(std::io::stderr(), "error: {}", p);
+        }
+    }
+    std::process::exit(1);
+}
+
+/// fullchain.pem and key.pem from the certificate directory.
+async fn load_certificate(cert_path: &str) -> Result<RustlsConfig, String> {
+    let cert = Path::new(cert_path).join("fullchain.pem");
+    let key = Path::new(cert_path).join("key.pem");
+    match RustlsConfig::from_pem_file(&cert, &key).await {
+        Ok(c) => Ok(c),
+        Err(e) => Err(format!(
+            "could not load {} and {}: {}",
+            cert.display(),
+            key.display(),
+            e
+        )),
+    }
+}
+
+/// Cancels `shutdown` on SIGTERM or SIGINT, which stops every server's
+/// background tasks, closes WebSockets and streams, and has the web server
+/// stop taking connections.
+async fn wait_for_signal(shutdown: CancellationToken) {
+    let mut terminate = match signal(SignalKind::terminate()) {
         Ok(s) => s,
-        Err(_) => return Err(""),
+        Err(e) => panic!("could not listen for SIGTERM: {}", e),
     };
-    let body = Body::from_stream(logstream);
+    tokio::select! {
+        _ = terminate.recv() => tracing::info!("got SIGTERM, shutting down"),
+        _ = tokio::signal::ctrl_c() => tracing::info!("got SIGINT, shutting down"),
+    }
+    shutdown.cancel();
+}
 
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        header::CONTENT_TYPE,
-        "text/plain; chatset=utf-8".parse().unwrap(),
-    );
+/// What was applied from config.toml the last time it was reloaded, or
+/// null if it hasn't been.
+#[utoipa::path(
+    get,
+    path = "/api/v1/config/reload-status",
+    tag = "config",
+    responses(
+        (status = 200, description = "The last reload, or null", body = Option<reload::ReloadStatus>),
+        (status = 403, description = "Only admins can see it", body = error::ErrorBody),
+    )
+)]
+async fn reload_status_handler(
+    State(state): State<AppState>,
+) -> Json<Option<reload::ReloadStatus>> {
+    Json(state.reloader.status().await)
+}
 
-    Ok((headers, body))
+/// 200 while every server's log is being read and its backend answers, and
+/// the config still loads. 503 otherwise, with which check failed.
+async fn ready_handler(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    readiness(&state, false).await
 }
 
-async fn command_writer(State(state): State<AppState>, body: String) -> impl IntoResponse {
-    match state.control.command(body).await {
-        Ok(_) => return StatusCode::OK,
-        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
-    }
+/// The same as /readyz with the server names and why each check failed,
+/// for admins only since they name paths and quote the config.
+async fn readiness_handler(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    readiness(&state, true).await
 }
 
-async fn ws_handler(
-    ws: WebSocketUpgrade,
-    version: Version,
-    State(mut state): State<AppState>,
-) -> impl IntoResponse {
-    println!("accepted a WebSocket using {version:?}");
-    let rx = state.control.subscribe();
-    ws.on_upgrade(move |socket| handle_socket(socket, rx))
-}
-
-async fn handle_socket(socket: WebSocket, mut rx: Receiver<String>) {
-    let (mut sender, mut receiver) = socket.split();
-    loop {
-        tokio::select! {
-            // Wait for the next message from the broadcast channel
-            msg = rx.recv() => match msg {
-                Ok(msg) => {
-                    // Try to send the message to the WebSocket client
-                    if let Err(e) = sender.send(Message::Text(msg)).await {
-                        println!("Failed to send message: {}. Closing connection.", e);
-                        break;
-                    }
-                },
-                Err(_e) => {}
-            },
-
-            // Handle WebSocket close from the client
-            result = receiver.next() => match result {
-                Some(Ok(_)) => {},
-                Some(Err(e)) => {
-                    println!("WebSocket error: {}. Closing connection.", e);
-                    break;
-                }
-                None => {
-                    println!("WebSocket closed by client.");
-                    break;
-                }
-            }
-        }
+async fn readiness(state: &AppState, details: bool) -> (StatusCode, Json<Readiness>) {
+    let config_error = match cli::read_config(state.reloader.path()).await {
+        Ok(raw) => check::parse(&raw).err().map(|p| p.join("; ")),
+        Err(e) => Some(e),
+    };
+    let mut servers = Vec::new();
+    for control in state.servers.iter() {
+        let backend_error = control.status().await.err().map(|e| e.to_string());
+        servers.push(ServerReadiness {
+            name: Some(control.name().to_owned()).filter(|_| details),
+            log: control.is_reading_log(),
+            backend: backend_error.is_none(),
+            backend_error: backend_error.filter(|_| details),
+        });
     }
+    let ready = config_error.is_none() && servers.iter().all(|s| s.log && s.backend);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(Readiness {
+            ready,
+            config: config_error.is_none(),
+            config_error: config_error.filter(|_| details),
+            servers,
+        }),
+    )
+}
+
+async fn metrics_handler(State(state): State<AppState>) -> Response {
+    metrics::response(state.metrics.render(&state.servers).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/servers",
+    tag = "servers",
+    responses((status = 200, description = "Every server the panel controls", body = Vec<ServerSummary>))
+)]
+async fn servers_handler(State(state): State<AppState>) -> Json<Vec<ServerSummary>> {
+    let servers = state
+        .servers
+        .iter()
+        .map(|s| ServerSummary {
+            name: s.name().to_owned(),
+            backend: s.backend().kind(),
+            unit: s.backend().target(),
+        })
+        .collect();
+    Json(servers)
 }
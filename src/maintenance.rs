@@ -0,0 +1,217 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::Mutex;
+
+use crate::commands::Command;
+use crate::events::ServerEvent;
+use crate::minecraft::MinecraftControl;
+use crate::motd;
+use crate::players::{parse_list, read_ops};
+use crate::properties;
+use crate::util::now;
+
+const MOTD: &str = "&cDown for maintenance, back soon";
+const KICK_MESSAGE: &str = "The server is down for maintenance, back soon";
+
+// What maintenance changes in server.properties, to be put back after.
+const KEYS: &[&str] = &["white-list", "enforce-whitelist", "motd"];
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MaintenanceState {
+    // Seconds since the epoch.
+    pub since: u64,
+    pub by: String,
+    pub motd: String,
+    // What each of KEYS was before, None where it wasn't set.
+    previous: Vec<(String, Option<String>)>,
+}
+
+#[derive(Debug)]
+pub enum MaintenanceError {
+    Io(std::io::Error),
+    Invalid(String),
+    // The server didn't take the whitelist command.
+    Command,
+    // Already on, or already off.
+    Unchanged,
+}
+
+impl From<std::io::Error> for MaintenanceError {
+    fn from(e: std::io::Error) -> Self {
+        MaintenanceError::Io(e)
+    }
+}
+
+impl std::fmt::Display for MaintenanceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MaintenanceError::Io(e) => write!(f, "{}", e),
+            MaintenanceError::Invalid(e) => write!(f, "{}", e),
+            MaintenanceError::Command => write!(f, "the server didn't take the whitelist command"),
+            MaintenanceError::Unchanged => write!(f, "maintenance is already that way"),
+        }
+    }
+}
+
+/// Maintenance mode: the whitelist enforced, a MOTD saying so, and
+/// whatever the server had before kept as JSON so it can be put back.
+#[derive(Clone)]
+pub struct Maintenance {
+    path: PathBuf,
+    state: Arc<Mutex<Option<MaintenanceState>>>,
+}
+
+impl Maintenance {
+    pub fn load(path: PathBuf) -> Result<Maintenance, String> {
+        let state = match std::fs::read_to_string(&path) {
+            Ok(file) => match serde_json::from_str(&file) {
+                Ok(s) => Some(s),
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            },
+            Err(_) => None,
+        };
+        Ok(Maintenance {
+            path,
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    pub async fn state(&self) -> Option<MaintenanceState> {
+        self.state.lock().await.clone()
+    }
+
+    async fn whitelist(
+        &self,
+        control: &MinecraftControl,
+        on: bool,
+    ) -> Result<(), MaintenanceError> {
+        let command = String::from(if on { "whitelist on" } else { "whitelist off" });
+        // Only matters while the server is running; otherwise the
+        // properties take care of it when it starts.
+        if !control.is_running().await {
+            return Ok(());
+        }
+        match control.command(command).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(MaintenanceError::Command),
+        }
+    }
+
+    /// Kicks everyone online who isn't an op. Returns who was kicked.
+    async fn kick_non_ops(
+        &self,
+        control: &MinecraftControl,
+        server_dir: &Path,
+        message: &str,
+    ) -> Result<Vec<String>, MaintenanceError> {
+        let ops: Vec<String> = read_ops(server_dir)
+            .await?
+            .into_iter()
+            .map(|o| o.name.to_lowercase())
+            .collect();
+        let online = match control.execute(String::from("list")).await {
+            Ok(Some(reply)) => parse_list(&reply).map(|l| l.players).unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        let mut kicked = Vec::new();
+        for player in online {
+            if ops.contains(&player.to_lowercase()) {
+                continue;
+            }
+            let kick = Command::Kick {
+                player: player.clone(),
+                reason: Some(message.to_owned()),
+            };
+            if kick.validate().is_ok() && control.command(kick.render()).await.is_ok() {
+                kicked.push(player);
+            }
+        }
+        Ok(kicked)
+    }
+
+    /// Turns maintenance on, and kicks everyone but ops if `kick`. The
+    /// MOTD only shows from the server's next start, since there's no
+    /// command for changing it. Returns who was kicked.
+    pub async fn enable(
+        &self,
+        control: &MinecraftControl,
+        server_dir: &Path,
+        by: &str,
+        motd: Option<&str>,
+        kick: bool,
+        kick_message: Option<&str>,
+    ) -> Result<Vec<String>, MaintenanceError> {
+        let mut state = self.state.lock().await;
+        if state.is_some() {
+            return Err(MaintenanceError::Unchanged);
+        }
+        let motd = match motd::normalize(&serde_json::json!(motd.unwrap_or(MOTD))) {
+            Ok(m) => m,
+            Err(e) => return Err(MaintenanceError::Invalid(e)),
+        };
+        let mut props = properties::read(server_dir).await?;
+        let previous = KEYS.iter().map(|k| (k.to_string(), props.get(k))).collect();
+        props.set("white-list", "true");
+        props.set("enforce-whitelist", "true");
+        props.set("motd", &motd);
+        props.write(server_dir).await?;
+        self.whitelist(control, true).await?;
+
+        let entered = MaintenanceState {
+            since: now(),
+            by: by.to_owned(),
+            motd,
+            previous,
+        };
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&entered).unwrap()).await?;
+        fs::rename(&tmp, &self.path).await?;
+        *state = Some(entered);
+        drop(state);
+        control.publish(ServerEvent::MaintenanceChanged { enabled: true });
+
+        if !kick {
+            return Ok(Vec::new());
+        }
+        let message = kick_message.unwrap_or(KICK_MESSAGE);
+        self.kick_non_ops(control, server_dir, message).await
+    }
+
+    /// Puts the whitelist and MOTD back the way they were.
+    pub async fn disable(
+        &self,
+        control: &MinecraftControl,
+        server_dir: &Path,
+    ) -> Result<(), MaintenanceError> {
+        let mut state = self.state.lock().await;
+        let entered = match state.as_ref() {
+            Some(s) => s,
+            None => return Err(MaintenanceError::Unchanged),
+        };
+        let mut props = properties::read(server_dir).await?;
+        for (key, value) in &entered.previous {
+            props.set(
+                key,
+                value.as_deref().unwrap_or(match key.as_str() {
+                    "motd" => "A Minecraft Server",
+                    _ => "false",
+                }),
+            );
+        }
+        props.write(server_dir).await?;
+        let whitelisted = props.get("white-list").as_deref() == Some("true");
+        self.whitelist(control, whitelisted).await?;
+
+        match fs::remove_file(&self.path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        *state = None;
+        drop(state);
+        control.publish(ServerEvent::MaintenanceChanged { enabled: false });
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::minecraft::MinecraftControl;
+use crate::ping;
+use crate::util::now;
+
+// Upper bounds of the request latency histogram, in seconds.
+const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// Per-server gauges and what they are.
+const GAUGES: &[(&str, &str)] = &[
+    ("mcctl_server_up", "Whether the server is running."),
+    (
+        "mcctl_players_online",
+        "Players online, from a server-list ping.",
+    ),
+    ("mcctl_players_max", "The most players there can be."),
+    ("mcctl_tps", "The latest ticks per second sampled."),
+    ("mcctl_mspt", "The latest milliseconds per tick sampled."),
+    ("mcctl_backup_age_seconds", "Time since the latest backup."),
+    (
+        "mcctl_log_channel_queued",
+        "Log entries waiting in the broadcast channel for slow subscribers.",
+    ),
+];
+
+#[derive(Default)]
+struct RouteStats {
+    // Requests at or under each of BUCKETS.
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    seconds: f64,
+}
+
+/// Counts of the panel's own HTTP requests, by method, route and status.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    requests: Arc<Mutex<BTreeMap<(String, String, u16), RouteStats>>>,
+}
+
+/// Records every request that goes through it.
+pub async fn track(State(metrics): State<Metrics>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    // The route rather than the path, so there's one series per route.
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(p) => p.as_str().to_owned(),
+        None => String::from("unmatched"),
+    };
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = started.elapsed().as_secs_f64();
+    let status = response.status().as_u16();
+    let mut requests = metrics.requests.lock().unwrap();
+    let stats = requests.entry((method, route, status)).or_default();
+    stats.count += 1;
+    stats.seconds += elapsed;
+    for (i, bound) in BUCKETS.iter().enumerate() {
+        if elapsed <= *bound {
+            stats.buckets[i] += 1;
+        }
+    }
+    response
+}
+
+/// Label values can't have quotes, backslashes or newlines unescaped.
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Resident memory of the panel, from /proc.
+fn rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+impl Metrics {
+    fn render_requests(&self, out: &mut String) {
+        let requests = self.requests.lock().unwrap();
+        describe(
+            out,
+            "mcctl_http_requests_total",
+            "counter",
+            "HTTP requests handled by the panel.",
+        );
+        for ((method, route, status), stats) in requests.iter() {
+            let _ = writeln!(
+                out,
+                "mcctl_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                method,
+                label(route),
+                status,
+                stats.count
+            );
+        }
+        describe(
+            out,
+            "mcctl_http_request_duration_seconds",
+            "histogram",
+            "How long the panel took to answer HTTP requests.",
+        );
+        for ((method, route, status), stats) in requests.iter() {
+            let labels = format!(
+                "method=\"{}\",route=\"{}\",status=\"{}\"",
+                method,
+                label(route),
+                status
+            );
+            for (bound, count) in BUCKETS.iter().zip(stats.buckets) {
+                let _ = writeln!(
+                    out,
+                    "mcctl_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "mcctl_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, stats.count
+            );
+            let _ = writeln!(
+                out,
+                "mcctl_http_request_duration_seconds_sum{{{}}} {}",
+                labels, stats.seconds
+            );
+            let _ = writeln!(
+                out,
+                "mcctl_http_request_duration_seconds_count{{{}}} {}",
+                labels, stats.count
+            );
+        }
+    }
+
+    /// Everything, in the Prometheus text format.
+    pub async fn render(&self, servers: &[MinecraftControl]) -> String {
+        let mut out = String::new();
+        // Each gauge's lines, in the order of GAUGES.
+        let mut gauges: Vec<Vec<String>> = vec![Vec::new(); GAUGES.len()];
+        let mut push = |i: usize, server: &str, value: String| {
+            gauges[i].push(format!(
+                "{}{{server=\"{}\"}} {}",
+                GAUGES[i].0, server, value
+            ));
+        };
+        for control in servers {
+            let server = label(control.name());
+            let running = control.is_running().await;
+            push(0, &server, (running as u8).to_string());
+            if running {
+                let address = control.status_address().await;
+                if let Ok(s) = ping::ping(&address, Duration::from_secs(2)).await {
+                    push(1, &server, s.online.to_string());
+                    push(2, &server, s.max.to_string());
+                }
+            }
+            let sample = match control.tps() {
+                Some(t) => t.latest().await,
+                None => None,
+            };
+            if let Some(sample) = sample {
+                push(3, &server, sample.tps.to_string());
+                if let Some(mspt) = sample.mspt {
+                    push(4, &server, mspt.to_string());
+                }
+            }
+            if let Some(backups) = control.backups() {
+                if let Some(latest) = backups.list().await.first() {
+                    push(5, &server, now().saturating_sub(latest.created).to_string());
+                }
+            }
+            push(6, &server, control.log_queued().to_string());
+        }
+        for ((name, help), lines) in GAUGES.iter().zip(gauges) {
+            describe(&mut out, name, "gauge", help);
+            for line in lines {
+                out.push_str(&line);
+                out.push('\n');
+            }
+        }
+        if let Some(control) = servers.first() {
+            describe(
+                &mut out,
+                "mcctl_event_channel_queued",
+                "gauge",
+                "Events waiting in the broadcast channel for slow subscribers.",
+            );
+            let _ = writeln!(
+                out,
+                "mcctl_event_channel_queued {}",
+                control.events().queued()
+            );
+        }
+        self.render_requests(&mut out);
+        if let Some(rss) = rss_bytes() {
+            describe(
+                &mut out,
+                "mcctl_process_resident_memory_bytes",
+                "gauge",
+                "Resident memory of the panel.",
+            );
+            let _ = writeln!(out, "mcctl_process_resident_memory_bytes {}", rss);
+        }
+        out
+    }
+}
+
+/// The body for GET /metrics.
+pub fn response(body: String) -> Response {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
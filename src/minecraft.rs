@@ -1,126 +1,832 @@
+use nix::unistd::{access, AccessFlags};
 use serde::Deserialize;
-use std::time::Duration;
-use systemd::{journal, Journal};
-use tokio::{
-    fs::OpenOptions,
-    io::AsyncWriteExt,
-    sync::broadcast::{self, Receiver, Sender},
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{
+    broadcast::{self, Receiver, Sender},
+    Mutex,
 };
-use tokio_util::io::ReaderStream;
+use tokio_util::sync::CancellationToken;
 
+use crate::backend::{
+    docker::DockerBackend,
+    kubernetes::KubernetesBackend,
+    podman,
+    process::ProcessBackend,
+    screen::{Multiplexer, ScreenBackend},
+    systemd::SystemdBackend,
+    BackendError, BackendKind, LogStream, ServerBackend, ServerStatus,
+};
+use crate::backup::{BackupConfig, Backups};
+use crate::bans::BanStore;
+use crate::crash::{self, CrashConfig};
+use crate::disk::{self, DiskConfig};
+use crate::events::{EventBus, ServerEvent};
+use crate::gc::{Gc, GcConfig};
+use crate::history::History;
+use crate::idle::{self, IdleConfig};
+use crate::jvm::{Jvm, JvmConfig};
+use crate::lifecycle::Lifecycle;
+use crate::logsource::{
+    self,
+    file::FileSource,
+    journal::{JournalFilter, JournalSource},
+    LogBuffer, LogEntry, LogSource, LogSourceKind,
+};
+use crate::maintenance::Maintenance;
+use crate::parser;
+use crate::ping;
+use crate::policy::CommandRules;
+use crate::query;
+use crate::queue::{CommandQueue, RateLimitConfig};
+use crate::rcon::{RconClient, RconError};
+use crate::resources::{Resources, ResourcesConfig};
+use crate::scheduler::{announcements::Announcements, Scheduler, Task};
+use crate::sessions::SessionStore;
+use crate::tps::{Tps, TpsConfig};
+use crate::updater::{Updater, UpdaterConfig};
+use crate::wake::{self, WakeConfig};
+use crate::watchdog::{Watchdog, WatchdogConfig};
+
+#[derive(Debug, thiserror::Error)]
 pub enum MinecraftError {
-    LogError(tokio::io::Error),
-    CommandError(String),
+    #[error("could not read the log: {0}")]
+    LogError(#[from] tokio::io::Error),
+    #[error(transparent)]
+    RconError(#[from] RconError),
+    #[error(transparent)]
+    BackendError(BackendError),
+    #[error("the server didn't reply in time")]
+    CommandTimeout,
+    // The console's FIFO, which systemd only makes while the unit is up.
+    #[error("the console socket {0} doesn't exist, is the server running?")]
+    SocketMissing(String),
+    #[error("the panel isn't allowed to write to the console socket {0}")]
+    PermissionDenied(String),
+    // Nothing has the other end of the console open any more.
+    #[error("the server stopped reading the console socket {0}")]
+    BrokenPipe(String),
+    // The rule in [commands] that turned it down.
+    #[error("command denied by rule: {0}")]
+    CommandDenied(String),
+    #[error("too many commands are waiting to be sent")]
+    QueueFull,
+    // Seconds until the caller can send another.
+    #[error("too many commands, try again in {0}s")]
+    RateLimited(u64),
 }
 
-impl From<tokio::io::Error> for MinecraftError {
-    fn from(e: tokio::io::Error) -> Self {
-        MinecraftError::LogError(e)
+impl From<BackendError> for MinecraftError {
+    fn from(e: BackendError) -> Self {
+        let (path, e) = match e {
+            BackendError::Console(path, e) => (path, e),
+            e => return MinecraftError::BackendError(e),
+        };
+        match e.kind() {
+            std::io::ErrorKind::NotFound => MinecraftError::SocketMissing(path),
+            std::io::ErrorKind::PermissionDenied => MinecraftError::PermissionDenied(path),
+            std::io::ErrorKind::BrokenPipe => MinecraftError::BrokenPipe(path),
+            _ => MinecraftError::BackendError(BackendError::Console(path, e)),
+        }
     }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct MinecraftConfig {
+    name: Option<String>,
+    backend: Option<BackendKind>,
+    log_source: Option<LogSourceKind>,
     log_path: Option<String>,
     socket_path: Option<String>,
     systemd_unit: Option<String>,
+    // Journal matches, defaulting to just systemd_unit.
+    journal_units: Option<Vec<String>>,
+    journal_identifiers: Option<Vec<String>>,
+    journal_max_priority: Option<u8>,
+    container: Option<String>,
+    docker_socket: Option<String>,
+    podman_socket: Option<String>,
+    exec_command: Option<Vec<String>>,
+    command: Option<Vec<String>>,
+    working_dir: Option<String>,
+    restart_on_exit: Option<bool>,
+    session: Option<String>,
+    namespace: Option<String>,
+    selector: Option<String>,
+    statefulset: Option<String>,
+    rcon_address: Option<String>,
+    rcon_password: Option<String>,
+    // Where /api/status pings the server, 127.0.0.1 on its server-port if
+    // unset.
+    status_address: Option<String>,
+    // Where /api/status asks for the player and plugin lists over the query
+    // protocol. 127.0.0.1 on query.port if unset, and only when
+    // enable-query is on.
+    query_address: Option<String>,
+    // The Bedrock port, for servers running Geyser, which /api/status
+    // pings as well when it's set.
+    bedrock_address: Option<String>,
+    command_timeout_ms: Option<u64>,
+    // How many recent lines new WebSocket clients are sent.
+    log_buffer_lines: Option<usize>,
+    // Where join and leave times are kept, sessions-<name>.json by default.
+    sessions_path: Option<String>,
+    // Ban expiry times, bans-<name>.json by default.
+    bans_path: Option<String>,
+    // Where server.properties and the world are, if not working_dir or
+    // the parent of the log directory.
+    server_dir: Option<String>,
+    // The most a world uploaded through the API may unpack to.
+    world_upload_limit_mb: Option<u64>,
+    // How long to wait for the server to confirm it has saved before a
+    // backup.
+    save_timeout_secs: Option<u64>,
+    backups: Option<BackupConfig>,
+    // What the server runs, e.g. "fabric" or "paper", and which Minecraft
+    // version. Worked out from its mods and world if unset.
+    loader: Option<String>,
+    game_version: Option<String>,
+    updater: Option<UpdaterConfig>,
+    // Tasks run on cron schedules, alongside those made through the API.
+    schedules: Option<Vec<Task>>,
+    // Where tasks made through the API are kept, schedules-<name>.json by
+    // default.
+    schedules_path: Option<String>,
+    // Where recurring announcements are kept, announcements-<name>.json by
+    // default.
+    announcements_path: Option<String>,
+    // Stops the server after a while without players.
+    idle: Option<IdleConfig>,
+    // Listens on the server's port while it's stopped and starts it when
+    // someone tries to join.
+    wake: Option<WakeConfig>,
+    // Restarts the server when it crashes.
+    crash_restart: Option<CrashConfig>,
+    // Restarts the server when it stops answering server-list pings.
+    watchdog: Option<WatchdogConfig>,
+    // Samples ticks per second for /api/metrics/tps.
+    tps: Option<TpsConfig>,
+    // How often the server process's CPU and memory are sampled for
+    // /api/metrics/process.
+    resources: Option<ResourcesConfig>,
+    // Reports when the world, logs or backups run short of disk space.
+    disk: Option<DiskConfig>,
+    // Where jcmd and jstack are, for thread dumps and heap summaries.
+    jvm: Option<JvmConfig>,
+    // Follows the JVM's GC log for /api/metrics/gc.
+    gc: Option<GcConfig>,
+    // Where maintenance mode keeps what it changed, maintenance-<name>.json
+    // by default.
+    maintenance_path: Option<String>,
+}
+
+/// Adds a problem if `path` doesn't exist, or isn't a directory when it
+/// should be.
+fn check_path(problems: &mut Vec<String>, key: &str, path: &Path, dir: bool) {
+    match std::fs::metadata(path) {
+        Ok(m) if dir && !m.is_dir() => {
+            problems.push(format!("{} {} isn't a directory", key, path.display()))
+        }
+        Ok(_) => {}
+        Err(e) => problems.push(format!("{} {}: {}", key, path.display(), e)),
+    }
+}
+
+impl MinecraftConfig {
+    pub fn name(&self) -> &str {
+        match &self.name {
+            Some(n) => n,
+            None => "default",
+        }
+    }
+
+    pub fn schedules(&self) -> &[Task] {
+        self.schedules.as_deref().unwrap_or_default()
+    }
+
+    /// What would keep this server from working: paths that don't exist,
+    /// a console socket that can't be written to, or a systemd unit that
+    /// isn't there. Each problem is a line for `--check`.
+    pub async fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let backend = self.backend.unwrap_or_default();
+        if let Some(p) = &self.log_path {
+            if let Some(dir) = Path::new(p).parent() {
+                check_path(&mut problems, "log_path's directory", dir, true);
+            }
+        }
+        if let Some(d) = &self.server_dir {
+            check_path(&mut problems, "server_dir", Path::new(d), true);
+        }
+        if let Some(b) = &self.backups {
+            if let Err(e) = Backups::load(b.clone()) {
+                problems.push(format!("backups: {}", e));
+            }
+        }
+        match backend {
+            BackendKind::Systemd => {
+                let socket = match &self.socket_path {
+                    Some(s) => s.as_str(),
+                    None => "/run/minecraft-server.stdin",
+                };
+                if let Err(e) = access(socket, AccessFlags::W_OK) {
+                    problems.push(format!("socket_path {} can't be written to: {}", socket, e));
+                }
+                let unit = match &self.systemd_unit {
+                    Some(u) => u.clone(),
+                    None => String::from("minecraft-server.service"),
+                };
+                if let Err(e) = Lifecycle::new(unit).check().await {
+                    problems.push(format!("systemd_unit: {}", e));
+                }
+            }
+            BackendKind::Docker => {
+                if let Some(s) = &self.docker_socket {
+                    check_path(&mut problems, "docker_socket", Path::new(s), false);
+                }
+            }
+            BackendKind::Podman => {
+                if let Some(s) = &self.podman_socket {
+                    check_path(&mut problems, "podman_socket", Path::new(s), false);
+                }
+            }
+            BackendKind::Process | BackendKind::Tmux | BackendKind::Screen => {
+                if let Some(d) = &self.working_dir {
+                    check_path(&mut problems, "working_dir", Path::new(d), true);
+                }
+            }
+            BackendKind::Kubernetes => {}
+        }
+        problems
+    }
+}
+
+/// Returned by `pause_saving`. Saving is turned back on when it's
+/// resumed, or on a task of its own if it's dropped.
+pub struct SavingPaused {
+    control: Option<MinecraftControl>,
+}
+
+impl SavingPaused {
+    pub async fn resume(mut self) -> Result<(), MinecraftError> {
+        match self.control.take() {
+            Some(c) => c.resume_saving().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for SavingPaused {
+    fn drop(&mut self) {
+        if let Some(control) = self.control.take() {
+            tokio::spawn(async move {
+                if control.resume_saving().await.is_err() {
+                    tracing::warn!("could not turn saving back on for {}", control.name());
+                }
+            });
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct MinecraftControl {
     config: MinecraftConfig,
-    tx: Sender<String>,
+    tx: Sender<LogEntry>,
+    buffer: LogBuffer,
+    rcon: Option<Arc<Mutex<RconClient>>>,
+    backend: Arc<dyn ServerBackend>,
+    history: Option<History>,
+    commands: CommandRules,
+    queue: CommandQueue,
+    events: EventBus,
+    sessions: SessionStore,
+    bans: BanStore,
+    backups: Option<Backups>,
+    updater: Option<Updater>,
+    scheduler: Scheduler,
+    announcements: Announcements,
+    maintenance: Maintenance,
+    watchdog: Option<Watchdog>,
+    tps: Option<Tps>,
+    resources: Resources,
+    jvm: Jvm,
+    gc: Option<Gc>,
+    // Whether the log source is still being read.
+    reading_log: Arc<AtomicBool>,
+    // Cancelled when the panel is shutting down.
+    shutdown: CancellationToken,
 }
 
-pub fn init(config: Option<MinecraftConfig>) -> MinecraftControl {
-    let mc_config = match config {
-        Some(c) => c,
-        None => MinecraftConfig {
-            log_path: None,
-            socket_path: None,
-            systemd_unit: None,
-        },
+pub fn init(
+    mc_config: MinecraftConfig,
+    history: Option<History>,
+    commands: CommandRules,
+    rate_limit: Option<RateLimitConfig>,
+    events: EventBus,
+    shutdown: CancellationToken,
+) -> Result<MinecraftControl, String> {
+    let (tx, _): (Sender<LogEntry>, Receiver<LogEntry>) = broadcast::channel(16);
+    let backend: Arc<dyn ServerBackend> = match mc_config.backend.unwrap_or_default() {
+        BackendKind::Systemd => Arc::new(SystemdBackend::new(
+            mc_config.systemd_unit.clone(),
+            mc_config.log_path.clone(),
+            mc_config.socket_path.clone(),
+            journal_filter(&mc_config),
+        )),
+        BackendKind::Docker => Arc::new(DockerBackend::new(
+            mc_config.docker_socket.clone(),
+            mc_config.container.clone(),
+            mc_config.exec_command.clone(),
+        )?),
+        BackendKind::Podman => Arc::new(podman::new(
+            mc_config.podman_socket.clone(),
+            mc_config.container.clone(),
+            mc_config.exec_command.clone(),
+        )?),
+        BackendKind::Process => Arc::new(ProcessBackend::new(
+            mc_config.command.clone(),
+            mc_config.working_dir.clone(),
+            mc_config.restart_on_exit,
+        )),
+        BackendKind::Tmux | BackendKind::Screen => Arc::new(ScreenBackend::new(
+            match mc_config.backend {
+                Some(BackendKind::Screen) => Multiplexer::Screen,
+                _ => Multiplexer::Tmux,
+            },
+            mc_config.session.clone(),
+            mc_config.log_path.clone(),
+            mc_config.command.clone(),
+            mc_config.working_dir.clone(),
+        )),
+        BackendKind::Kubernetes => Arc::new(KubernetesBackend::new(
+            mc_config.namespace.clone(),
+            mc_config.selector.clone(),
+            mc_config.container.clone(),
+            mc_config.statefulset.clone(),
+            mc_config.exec_command.clone(),
+        )),
     };
-    let (tx, _): (Sender<String>, Receiver<String>) = broadcast::channel(16);
-    let tx_real = tx.clone();
-    let systemd_unit: String = match mc_config.systemd_unit {
-        Some(ref s) => s.clone(),
-        None => String::from("minecraft-server.service"),
+    let source: Box<dyn LogSource> = match mc_config.log_source {
+        Some(LogSourceKind::Journal) => Box::new(JournalSource::new(journal_filter(&mc_config))),
+        Some(LogSourceKind::File) => {
+            Box::new(FileSource::new(PathBuf::from(match mc_config.log_path {
+                Some(ref p) => p.as_str(),
+                None => "/var/lib/minecraft/logs/latest.log",
+            })))
+        }
+        None => backend.log_source(),
     };
+    let buffer = LogBuffer::new(mc_config.log_buffer_lines.unwrap_or(500));
+    let name = match &mc_config.name {
+        Some(n) => n.clone(),
+        None => String::from("default"),
+    };
+    // Subscribed to first so neither misses the first line.
+    if let Some(h) = &history {
+        h.record(name.clone(), tx.subscribe(), shutdown.clone());
+    }
+    events.publish_log_events(name.clone(), tx.subscribe(), shutdown.clone());
+    let sessions = SessionStore::load(PathBuf::from(match &mc_config.sessions_path {
+        Some(p) => p.clone(),
+        None => format!("sessions-{}.json", name),
+    }))?;
+    sessions.track(name.clone(), &events, shutdown.clone());
+    let bans = BanStore::load(PathBuf::from(match &mc_config.bans_path {
+        Some(p) => p.clone(),
+        None => format!("bans-{}.json", name),
+    }))?;
+    let scheduler = Scheduler::load(
+        PathBuf::from(match &mc_config.schedules_path {
+            Some(p) => p.clone(),
+            None => format!("schedules-{}.json", name),
+        }),
+        mc_config.schedules.clone().unwrap_or_default(),
+        shutdown.clone(),
+    )?;
+    let announcements = Announcements::load(
+        PathBuf::from(match &mc_config.announcements_path {
+            Some(p) => p.clone(),
+            None => format!("announcements-{}.json", name),
+        }),
+        shutdown.clone(),
+    )?;
+    let maintenance = Maintenance::load(PathBuf::from(match &mc_config.maintenance_path {
+        Some(p) => p.clone(),
+        None => format!("maintenance-{}.json", name),
+    }))?;
+    let reading_log = logsource::forward(source, tx.clone(), buffer.clone(), shutdown.clone());
 
-    let _ = tokio::task::spawn_blocking(move || read_journal(tx_real, systemd_unit));
+    // RCON is only used when a password is configured, since the server
+    // refuses to enable it without one.
+    let rcon = match mc_config.rcon_password {
+        Some(ref password) => {
+            let address = match mc_config.rcon_address {
+                Some(ref a) => a.clone(),
+                None => String::from("127.0.0.1:25575"),
+            };
+            let client = RconClient::new(address, password.clone());
+            Some(Arc::new(Mutex::new(client)))
+        }
+        None => None,
+    };
+    let queue = CommandQueue::start(backend.clone(), rcon.clone(), rate_limit);
 
-    MinecraftControl {
+    let backups = match mc_config.backups.clone() {
+        Some(b) => Some(Backups::load(b)?),
+        None => None,
+    };
+    let updater = mc_config.updater.clone().map(Updater::new);
+    let watchdog = match mc_config.watchdog.clone() {
+        Some(w) => Some(Watchdog::load(w, format!("watchdog-{}.json", name))?),
+        None => None,
+    };
+    let tps = mc_config.tps.clone().map(Tps::new);
+    let resources = Resources::new(mc_config.resources.clone());
+    let jvm = Jvm::new(mc_config.jvm.clone());
+    let gc = mc_config.gc.clone().map(Gc::new);
+
+    let control = MinecraftControl {
         config: mc_config,
         tx,
+        buffer,
+        rcon,
+        backend,
+        history,
+        commands,
+        queue,
+        events,
+        sessions,
+        bans,
+        backups,
+        updater,
+        scheduler,
+        announcements,
+        maintenance,
+        watchdog,
+        tps,
+        resources,
+        jvm,
+        gc,
+        reading_log,
+        shutdown: shutdown.clone(),
+    };
+    if let Some(b) = &control.backups {
+        b.schedule(control.clone(), shutdown.clone());
+    }
+    if let Some(i) = &control.config.idle {
+        idle::watch(i.clone(), control.clone(), shutdown.clone());
+    }
+    if let Some(w) = &control.config.wake {
+        wake::watch(w.clone(), control.clone(), shutdown.clone());
+    }
+    if let Some(c) = &control.config.crash_restart {
+        crash::watch(c.clone(), control.clone(), shutdown.clone());
+    }
+    if let Some(w) = &control.watchdog {
+        w.watch(control.clone(), shutdown.clone());
+    }
+    if let Some(t) = &control.tps {
+        t.start(control.clone(), shutdown.clone());
+    }
+    control.resources.start(control.clone(), shutdown.clone());
+    if let Some(g) = &control.gc {
+        g.start(control.clone(), shutdown.clone());
+    }
+    if let Some(d) = &control.config.disk {
+        disk::watch(d.clone(), control.clone(), shutdown.clone());
     }
+    control.bans.expire(control.clone(), shutdown);
+    control.scheduler.start(control.clone());
+    control.announcements.start(control.clone());
+    Ok(control)
 }
 
 impl MinecraftControl {
-    pub fn subscribe(&mut self) -> Receiver<String> {
-        self.tx.subscribe()
+    pub fn name(&self) -> &str {
+        match &self.config.name {
+            Some(n) => n,
+            None => "default",
+        }
     }
 
-    pub async fn log(&self) -> Result<ReaderStream<tokio::fs::File>, MinecraftError> {
-        let filename = match &self.config.log_path {
-            Some(f) => f,
-            None => &String::from("/var/lib/minecraft/logs/latest.log"),
-        };
-        let file = tokio::fs::File::open(filename).await?;
-        Ok(ReaderStream::new(file))
+    /// Recent log entries and a receiver for the ones after them.
+    pub fn subscribe(&self) -> (Vec<LogEntry>, Receiver<LogEntry>) {
+        self.buffer.subscribe(&self.tx)
+    }
+
+    pub fn is_reading_log(&self) -> bool {
+        self.reading_log.load(Ordering::Relaxed)
+    }
+
+    /// Log entries sent that the slowest subscriber hasn't had yet.
+    pub fn log_queued(&self) -> usize {
+        self.tx.len()
     }
 
-    pub async fn command(&self, mut command: String) -> Result<bool, MinecraftError> {
-        let filename = match &self.config.socket_path {
-            Some(f) => f,
-            None => &String::from("/run/minecraft-server.stdin"),
+    /// Selects this server's entries in the journal, if its log is there.
+    pub fn journal_filter(&self) -> Option<JournalFilter> {
+        let journal = match self.config.log_source {
+            Some(kind) => kind == LogSourceKind::Journal,
+            None => self.config.backend.unwrap_or_default() == BackendKind::Systemd,
         };
-        let mut file = OpenOptions::new()
-            .read(false)
-            .write(true)
-            .open(filename)
-            .await
-            .unwrap();
-        if !command.ends_with("\n") {
-            command = format!("{}\n", command);
+        if !journal {
+            return None;
+        }
+        Some(journal_filter(&self.config))
+    }
+
+    /// The directory latest.log and the archived logs are in, if they're
+    /// somewhere the panel can read.
+    pub fn log_dir(&self) -> Option<PathBuf> {
+        if let Some(path) = &self.config.log_path {
+            return PathBuf::from(path).parent().map(|p| p.to_path_buf());
+        }
+        match self.config.backend.unwrap_or_default() {
+            BackendKind::Process => Some(
+                PathBuf::from(match &self.config.working_dir {
+                    Some(d) => d.as_str(),
+                    None => "/var/lib/minecraft",
+                })
+                .join("logs"),
+            ),
+            BackendKind::Systemd | BackendKind::Tmux | BackendKind::Screen => {
+                Some(PathBuf::from("/var/lib/minecraft/logs"))
+            }
+            // Inside the container, unless log_path points at a volume.
+            BackendKind::Docker | BackendKind::Podman | BackendKind::Kubernetes => None,
         }
-        let bytes = command.as_bytes();
-        let _ = file.write_all(bytes).await.unwrap();
-        let _ = file.flush().await.unwrap();
+    }
 
-        Ok(true)
+    /// The server's own directory, with server.properties and the world,
+    /// if the panel can read it.
+    pub fn server_dir(&self) -> Option<PathBuf> {
+        if let Some(d) = &self.config.server_dir {
+            return Some(PathBuf::from(d));
+        }
+        if let Some(d) = &self.config.working_dir {
+            return Some(PathBuf::from(d));
+        }
+        self.log_dir()
+            .and_then(|d| d.parent().map(|p| p.to_path_buf()))
     }
-}
 
-fn read_journal(tx: Sender<String>, systemd_unit: String) {
-    println!("opening journal");
-    let _ = tx.send("starting up".to_owned());
-    let mut j: Journal = journal::OpenOptions::default().open().unwrap();
-    let _ = j.seek_tail();
-    let _ = j.previous();
-
-    while let Ok(e) = j.next_entry() {
-        match e {
-            Some(entry) => {
-                let unit = match entry.get("_SYSTEMD_UNIT") {
-                    Some(value) => value,
-                    None => &"".to_owned(),
-                };
-                if unit == &systemd_unit {
-                    let message = match entry.get("MESSAGE") {
-                        Some(value) => value,
-                        None => &"".to_owned(),
-                    };
-
-                    match tx.send(message.to_owned()) {
-                        Ok(_s) => {}
-                        Err(e) => println!("could not write to tx {}", e),
-                    };
+    pub async fn status_address(&self) -> String {
+        match &self.config.status_address {
+            Some(a) => a.clone(),
+            None => ping::local_address(self).await,
+        }
+    }
+
+    pub async fn query_address(&self) -> Option<String> {
+        match &self.config.query_address {
+            Some(a) => Some(a.clone()),
+            None => query::local_address(self).await,
+        }
+    }
+
+    pub fn bedrock_address(&self) -> Option<&str> {
+        self.config.bedrock_address.as_deref()
+    }
+
+    pub fn world_upload_limit(&self) -> u64 {
+        self.config.world_upload_limit_mb.unwrap_or(8192) * 1024 * 1024
+    }
+
+    /// Whether the server might be running, which it's taken to be when
+    /// the backend can't tell.
+    pub async fn is_running(&self) -> bool {
+        match self.status().await {
+            Ok(s) => s.active_state != "inactive" && s.active_state != "failed",
+            Err(_) => true,
+        }
+    }
+
+    pub fn loader(&self) -> Option<&str> {
+        self.config.loader.as_deref()
+    }
+
+    pub fn game_version(&self) -> Option<&str> {
+        self.config.game_version.as_deref()
+    }
+
+    pub fn backups(&self) -> Option<&Backups> {
+        self.backups.as_ref()
+    }
+
+    pub fn updater(&self) -> Option<&Updater> {
+        self.updater.as_ref()
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    pub fn announcements(&self) -> &Announcements {
+        &self.announcements
+    }
+
+    pub fn maintenance(&self) -> &Maintenance {
+        &self.maintenance
+    }
+
+    pub fn watchdog(&self) -> Option<&Watchdog> {
+        self.watchdog.as_ref()
+    }
+
+    pub fn tps(&self) -> Option<&Tps> {
+        self.tps.as_ref()
+    }
+
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    pub fn jvm(&self) -> &Jvm {
+        &self.jvm
+    }
+
+    pub fn gc(&self) -> Option<&Gc> {
+        self.gc.as_ref()
+    }
+
+    /// Cancelled when the panel is shutting down, for connections that
+    /// would otherwise stay open.
+    pub fn shutdown(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    pub fn bans(&self) -> &BanStore {
+        &self.bans
+    }
+
+    /// Every server's events. Subscribers get those of other servers too.
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub fn publish(&self, event: ServerEvent) {
+        self.events.publish(self.name(), event);
+    }
+
+    pub fn sessions(&self) -> &SessionStore {
+        &self.sessions
+    }
+
+    /// The log history kept in SQLite, if there is one.
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    pub fn backend(&self) -> &dyn ServerBackend {
+        self.backend.as_ref()
+    }
+
+    #[tracing::instrument(skip(self), fields(server = %self.name(), backend = self.backend.kind()))]
+    pub async fn log(&self) -> Result<LogStream, MinecraftError> {
+        Ok(self.backend.log().await?)
+    }
+
+    /// Sends a command to the server, after any already waiting. Over RCON
+    /// the server's reply is returned, while the console has no way of
+    /// producing one.
+    #[tracing::instrument(skip(self, command), fields(server = %self.name(), command = %command.trim_end()))]
+    pub async fn command(&self, command: String) -> Result<Option<String>, MinecraftError> {
+        if let Err(rule) = self.commands.check(&command) {
+            tracing::warn!("denied by rule {}", rule);
+            return Err(MinecraftError::CommandDenied(rule));
+        }
+        self.queue.send(command).await
+    }
+
+    /// Counts a command sent by an API caller against their rate limit.
+    pub async fn rate_limit(&self, client: &str) -> Result<(), MinecraftError> {
+        self.queue.limit(client).await
+    }
+
+    /// Stops the server writing to its world and has it flush everything
+    /// to disk, waiting for "Saved the game" so the world can be copied
+    /// without half-written chunks. Saving comes back on when the returned
+    /// guard is resumed or dropped, however the copy goes.
+    pub async fn pause_saving(&self) -> Result<SavingPaused, MinecraftError> {
+        // Subscribed to first so the confirmation can't be missed.
+        let mut rx = self.tx.subscribe();
+        self.command(String::from("save-off")).await?;
+        let paused = SavingPaused {
+            control: Some(self.clone()),
+        };
+        let reply = self.command(String::from("save-all flush")).await?;
+        if reply.is_some_and(|r| r.contains("Saved the game")) {
+            return Ok(paused);
+        }
+
+        let limit = Duration::from_secs(self.config.save_timeout_secs.unwrap_or(60));
+        let saved = tokio::time::timeout(limit, async {
+            loop {
+                match rx.recv().await {
+                    Ok(entry) if entry.message.contains("Saved the game") => return true,
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return false,
                 }
             }
-            None => {
-                std::thread::sleep(Duration::from_secs(1));
+        })
+        .await;
+        match saved {
+            Ok(true) => Ok(paused),
+            _ => Err(MinecraftError::CommandTimeout),
+        }
+    }
+
+    async fn resume_saving(&self) -> Result<(), MinecraftError> {
+        self.command(String::from("save-on")).await?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), fields(server = %self.name(), backend = self.backend.kind()))]
+    pub async fn start(&self) -> Result<String, BackendError> {
+        let job = self.backend.start().await?;
+        self.publish(ServerEvent::LifecycleRequested { action: "start" });
+        Ok(job)
+    }
+
+    #[tracing::instrument(skip(self), fields(server = %self.name(), backend = self.backend.kind()))]
+    pub async fn stop(&self) -> Result<String, BackendError> {
+        let job = self.backend.stop().await?;
+        self.publish(ServerEvent::LifecycleRequested { action: "stop" });
+        Ok(job)
+    }
+
+    #[tracing::instrument(skip(self), fields(server = %self.name(), backend = self.backend.kind()))]
+    pub async fn restart(&self) -> Result<String, BackendError> {
+        let job = self.backend.restart().await?;
+        self.publish(ServerEvent::LifecycleRequested { action: "restart" });
+        Ok(job)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self), fields(server = %self.name(), backend = self.backend.kind()))]
+    pub async fn status(&self) -> Result<ServerStatus, BackendError> {
+        self.backend.status().await
+    }
+
+    /// Sends a command and waits for the server's reply. Over RCON this is
+    /// the response packet; otherwise the log lines that show up right after
+    /// the write are taken as the reply.
+    #[tracing::instrument(skip(self, command), fields(server = %self.name(), command = %command.trim_end()))]
+    pub async fn execute(&self, command: String) -> Result<Option<String>, MinecraftError> {
+        let limit = Duration::from_millis(self.config.command_timeout_ms.unwrap_or(2000));
+
+        if self.rcon.is_some() {
+            return match tokio::time::timeout(limit, self.command(command)).await {
+                Ok(r) => r,
+                Err(_) => Err(MinecraftError::CommandTimeout),
+            };
+        }
+
+        // Subscribe before writing so the first line of the reply can't be
+        // missed.
+        let mut rx = self.tx.subscribe();
+        self.command(command).await?;
+
+        let deadline = Instant::now() + limit;
+        let mut lines: Vec<String> = Vec::new();
+        loop {
+            // Once something has arrived, only wait a short while for the
+            // rest of a multi-line reply.
+            let wait = if lines.is_empty() {
+                deadline.saturating_duration_since(Instant::now())
+            } else {
+                Duration::from_millis(250).min(deadline.saturating_duration_since(Instant::now()))
+            };
+            match tokio::time::timeout(wait, rx.recv()).await {
+                Ok(Ok(entry)) => lines.push(parser::strip_log_prefix(&entry.message).to_owned()),
+                Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+                Ok(Err(broadcast::error::RecvError::Closed)) => break,
+                Err(_) => break,
             }
         }
+
+        if lines.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(lines.join("\n")))
+    }
+}
+
+fn journal_filter(config: &MinecraftConfig) -> JournalFilter {
+    let units = match &config.journal_units {
+        Some(u) => u.clone(),
+        None => match &config.systemd_unit {
+            Some(u) => vec![u.clone()],
+            None => vec![String::from("minecraft-server.service")],
+        },
+    };
+    JournalFilter {
+        units,
+        identifiers: match &config.journal_identifiers {
+            Some(i) => i.clone(),
+            None => Vec::new(),
+        },
+        max_priority: config.journal_max_priority,
     }
 }
@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use data_encoding::HEXLOWER;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha1::Sha1;
+use sha2::{Digest, Sha512};
+use tokio::fs;
+
+const API_URL: &str = "https://api.modrinth.com/v2";
+// Modrinth asks for one that says who's calling.
+const USER_AGENT: &str = concat!("gmemstr/minecraft-control/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ModrinthConfig {
+    // For a mirror, or a staging instance when testing.
+    api_url: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ModrinthError {
+    Http(reqwest::Error),
+    Io(std::io::Error),
+    NotFound,
+    // Nothing on Modrinth for this loader and Minecraft version.
+    Incompatible,
+    // The download didn't match the hash Modrinth gave for it.
+    Corrupt,
+}
+
+impl From<reqwest::Error> for ModrinthError {
+    fn from(e: reqwest::Error) -> Self {
+        ModrinthError::Http(e)
+    }
+}
+
+impl From<std::io::Error> for ModrinthError {
+    fn from(e: std::io::Error) -> Self {
+        ModrinthError::Io(e)
+    }
+}
+
+impl std::fmt::Display for ModrinthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModrinthError::Http(e) => write!(f, "could not reach Modrinth: {}", e),
+            ModrinthError::Io(e) => write!(f, "{}", e),
+            ModrinthError::NotFound => write!(f, "there's no such project or version on Modrinth"),
+            ModrinthError::Incompatible => {
+                write!(
+                    f,
+                    "there's no version for this server's loader and Minecraft version"
+                )
+            }
+            ModrinthError::Corrupt => write!(f, "the download didn't match its hash"),
+        }
+    }
+}
+
+/// What a server runs, which is what versions are picked for.
+pub struct Platform {
+    // Modrinth's name for it, e.g. "fabric" or "paper".
+    pub loader: String,
+    pub game_version: String,
+}
+
+impl Platform {
+    /// Plugin loaders take jars in plugins/ rather than mods/.
+    pub fn folder(&self) -> &'static str {
+        match self.loader.as_str() {
+            "paper" | "purpur" | "spigot" | "bukkit" | "folia" => "plugins",
+            _ => "mods",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SearchHit {
+    pub project_id: String,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub author: String,
+    pub downloads: u64,
+    pub icon_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SearchResults {
+    hits: Vec<SearchHit>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Version {
+    pub id: String,
+    pub project_id: String,
+    pub version_number: String,
+    pub files: Vec<VersionFile>,
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VersionFile {
+    pub url: String,
+    pub filename: String,
+    #[serde(default)]
+    pub primary: bool,
+    pub hashes: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Dependency {
+    pub project_id: Option<String>,
+    // "required", "optional", "incompatible" or "embedded".
+    pub dependency_type: String,
+}
+
+impl Version {
+    fn primary_file(&self) -> Option<&VersionFile> {
+        self.files.iter().find(|f| f.primary).or(self.files.first())
+    }
+}
+
+#[derive(Serialize)]
+pub struct Installed {
+    // Relative to the server directory, e.g. "mods/sodium-0.5.11.jar".
+    pub file: String,
+    pub version: Version,
+    // Projects it needs that aren't installed, which have to be installed
+    // as well.
+    pub missing: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Update {
+    pub file: String,
+    pub project_id: String,
+    pub current: String,
+    pub latest: Version,
+}
+
+/// A client for the Modrinth API.
+#[derive(Clone)]
+pub struct Modrinth {
+    http: reqwest::Client,
+    api_url: String,
+}
+
+pub fn init(config: Option<ModrinthConfig>) -> Modrinth {
+    let api_url = match config.and_then(|c| c.api_url) {
+        Some(u) => u.trim_end_matches('/').to_owned(),
+        None => String::from(API_URL),
+    };
+    Modrinth {
+        http: reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .unwrap(),
+        api_url,
+    }
+}
+
+fn sha1_file(bytes: &[u8]) -> String {
+    HEXLOWER.encode(&Sha1::digest(bytes))
+}
+
+/// Jar names from Modrinth end up in the server directory, so they can't
+/// be paths.
+fn safe_filename(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name.ends_with(".jar")
+        && !name.contains(['/', '\\'])
+}
+
+impl Modrinth {
+    /// Projects matching `query` that have a version for the platform.
+    pub async fn search(
+        &self,
+        query: &str,
+        platform: &Platform,
+        limit: u32,
+    ) -> Result<Vec<SearchHit>, ModrinthError> {
+        let facets = json!([
+            [format!("categories:{}", platform.loader)],
+            [format!("versions:{}", platform.game_version)],
+            ["server_side:required", "server_side:optional"],
+        ]);
+        let results: SearchResults = self
+            .http
+            .get(format!("{}/search", self.api_url))
+            .query(&[
+                ("query", query.to_owned()),
+                ("facets", facets.to_string()),
+                ("limit", limit.min(100).to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(results.hits)
+    }
+
+    /// The project's versions for the platform, newest first.
+    pub async fn versions(
+        &self,
+        project: &str,
+        platform: &Platform,
+    ) -> Result<Vec<Version>, ModrinthError> {
+        let response = self
+            .http
+            .get(format!("{}/project/{}/version", self.api_url, project))
+            .query(&[
+                ("loaders", json!([platform.loader]).to_string()),
+                ("game_versions", json!([platform.game_version]).to_string()),
+            ])
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(ModrinthError::NotFound);
+        }
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    async fn version(&self, id: &str) -> Result<Version, ModrinthError> {
+        let response = self
+            .http
+            .get(format!("{}/version/{}", self.api_url, id))
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(ModrinthError::NotFound);
+        }
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    /// Downloads a version of a project into the server's mods or plugins
+    /// folder: the one given, or the newest that suits the platform.
+    pub async fn install(
+        &self,
+        server_dir: &Path,
+        project: &str,
+        version: Option<&str>,
+        platform: &Platform,
+    ) -> Result<Installed, ModrinthError> {
+        let version = match version {
+            Some(v) => self.version(v).await?,
+            None => match self.versions(project, platform).await?.into_iter().next() {
+                Some(v) => v,
+                None => return Err(ModrinthError::Incompatible),
+            },
+        };
+        let file = match version.primary_file() {
+            Some(f) if safe_filename(&f.filename) => f.clone(),
+            _ => return Err(ModrinthError::NotFound),
+        };
+
+        let bytes = self
+            .http
+            .get(&file.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let expected = file.hashes.get("sha512").or(file.hashes.get("sha1"));
+        let actual = match file.hashes.get("sha512") {
+            Some(_) => HEXLOWER.encode(&Sha512::digest(&bytes)),
+            None => sha1_file(&bytes),
+        };
+        if expected != Some(&actual) {
+            return Err(ModrinthError::Corrupt);
+        }
+
+        let folder = server_dir.join(platform.folder());
+        fs::create_dir_all(&folder).await?;
+        let tmp = folder.join(format!(".{}.tmp", file.filename));
+        fs::write(&tmp, &bytes).await?;
+        fs::rename(&tmp, folder.join(&file.filename)).await?;
+
+        let installed = self.installed_projects(server_dir, platform).await?;
+        let missing = version
+            .dependencies
+            .iter()
+            .filter(|d| d.dependency_type == "required")
+            .filter_map(|d| d.project_id.clone())
+            .filter(|p| !installed.contains(p))
+            .collect();
+        Ok(Installed {
+            file: format!("{}/{}", platform.folder(), file.filename),
+            version,
+            missing,
+        })
+    }
+
+    /// SHA-1 of every jar in the platform's folder, by file name.
+    async fn hashes(
+        &self,
+        server_dir: &Path,
+        platform: &Platform,
+    ) -> Result<HashMap<String, String>, ModrinthError> {
+        let mut hashes = HashMap::new();
+        let mut entries = match fs::read_dir(server_dir.join(platform.folder())).await {
+            Ok(e) => e,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(hashes),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.ends_with(".jar") {
+                hashes.insert(sha1_file(&fs::read(entry.path()).await?), name);
+            }
+        }
+        Ok(hashes)
+    }
+
+    /// Which Modrinth version each hash is, for the jars that came from
+    /// there.
+    async fn identify(
+        &self,
+        hashes: Vec<&String>,
+    ) -> Result<HashMap<String, Version>, ModrinthError> {
+        Ok(self
+            .http
+            .post(format!("{}/version_files", self.api_url))
+            .json(&json!({ "hashes": hashes, "algorithm": "sha1" }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    async fn installed_projects(
+        &self,
+        server_dir: &Path,
+        platform: &Platform,
+    ) -> Result<Vec<String>, ModrinthError> {
+        let hashes = self.hashes(server_dir, platform).await?;
+        let known = self.identify(hashes.keys().collect()).await?;
+        Ok(known.into_values().map(|v| v.project_id).collect())
+    }
+
+    /// Installed jars from Modrinth that have a newer version for the
+    /// platform.
+    pub async fn updates(
+        &self,
+        server_dir: &Path,
+        platform: &Platform,
+    ) -> Result<Vec<Update>, ModrinthError> {
+        let hashes = self.hashes(server_dir, platform).await?;
+        if hashes.is_empty() {
+            return Ok(Vec::new());
+        }
+        let current = self.identify(hashes.keys().collect()).await?;
+        let latest: HashMap<String, Version> = self
+            .http
+            .post(format!("{}/version_files/update", self.api_url))
+            .json(&json!({
+                "hashes": hashes.keys().collect::<Vec<_>>(),
+                "algorithm": "sha1",
+                "loaders": [platform.loader],
+                "game_versions": [platform.game_version],
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let mut updates: Vec<Update> = latest
+            .into_iter()
+            .filter_map(|(hash, latest)| {
+                let installed = current.get(&hash)?;
+                if installed.id == latest.id {
+                    return None;
+                }
+                Some(Update {
+                    file: format!("{}/{}", platform.folder(), hashes.get(&hash)?),
+                    project_id: latest.project_id.clone(),
+                    current: installed.version_number.clone(),
+                    latest,
+                })
+            })
+            .collect();
+        updates.sort_by(|a, b| a.file.cmp(&b.file));
+        Ok(updates)
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value;
+
+// Where loaders look for jars, relative to the server directory.
+const FOLDERS: &[&str] = &["mods", "plugins"];
+// Metadata bigger than this isn't read.
+const MAX_METADATA: u64 = 1024 * 1024;
+
+#[derive(Serialize)]
+pub struct Mod {
+    // e.g. "mods/sodium-fabric-0.5.11.jar".
+    pub file: String,
+    // "fabric", "quilt", "forge", "neoforge", "paper" or "bukkit", or None
+    // for jars without any metadata the panel knows.
+    pub loader: Option<&'static str>,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    // What it needs, like "minecraft" or "fabricloader", and which
+    // versions of each.
+    pub requires: BTreeMap<String, String>,
+    // Bytes on disk.
+    pub size: u64,
+}
+
+fn read_entry(jar: &mut zip::ZipArchive<std::fs::File>, name: &str) -> Option<String> {
+    let entry = jar.by_name(name).ok()?;
+    let mut text = String::new();
+    entry.take(MAX_METADATA).read_to_string(&mut text).ok()?;
+    Some(text)
+}
+
+fn json_text(value: Option<&Value>) -> Option<String> {
+    value.and_then(|v| v.as_str()).map(|s| s.to_owned())
+}
+
+/// fabric.mod.json and quilt.mod.json. Fabric lets a dependency be a list
+/// of version ranges, any of which will do.
+fn fabric(mod_json: &str, loader: &'static str, m: &mut Mod) {
+    let json: Value = match serde_json::from_str(mod_json) {
+        Ok(j) => j,
+        Err(_) => return,
+    };
+    // Quilt puts everything under quilt_loader.
+    let (info, depends) = match json.get("quilt_loader") {
+        Some(q) => (q.get("metadata").unwrap_or(q), q.get("depends")),
+        None => (&json, json.get("depends")),
+    };
+    m.loader = Some(loader);
+    m.id = json_text(
+        json.get("id")
+            .or(json.get("quilt_loader").and_then(|q| q.get("id"))),
+    );
+    m.name = json_text(info.get("name"));
+    m.version = json_text(
+        json.get("version")
+            .or(json.get("quilt_loader").and_then(|q| q.get("version"))),
+    );
+    match depends {
+        Some(Value::Object(deps)) => {
+            for (dep, range) in deps {
+                let range = match range {
+                    Value::String(s) => s.clone(),
+                    Value::Array(a) => a
+                        .iter()
+                        .filter_map(|r| r.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" || "),
+                    _ => String::from("*"),
+                };
+                m.requires.insert(dep.clone(), range);
+            }
+        }
+        // Quilt's are a list of ids or of objects with an id and versions.
+        Some(Value::Array(deps)) => {
+            for dep in deps {
+                match dep {
+                    Value::String(id) => {
+                        m.requires.insert(id.clone(), String::from("*"));
+                    }
+                    Value::Object(d) => {
+                        if let Some(id) = d.get("id").and_then(|i| i.as_str()) {
+                            let versions = json_text(d.get("versions"));
+                            m.requires
+                                .insert(id.to_owned(), versions.unwrap_or(String::from("*")));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// META-INF/mods.toml and neoforge.mods.toml. Only the first mod in the
+/// jar is described.
+fn forge(mods_toml: &str, loader: &'static str, manifest: Option<String>, m: &mut Mod) {
+    let toml: toml::Value = match toml::from_str(mods_toml) {
+        Ok(t) => t,
+        Err(_) => return,
+    };
+    let first = match toml
+        .get("mods")
+        .and_then(|m| m.as_array())
+        .and_then(|m| m.first())
+    {
+        Some(f) => f,
+        None => return,
+    };
+    let text =
+        |v: &toml::Value, key: &str| v.get(key).and_then(|t| t.as_str()).map(|s| s.to_owned());
+    m.loader = Some(loader);
+    m.id = text(first, "modId");
+    m.name = text(first, "displayName");
+    m.version = text(first, "version");
+    // Filled in from the manifest when the jar is built.
+    if m.version.as_deref() == Some("${file.jarVersion}") {
+        m.version = manifest.and_then(|mf| {
+            mf.lines()
+                .find_map(|l| l.strip_prefix("Implementation-Version:"))
+                .map(|v| v.trim().to_owned())
+        });
+    }
+    if let Some(range) = text(&toml, "loaderVersion") {
+        m.requires.insert(loader.to_owned(), range);
+    }
+    let deps =
+        m.id.as_ref()
+            .and_then(|id| toml.get("dependencies")?.get(id)?.as_array());
+    for dep in deps.into_iter().flatten() {
+        // Forge marks them mandatory, NeoForge gives them a type.
+        let required = dep
+            .get("mandatory")
+            .and_then(|b| b.as_bool())
+            .unwrap_or(false)
+            || text(dep, "type").as_deref() == Some("required");
+        if let (true, Some(id)) = (required, text(dep, "modId")) {
+            m.requires
+                .insert(id, text(dep, "versionRange").unwrap_or(String::from("*")));
+        }
+    }
+}
+
+/// The top-level `key: value` pairs of plugin.yml, which is all that's
+/// needed from it. Lists are kept in their flow form, e.g. "[Vault]".
+fn yaml_value(yaml: &str, key: &str) -> Option<String> {
+    yaml.lines().find_map(|l| {
+        let value = l.strip_prefix(key)?.strip_prefix(':')?.trim();
+        let value = value.trim_matches(|c| c == '"' || c == '\'');
+        if value.is_empty() {
+            None
+        } else {
+            Some(value.to_owned())
+        }
+    })
+}
+
+fn bukkit(plugin_yml: &str, loader: &'static str, m: &mut Mod) {
+    m.loader = Some(loader);
+    m.name = yaml_value(plugin_yml, "name");
+    m.id = m.name.clone();
+    m.version = yaml_value(plugin_yml, "version");
+    if let Some(api) = yaml_value(plugin_yml, "api-version") {
+        m.requires.insert(String::from("minecraft"), api);
+    }
+    if let Some(depend) = yaml_value(plugin_yml, "depend") {
+        for dep in depend.trim_matches(|c| c == '[' || c == ']').split(',') {
+            let dep = dep.trim().trim_matches(|c| c == '"' || c == '\'');
+            if !dep.is_empty() {
+                m.requires.insert(dep.to_owned(), String::from("*"));
+            }
+        }
+    }
+}
+
+fn describe(path: &Path, file: String) -> Mod {
+    let mut m = Mod {
+        file,
+        loader: None,
+        id: None,
+        name: None,
+        version: None,
+        requires: BTreeMap::new(),
+        size: std::fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+    };
+    let mut jar = match std::fs::File::open(path).map(zip::ZipArchive::new) {
+        Ok(Ok(j)) => j,
+        _ => return m,
+    };
+    if let Some(json) = read_entry(&mut jar, "fabric.mod.json") {
+        fabric(&json, "fabric", &mut m);
+    } else if let Some(json) = read_entry(&mut jar, "quilt.mod.json") {
+        fabric(&json, "quilt", &mut m);
+    } else if let Some(toml) = read_entry(&mut jar, "META-INF/neoforge.mods.toml") {
+        let manifest = read_entry(&mut jar, "META-INF/MANIFEST.MF");
+        forge(&toml, "neoforge", manifest, &mut m);
+    } else if let Some(toml) = read_entry(&mut jar, "META-INF/mods.toml") {
+        let manifest = read_entry(&mut jar, "META-INF/MANIFEST.MF");
+        forge(&toml, "forge", manifest, &mut m);
+    } else if let Some(yml) = read_entry(&mut jar, "paper-plugin.yml") {
+        bukkit(&yml, "paper", &mut m);
+    } else if let Some(yml) = read_entry(&mut jar, "plugin.yml") {
+        bukkit(&yml, "bukkit", &mut m);
+    }
+    m
+}
+
+/// Every jar in the server's mods and plugins folders, with what its
+/// metadata says about it. This blocks, so call it from `spawn_blocking`.
+pub fn list(server_dir: &Path) -> Vec<Mod> {
+    let mut mods = Vec::new();
+    for folder in FOLDERS {
+        let entries = match std::fs::read_dir(server_dir.join(folder)) {
+            Ok(e) => e,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.ends_with(".jar") {
+                continue;
+            }
+            mods.push(describe(&entry.path(), format!("{}/{}", folder, name)));
+        }
+    }
+    mods.sort_by(|a, b| a.file.cmp(&b.file));
+    mods
+}
+
+/// The loader most of the jars are for, going by their metadata.
+pub fn loader(mods: &[Mod]) -> Option<&'static str> {
+    let mut counts: BTreeMap<&'static str, usize> = BTreeMap::new();
+    for loader in mods.iter().filter_map(|m| m.loader) {
+        *counts.entry(loader).or_default() += 1;
+    }
+    counts.into_iter().max_by_key(|(_, n)| *n).map(|(l, _)| l)
+}
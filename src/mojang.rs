@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::parser::is_player_name;
+use crate::util::now;
+
+const PROFILE_BY_NAME: &str = "https://api.mojang.com/users/profiles/minecraft/";
+const PROFILE_BY_UUID: &str = "https://sessionserver.mojang.com/session/minecraft/profile/";
+const HEAD_URL: &str = "https://crafatar.com/avatars/{uuid}?size=64&overlay";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MojangConfig {
+    // Where looked up players are kept between restarts.
+    cache_path: Option<String>,
+    // How long a name is trusted for, since players can change theirs.
+    cache_days: Option<u64>,
+    // Mojang allows around 600 requests every ten minutes per address.
+    requests_per_minute: Option<u32>,
+    // Where player heads come from, with {uuid} in place of the player's.
+    head_url: Option<String>,
+    // Where fetched heads are kept.
+    head_cache_dir: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Profile {
+    // Lowercase and hyphenated, as the server writes them.
+    pub uuid: String,
+    pub name: String,
+    // Seconds since the epoch.
+    looked_up: u64,
+}
+
+#[derive(Debug)]
+pub enum MojangError {
+    Http(reqwest::Error),
+    // Either our own limit or Mojang's.
+    RateLimited,
+}
+
+impl From<reqwest::Error> for MojangError {
+    fn from(e: reqwest::Error) -> Self {
+        MojangError::Http(e)
+    }
+}
+
+impl std::fmt::Display for MojangError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MojangError::Http(e) => write!(f, "could not reach Mojang: {}", e),
+            MojangError::RateLimited => write!(f, "too many Mojang lookups, try again shortly"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiProfile {
+    id: String,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Cache {
+    // Both keyed by the lowercase name or the UUID.
+    by_name: HashMap<String, Profile>,
+    by_uuid: HashMap<String, Profile>,
+}
+
+/// Turns player names into UUIDs and back through the Mojang API, keeping
+/// what it finds in a JSON file so each player is only looked up once in a
+/// while.
+#[derive(Clone)]
+pub struct Mojang {
+    http: reqwest::Client,
+    path: PathBuf,
+    ttl: u64,
+    cache: Arc<Mutex<Cache>>,
+    // Requests are spaced out by this much, and wait at most MAX_WAIT for
+    // their turn.
+    spacing: Duration,
+    next_request: Arc<Mutex<Instant>>,
+    head_url: String,
+    head_dir: PathBuf,
+}
+
+const MAX_WAIT: Duration = Duration::from_secs(5);
+
+/// Reads a UUID with or without hyphens into the hyphenated form.
+pub fn parse_uuid(text: &str) -> Option<String> {
+    let hex: String = text.chars().filter(|c| *c != '-').collect();
+    if hex.len() != 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let hex = hex.to_lowercase();
+    Some(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    ))
+}
+
+pub fn init(config: Option<MojangConfig>) -> Mojang {
+    let config = match config {
+        Some(c) => c,
+        None => MojangConfig {
+            cache_path: None,
+            cache_days: None,
+            requests_per_minute: None,
+            head_url: None,
+            head_cache_dir: None,
+        },
+    };
+    let path = PathBuf::from(match config.cache_path {
+        Some(p) => p,
+        None => String::from("mojang-cache.json"),
+    });
+    // It's only a cache, so a corrupt one is started over rather than
+    // keeping the panel from starting.
+    let cache = match std::fs::read_to_string(&path) {
+        Ok(file) => match serde_json::from_str(&file) {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "discarding the corrupt Mojang cache");
+                Cache::default()
+            }
+        },
+        Err(_) => Cache::default(),
+    };
+    let per_minute = config.requests_per_minute.unwrap_or(60).max(1);
+    Mojang {
+        http: reqwest::Client::new(),
+        path,
+        ttl: config.cache_days.unwrap_or(7) * 24 * 60 * 60,
+        cache: Arc::new(Mutex::new(cache)),
+        spacing: Duration::from_secs(60) / per_minute,
+        next_request: Arc::new(Mutex::new(Instant::now())),
+        head_url: match config.head_url {
+            Some(u) => u,
+            None => String::from(HEAD_URL),
+        },
+        head_dir: PathBuf::from(match config.head_cache_dir {
+            Some(d) => d,
+            None => String::from("heads"),
+        }),
+    }
+}
+
+impl Mojang {
+    /// Looks up a player by name or by UUID. None if there's no such
+    /// account.
+    pub async fn resolve(&self, player: &str) -> Result<Option<Profile>, MojangError> {
+        match parse_uuid(player) {
+            Some(uuid) => self.by_uuid(&uuid).await,
+            None => self.by_name(player).await,
+        }
+    }
+
+    pub async fn by_name(&self, name: &str) -> Result<Option<Profile>, MojangError> {
+        if !is_player_name(name) {
+            return Ok(None);
+        }
+        let key = name.to_lowercase();
+        if let Some(p) = self.cached(|c| c.by_name.get(&key).cloned()).await {
+            return Ok(Some(p));
+        }
+        self.fetch(&format!("{}{}", PROFILE_BY_NAME, name)).await
+    }
+
+    pub async fn by_uuid(&self, uuid: &str) -> Result<Option<Profile>, MojangError> {
+        let uuid = match parse_uuid(uuid) {
+            Some(u) => u,
+            None => return Ok(None),
+        };
+        if let Some(p) = self.cached(|c| c.by_uuid.get(&uuid).cloned()).await {
+            return Ok(Some(p));
+        }
+        self.fetch(&format!("{}{}", PROFILE_BY_UUID, uuid.replace('-', "")))
+            .await
+    }
+
+    async fn cached(&self, get: impl FnOnce(&Cache) -> Option<Profile>) -> Option<Profile> {
+        let cache = self.cache.lock().await;
+        get(&cache).filter(|p| p.looked_up + self.ttl > now())
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Option<Profile>, MojangError> {
+        self.wait_turn().await?;
+        let response = self.http.get(url).send().await?;
+        match response.status() {
+            // Either endpoint answers an unknown player with no content or
+            // a 404, depending on its mood.
+            StatusCode::NO_CONTENT | StatusCode::NOT_FOUND => return Ok(None),
+            StatusCode::TOO_MANY_REQUESTS => return Err(MojangError::RateLimited),
+            _ => {}
+        }
+        let found: ApiProfile = response.error_for_status()?.json().await?;
+        let uuid = match parse_uuid(&found.id) {
+            Some(u) => u,
+            None => return Ok(None),
+        };
+        let profile = Profile {
+            uuid: uuid.clone(),
+            name: found.name,
+            looked_up: now(),
+        };
+
+        let mut cache = self.cache.lock().await;
+        cache
+            .by_name
+            .insert(profile.name.to_lowercase(), profile.clone());
+        cache.by_uuid.insert(uuid, profile.clone());
+        if let Err(e) = self.save(&cache).await {
+            tracing::warn!(error = %e, "could not save the Mojang cache");
+        }
+        Ok(Some(profile))
+    }
+
+    async fn wait_turn(&self) -> Result<(), MojangError> {
+        let at = {
+            let mut next = self.next_request.lock().await;
+            let now = Instant::now();
+            let at = (*next).max(now);
+            if at > now + MAX_WAIT {
+                return Err(MojangError::RateLimited);
+            }
+            *next = at + self.spacing;
+            at
+        };
+        tokio::time::sleep_until(at).await;
+        Ok(())
+    }
+
+    /// A PNG of the player's head, fetched once and then kept for as long
+    /// as names are.
+    pub async fn head(&self, uuid: &str) -> Result<Option<Bytes>, MojangError> {
+        let uuid = match parse_uuid(uuid) {
+            Some(u) => u,
+            None => return Ok(None),
+        };
+        let path = self.head_dir.join(format!("{}.png", uuid));
+        if let Ok(metadata) = fs::metadata(&path).await {
+            let fresh = match metadata.modified().map(|m| m.elapsed()) {
+                Ok(Ok(age)) => age.as_secs() < self.ttl,
+                _ => false,
+            };
+            if fresh {
+                if let Ok(png) = fs::read(&path).await {
+                    return Ok(Some(Bytes::from(png)));
+                }
+            }
+        }
+
+        let response = self
+            .http
+            .get(self.head_url.replace("{uuid}", &uuid))
+            .send()
+            .await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => return Ok(None),
+            StatusCode::TOO_MANY_REQUESTS => return Err(MojangError::RateLimited),
+            _ => {}
+        }
+        let png = response.error_for_status()?.bytes().await?;
+        let saved = async {
+            fs::create_dir_all(&self.head_dir).await?;
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, &png).await?;
+            fs::rename(&tmp, &path).await
+        };
+        if let Err(e) = saved.await {
+            tracing::warn!(%uuid, error = %e, "could not save the head");
+        }
+        Ok(Some(png))
+    }
+
+    async fn save(&self, cache: &Cache) -> Result<(), std::io::Error> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(cache)?).await?;
+        fs::rename(&tmp, &self.path).await
+    }
+}
@@ -0,0 +1,309 @@
+use serde_json::{json, Map, Value};
+
+// Legacy colour codes and what the client shows for them.
+const COLOURS: &[(char, &str, &str)] = &[
+    ('0', "black", "#000000"),
+    ('1', "dark_blue", "#0000aa"),
+    ('2', "dark_green", "#00aa00"),
+    ('3', "dark_aqua", "#00aaaa"),
+    ('4', "dark_red", "#aa0000"),
+    ('5', "dark_purple", "#aa00aa"),
+    ('6', "gold", "#ffaa00"),
+    ('7', "gray", "#aaaaaa"),
+    ('8', "dark_gray", "#555555"),
+    ('9', "blue", "#5555ff"),
+    ('a', "green", "#55ff55"),
+    ('b', "aqua", "#55ffff"),
+    ('c', "red", "#ff5555"),
+    ('d', "light_purple", "#ff55ff"),
+    ('e', "yellow", "#ffff55"),
+    ('f', "white", "#ffffff"),
+];
+
+// Obfuscated, bold, strikethrough, underlined, italic and reset.
+const FORMATS: &str = "klmnor";
+
+/// The server list only shows two lines.
+const MAX_LINES: usize = 2;
+
+/// Turns a MOTD written with `&` or `§` codes, or as JSON text, into the
+/// `§` codes server.properties takes. The server doesn't read JSON there,
+/// so hex colours become the nearest legacy one.
+pub fn normalize(motd: &Value) -> Result<String, String> {
+    let legacy = match motd {
+        Value::String(s) if s.trim_start().starts_with(['{', '[']) => {
+            match serde_json::from_str::<Value>(s) {
+                Ok(json) => from_json(&json),
+                // Just text that happens to start with a bracket.
+                Err(_) => from_codes(s),
+            }
+        }
+        Value::String(s) => from_codes(s),
+        Value::Object(_) | Value::Array(_) => from_json(motd),
+        _ => return Err(String::from("motd has to be text or JSON text")),
+    };
+    let lines: Vec<&str> = legacy.split('\n').collect();
+    if lines.len() > MAX_LINES {
+        return Err(format!("motd can be at most {} lines", MAX_LINES));
+    }
+    Ok(lines.join("\n"))
+}
+
+fn is_code(c: char) -> bool {
+    let c = c.to_ascii_lowercase();
+    COLOURS.iter().any(|(code, _, _)| *code == c) || FORMATS.contains(c)
+}
+
+fn from_codes(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' | '§' if chars.peek().is_some_and(|n| is_code(*n)) => {
+                out.push('§');
+                out.extend(chars.next().map(|n| n.to_ascii_lowercase()));
+            }
+            '\n' => out.push('\n'),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn colour_code(name: &str) -> Option<char> {
+    if let Some((code, _, _)) = COLOURS.iter().find(|(_, n, _)| *n == name) {
+        return Some(*code);
+    }
+    let hex = u32::from_str_radix(name.strip_prefix('#')?, 16).ok()?;
+    let rgb = |v: u32| [(v >> 16) & 0xff, (v >> 8) & 0xff, v & 0xff];
+    let want = rgb(hex);
+    COLOURS
+        .iter()
+        .min_by_key(|(_, _, h)| {
+            let have = rgb(u32::from_str_radix(&h[1..], 16).unwrap_or(0));
+            (0..3)
+                .map(|i| (want[i] as i64 - have[i] as i64).pow(2))
+                .sum::<i64>()
+        })
+        .map(|(code, _, _)| *code)
+}
+
+fn from_json(json: &Value) -> String {
+    let mut out = String::new();
+    push_component(json, &mut out);
+    out
+}
+
+/// Writes a component and its children. Each one starts from a reset, so
+/// styles are written out again rather than inherited.
+fn push_component(json: &Value, out: &mut String) {
+    match json {
+        Value::String(s) => out.push_str(&from_codes(s)),
+        Value::Array(parts) => {
+            for part in parts {
+                push_component(part, out);
+            }
+        }
+        Value::Object(component) => {
+            let mut style = String::from("§r");
+            if let Some(code) = component
+                .get("color")
+                .and_then(|c| c.as_str())
+                .and_then(colour_code)
+            {
+                style.push('§');
+                style.push(code);
+            }
+            for (key, code) in [
+                ("obfuscated", 'k'),
+                ("bold", 'l'),
+                ("strikethrough", 'm'),
+                ("underlined", 'n'),
+                ("italic", 'o'),
+            ] {
+                if component.get(key).and_then(|v| v.as_bool()) == Some(true) {
+                    style.push('§');
+                    style.push(code);
+                }
+            }
+            if let Some(text) = component.get("text").and_then(|t| t.as_str()) {
+                out.push_str(&style);
+                out.push_str(&from_codes(text));
+            }
+            if let Some(extra) = component.get("extra") {
+                push_component(extra, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            '\n' => out.push_str("<br>"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Renders `§` codes as HTML spans with inline styles. Obfuscated text gets
+/// an `obfuscated` class for the frontend to animate.
+pub fn preview(motd: &str) -> String {
+    let mut html = String::new();
+    let mut colour: Option<&str> = None;
+    let mut formats = String::new();
+    let mut text = String::new();
+
+    let flush = |html: &mut String, text: &mut String, colour: Option<&str>, formats: &str| {
+        if text.is_empty() {
+            return;
+        }
+        let mut style = String::new();
+        if let Some(c) = colour {
+            style.push_str(&format!("color:{};", c));
+        }
+        if formats.contains('l') {
+            style.push_str("font-weight:bold;");
+        }
+        if formats.contains('o') {
+            style.push_str("font-style:italic;");
+        }
+        match (formats.contains('n'), formats.contains('m')) {
+            (true, true) => style.push_str("text-decoration:underline line-through;"),
+            (true, false) => style.push_str("text-decoration:underline;"),
+            (false, true) => style.push_str("text-decoration:line-through;"),
+            (false, false) => {}
+        }
+        html.push_str("<span");
+        if formats.contains('k') {
+            html.push_str(" class=\"obfuscated\"");
+        }
+        html.push_str(&format!(" style=\"{}\">", style));
+        escape_html(text, html);
+        html.push_str("</span>");
+        text.clear();
+    };
+
+    let mut chars = motd.chars();
+    while let Some(c) = chars.next() {
+        if c != '§' {
+            text.push(c);
+            continue;
+        }
+        let code = match chars.next() {
+            Some(code) => code,
+            None => break,
+        };
+        flush(&mut html, &mut text, colour, &formats);
+        if let Some((_, _, hex)) = COLOURS.iter().find(|(c, _, _)| *c == code) {
+            // A colour clears the formatting, as it does in game.
+            colour = Some(hex);
+            formats.clear();
+        } else if code == 'r' {
+            colour = None;
+            formats.clear();
+        } else if FORMATS.contains(code) {
+            formats.push(code);
+        }
+    }
+    flush(&mut html, &mut text, colour, &formats);
+    html
+}
+
+/// A `[label](https://...)` link at the start of `text`, and how many
+/// bytes it takes.
+fn link(text: &str) -> Option<(&str, &str, usize)> {
+    let (label, rest) = text.strip_prefix('[')?.split_once("](")?;
+    let (url, _) = rest.split_once(')')?;
+    if label.is_empty() || label.contains(['[', ']']) || url.contains(char::is_whitespace) {
+        return None;
+    }
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return None;
+    }
+    Some((label, url, label.len() + url.len() + 4))
+}
+
+fn styled(text: &str, colour: Option<&str>, formats: &str) -> Map<String, Value> {
+    let mut component = Map::new();
+    component.insert(String::from("text"), json!(text));
+    if let Some(c) = colour {
+        component.insert(String::from("color"), json!(c));
+    }
+    for (code, key) in [
+        ('k', "obfuscated"),
+        ('l', "bold"),
+        ('m', "strikethrough"),
+        ('n', "underlined"),
+        ('o', "italic"),
+    ] {
+        if formats.contains(code) {
+            component.insert(String::from(key), json!(true));
+        }
+    }
+    component
+}
+
+/// Turns a message written with `&` or `§` codes into JSON text for
+/// `tellraw`, with `[label](https://...)` made into links that open when
+/// clicked.
+pub fn to_json(message: &str) -> Value {
+    let legacy = from_codes(message);
+    let mut parts = vec![json!("")];
+    let mut colour: Option<&str> = None;
+    let mut formats = String::new();
+    let mut text = String::new();
+
+    let mut rest = legacy.as_str();
+    while let Some(c) = rest.chars().next() {
+        if let Some((label, url, len)) = link(rest) {
+            if !text.is_empty() {
+                parts.push(Value::Object(styled(&text, colour, &formats)));
+                text.clear();
+            }
+            let mut component = styled(label, colour, &formats);
+            component.insert(String::from("underlined"), json!(true));
+            component.insert(
+                String::from("clickEvent"),
+                json!({ "action": "open_url", "value": url }),
+            );
+            parts.push(Value::Object(component));
+            rest = &rest[len..];
+            continue;
+        }
+        rest = &rest[c.len_utf8()..];
+        if c != '§' {
+            text.push(c);
+            continue;
+        }
+        let code = match rest.chars().next() {
+            Some(code) => code,
+            None => break,
+        };
+        rest = &rest[code.len_utf8()..];
+        if !text.is_empty() {
+            parts.push(Value::Object(styled(&text, colour, &formats)));
+            text.clear();
+        }
+        if let Some((_, name, _)) = COLOURS.iter().find(|(c, _, _)| *c == code) {
+            colour = Some(name);
+            formats.clear();
+        } else if code == 'r' {
+            colour = None;
+            formats.clear();
+        } else if FORMATS.contains(code) {
+            formats.push(code);
+        }
+    }
+    if !text.is_empty() {
+        parts.push(Value::Object(styled(&text, colour, &formats)));
+    }
+    Value::Array(parts)
+}
@@ -0,0 +1,45 @@
+use std::path::Path;
+
+use async_compression::tokio::bufread::GzipDecoder;
+use serde::de::DeserializeOwned;
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+
+#[derive(Debug)]
+pub enum NbtError {
+    Io(std::io::Error),
+    Nbt(fastnbt::error::Error),
+}
+
+impl std::fmt::Display for NbtError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NbtError::Io(e) => write!(f, "{}", e),
+            NbtError::Nbt(e) => write!(f, "could not read NBT: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for NbtError {
+    fn from(e: std::io::Error) -> Self {
+        NbtError::Io(e)
+    }
+}
+
+/// Reads a gzipped NBT file like level.dat or player data, or None if
+/// there isn't one.
+pub async fn read_gzipped<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, NbtError> {
+    let file = match fs::read(path).await {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut nbt = Vec::new();
+    GzipDecoder::new(file.as_slice())
+        .read_to_end(&mut nbt)
+        .await?;
+    match fastnbt::from_bytes(&nbt) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => Err(NbtError::Nbt(e)),
+    }
+}
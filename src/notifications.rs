@@ -0,0 +1,344 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::sync::broadcast::{self, Receiver};
+use tokio_util::sync::CancellationToken;
+
+use crate::events::{Event, EventBus, ServerEvent};
+use crate::minecraft::MinecraftControl;
+
+pub mod discord;
+pub mod matrix;
+pub mod slack;
+pub mod webhook;
+
+// What chat sinks post when no events are configured.
+const CHAT_EVENTS: &[&str] = &[
+    "chat",
+    "player_joined",
+    "player_left",
+    "death",
+    "server_started",
+    "server_stopping",
+    "crash",
+];
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NotificationsConfig {
+    webhooks: Option<Vec<webhook::WebhookConfig>>,
+    discord: Option<Vec<discord::DiscordConfig>>,
+    slack: Option<Vec<slack::SlackConfig>>,
+    matrix: Option<Vec<matrix::MatrixConfig>>,
+}
+
+/// Which events a notification is sent for.
+#[derive(Deserialize, Debug, Clone)]
+pub struct EventFilter {
+    // Event types, like "player_joined" or "crash". All of them if unset.
+    events: Option<Vec<String>>,
+    // Server names. All of them if unset.
+    servers: Option<Vec<String>>,
+}
+
+impl EventFilter {
+    /// Uses `events` for the types when none are configured.
+    pub fn with_default_events(mut self, events: &[&str]) -> EventFilter {
+        if self.events.is_none() {
+            self.events = Some(events.iter().map(|e| e.to_string()).collect());
+        }
+        self
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        let kind = match &self.events {
+            Some(e) => e.iter().any(|e| e == event.event.name()),
+            None => true,
+        };
+        let server = match &self.servers {
+            Some(s) => s.iter().any(|s| *s == event.server),
+            None => true,
+        };
+        kind && server
+    }
+}
+
+/// Somewhere people read notifications, like a chat channel.
+#[async_trait]
+pub trait Sink: Send + Sync + 'static {
+    fn describe(&self) -> String;
+
+    async fn send(&self, http: &reqwest::Client, event: &Event) -> Result<(), reqwest::Error>;
+}
+
+/// A notification in parts, so each sink can mark it up its own way:
+/// `subject` is shown in bold before `text`.
+pub struct Message {
+    pub icon: &'static str,
+    pub subject: Option<String>,
+    pub text: String,
+}
+
+impl Message {
+    /// The message as text, with everything put through `escape` and the
+    /// subject through `bold` as well.
+    pub fn render(&self, bold: impl Fn(&str) -> String, escape: impl Fn(&str) -> String) -> String {
+        let mut out = String::new();
+        if !self.icon.is_empty() {
+            out.push_str(self.icon);
+            out.push(' ');
+        }
+        if let Some(s) = &self.subject {
+            out.push_str(&bold(&escape(s)));
+            out.push(' ');
+        }
+        out.push_str(&escape(&self.text));
+        out
+    }
+}
+
+pub fn message(event: &Event) -> Message {
+    let (icon, subject, text) = match &event.event {
+        ServerEvent::Chat { player, message } => {
+            ("", Some(format!("<{}>", player)), message.clone())
+        }
+        ServerEvent::PlayerJoined { player } => {
+            ("", Some(player.clone()), String::from("joined the game"))
+        }
+        ServerEvent::PlayerLeft { player } => {
+            ("", Some(player.clone()), String::from("left the game"))
+        }
+        ServerEvent::Death { message, .. } => ("💀", None, message.clone()),
+        ServerEvent::Advancement {
+            player,
+            advancement,
+            ..
+        } => (
+            "🏆",
+            Some(player.clone()),
+            format!("made the advancement [{}]", advancement),
+        ),
+        ServerEvent::ServerStarted { seconds } => (
+            "🟢",
+            Some(event.server.clone()),
+            format!("started in {:.1}s", seconds),
+        ),
+        ServerEvent::ServerStopping => (
+            "🔴",
+            Some(event.server.clone()),
+            String::from("is stopping"),
+        ),
+        ServerEvent::Crash { message } => (
+            "💥",
+            Some(event.server.clone()),
+            format!("crashed: {}", message),
+        ),
+        ServerEvent::LifecycleRequested { action } => (
+            "",
+            Some(event.server.clone()),
+            format!("was asked to {}", action),
+        ),
+        ServerEvent::Error { message } => ("⚠️", Some(event.server.clone()), message.clone()),
+        ServerEvent::BackupFinished { id, size } => (
+            "💾",
+            Some(event.server.clone()),
+            format!("backed up as {} ({} MB)", id, size / 1024 / 1024),
+        ),
+        ServerEvent::BackupFailed { message } => (
+            "⚠️",
+            Some(event.server.clone()),
+            format!("backup failed: {}", message),
+        ),
+        ServerEvent::RestoreProgress { id, step } => (
+            "",
+            Some(event.server.clone()),
+            format!("restoring {}: {}", id, step),
+        ),
+        ServerEvent::RestoreFinished { id } => (
+            "♻️",
+            Some(event.server.clone()),
+            format!("was restored from {}", id),
+        ),
+        ServerEvent::RestoreFailed { id, message } => (
+            "⚠️",
+            Some(event.server.clone()),
+            format!("restoring {} failed: {}", id, message),
+        ),
+        ServerEvent::RestartWarning { minutes, .. } => (
+            "⏳",
+            Some(event.server.clone()),
+            format!("restarts in {} minutes", minutes),
+        ),
+        ServerEvent::IdleShutdown { minutes } => (
+            "💤",
+            Some(event.server.clone()),
+            format!("was stopped after {} minutes without players", minutes),
+        ),
+        ServerEvent::ServerUpdated { version, build } => (
+            "⬆️",
+            Some(event.server.clone()),
+            format!("was updated to {} build {}", version, build),
+        ),
+        ServerEvent::CrashRestart {
+            attempt,
+            delay_secs,
+            reason,
+        } => (
+            "🔁",
+            Some(event.server.clone()),
+            format!(
+                "crashed ({}), restart {} in {}s",
+                reason, attempt, delay_secs
+            ),
+        ),
+        ServerEvent::CrashLoop { restarts, minutes } => (
+            "🛑",
+            Some(event.server.clone()),
+            format!(
+                "kept crashing, {} restarts in {} minutes, and was left down",
+                restarts, minutes
+            ),
+        ),
+        ServerEvent::Unresponsive { failures } => (
+            "🐕",
+            Some(event.server.clone()),
+            format!("didn't answer {} pings and was restarted", failures),
+        ),
+        ServerEvent::MaintenanceChanged { enabled } => (
+            "🚧",
+            Some(event.server.clone()),
+            String::from(if *enabled {
+                "is down for maintenance"
+            } else {
+                "is out of maintenance"
+            }),
+        ),
+        ServerEvent::DiskSpaceLow {
+            directory,
+            available,
+            percent,
+        } => (
+            "💾",
+            Some(event.server.clone()),
+            format!(
+                "is low on disk space for its {}, {} MB ({:.1}%) left",
+                directory,
+                available / (1024 * 1024),
+                percent
+            ),
+        ),
+        ServerEvent::DirectoryOverLimit {
+            directory,
+            size,
+            limit,
+        } => (
+            "📦",
+            Some(event.server.clone()),
+            format!(
+                "has {} MB of {}, over its limit of {} MB",
+                size / (1024 * 1024),
+                directory,
+                limit / (1024 * 1024)
+            ),
+        ),
+        ServerEvent::LongGcPause { kind, duration_ms } => (
+            "🐢",
+            Some(event.server.clone()),
+            format!("was paused {:.0}ms by a GC ({})", duration_ms, kind),
+        ),
+        ServerEvent::MemoryPressure { percent } => (
+            "🧠",
+            Some(event.server.clone()),
+            format!(
+                "is short of memory, its heap is {:.0}% full after collecting",
+                percent
+            ),
+        ),
+    };
+    Message {
+        icon,
+        subject,
+        text,
+    }
+}
+
+/// Sends every matching event to a sink until `shutdown` is cancelled.
+pub async fn run_sink<S: Sink>(
+    sink: S,
+    filter: EventFilter,
+    http: reqwest::Client,
+    mut rx: Receiver<Event>,
+    shutdown: CancellationToken,
+) {
+    tracing::info!(sink = %sink.describe(), "sending events");
+    loop {
+        let event = tokio::select! {
+            event = rx.recv() => match event {
+                Ok(e) => e,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!(sink = %sink.describe(), missed = n, "notification sink fell behind");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            _ = shutdown.cancelled() => return,
+        };
+        if !filter.matches(&event) {
+            continue;
+        }
+        if let Err(e) = sink.send(&http, &event).await {
+            tracing::warn!(sink = %sink.describe(), error = %e, "could not send a notification");
+        }
+    }
+}
+
+/// Starts sending notifications for every configured destination until
+/// `shutdown` is cancelled.
+pub fn init(
+    config: Option<NotificationsConfig>,
+    events: &EventBus,
+    servers: &[MinecraftControl],
+    shutdown: CancellationToken,
+) {
+    let config = match config {
+        Some(c) => c,
+        None => return,
+    };
+    let http = reqwest::Client::new();
+    for hook in config.webhooks.unwrap_or_default() {
+        tracing::info!(url = %hook.url, "sending webhooks");
+        tokio::spawn(webhook::run(
+            hook,
+            http.clone(),
+            events.subscribe(),
+            shutdown.clone(),
+        ));
+    }
+    for discord in config.discord.unwrap_or_default() {
+        discord::start(
+            discord,
+            http.clone(),
+            events.subscribe(),
+            servers,
+            shutdown.clone(),
+        );
+    }
+    for slack in config.slack.unwrap_or_default() {
+        let filter = slack.filter.clone().with_default_events(CHAT_EVENTS);
+        tokio::spawn(run_sink(
+            slack,
+            filter,
+            http.clone(),
+            events.subscribe(),
+            shutdown.clone(),
+        ));
+    }
+    for matrix in config.matrix.unwrap_or_default() {
+        let filter = matrix.filter.clone().with_default_events(CHAT_EVENTS);
+        tokio::spawn(run_sink(
+            matrix::MatrixSink::new(matrix),
+            filter,
+            http.clone(),
+            events.subscribe(),
+            shutdown.clone(),
+        ));
+    }
+}
@@ -0,0 +1,208 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::broadcast::Receiver;
+use tokio_util::sync::CancellationToken;
+
+use super::{EventFilter, Sink, CHAT_EVENTS};
+use crate::events::{Event, ServerEvent};
+use crate::minecraft::MinecraftControl;
+
+const API: &str = "https://discord.com/api/v10";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DiscordConfig {
+    // Either a channel webhook, or a bot token and the channel to post in.
+    // Only a bot can read the channel to relay messages back.
+    webhook_url: Option<String>,
+    bot_token: Option<String>,
+    channel_id: Option<String>,
+    // Sends messages from the channel to the server with `say`.
+    relay_to_server: Option<bool>,
+    // Which server messages are relayed to; the first one if unset.
+    server: Option<String>,
+    #[serde(flatten)]
+    filter: EventFilter,
+}
+
+/// Posts events to the channel and, if asked to, relays the channel back.
+pub fn start(
+    config: DiscordConfig,
+    http: reqwest::Client,
+    rx: Receiver<Event>,
+    servers: &[MinecraftControl],
+    shutdown: CancellationToken,
+) {
+    if config.relay_to_server.unwrap_or(false) {
+        let target = match &config.server {
+            Some(name) => servers.iter().find(|s| s.name() == name).cloned(),
+            None => servers.first().cloned(),
+        };
+        match (target, &config.bot_token, &config.channel_id) {
+            (Some(control), Some(token), Some(channel)) => {
+                tokio::spawn(relay_to_server(
+                    http.clone(),
+                    token.clone(),
+                    channel.clone(),
+                    control,
+                    shutdown.clone(),
+                ));
+            }
+            (None, _, _) => tracing::warn!("no server to relay Discord messages to"),
+            _ => tracing::warn!(
+                "relaying Discord messages to the server needs bot_token and channel_id"
+            ),
+        }
+    }
+    let filter = config.filter.clone().with_default_events(CHAT_EVENTS);
+    tokio::spawn(super::run_sink(config, filter, http, rx, shutdown));
+}
+
+#[async_trait]
+impl Sink for DiscordConfig {
+    fn describe(&self) -> String {
+        match (&self.webhook_url, &self.channel_id) {
+            (Some(_), _) => String::from("a Discord webhook"),
+            (None, Some(c)) => format!("Discord channel {}", c),
+            (None, None) => String::from("nowhere (no webhook_url or channel_id)"),
+        }
+    }
+
+    async fn send(&self, http: &reqwest::Client, event: &Event) -> Result<(), reqwest::Error> {
+        let message = match &event.event {
+            // Webhooks can post chat under the player's name.
+            ServerEvent::Chat { player, message } if self.webhook_url.is_some() => json!({
+                "username": player,
+                "content": message,
+                "allowed_mentions": { "parse": [] },
+            }),
+            _ => json!({
+                "content": super::message(event).render(|s| format!("**{}**", s), escape),
+                "allowed_mentions": { "parse": [] },
+            }),
+        };
+        post(http, self, message).await
+    }
+}
+
+/// Keeps names like `__init__` from turning into markdown.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '_' | '~' | '`' | '|' | '>' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+async fn post(
+    http: &reqwest::Client,
+    config: &DiscordConfig,
+    message: Value,
+) -> Result<(), reqwest::Error> {
+    let request = match (&config.webhook_url, &config.bot_token, &config.channel_id) {
+        (Some(url), _, _) => http.post(url),
+        (None, Some(token), Some(channel)) => http
+            .post(format!("{}/channels/{}/messages", API, channel))
+            .header("Authorization", format!("Bot {}", token)),
+        _ => return Ok(()),
+    };
+    // Tried once more after waiting out a rate limit.
+    for _ in 0..2 {
+        let response = request
+            .try_clone()
+            .unwrap()
+            .json(&message)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?;
+        if response.status().as_u16() != 429 {
+            response.error_for_status()?;
+            return Ok(());
+        }
+        let retry_after = match response.json::<Value>().await {
+            Ok(v) => v["retry_after"].as_f64().unwrap_or(1.0),
+            Err(_) => 1.0,
+        };
+        tokio::time::sleep(Duration::from_secs_f64(retry_after.min(60.0))).await;
+    }
+    Ok(())
+}
+
+/// Polls the channel for new messages and says them in the server. Polling
+/// saves keeping a gateway connection for what's a low-traffic channel.
+async fn relay_to_server(
+    http: reqwest::Client,
+    token: String,
+    channel: String,
+    control: MinecraftControl,
+    shutdown: CancellationToken,
+) {
+    let url = format!("{}/channels/{}/messages", API, channel);
+    let auth = format!("Bot {}", token);
+    // Only messages sent from now on are relayed.
+    let mut last: Option<String> = None;
+    let mut started = false;
+    tracing::info!(%channel, server = %control.name(), "relaying a Discord channel");
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(3)) => {},
+            _ = shutdown.cancelled() => return,
+        }
+        let mut request = http
+            .get(&url)
+            .header("Authorization", &auth)
+            .timeout(Duration::from_secs(10));
+        request = match &last {
+            Some(id) => request.query(&[("after", id.as_str()), ("limit", "50")]),
+            None => request.query(&[("limit", "1")]),
+        };
+        let messages: Vec<Value> = match request.send().await.and_then(|r| r.error_for_status()) {
+            Ok(r) => match r.json().await {
+                Ok(m) => m,
+                Err(e) => {
+                    tracing::warn!(error = %e, "could not read Discord messages");
+                    continue;
+                }
+            },
+            Err(e) => {
+                tracing::warn!(error = %e, "could not read Discord messages");
+                continue;
+            }
+        };
+
+        // Newest first.
+        if let Some(id) = messages.first().and_then(|m| m["id"].as_str()) {
+            last = Some(id.to_owned());
+        }
+        if !started {
+            started = true;
+            continue;
+        }
+        for m in messages.iter().rev() {
+            // Skips the panel's own posts, and other bots.
+            if m["author"]["bot"].as_bool().unwrap_or(false) || m.get("webhook_id").is_some() {
+                continue;
+            }
+            let content = match m["content"].as_str() {
+                Some(c) if !c.trim().is_empty() => c.replace(['\n', '\r'], " "),
+                _ => continue,
+            };
+            let author = match m["author"]["global_name"]
+                .as_str()
+                .or_else(|| m["author"]["username"].as_str())
+            {
+                Some(a) => a.to_owned(),
+                None => String::from("discord"),
+            };
+            let command = format!("say [Discord] {}: {}", author, content);
+            if control.command(command).await.is_err() {
+                tracing::warn!(server = %control.name(), "could not relay a Discord message");
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{EventFilter, Sink};
+use crate::events::Event;
+
+/// A Matrix room, posted to as a user (usually a bot account) with an
+/// access token.
+#[derive(Deserialize, Debug, Clone)]
+pub struct MatrixConfig {
+    // Like "https://matrix.example.org".
+    homeserver: String,
+    access_token: String,
+    // The room's ID, "!abc:example.org", rather than an alias.
+    room_id: String,
+    #[serde(flatten)]
+    pub filter: EventFilter,
+}
+
+pub struct MatrixSink {
+    config: MatrixConfig,
+    // Transaction IDs have to be unique per access token, or the
+    // homeserver takes a message for a repeat of an earlier one.
+    transactions: AtomicU64,
+}
+
+impl MatrixSink {
+    pub fn new(config: MatrixConfig) -> MatrixSink {
+        MatrixSink {
+            config,
+            transactions: AtomicU64::new(0),
+        }
+    }
+
+    fn send_url(&self) -> Option<Url> {
+        let started = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_millis(),
+            Err(_) => 0,
+        };
+        let txn = format!(
+            "mcctl-{}-{}",
+            started,
+            self.transactions.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut url = Url::parse(&self.config.homeserver).ok()?;
+        // Segments are percent-encoded, which the ! and : in room IDs need.
+        url.path_segments_mut()
+            .ok()?
+            .pop_if_empty()
+            .extend(&["_matrix", "client", "v3", "rooms"])
+            .push(&self.config.room_id)
+            .extend(&["send", "m.room.message", txn.as_str()]);
+        Some(url)
+    }
+}
+
+#[async_trait]
+impl Sink for MatrixSink {
+    fn describe(&self) -> String {
+        format!("Matrix room {}", self.config.room_id)
+    }
+
+    async fn send(&self, http: &reqwest::Client, event: &Event) -> Result<(), reqwest::Error> {
+        let url = match self.send_url() {
+            Some(u) => u,
+            None => {
+                tracing::warn!(homeserver = %self.config.homeserver, "invalid Matrix homeserver");
+                return Ok(());
+            }
+        };
+        let message = super::message(event);
+        let body = message.render(|s| s.to_owned(), |s| s.to_owned());
+        let html = message.render(|s| format!("<b>{}</b>", s), escape);
+        http.put(url)
+            .bearer_auth(&self.config.access_token)
+            .json(&json!({
+                // Notices are what bots are meant to send.
+                "msgtype": "m.notice",
+                "body": body,
+                "format": "org.matrix.custom.html",
+                "formatted_body": html,
+            }))
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
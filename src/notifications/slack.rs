@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{EventFilter, Sink};
+use crate::events::Event;
+
+/// A Slack incoming webhook.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SlackConfig {
+    webhook_url: String,
+    #[serde(flatten)]
+    pub filter: EventFilter,
+}
+
+#[async_trait]
+impl Sink for SlackConfig {
+    fn describe(&self) -> String {
+        String::from("a Slack webhook")
+    }
+
+    async fn send(&self, http: &reqwest::Client, event: &Event) -> Result<(), reqwest::Error> {
+        let text = super::message(event).render(|s| format!("*{}*", s), escape);
+        http.post(&self.webhook_url)
+            .json(&json!({ "text": text }))
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Slack only needs these three escaped for its markup.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
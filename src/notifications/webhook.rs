@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use data_encoding::HEXLOWER;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use tokio::sync::broadcast::{self, Receiver};
+use tokio_util::sync::CancellationToken;
+
+use super::EventFilter;
+use crate::events::Event;
+
+// Deliveries are tried this many times, waiting twice as long after each
+// failure, starting at a second.
+const ATTEMPTS: u32 = 5;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    // Signs each body so the receiver can check it came from the panel.
+    secret: Option<String>,
+    #[serde(flatten)]
+    filter: EventFilter,
+}
+
+/// POSTs every matching event to the webhook as JSON.
+pub async fn run(
+    hook: WebhookConfig,
+    http: reqwest::Client,
+    mut rx: Receiver<Event>,
+    shutdown: CancellationToken,
+) {
+    loop {
+        let event = tokio::select! {
+            event = rx.recv() => match event {
+                Ok(e) => e,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!(url = %hook.url, missed = n, "webhook fell behind");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            _ = shutdown.cancelled() => return,
+        };
+        if !hook.filter.matches(&event) {
+            continue;
+        }
+        let body = match serde_json::to_vec(&event) {
+            Ok(b) => b,
+            Err(e) => {
+                tracing::warn!(error = %e, "could not serialize an event");
+                continue;
+            }
+        };
+        // Retries happen on their own task so a slow receiver doesn't hold
+        // up the events after this one.
+        tokio::spawn(deliver(
+            hook.clone(),
+            http.clone(),
+            event.event.name(),
+            body,
+        ));
+    }
+}
+
+async fn deliver(hook: WebhookConfig, http: reqwest::Client, kind: &'static str, body: Vec<u8>) {
+    let signature = hook.secret.as_ref().map(|secret| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(&body);
+        format!("sha256={}", HEXLOWER.encode(&mac.finalize().into_bytes()))
+    });
+
+    let mut delay = Duration::from_secs(1);
+    for attempt in 1..=ATTEMPTS {
+        let mut request = http
+            .post(&hook.url)
+            .header("Content-Type", "application/json")
+            .header("X-Mcctl-Event", kind)
+            .timeout(Duration::from_secs(10))
+            .body(body.clone());
+        if let Some(s) = &signature {
+            request = request.header("X-Mcctl-Signature", s);
+        }
+        match request.send().await {
+            Ok(r) if r.status().is_success() => return,
+            // The receiver won't change its mind about these.
+            Ok(r) if r.status().is_client_error() && r.status().as_u16() != 429 => {
+                tracing::warn!(url = %hook.url, %kind, status = %r.status(), "webhook rejected the event");
+                return;
+            }
+            Ok(r) => {
+                tracing::warn!(url = %hook.url, status = %r.status(), attempt, attempts = ATTEMPTS, "webhook failed")
+            }
+            Err(e) => {
+                tracing::warn!(url = %hook.url, error = %e, attempt, attempts = ATTEMPTS, "webhook failed")
+            }
+        }
+        if attempt < ATTEMPTS {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+}
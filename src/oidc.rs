@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use rand::RngCore;
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+
+use crate::auth::Role;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OidcConfig {
+    issuer: String,
+    client_id: String,
+    client_secret: Option<String>,
+    // Must point at /auth/oidc/callback on this panel.
+    redirect_url: String,
+    scopes: Option<Vec<String>>,
+    username_claim: Option<String>,
+    roles_claim: Option<String>,
+    // Maps values of the roles claim (usually group names) to roles.
+    roles: Option<HashMap<String, Role>>,
+    // Role for users none of whose claim values are mapped. Without it such
+    // users are turned away.
+    default_role: Option<Role>,
+}
+
+#[derive(Deserialize, Clone)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug)]
+pub enum OidcError {
+    Http(reqwest::Error),
+    Token(jsonwebtoken::errors::Error),
+    Invalid(String),
+    NoRole(String),
+}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(e: reqwest::Error) -> Self {
+        OidcError::Http(e)
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for OidcError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        OidcError::Token(e)
+    }
+}
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OidcError::Http(e) => write!(f, "identity provider request failed: {}", e),
+            OidcError::Token(e) => write!(f, "invalid id token: {}", e),
+            OidcError::Invalid(s) => write!(f, "invalid response: {}", s),
+            OidcError::NoRole(u) => write!(f, "no role is mapped for {}", u),
+        }
+    }
+}
+
+/// State for a login redirect that has to survive the round trip through
+/// the identity provider.
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub verifier: String,
+}
+
+pub struct Identity {
+    pub username: String,
+    pub role: Role,
+}
+
+pub struct OidcClient {
+    config: OidcConfig,
+    http: reqwest::Client,
+    metadata: RwLock<Option<(Discovery, JwkSet)>>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> OidcClient {
+        OidcClient {
+            config,
+            http: reqwest::Client::new(),
+            metadata: RwLock::new(None),
+        }
+    }
+
+    /// Fetches the provider's discovery document and signing keys, caching
+    /// them until `refresh` is set after an unknown key id shows up.
+    async fn metadata(&self, refresh: bool) -> Result<(Discovery, JwkSet), OidcError> {
+        if !refresh {
+            if let Some(m) = self.metadata.read().await.as_ref() {
+                return Ok(m.clone());
+            }
+        }
+
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer.trim_end_matches('/')
+        );
+        let discovery: Discovery = self
+            .http
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let jwks: JwkSet = self
+            .http
+            .get(&discovery.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let metadata = (discovery, jwks);
+        *self.metadata.write().await = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    pub async fn authorize(&self) -> Result<AuthorizationRequest, OidcError> {
+        let (discovery, _) = self.metadata(false).await?;
+        let state = random_string();
+        let nonce = random_string();
+        let verifier = random_string();
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        let scopes = match &self.config.scopes {
+            Some(s) => s.join(" "),
+            None => String::from("openid profile email"),
+        };
+
+        let url = Url::parse_with_params(
+            &discovery.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.config.redirect_url.as_str()),
+                ("scope", scopes.as_str()),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+                ("code_challenge", challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| OidcError::Invalid(e.to_string()))?;
+
+        Ok(AuthorizationRequest {
+            url: url.to_string(),
+            state,
+            nonce,
+            verifier,
+        })
+    }
+
+    /// Trades the authorization code for an ID token, validates it and maps
+    /// its claims to a user and role.
+    pub async fn exchange(
+        &self,
+        code: &str,
+        verifier: &str,
+        nonce: &str,
+    ) -> Result<Identity, OidcError> {
+        let (discovery, _) = self.metadata(false).await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_url.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        let tokens: TokenResponse = self
+            .http
+            .post(&discovery.token_endpoint)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let claims = self.validate(&tokens.id_token, nonce).await?;
+        self.identity(&claims)
+    }
+
+    async fn validate(&self, id_token: &str, nonce: &str) -> Result<Value, OidcError> {
+        let header = decode_header(id_token)?;
+        let kid = match header.kid {
+            Some(k) => k,
+            None => return Err(OidcError::Invalid(String::from("id token has no key id"))),
+        };
+
+        let (mut discovery, mut jwks) = self.metadata(false).await?;
+        if jwks.find(&kid).is_none() {
+            // The provider may have rotated its keys since they were cached.
+            (discovery, jwks) = self.metadata(true).await?;
+        }
+        let jwk = match jwks.find(&kid) {
+            Some(j) => j,
+            None => return Err(OidcError::Invalid(format!("unknown key id {}", kid))),
+        };
+
+        // Taken from the key rather than the token, which could name any
+        // algorithm it likes.
+        let alg = key_algorithm(jwk)?;
+        if header.alg != alg {
+            return Err(OidcError::Invalid(format!(
+                "id token is signed with {:?} but key {} is for {:?}",
+                header.alg, kid, alg
+            )));
+        }
+        let mut validation = Validation::new(alg);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_issuer(&[&discovery.issuer]);
+        let data = decode::<Value>(id_token, &DecodingKey::from_jwk(jwk)?, &validation)?;
+
+        if data.claims.get("nonce").and_then(|n| n.as_str()) != Some(nonce) {
+            return Err(OidcError::Invalid(String::from("nonce mismatch")));
+        }
+        Ok(data.claims)
+    }
+
+    fn identity(&self, claims: &Value) -> Result<Identity, OidcError> {
+        let username_claim = self
+            .config
+            .username_claim
+            .as_deref()
+            .unwrap_or("preferred_username");
+        let username = match claims.get(username_claim).and_then(|u| u.as_str()) {
+            // It goes into the session cookie, whose fields are split by
+            // line.
+            Some(u) if u.chars().any(char::is_control) => {
+                return Err(OidcError::Invalid(format!(
+                    "the {} claim has control characters",
+                    username_claim
+                )))
+            }
+            Some(u) => u.to_owned(),
+            None => {
+                return Err(OidcError::Invalid(format!(
+                    "id token has no {} claim",
+                    username_claim
+                )))
+            }
+        };
+
+        // The claim can be a single value or a list, like most group claims.
+        let roles_claim = self.config.roles_claim.as_deref().unwrap_or("groups");
+        let values: Vec<&str> = match claims.get(roles_claim) {
+            Some(Value::String(s)) => vec![s.as_str()],
+            Some(Value::Array(a)) => a.iter().filter_map(|v| v.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        let mapped = match &self.config.roles {
+            Some(roles) => values.iter().filter_map(|v| roles.get(*v)).max().copied(),
+            None => None,
+        };
+
+        match mapped.or(self.config.default_role) {
+            Some(role) => Ok(Identity { username, role }),
+            None => Err(OidcError::NoRole(username)),
+        }
+    }
+}
+
+/// What the provider signs with a key, going by its `alg` or, where it
+/// doesn't give one, its type.
+fn key_algorithm(jwk: &Jwk) -> Result<Algorithm, OidcError> {
+    let alg = match &jwk.common.key_algorithm {
+        Some(a) => match Algorithm::from_str(&a.to_string()) {
+            Ok(alg) => alg,
+            Err(_) => return Err(OidcError::Invalid(format!("key is for {}, not signing", a))),
+        },
+        None => match &jwk.algorithm {
+            AlgorithmParameters::RSA(_) => Algorithm::RS256,
+            AlgorithmParameters::EllipticCurve(p) => match p.curve {
+                EllipticCurve::P256 => Algorithm::ES256,
+                EllipticCurve::P384 => Algorithm::ES384,
+                _ => {
+                    return Err(OidcError::Invalid(String::from(
+                        "key has an unsupported curve",
+                    )))
+                }
+            },
+            AlgorithmParameters::OctetKeyPair(_) => Algorithm::EdDSA,
+            _ => {
+                return Err(OidcError::Invalid(String::from(
+                    "key has an unsupported type",
+                )))
+            }
+        },
+    };
+    // A secret anyone can fetch from the key set isn't one.
+    if matches!(alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        return Err(OidcError::Invalid(String::from("key is a shared secret")));
+    }
+    Ok(alg)
+}
+
+fn random_string() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
@@ -0,0 +1,79 @@
+use axum::{response::Html, Json};
+use utoipa::openapi::path::{Parameter, ParameterIn};
+use utoipa::openapi::schema::{ObjectBuilder, Type};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme};
+use utoipa::openapi::Required;
+use utoipa::{Modify, OpenApi};
+
+// Where every server's routes are, each described once rather than per
+// server.
+const SERVER_PREFIX: &str = "/api/v1/servers/{server}";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "minecraft-control"),
+    paths(crate::servers_handler, crate::reload_status_handler),
+    nest((path = "/api/v1/servers/{server}", api = crate::server::ServerApi)),
+    modifiers(&Security)
+)]
+struct ApiDoc;
+
+/// Adds the bearer token every route takes, and the `{server}` the nested
+/// routes are under.
+struct Security;
+
+impl Modify for Security {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        openapi.security = Some(vec![SecurityRequirement::new(
+            "token",
+            Vec::<String>::new(),
+        )]);
+
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            if !path.starts_with(SERVER_PREFIX) {
+                continue;
+            }
+            let server = Parameter::builder()
+                .name("server")
+                .parameter_in(ParameterIn::Path)
+                .required(Required::True)
+                .description(Some("The server's name, as in /api/v1/servers"))
+                .schema(Some(ObjectBuilder::new().schema_type(Type::String)))
+                .build();
+            item.parameters
+                .get_or_insert_with(Vec::new)
+                .insert(0, server);
+        }
+    }
+}
+
+pub async fn openapi_handler() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI for the document above, only served when `swagger_ui` is on.
+pub async fn docs_handler() -> Html<&'static str> {
+    Html(
+        r##"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>minecraft-control API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    SwaggerUIBundle({ url: "/api/v1/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##,
+    )
+}
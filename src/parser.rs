@@ -0,0 +1,173 @@
+use serde::Serialize;
+
+/// Something the server logged that other parts of the panel care about.
+/// Recognised from the vanilla log format, so servers whose plugins reword
+/// these messages may not produce them.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LogEvent {
+    PlayerJoined {
+        player: String,
+    },
+    PlayerLeft {
+        player: String,
+    },
+    Chat {
+        player: String,
+        message: String,
+    },
+    Death {
+        player: String,
+        // The whole death message, e.g. "Steve fell from a high place".
+        message: String,
+    },
+    Advancement {
+        player: String,
+        advancement: String,
+        // "advancement", "goal" or "challenge".
+        kind: &'static str,
+    },
+    // "Done (12.345s)! For help, type "help""
+    ServerReady {
+        seconds: f64,
+    },
+    ServerStopping,
+    // The server wrote a crash report, which it does just before it dies.
+    Crash {
+        message: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+// The ways a vanilla death message can go on after the player's name.
+const DEATH_PHRASES: &[&str] = &[
+    "was ",
+    "walked into ",
+    "fell ",
+    "drowned",
+    "died",
+    "blew up",
+    "burned to death",
+    "went up in flames",
+    "went off with a bang",
+    "hit the ground too hard",
+    "experienced kinetic energy",
+    "tried to swim in lava",
+    "discovered the floor was lava",
+    "starved to death",
+    "suffocated in a wall",
+    "froze to death",
+    "withered away",
+    "didn't want to live",
+    "left the confines of this world",
+];
+
+/// Reads the event out of a log line, if it is one.
+pub fn parse(line: &str) -> Option<LogEvent> {
+    let (level, body) = split_line(line);
+    if body.starts_with("Preparing crash report")
+        || body.starts_with("This crash report has been saved to")
+        || body.starts_with("We were unable to save this crash report")
+    {
+        return Some(LogEvent::Crash {
+            message: body.to_owned(),
+        });
+    }
+    match level {
+        "ERROR" | "FATAL" => {
+            return Some(LogEvent::Error {
+                message: body.to_owned(),
+            })
+        }
+        "INFO" => {}
+        _ => return None,
+    }
+
+    if let Some(rest) = body.strip_prefix("Done (") {
+        let seconds = rest.split_once("s)!")?.0.parse().ok()?;
+        return Some(LogEvent::ServerReady { seconds });
+    }
+    if body == "Stopping server" || body == "Stopping the server" {
+        return Some(LogEvent::ServerStopping);
+    }
+
+    // Chat since 1.19 may be marked as unsigned.
+    let chat = body.strip_prefix("[Not Secure] ").unwrap_or(body);
+    if let Some(rest) = chat.strip_prefix('<') {
+        let (player, message) = rest.split_once("> ")?;
+        if !is_player_name(player) {
+            return None;
+        }
+        return Some(LogEvent::Chat {
+            player: player.to_owned(),
+            message: message.to_owned(),
+        });
+    }
+
+    let (player, rest) = body.split_once(' ')?;
+    if !is_player_name(player) {
+        return None;
+    }
+    let player = player.to_owned();
+    match rest {
+        "joined the game" => return Some(LogEvent::PlayerJoined { player }),
+        "left the game" => return Some(LogEvent::PlayerLeft { player }),
+        _ => {}
+    }
+    for (prefix, kind) in [
+        ("has made the advancement [", "advancement"),
+        ("has reached the goal [", "goal"),
+        ("has completed the challenge [", "challenge"),
+    ] {
+        if let Some(name) = rest.strip_prefix(prefix) {
+            return Some(LogEvent::Advancement {
+                player,
+                advancement: name.trim_end_matches(']').to_owned(),
+                kind,
+            });
+        }
+    }
+    // Kicks and bans also start with "was".
+    if rest.starts_with("was kicked") || rest.starts_with("was banned") {
+        return None;
+    }
+    if DEATH_PHRASES.iter().any(|p| rest.starts_with(p)) {
+        return Some(LogEvent::Death {
+            player,
+            message: body.to_owned(),
+        });
+    }
+    None
+}
+
+/// Splits `[12:00:00] [Server thread/INFO]: message` into its level and
+/// message. Lines without the prefix are taken as INFO.
+fn split_line(line: &str) -> (&str, &str) {
+    if !line.starts_with('[') {
+        return ("INFO", line);
+    }
+    match line.find("]: ") {
+        Some(end) => {
+            let level = match line[..end].rfind('/') {
+                Some(start) => &line[start + 1..end],
+                None => "INFO",
+            };
+            (level, &line[end + 3..])
+        }
+        None => ("INFO", line),
+    }
+}
+
+/// Removes the `[12:00:00] [Server thread/INFO]: ` prefix from a log line.
+pub fn strip_log_prefix(line: &str) -> &str {
+    split_line(line).1
+}
+
+/// Java Edition names are 3 to 16 letters, digits and underscores. Geyser
+/// prefixes Bedrock players with a dot.
+pub fn is_player_name(name: &str) -> bool {
+    let name = name.strip_prefix('.').unwrap_or(name);
+    (3..=16).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,156 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+
+use crate::minecraft::MinecraftControl;
+use crate::motd;
+use crate::properties;
+use crate::protocol::{read_packet, write_packet, write_string, write_varint, Reader};
+use crate::util::invalid;
+
+// What's sent in the handshake. Servers answer a status request whatever
+// version it claims.
+const PROTOCOL: i32 = -1;
+
+/// The status JSON, as much of it as the panel uses.
+#[derive(Deserialize)]
+struct Reply {
+    version: ReplyVersion,
+    players: Option<ReplyPlayers>,
+    description: Option<Value>,
+    favicon: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ReplyVersion {
+    name: String,
+    protocol: i32,
+}
+
+#[derive(Deserialize)]
+struct ReplyPlayers {
+    max: u32,
+    online: u32,
+    // Some of who's online. Servers can leave it out or fill it with
+    // anything.
+    sample: Option<Vec<ReplyPlayer>>,
+}
+
+#[derive(Deserialize)]
+struct ReplyPlayer {
+    name: String,
+}
+
+/// What the server shows in the server list.
+#[derive(Serialize)]
+pub struct Status {
+    // With `§` codes.
+    pub motd: String,
+    // HTML for showing it as the server list would.
+    pub preview: String,
+    // e.g. "Paper 1.21.1".
+    pub version: String,
+    pub protocol: i32,
+    pub online: u32,
+    pub max: u32,
+    pub sample: Vec<String>,
+    // A data: URL of the server icon.
+    pub favicon: Option<String>,
+    pub latency_ms: u64,
+}
+
+async fn exchange(address: &str) -> Result<Status, std::io::Error> {
+    let mut stream = TcpStream::connect(address).await?;
+    let (host, port) = match address.rsplit_once(':') {
+        Some((h, p)) => (h, p.parse().unwrap_or(25565)),
+        None => (address, 25565),
+    };
+    let mut handshake = Vec::new();
+    write_varint(&mut handshake, PROTOCOL);
+    write_string(&mut handshake, host);
+    handshake.extend_from_slice(&u16::to_be_bytes(port));
+    write_varint(&mut handshake, 1);
+    write_packet(&mut stream, 0, &handshake).await?;
+    write_packet(&mut stream, 0, &[]).await?;
+    let reply = match read_packet(&mut stream).await? {
+        (0, body) => match Reader::new(&body).string() {
+            Some(s) => s,
+            None => return Err(invalid("the status reply is not a string")),
+        },
+        _ => return Err(invalid("expected a status reply")),
+    };
+    let reply: Reply = match serde_json::from_str(&reply) {
+        Ok(r) => r,
+        Err(e) => return Err(invalid(&format!("the status reply is not valid: {}", e))),
+    };
+
+    // The client sends the time and expects it back.
+    let millis = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as i64,
+        Err(_) => 0,
+    };
+    let payload = millis.to_be_bytes();
+    let sent = Instant::now();
+    write_packet(&mut stream, 1, &payload).await?;
+    let latency = match read_packet(&mut stream).await? {
+        (1, pong) if Reader::new(&pong).i64() == Some(i64::from_be_bytes(payload)) => {
+            sent.elapsed()
+        }
+        _ => return Err(invalid("expected the ping back")),
+    };
+
+    let motd = match reply.description {
+        Some(d) => motd::normalize(&d).unwrap_or_default(),
+        None => String::new(),
+    };
+    let (online, max, sample) = match reply.players {
+        Some(p) => (
+            p.online,
+            p.max,
+            p.sample
+                .unwrap_or_default()
+                .into_iter()
+                .map(|s| s.name)
+                .collect(),
+        ),
+        None => (0, 0, Vec::new()),
+    };
+    Ok(Status {
+        preview: motd::preview(&motd),
+        motd,
+        version: reply.version.name,
+        protocol: reply.version.protocol,
+        online,
+        max,
+        sample,
+        favicon: reply.favicon,
+        latency_ms: latency.as_millis() as u64,
+    })
+}
+
+/// Does a server-list ping, the way the client does for its server list.
+pub async fn ping(address: &str, timeout: Duration) -> Result<Status, std::io::Error> {
+    match tokio::time::timeout(timeout, exchange(address)).await {
+        Ok(r) => r,
+        Err(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "the server didn't answer in time",
+        )),
+    }
+}
+
+/// 127.0.0.1 on the server-port in server.properties, 25565 if it can't be
+/// read.
+pub async fn local_address(control: &MinecraftControl) -> String {
+    let port = match control.server_dir() {
+        Some(dir) => properties::read(&dir)
+            .await
+            .ok()
+            .and_then(|p| p.get("server-port")),
+        None => None,
+    };
+    format!("127.0.0.1:{}", port.unwrap_or(String::from("25565")))
+}
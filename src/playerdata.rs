@@ -0,0 +1,129 @@
+use std::path::Path;
+
+use fastnbt::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::nbt::{self, NbtError};
+
+/// The parts of `world/playerdata/<uuid>.dat` the panel shows.
+#[derive(Deserialize)]
+struct PlayerFile {
+    #[serde(rename = "Pos")]
+    pos: Vec<f64>,
+    // A name like "minecraft:the_nether" since 1.16, and -1, 0 or 1 before.
+    #[serde(rename = "Dimension")]
+    dimension: Option<Value>,
+    #[serde(rename = "Health")]
+    health: f32,
+    #[serde(rename = "foodLevel")]
+    food_level: i32,
+    #[serde(rename = "XpLevel")]
+    xp_level: i32,
+    #[serde(rename = "XpTotal")]
+    xp_total: i32,
+    #[serde(rename = "playerGameType")]
+    game_type: i32,
+    #[serde(rename = "SelectedItemSlot", default)]
+    selected_slot: i32,
+    #[serde(rename = "Inventory", default)]
+    inventory: Vec<ItemFile>,
+    #[serde(rename = "EnderItems", default)]
+    ender_items: Vec<ItemFile>,
+}
+
+#[derive(Deserialize)]
+struct ItemFile {
+    #[serde(rename = "Slot", default)]
+    slot: i8,
+    id: String,
+    // "Count" as a byte until 1.20.5, "count" as an int since.
+    #[serde(rename = "Count")]
+    old_count: Option<i8>,
+    count: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct Item {
+    // 0-8 the hotbar, 9-35 the rest, 100-103 armour and -106 the offhand.
+    pub slot: i8,
+    pub id: String,
+    pub count: i32,
+}
+
+#[derive(Serialize)]
+pub struct PlayerData {
+    pub uuid: String,
+    pub position: [f64; 3],
+    pub dimension: String,
+    pub game_mode: &'static str,
+    pub health: f32,
+    pub food_level: i32,
+    pub xp_level: i32,
+    pub xp_total: i32,
+    pub selected_slot: i32,
+    pub inventory: Vec<Item>,
+    pub ender_chest: Vec<Item>,
+}
+
+fn items(items: Vec<ItemFile>) -> Vec<Item> {
+    items
+        .into_iter()
+        .map(|i| Item {
+            slot: i.slot,
+            count: match (i.count, i.old_count) {
+                (Some(c), _) => c,
+                (None, Some(c)) => c as i32,
+                (None, None) => 1,
+            },
+            id: i.id,
+        })
+        .collect()
+}
+
+fn dimension(value: Option<Value>) -> String {
+    let name = match value {
+        Some(Value::String(s)) => return s,
+        Some(Value::Int(-1)) => "minecraft:the_nether",
+        Some(Value::Int(1)) => "minecraft:the_end",
+        _ => "minecraft:overworld",
+    };
+    name.to_owned()
+}
+
+fn game_mode(game_type: i32) -> &'static str {
+    match game_type {
+        1 => "creative",
+        2 => "adventure",
+        3 => "spectator",
+        _ => "survival",
+    }
+}
+
+/// Reads a player's saved state. The server only writes it every few
+/// minutes and when they leave, so it lags behind players who are online.
+/// None if the player has never been on the server.
+pub async fn read(world_dir: &Path, uuid: &str) -> Result<Option<PlayerData>, NbtError> {
+    let path = world_dir.join("playerdata").join(format!("{}.dat", uuid));
+    let player: PlayerFile = match nbt::read_gzipped(&path).await? {
+        Some(p) => p,
+        None => return Ok(None),
+    };
+
+    let mut position = [0.0; 3];
+    for (p, v) in position.iter_mut().zip(player.pos) {
+        *p = v;
+    }
+    Ok(Some(PlayerData {
+        uuid: uuid.to_owned(),
+        position,
+        dimension: dimension(player.dimension),
+        game_mode: game_mode(player.game_type),
+        health: player.health,
+        food_level: player.food_level,
+        xp_level: player.xp_level,
+        xp_total: player.xp_total,
+        selected_slot: player.selected_slot,
+        inventory: items(player.inventory),
+        ender_chest: items(player.ender_items),
+    }))
+}
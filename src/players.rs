@@ -0,0 +1,98 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use utoipa::ToSchema;
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct PlayerList {
+    pub online: u32,
+    pub max: u32,
+    pub players: Vec<String>,
+}
+
+/// Reads the reply to `list`, which is one of
+/// `There are 2 of a max of 20 players online: Steve, Alex` or, before
+/// 1.13, `There are 2/20 players online:` with the names on the next line.
+pub fn parse_list(reply: &str) -> Option<PlayerList> {
+    let (head, names) = match reply.split_once(':') {
+        Some((h, n)) => (h, n),
+        None => (reply, ""),
+    };
+    let mut numbers = head
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|n| !n.is_empty())
+        .map(|n| n.parse::<u32>());
+    let online = numbers.next()?.ok()?;
+    let max = numbers.next()?.ok()?;
+    let players = names
+        .split([',', '\n'])
+        .map(|n| n.trim())
+        .filter(|n| !n.is_empty())
+        .map(|n| n.to_owned())
+        .collect();
+    Some(PlayerList {
+        online,
+        max,
+        players,
+    })
+}
+
+/// An entry in ops.json.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Op {
+    pub uuid: String,
+    pub name: String,
+    pub level: u8,
+    pub bypasses_player_limit: bool,
+}
+
+pub async fn read_ops(server_dir: &Path) -> Result<Vec<Op>, std::io::Error> {
+    match fs::read_to_string(server_dir.join("ops.json")).await {
+        Ok(file) => Ok(serde_json::from_str(&file)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Changes an op's permission level in ops.json. There's no command for
+/// this, and the server only reads the file when it starts, so it applies
+/// from the next restart. Returns whether the player was found.
+pub async fn set_op_level(
+    server_dir: &Path,
+    player: &str,
+    level: u8,
+) -> Result<bool, std::io::Error> {
+    let mut ops = read_ops(server_dir).await?;
+    let op = match ops.iter_mut().find(|o| o.name.eq_ignore_ascii_case(player)) {
+        Some(o) => o,
+        None => return Ok(false),
+    };
+    op.level = level;
+    let path = server_dir.join("ops.json");
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(&ops)?).await?;
+    fs::rename(&tmp, &path).await?;
+    Ok(true)
+}
+
+#[derive(Deserialize)]
+struct CachedUser {
+    name: String,
+    uuid: String,
+}
+
+/// Looks a player up in usercache.json, where the server remembers
+/// everyone who has joined. This also works for offline-mode servers,
+/// whose UUIDs Mojang doesn't know.
+pub async fn cached_uuid(server_dir: &Path, name: &str) -> Option<String> {
+    let file = fs::read_to_string(server_dir.join("usercache.json"))
+        .await
+        .ok()?;
+    let users: Vec<CachedUser> = serde_json::from_str(&file).ok()?;
+    users
+        .into_iter()
+        .find(|u| u.name.eq_ignore_ascii_case(name))
+        .map(|u| u.uuid)
+}
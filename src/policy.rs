@@ -0,0 +1,430 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::queue::RateLimitConfig;
+
+/// Allow and deny lists of command names attached to an API token.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CommandPolicy {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    allow: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    deny: Vec<String>,
+}
+
+impl CommandPolicy {
+    pub fn new(allow: Vec<String>, deny: Vec<String>) -> CommandPolicy {
+        CommandPolicy {
+            allow: allow.iter().map(|c| normalize(c)).collect(),
+            deny: deny.iter().map(|c| normalize(c)).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// Checks a console command against the lists, returning the rule that
+    /// rejected it. The deny list wins over the allow list, and the commands
+    /// wrapped by `execute ... run` are checked as well so it can't be used
+    /// to sneak past either list.
+    pub fn check(&self, command: &str) -> Result<(), String> {
+        check_each(command, |c| {
+            let name = command_name(c);
+            if self.deny.contains(&name) {
+                return Err(format!("deny {}", name));
+            }
+            if !self.allow.is_empty() && !self.allow.contains(&name) {
+                return Err(format!("allow [{}]", self.allow.join(", ")));
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Runs `rule` on a command and on everything `execute` could run from
+/// inside it. A bare `run` can also be an argument, such as a player
+/// called run, so the rest of the command after every `run` is checked
+/// rather than guessing which one the server takes.
+fn check_each(command: &str, rule: impl Fn(&str) -> Result<(), String>) -> Result<(), String> {
+    // A line break would start another command on the console, unless it's
+    // the one at the end, which the backends strip.
+    let command = strip_line_end(command);
+    if command.chars().any(char::is_control) {
+        return Err(String::from("a single line"));
+    }
+    let command = command.trim();
+    rule(command)?;
+    if command_name(command) != "execute" {
+        return Ok(());
+    }
+    let args = match arguments(command) {
+        Some(a) => a,
+        None => return Err(String::from("execute with closed quotes and brackets")),
+    };
+    for pair in args.windows(2) {
+        if pair[0].1 == "run" {
+            rule(&command[pair[1].0..])?;
+        }
+    }
+    Ok(())
+}
+
+/// Drops the one line ending that clients often send after a command.
+pub fn strip_line_end(command: &str) -> &str {
+    match command.strip_suffix('\n') {
+        Some(c) => c.strip_suffix('\r').unwrap_or(c),
+        None => command,
+    }
+}
+
+/// Splits a command at its spaces the way the server reads it, keeping
+/// quoted strings, selectors like `@a[name="a run b"]` and NBT whole, with
+/// where each argument starts. None if a quote or bracket isn't closed.
+fn arguments(command: &str) -> Option<Vec<(usize, &str)>> {
+    let mut args = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in command.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            c if c.is_whitespace() && depth == 0 => {
+                if let Some(s) = start.take() {
+                    args.push((s, &command[s..i]));
+                }
+                continue;
+            }
+            '"' | '\'' => quote = Some(c),
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth = depth.checked_sub(1)?,
+            _ => {}
+        }
+        if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if quote.is_some() || depth != 0 {
+        return None;
+    }
+    if let Some(s) = start {
+        args.push((s, &command[s..]));
+    }
+    Some(args)
+}
+
+/// Returns the lowercased command name without a leading slash or
+/// namespace, e.g. `/minecraft:say hi` becomes `say`.
+pub fn command_name(command: &str) -> String {
+    let first = command.split_whitespace().next().unwrap_or("");
+    normalize(first)
+}
+
+fn normalize(name: &str) -> String {
+    let name = name.trim().trim_start_matches('/');
+    let name = match name.rsplit_once(':') {
+        Some((_, n)) => n,
+        None => name,
+    };
+    name.to_lowercase()
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct CommandsConfig {
+    // Regexes a command has to match one of, e.g. "say .*". Everything is
+    // allowed if unset.
+    allow: Option<Vec<String>>,
+    // Regexes for commands that are never run, whatever allow says.
+    deny: Option<Vec<String>>,
+    // How fast each API caller can send commands. Not limited if unset.
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+// Examples of what the panel sends on its own, and what needs them.
+const OWN_COMMANDS: &[(&str, &str)] = &[
+    ("save-off", "backups"),
+    ("save-all flush", "backups"),
+    ("save-on", "backups"),
+    (
+        "list",
+        "player lists, idle detection, maintenance and announcements",
+    ),
+    ("kick Steve", "maintenance and scheduled restarts"),
+    (r#"tellraw @a {"text":""}"#, "announcements"),
+    ("pardon Steve", "temporary bans"),
+];
+
+/// The `[commands]` lists, which every command sent to a server is checked
+/// against, whether it comes from the API, a schedule or the panel itself.
+#[derive(Debug, Clone, Default)]
+pub struct CommandRules {
+    allow: Vec<Regex>,
+    deny: Vec<Regex>,
+}
+
+fn patterns(patterns: &Option<Vec<String>>) -> Result<Vec<Regex>, String> {
+    let mut regexes = Vec::new();
+    for p in patterns.iter().flatten() {
+        match Regex::new(&format!("^(?:{})$", p)) {
+            Ok(r) => regexes.push(r),
+            Err(e) => return Err(format!("{:?}: {}", p, e)),
+        }
+    }
+    Ok(regexes)
+}
+
+impl CommandRules {
+    pub fn new(config: Option<&CommandsConfig>) -> Result<CommandRules, String> {
+        let config = match config {
+            Some(c) => c,
+            None => return Ok(CommandRules::default()),
+        };
+        Ok(CommandRules {
+            allow: patterns(&config.allow)?,
+            deny: patterns(&config.deny)?,
+        })
+    }
+
+    /// Checks a command, returning the rule that rejected it. Like
+    /// `CommandPolicy::check`, the name is matched without its slash or
+    /// namespace, and whatever `execute ... run` wraps is checked too.
+    pub fn check(&self, command: &str) -> Result<(), String> {
+        check_each(command, |c| {
+            let text = match c.split_once(char::is_whitespace) {
+                Some((_, args)) => format!("{} {}", command_name(c), args.trim_start()),
+                None => command_name(c),
+            };
+            if let Some(r) = self.deny.iter().find(|r| r.is_match(&text)) {
+                return Err(format!("deny {}", pattern(r)));
+            }
+            if !self.allow.is_empty() && !self.allow.iter().any(|r| r.is_match(&text)) {
+                let allow: Vec<&str> = self.allow.iter().map(pattern).collect();
+                return Err(format!("allow [{}]", allow.join(", ")));
+            }
+            Ok(())
+        })
+    }
+
+    /// Warns about the commands the panel sends itself that the rules turn
+    /// down, since what needs them stops working without saying so.
+    pub fn warn_own_commands(&self) {
+        for (command, needed_by) in OWN_COMMANDS {
+            if let Err(rule) = self.check(command) {
+                tracing::warn!(
+                    "[commands] rule {} denies {:?}, which {} depend on",
+                    rule,
+                    command,
+                    needed_by
+                );
+            }
+        }
+    }
+}
+
+/// The pattern as it was written in the config.
+fn pattern(regex: &Regex) -> &str {
+    let p = regex.as_str();
+    &p["^(?:".len()..p.len() - ")$".len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &[&str], deny: &[&str]) -> CommandPolicy {
+        let strings = |l: &[&str]| l.iter().map(|s| s.to_string()).collect();
+        CommandPolicy::new(strings(allow), strings(deny))
+    }
+
+    #[test]
+    fn allow_and_deny() {
+        let p = policy(&["say", "List"], &[]);
+        assert_eq!(p.check("say hi"), Ok(()));
+        assert_eq!(p.check("/minecraft:SAY hi"), Ok(()));
+        assert_eq!(p.check("list"), Ok(()));
+        assert_eq!(p.check("op Steve"), Err(String::from("allow [say, list]")));
+
+        let p = policy(&["op", "say"], &["/op"]);
+        assert_eq!(p.check("op Steve"), Err(String::from("deny op")));
+        assert_eq!(p.check("minecraft:op Steve"), Err(String::from("deny op")));
+        assert_eq!(p.check("say op"), Ok(()));
+
+        assert_eq!(policy(&[], &[]).check("anything at all"), Ok(()));
+    }
+
+    #[test]
+    fn checks_what_execute_runs() {
+        let p = policy(&[], &["op"]);
+        assert_eq!(
+            p.check("execute as @a at @s run op Steve"),
+            Err(String::from("deny op"))
+        );
+        assert_eq!(
+            p.check("execute as @a run execute if entity @s run op Steve"),
+            Err(String::from("deny op"))
+        );
+        // A player called run, where everything after either run is checked.
+        assert_eq!(
+            p.check("execute as run run op Steve"),
+            Err(String::from("deny op"))
+        );
+        assert_eq!(p.check("execute as @a run say hi"), Ok(()));
+
+        // The selector is one argument, so its run isn't the one executed.
+        let p = policy(&["execute", "say"], &[]);
+        assert_eq!(
+            p.check(r#"execute as @a[name="a run op"] run say hi"#),
+            Ok(())
+        );
+        assert_eq!(
+            p.check(r#"execute as @a[name="a] run say"] run op Steve"#),
+            Err(String::from("allow [execute, say]"))
+        );
+        assert_eq!(
+            p.check("execute as @a run op Steve"),
+            Err(String::from("allow [execute, say]"))
+        );
+    }
+
+    #[test]
+    fn unclosed_quotes_and_brackets() {
+        let p = policy(&[], &["op"]);
+        let refused = Err(String::from("execute with closed quotes and brackets"));
+        assert_eq!(p.check("execute as @a[name=\"x run op Steve"), refused);
+        assert_eq!(p.check("execute as @a[ run op Steve"), refused);
+        assert_eq!(p.check("execute as @a] run op Steve"), refused);
+        assert_eq!(
+            p.check(r#"execute as @a[name="\") run op"] run say"#),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn one_line_only() {
+        let p = policy(&["say"], &[]);
+        let refused = Err(String::from("a single line"));
+        assert_eq!(p.check("say hi\nop Steve"), refused);
+        assert_eq!(p.check("say hi\rop Steve"), refused);
+        assert_eq!(p.check("say hi\n\n"), refused);
+        assert_eq!(p.check("say\thi"), refused);
+        assert_eq!(p.check("say hi\u{0}"), refused);
+        // The line ending clients put after the command is fine.
+        assert_eq!(p.check("say hi\n"), Ok(()));
+        assert_eq!(p.check("say hi\r\n"), Ok(()));
+        assert_eq!(strip_line_end("say hi\r\n"), "say hi");
+        assert_eq!(strip_line_end("say hi\r"), "say hi\r");
+    }
+
+    #[test]
+    fn splits_arguments() {
+        fn args(command: &str) -> Vec<&str> {
+            let args = arguments(command).unwrap();
+            args.into_iter().map(|(_, a)| a).collect()
+        }
+        assert_eq!(
+            args(r#"give @p[name="A B"] stone{display:{Name:'"x y"'}} 1"#),
+            [
+                "give",
+                r#"@p[name="A B"]"#,
+                r#"stone{display:{Name:'"x y"'}}"#,
+                "1"
+            ]
+        );
+        assert_eq!(args("  say   hi  "), ["say", "hi"]);
+        assert_eq!(arguments("say 'hi"), None);
+        assert_eq!(arguments("say {]"), Some(vec![(0, "say"), (4, "{]")]));
+    }
+
+    fn rules(allow: &[&str], deny: &[&str]) -> CommandRules {
+        let strings = |l: &[&str]| Some(l.iter().map(|s| s.to_string()).collect());
+        let config = CommandsConfig {
+            allow: strings(allow),
+            deny: strings(deny),
+            rate_limit: None,
+        };
+        CommandRules::new(Some(&config)).unwrap()
+    }
+
+    #[test]
+    fn rule_patterns() {
+        let r = rules(&["say .*", "list"], &["say .*badword.*"]);
+        assert_eq!(r.check("say hello"), Ok(()));
+        assert_eq!(r.check("/minecraft:Say  hello"), Ok(()));
+        assert_eq!(r.check("list"), Ok(()));
+        assert_eq!(
+            r.check("say a badword"),
+            Err(String::from("deny say .*badword.*"))
+        );
+        assert_eq!(
+            r.check("op Steve"),
+            Err(String::from("allow [say .*, list]"))
+        );
+        // Patterns match the whole command, not a part of it.
+        assert_eq!(r.check("listen"), Err(String::from("allow [say .*, list]")));
+
+        let r = rules(&[], &["stop"]);
+        assert_eq!(r.check("stop"), Err(String::from("deny stop")));
+        assert_eq!(r.check("stopsound @a"), Ok(()));
+        assert_eq!(CommandRules::default().check("stop"), Ok(()));
+    }
+
+    #[test]
+    fn rules_check_what_execute_runs() {
+        let r = rules(&[], &["op .*"]);
+        assert_eq!(
+            r.check("execute as @a run op Steve"),
+            Err(String::from("deny op .*"))
+        );
+        assert_eq!(
+            r.check("execute as run run minecraft:op Steve"),
+            Err(String::from("deny op .*"))
+        );
+        assert_eq!(
+            r.check("execute as @a[ run op Steve"),
+            Err(String::from("execute with closed quotes and brackets"))
+        );
+        assert_eq!(
+            r.check("say hi\nop Steve"),
+            Err(String::from("a single line"))
+        );
+        assert_eq!(r.check("say hi\r\n"), Ok(()));
+
+        let r = rules(&["execute .*", "say .*"], &[]);
+        assert_eq!(r.check("execute at @p run say hi"), Ok(()));
+        assert_eq!(
+            r.check("execute at @p run op Steve"),
+            Err(String::from("allow [execute .*, say .*]"))
+        );
+    }
+
+    #[test]
+    fn bad_patterns() {
+        let config = CommandsConfig {
+            allow: Some(vec![String::from("say (")]),
+            deny: None,
+            rate_limit: None,
+        };
+        let e = CommandRules::new(Some(&config)).unwrap_err();
+        assert!(e.starts_with("\"say (\": "), "{}", e);
+    }
+
+    #[test]
+    fn own_commands_pass_by_default() {
+        let r = CommandRules::default();
+        for (command, _) in OWN_COMMANDS {
+            assert_eq!(r.check(command), Ok(()));
+        }
+        let r = rules(&["say .*"], &[]);
+        assert!(OWN_COMMANDS.iter().all(|(c, _)| r.check(c).is_err()));
+    }
+}
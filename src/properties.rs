@@ -0,0 +1,282 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use tokio::fs;
+
+/// server.properties as the server wrote it. Lines are kept as they are
+/// so comments, ordering and keys the panel doesn't know survive edits.
+pub struct Properties {
+    lines: Vec<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Bool,
+    Int(i64, i64),
+    OneOf(&'static [&'static str]),
+    Text,
+}
+
+const PORT: Kind = Kind::Int(1, 65535);
+
+// What each vanilla property takes.
+const KNOWN: &[(&str, Kind)] = &[
+    ("accepts-transfers", Kind::Bool),
+    ("allow-flight", Kind::Bool),
+    ("allow-nether", Kind::Bool),
+    ("broadcast-console-to-ops", Kind::Bool),
+    ("broadcast-rcon-to-ops", Kind::Bool),
+    ("bug-report-link", Kind::Text),
+    (
+        "difficulty",
+        Kind::OneOf(&["peaceful", "easy", "normal", "hard"]),
+    ),
+    ("enable-command-block", Kind::Bool),
+    ("enable-jmx-monitoring", Kind::Bool),
+    ("enable-query", Kind::Bool),
+    ("enable-rcon", Kind::Bool),
+    ("enable-status", Kind::Bool),
+    ("enforce-secure-profile", Kind::Bool),
+    ("enforce-whitelist", Kind::Bool),
+    ("entity-broadcast-range-percentage", Kind::Int(10, 1000)),
+    ("force-gamemode", Kind::Bool),
+    ("function-permission-level", Kind::Int(1, 4)),
+    (
+        "gamemode",
+        Kind::OneOf(&["survival", "creative", "adventure", "spectator"]),
+    ),
+    ("generate-structures", Kind::Bool),
+    ("generator-settings", Kind::Text),
+    ("hardcore", Kind::Bool),
+    ("hide-online-players", Kind::Bool),
+    ("initial-disabled-packs", Kind::Text),
+    ("initial-enabled-packs", Kind::Text),
+    ("level-name", Kind::Text),
+    ("level-seed", Kind::Text),
+    ("level-type", Kind::Text),
+    ("log-ips", Kind::Bool),
+    (
+        "max-chained-neighbor-updates",
+        Kind::Int(-1, i32::MAX as i64),
+    ),
+    ("max-players", Kind::Int(0, i32::MAX as i64)),
+    ("max-tick-time", Kind::Int(-1, i64::MAX)),
+    ("max-world-size", Kind::Int(1, 29999984)),
+    ("motd", Kind::Text),
+    (
+        "network-compression-threshold",
+        Kind::Int(-1, i32::MAX as i64),
+    ),
+    ("online-mode", Kind::Bool),
+    ("op-permission-level", Kind::Int(0, 4)),
+    ("pause-when-empty-seconds", Kind::Int(0, i32::MAX as i64)),
+    ("player-idle-timeout", Kind::Int(0, i32::MAX as i64)),
+    ("prevent-proxy-connections", Kind::Bool),
+    ("pvp", Kind::Bool),
+    ("query.port", PORT),
+    ("rate-limit", Kind::Int(0, i32::MAX as i64)),
+    ("rcon.password", Kind::Text),
+    ("rcon.port", PORT),
+    (
+        "region-file-compression",
+        Kind::OneOf(&["deflate", "lz4", "none"]),
+    ),
+    ("require-resource-pack", Kind::Bool),
+    ("resource-pack", Kind::Text),
+    ("resource-pack-id", Kind::Text),
+    ("resource-pack-prompt", Kind::Text),
+    ("resource-pack-sha1", Kind::Text),
+    ("server-ip", Kind::Text),
+    ("server-port", PORT),
+    ("simulation-distance", Kind::Int(3, 32)),
+    ("spawn-animals", Kind::Bool),
+    ("spawn-monsters", Kind::Bool),
+    ("spawn-npcs", Kind::Bool),
+    ("spawn-protection", Kind::Int(0, i32::MAX as i64)),
+    ("sync-chunk-writes", Kind::Bool),
+    ("text-filtering-config", Kind::Text),
+    ("use-native-transport", Kind::Bool),
+    ("view-distance", Kind::Int(3, 32)),
+    ("white-list", Kind::Bool),
+];
+
+fn kind(key: &str) -> Option<Kind> {
+    KNOWN.iter().find(|(k, _)| *k == key).map(|(_, kind)| *kind)
+}
+
+/// The command that applies a property to a running server, for the few
+/// that have one. Everything else is only read when the server starts.
+pub fn live_command(key: &str, value: &str) -> Option<String> {
+    match key {
+        "difficulty" => Some(format!("difficulty {}", value)),
+        "white-list" => Some(String::from(if value == "true" {
+            "whitelist on"
+        } else {
+            "whitelist off"
+        })),
+        _ => None,
+    }
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    Some(c) => out.push(c),
+                    None => out.push_str(&hex),
+                }
+            }
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+/// Escapes a value the way Java writes properties. Anything outside ASCII
+/// is written as \uXXXX, which both old and new servers read.
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ':' => out.push_str("\\:"),
+            '=' => out.push_str("\\="),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_ascii() && !c.is_ascii_control() => out.push(c),
+            c => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    out.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn split(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start();
+    if line.starts_with('#') || line.starts_with('!') {
+        return None;
+    }
+    let (k, v) = line.split_once('=')?;
+    Some((k.trim(), v.trim_start()))
+}
+
+pub async fn read(server_dir: &Path) -> Result<Properties, std::io::Error> {
+    let file = fs::read_to_string(server_dir.join("server.properties")).await?;
+    Ok(Properties {
+        lines: file.lines().map(|l| l.to_owned()).collect(),
+    })
+}
+
+impl Properties {
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.lines.iter().find_map(|line| match split(line) {
+            Some((k, v)) if k == key => Some(unescape(v)),
+            _ => None,
+        })
+    }
+
+    /// Every property in file order, typed where the panel knows the key.
+    pub fn to_json(&self) -> serde_json::Map<String, Value> {
+        let mut map = serde_json::Map::new();
+        for (key, value) in self.lines.iter().filter_map(|l| split(l)) {
+            let value = unescape(value);
+            let typed = match kind(key) {
+                Some(Kind::Bool) => match value.as_str() {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    _ => Value::String(value),
+                },
+                Some(Kind::Int(..)) => match value.parse::<i64>() {
+                    Ok(n) => Value::from(n),
+                    Err(_) => Value::String(value),
+                },
+                _ => Value::String(value),
+            };
+            map.insert(key.to_owned(), typed);
+        }
+        map
+    }
+
+    /// Checks a new value and turns it into what goes in the file. Keys
+    /// the panel doesn't know can be changed if they're already there,
+    /// which catches typos without getting in the way of mods.
+    pub fn check(&self, key: &str, value: &Value) -> Result<String, String> {
+        let text = match value {
+            Value::String(s) => s.clone(),
+            Value::Bool(b) => b.to_string(),
+            Value::Number(n) => n.to_string(),
+            _ => return Err(format!("{} has to be a string, number or boolean", key)),
+        };
+        let kind = match kind(key) {
+            Some(k) => k,
+            None if self.get(key).is_some() => Kind::Text,
+            None => return Err(format!("{} isn't a server property", key)),
+        };
+        match kind {
+            Kind::Bool if text != "true" && text != "false" => {
+                Err(format!("{} has to be true or false", key))
+            }
+            Kind::Int(min, max) => match text.parse::<i64>() {
+                Ok(n) if (min..=max).contains(&n) => Ok(text),
+                _ => Err(format!(
+                    "{} has to be a number from {} to {}",
+                    key, min, max
+                )),
+            },
+            Kind::OneOf(options) if !options.contains(&text.as_str()) => {
+                Err(format!("{} has to be one of {}", key, options.join(", ")))
+            }
+            _ => Ok(text),
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        let line = format!("{}={}", key, escape(value));
+        match self
+            .lines
+            .iter()
+            .position(|l| matches!(split(l), Some((k, _)) if k == key))
+        {
+            Some(i) => self.lines[i] = line,
+            None => self.lines.push(line),
+        }
+    }
+
+    pub async fn write(&self, server_dir: &Path) -> Result<(), std::io::Error> {
+        let path = server_dir.join("server.properties");
+        let tmp = path.with_extension("tmp");
+        let mut file = self.lines.join("\n");
+        file.push('\n');
+        fs::write(&tmp, file).await?;
+        fs::rename(&tmp, &path).await
+    }
+}
+
+/// Where the server keeps its world, going by level-name.
+pub async fn world_dir(server_dir: &Path) -> PathBuf {
+    let name = match read(server_dir).await {
+        Ok(p) => p.get("level-name"),
+        Err(_) => None,
+    };
+    server_dir.join(match name {
+        Some(n) if !n.is_empty() => n,
+        _ => String::from("world"),
+    })
+}
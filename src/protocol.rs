@@ -0,0 +1,268 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::util::invalid;
+
+// Bigger than any handshake or status reply, favicon included.
+const MAX_PACKET_LENGTH: i32 = 2 * 1024 * 1024;
+
+/// Reads a VarInt straight off the connection, for the packet length.
+async fn read_varint<R: AsyncRead + Unpin>(r: &mut R) -> Result<i32, std::io::Error> {
+    let mut value: u32 = 0;
+    for i in 0..5 {
+        let byte = r.read_u8().await?;
+        value |= ((byte & 0x7f) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(invalid("VarInt is too long"))
+}
+
+pub fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7f == 0 {
+            buf.push(value as u8);
+            return;
+        }
+        buf.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+pub fn write_string(buf: &mut Vec<u8>, s: &str) {
+    write_varint(buf, s.len() as i32);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// Reads a packet, giving back its id and the rest of it.
+pub async fn read_packet<R: AsyncRead + Unpin>(
+    r: &mut R,
+) -> Result<(i32, Vec<u8>), std::io::Error> {
+    let length = read_varint(r).await?;
+    if !(1..=MAX_PACKET_LENGTH).contains(&length) {
+        return Err(invalid("packet length is out of range"));
+    }
+    let mut packet = vec![0; length as usize];
+    r.read_exact(&mut packet).await?;
+    let mut reader = Reader::new(&packet);
+    let id = match reader.varint() {
+        Some(id) => id,
+        None => return Err(invalid("packet has no id")),
+    };
+    let body = packet[reader.pos..].to_vec();
+    Ok((id, body))
+}
+
+pub async fn write_packet<W: AsyncWrite + Unpin>(
+    w: &mut W,
+    id: i32,
+    body: &[u8],
+) -> Result<(), std::io::Error> {
+    let mut packet = Vec::with_capacity(body.len() + 8);
+    let mut header = Vec::new();
+    write_varint(&mut header, id);
+    write_varint(&mut packet, (header.len() + body.len()) as i32);
+    packet.extend_from_slice(&header);
+    packet.extend_from_slice(body);
+    w.write_all(&packet).await?;
+    w.flush().await
+}
+
+/// Reads the fields of a packet in order. Each gives None once the packet
+/// runs out.
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Reader<'a> {
+        Reader { buf, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.buf.get(self.pos..self.pos + n)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    pub fn varint(&mut self) -> Option<i32> {
+        let mut value: u32 = 0;
+        for i in 0..5 {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u32) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Some(value as i32);
+            }
+        }
+        None
+    }
+
+    pub fn string(&mut self) -> Option<String> {
+        let length = self.varint()?;
+        let bytes = self.take(usize::try_from(length).ok()?)?;
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        let bytes = self.take(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn i64(&mut self) -> Option<i64> {
+        let bytes = self.take(8)?;
+        Some(i64::from_be_bytes(bytes.try_into().ok()?))
+    }
+}
+
+/// The first packet of every connection.
+pub struct Handshake {
+    pub protocol: i32,
+    // 1 for a server-list ping, 2 to log in, 3 for a transfer.
+    pub next_state: i32,
+}
+
+impl Handshake {
+    pub fn parse(body: &[u8]) -> Option<Handshake> {
+        let mut reader = Reader::new(body);
+        let protocol = reader.varint()?;
+        let _address = reader.string()?;
+        let _port = reader.u16()?;
+        let next_state = reader.varint()?;
+        Some(Handshake {
+            protocol,
+            next_state,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varints_round_trip() {
+        for value in [
+            0,
+            1,
+            127,
+            128,
+            255,
+            25565,
+            2_097_151,
+            i32::MAX,
+            -1,
+            i32::MIN,
+        ] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, value);
+            let mut reader = Reader::new(&buf);
+            assert_eq!(reader.varint(), Some(value));
+            assert_eq!(reader.pos, buf.len());
+        }
+    }
+
+    #[test]
+    fn varint_lengths() {
+        let length = |value| {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, value);
+            buf.len()
+        };
+        assert_eq!(length(0), 1);
+        assert_eq!(length(127), 1);
+        assert_eq!(length(128), 2);
+        // Negative numbers always take all five bytes.
+        assert_eq!(length(-1), 5);
+    }
+
+    #[test]
+    fn varint_too_long() {
+        assert_eq!(Reader::new(&[0x80; 5]).varint(), None);
+        assert_eq!(
+            Reader::new(&[0xff, 0xff, 0xff, 0xff, 0xff, 0x01]).varint(),
+            None
+        );
+    }
+
+    #[test]
+    fn varint_cut_short() {
+        assert_eq!(Reader::new(&[]).varint(), None);
+        assert_eq!(Reader::new(&[0x80, 0x80]).varint(), None);
+    }
+
+    #[tokio::test]
+    async fn stream_varint_too_long() {
+        let mut reader: &[u8] = &[0x80; 6];
+        let e = read_varint(&mut reader).await.unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn strings() {
+        let mut buf = Vec::new();
+        write_string(&mut buf, "mc.example.com");
+        assert_eq!(
+            Reader::new(&buf).string().as_deref(),
+            Some("mc.example.com")
+        );
+
+        // Longer than what's left, negative, and not UTF-8.
+        assert_eq!(Reader::new(&[5, b'a', b'b']).string(), None);
+        assert_eq!(Reader::new(&[0xff, 0xff, 0xff, 0xff, 0x0f]).string(), None);
+        assert_eq!(Reader::new(&[2, 0xc3, 0x28]).string(), None);
+    }
+
+    #[test]
+    fn fixed_width_fields_cut_short() {
+        assert_eq!(Reader::new(&[0x63]).u16(), None);
+        assert_eq!(Reader::new(&[0; 7]).i64(), None);
+        assert_eq!(Reader::new(&[0x63, 0xdd]).u16(), Some(25565));
+    }
+
+    #[tokio::test]
+    async fn packets_round_trip() {
+        let mut buf = Vec::new();
+        write_packet(&mut buf, 0x01, &42i64.to_be_bytes())
+            .await
+            .unwrap();
+        let mut reader = buf.as_slice();
+        let (id, body) = read_packet(&mut reader).await.unwrap();
+        assert_eq!(id, 0x01);
+        assert_eq!(body, 42i64.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_packet_lengths() {
+        let mut empty: &[u8] = &[0x00];
+        assert!(read_packet(&mut empty).await.is_err());
+
+        let mut huge = Vec::new();
+        write_varint(&mut huge, MAX_PACKET_LENGTH + 1);
+        assert!(read_packet(&mut huge.as_slice()).await.is_err());
+
+        let mut negative = Vec::new();
+        write_varint(&mut negative, -1);
+        assert!(read_packet(&mut negative.as_slice()).await.is_err());
+
+        // Says 10 bytes but has 2.
+        let mut short: &[u8] = &[10, 0x00, 0x01];
+        assert!(read_packet(&mut short).await.is_err());
+    }
+
+    #[test]
+    fn handshakes() {
+        let mut body = Vec::new();
+        write_varint(&mut body, 767);
+        write_string(&mut body, "localhost");
+        body.extend_from_slice(&25565u16.to_be_bytes());
+        write_varint(&mut body, 2);
+        let handshake = Handshake::parse(&body).unwrap();
+        assert_eq!(handshake.protocol, 767);
+        assert_eq!(handshake.next_state, 2);
+
+        assert!(Handshake::parse(&body[..body.len() - 1]).is_none());
+        assert!(Handshake::parse(&[]).is_none());
+    }
+}
@@ -0,0 +1,248 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::net::UdpSocket;
+
+use crate::minecraft::MinecraftControl;
+use crate::properties;
+use crate::util::invalid;
+
+const MAGIC: [u8; 2] = [0xfe, 0xfd];
+const HANDSHAKE: u8 = 9;
+const STAT: u8 = 0;
+// Only the low four bits of each byte are used by the server.
+const SESSION: i32 = 0x0102_0304;
+// Between the session id and the first key of a full stat reply.
+const PADDING: usize = 11;
+// "\x01player_\0\0", between the keys and the player names.
+const PLAYERS_MARKER: &[u8] = b"\x01player_\0\0";
+
+/// What a full stat over the GameSpy4 query protocol gives, which has the
+/// names of everyone online and, on Bukkit servers, the plugins.
+#[derive(Serialize)]
+pub struct FullStat {
+    pub hostname: String,
+    pub version: String,
+    // e.g. "Paper on 1.21.1", for servers that say.
+    pub server_mod: Option<String>,
+    // Names and versions, e.g. "WorldEdit 7.3.4".
+    pub plugins: Vec<String>,
+    pub map: String,
+    pub online: u32,
+    pub max: u32,
+    pub players: Vec<String>,
+}
+
+fn request(kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = MAGIC.to_vec();
+    packet.push(kind);
+    packet.extend_from_slice(&SESSION.to_be_bytes());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+/// Sends one request and takes the reply, checking it's for this session.
+/// Gives back what's after the session id.
+async fn send(socket: &UdpSocket, kind: u8, payload: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    socket.send(&request(kind, payload)).await?;
+    let mut buf = vec![0; 64 * 1024];
+    let n = socket.recv(&mut buf).await?;
+    buf.truncate(n);
+    if buf.len() < 5 || buf[0] != kind || buf[1..5] != SESSION.to_be_bytes() {
+        return Err(invalid("the reply isn't for this request"));
+    }
+    Ok(buf.split_off(5))
+}
+
+/// Splits on the nulls the protocol ends strings with.
+fn strings(buf: &[u8]) -> impl Iterator<Item = String> + '_ {
+    buf.split(|b| *b == 0)
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+}
+
+/// "Paper on 1.21.1: WorldEdit 7.3.4; Vault 1.7.3", or just the first part
+/// with no plugins.
+fn parse_plugins(plugins: &str) -> (Option<String>, Vec<String>) {
+    if plugins.is_empty() {
+        return (None, Vec::new());
+    }
+    match plugins.split_once(": ") {
+        Some((server_mod, list)) => (
+            Some(server_mod.to_owned()),
+            list.split("; ")
+                .filter(|p| !p.is_empty())
+                .map(|p| p.to_owned())
+                .collect(),
+        ),
+        None => (Some(plugins.to_owned()), Vec::new()),
+    }
+}
+
+async fn exchange(address: &str) -> Result<FullStat, std::io::Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(address).await?;
+
+    // The challenge comes back as a number in text.
+    let reply = send(&socket, HANDSHAKE, &[]).await?;
+    let challenge: i32 = match strings(&reply).next().map(|c| c.parse()) {
+        Some(Ok(c)) => c,
+        _ => return Err(invalid("the challenge isn't a number")),
+    };
+    let mut payload = challenge.to_be_bytes().to_vec();
+    // Asks for the full stat rather than the basic one.
+    payload.extend_from_slice(&[0; 4]);
+    let reply = send(&socket, STAT, &payload).await?;
+    parse(&reply)
+}
+
+/// Reads a full stat reply from after the session id: padding, pairs of
+/// keys and values, then the player names.
+fn parse(reply: &[u8]) -> Result<FullStat, std::io::Error> {
+    let body = match reply.get(PADDING..) {
+        Some(b) => b,
+        None => return Err(invalid("the reply is too short")),
+    };
+    let (keys, names) = match body
+        .windows(PLAYERS_MARKER.len())
+        .position(|w| w == PLAYERS_MARKER)
+    {
+        Some(i) => (&body[..i], &body[i + PLAYERS_MARKER.len()..]),
+        None => return Err(invalid("the reply has no player list")),
+    };
+    let mut fields = strings(keys);
+    let mut stat = FullStat {
+        hostname: String::new(),
+        version: String::new(),
+        server_mod: None,
+        plugins: Vec::new(),
+        map: String::new(),
+        online: 0,
+        max: 0,
+        players: strings(names).filter(|n| !n.is_empty()).collect(),
+    };
+    while let (Some(key), Some(value)) = (fields.next(), fields.next()) {
+        match key.as_str() {
+            "hostname" => stat.hostname = value,
+            "version" => stat.version = value,
+            "plugins" => (stat.server_mod, stat.plugins) = parse_plugins(&value),
+            "map" => stat.map = value,
+            "numplayers" => stat.online = value.parse().unwrap_or(0),
+            "maxplayers" => stat.max = value.parse().unwrap_or(0),
+            _ => {}
+        }
+    }
+    Ok(stat)
+}
+
+/// Asks the server for a full stat over the query protocol.
+pub async fn full_stat(address: &str, timeout: Duration) -> Result<FullStat, std::io::Error> {
+    match tokio::time::timeout(timeout, exchange(address)).await {
+        Ok(r) => r,
+        Err(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "the server didn't answer the query in time",
+        )),
+    }
+}
+
+/// 127.0.0.1 on query.port, or None if enable-query isn't on in
+/// server.properties.
+pub async fn local_address(control: &MinecraftControl) -> Option<String> {
+    let props = properties::read(&control.server_dir()?).await.ok()?;
+    if props.get("enable-query").as_deref() != Some("true") {
+        return None;
+    }
+    let port = props.get("query.port").unwrap_or(String::from("25565"));
+    Some(format!("127.0.0.1:{}", port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(keys: &[&str], players: &[&str]) -> Vec<u8> {
+        let mut reply = b"splitnum\0\x80\0".to_vec();
+        for k in keys {
+            reply.extend_from_slice(k.as_bytes());
+            reply.push(0);
+        }
+        reply.push(0);
+        reply.extend_from_slice(PLAYERS_MARKER);
+        for p in players {
+            reply.extend_from_slice(p.as_bytes());
+            reply.push(0);
+        }
+        reply.push(0);
+        reply
+    }
+
+    #[test]
+    fn full_stat() {
+        let keys = [
+            "hostname",
+            "A Minecraft Server",
+            "gametype",
+            "SMP",
+            "version",
+            "1.21.1",
+            "plugins",
+            "Paper on 1.21.1: WorldEdit 7.3.4; Vault 1.7.3",
+            "map",
+            "world",
+            "numplayers",
+            "2",
+            "maxplayers",
+            "20",
+        ];
+        let stat = parse(&reply(&keys, &["Steve", "Alex"])).unwrap();
+        assert_eq!(stat.hostname, "A Minecraft Server");
+        assert_eq!(stat.version, "1.21.1");
+        assert_eq!(stat.server_mod.as_deref(), Some("Paper on 1.21.1"));
+        assert_eq!(stat.plugins, ["WorldEdit 7.3.4", "Vault 1.7.3"]);
+        assert_eq!(stat.map, "world");
+        assert_eq!((stat.online, stat.max), (2, 20));
+        assert_eq!(stat.players, ["Steve", "Alex"]);
+    }
+
+    #[test]
+    fn nobody_online() {
+        let stat = parse(&reply(&["numplayers", "0"], &[])).unwrap();
+        assert_eq!(stat.online, 0);
+        assert!(stat.players.is_empty());
+    }
+
+    #[test]
+    fn odd_and_bad_fields() {
+        // A key without a value is dropped, and numbers that aren't are 0.
+        let stat = parse(&reply(&["numplayers", "lots", "hostname"], &["Steve"])).unwrap();
+        assert_eq!(stat.online, 0);
+        assert_eq!(stat.hostname, "");
+        assert_eq!(stat.players, ["Steve"]);
+    }
+
+    #[test]
+    fn malformed_replies() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&[0; PADDING - 1]).is_err());
+        // No player list.
+        assert!(parse(b"splitnum\0\x80\0hostname\0x\0\0").is_err());
+        // Not UTF-8, which is replaced rather than rejected.
+        let mut bad = reply(&["hostname", "x"], &[]);
+        let i = bad.iter().position(|b| *b == b'x').unwrap();
+        bad[i] = 0xff;
+        assert_eq!(parse(&bad).unwrap().hostname, "\u{fffd}");
+    }
+
+    #[test]
+    fn plugins() {
+        assert_eq!(parse_plugins(""), (None, Vec::new()));
+        assert_eq!(
+            parse_plugins("CraftBukkit on Bukkit 1.21"),
+            (Some(String::from("CraftBukkit on Bukkit 1.21")), Vec::new())
+        );
+        assert_eq!(
+            parse_plugins("Paper: "),
+            (Some(String::from("Paper")), Vec::new())
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tokio::sync::{mpsc, oneshot, Mutex};
+
+use crate::backend::ServerBackend;
+use crate::minecraft::MinecraftError;
+use crate::rcon::RconClient;
+
+// Commands waiting to be sent before more are turned away.
+const QUEUE_LENGTH: usize = 64;
+// How long one write can hold up the rest. Opening the console's FIFO
+// blocks for as long as nothing has the other end open.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RateLimitConfig {
+    // Commands each API caller can send to a server a minute.
+    per_minute: u32,
+    // How many they can send at once before that applies, 10 by default.
+    burst: Option<u32>,
+}
+
+// A caller's allowance, topped up at per_minute.
+struct Bucket {
+    tokens: f64,
+    at: Instant,
+}
+
+struct Job {
+    command: String,
+    reply: oneshot::Sender<Result<Option<String>, MinecraftError>>,
+}
+
+/// Sends a server's commands one at a time, in the order they were given,
+/// so two writes to the console can't end up mixed together on it. It
+/// also keeps how fast each API caller is sending them.
+#[derive(Clone)]
+pub struct CommandQueue {
+    tx: mpsc::Sender<Job>,
+    rate_limit: Option<RateLimitConfig>,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl CommandQueue {
+    /// Starts the worker, which runs for as long as the queue is kept.
+    pub fn start(
+        backend: Arc<dyn ServerBackend>,
+        rcon: Option<Arc<Mutex<RconClient>>>,
+        rate_limit: Option<RateLimitConfig>,
+    ) -> CommandQueue {
+        let (tx, mut rx) = mpsc::channel::<Job>(QUEUE_LENGTH);
+        tokio::spawn(async move {
+            while let Some(job) = rx.recv().await {
+                let result = match tokio::time::timeout(
+                    WRITE_TIMEOUT,
+                    write(backend.as_ref(), rcon.as_deref(), &job.command),
+                )
+                .await
+                {
+                    Ok(r) => r,
+                    Err(_) => Err(MinecraftError::CommandTimeout),
+                };
+                // The caller may have stopped waiting.
+                let _ = job.reply.send(result);
+            }
+        });
+        CommandQueue {
+            tx,
+            rate_limit,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Waits for the command's turn and sends it, with the reply if it
+    /// went over RCON.
+    pub async fn send(&self, command: String) -> Result<Option<String>, MinecraftError> {
+        let (reply, rx) = oneshot::channel();
+        // Full, or the worker has stopped.
+        if self.tx.try_send(Job { command, reply }).is_err() {
+            return Err(MinecraftError::QueueFull);
+        }
+        match rx.await {
+            Ok(r) => r,
+            Err(_) => Err(MinecraftError::QueueFull),
+        }
+    }
+
+    /// Takes one command from the caller's allowance, or says how many
+    /// seconds until they have another.
+    pub async fn limit(&self, client: &str) -> Result<(), MinecraftError> {
+        let config = match &self.rate_limit {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let rate = config.per_minute as f64 / 60.0;
+        let burst = config.burst.unwrap_or(10).max(1) as f64;
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().await;
+        let bucket = buckets.entry(client.to_owned()).or_insert(Bucket {
+            tokens: burst,
+            at: now,
+        });
+        let refill = now.duration_since(bucket.at).as_secs_f64() * rate;
+        bucket.tokens = (bucket.tokens + refill).min(burst);
+        bucket.at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        let wait = if rate > 0.0 {
+            ((1.0 - bucket.tokens) / rate).ceil() as u64
+        } else {
+            60
+        };
+        Err(MinecraftError::RateLimited(wait.max(1)))
+    }
+}
+
+/// Over RCON when it's set up, since that gives a reply, and to the
+/// console otherwise.
+async fn write(
+    backend: &dyn ServerBackend,
+    rcon: Option<&Mutex<RconClient>>,
+    command: &str,
+) -> Result<Option<String>, MinecraftError> {
+    if let Some(rcon) = rcon {
+        let mut client = rcon.lock().await;
+        let reply = client.exec(command.trim_end()).await?;
+        return Ok(Some(reply));
+    }
+    backend.send_command(command).await?;
+    Ok(None)
+}
@@ -0,0 +1,295 @@
+use std::fmt;
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+const SERVERDATA_AUTH: i32 = 3;
+const SERVERDATA_AUTH_RESPONSE: i32 = 2;
+const SERVERDATA_EXECCOMMAND: i32 = 2;
+const SERVERDATA_RESPONSE_VALUE: i32 = 0;
+
+// Minecraft rejects client packets with a body larger than this.
+const MAX_COMMAND_LENGTH: usize = 1446;
+const MAX_PACKET_LENGTH: i32 = 65536;
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum RconError {
+    Io(std::io::Error),
+    AuthFailed,
+    Timeout,
+    Protocol(String),
+}
+
+impl From<std::io::Error> for RconError {
+    fn from(e: std::io::Error) -> Self {
+        RconError::Io(e)
+    }
+}
+
+impl std::error::Error for RconError {}
+
+impl fmt::Display for RconError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RconError::Io(e) => write!(f, "rcon io error: {}", e),
+            RconError::AuthFailed => write!(f, "rcon authentication failed"),
+            RconError::Timeout => write!(f, "rcon request timed out"),
+            RconError::Protocol(s) => write!(f, "rcon protocol error: {}", s),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Packet {
+    id: i32,
+    kind: i32,
+    body: String,
+}
+
+pub struct RconClient {
+    address: String,
+    password: String,
+    stream: Option<TcpStream>,
+    next_id: i32,
+}
+
+impl RconClient {
+    pub fn new(address: String, password: String) -> RconClient {
+        RconClient {
+            address,
+            password,
+            stream: None,
+            next_id: 1,
+        }
+    }
+
+    /// Runs a command and returns the server's reply, reconnecting once if
+    /// the existing connection turns out to be dead.
+    pub async fn exec(&mut self, command: &str) -> Result<String, RconError> {
+        if command.len() > MAX_COMMAND_LENGTH {
+            return Err(RconError::Protocol(format!(
+                "command exceeds {} bytes",
+                MAX_COMMAND_LENGTH
+            )));
+        }
+
+        let reused = self.stream.is_some();
+        match self.exec_once(command).await {
+            Ok(reply) => Ok(reply),
+            Err(e) => {
+                self.stream = None;
+                match e {
+                    RconError::Io(_) | RconError::Timeout if reused => {
+                        tracing::warn!(address = %self.address, "rcon connection lost, reconnecting");
+                        let result = self.exec_once(command).await;
+                        if result.is_err() {
+                            self.stream = None;
+                        }
+                        result
+                    }
+                    e => Err(e),
+                }
+            }
+        }
+    }
+
+    async fn exec_once(&mut self, command: &str) -> Result<String, RconError> {
+        if self.stream.is_none() {
+            self.connect().await?;
+        }
+
+        let id = self.take_id();
+        // Minecraft answers requests in order, so a trailing packet with an
+        // unknown type marks the end of a reply that was split across
+        // several response packets.
+        let sentinel = self.take_id();
+        let stream = self.stream.as_mut().unwrap();
+        write_packet(stream, id, SERVERDATA_EXECCOMMAND, command).await?;
+        write_packet(stream, sentinel, SERVERDATA_RESPONSE_VALUE, "").await?;
+
+        let mut reply = String::new();
+        loop {
+            let packet = read_packet(stream).await?;
+            if packet.id == sentinel {
+                break;
+            }
+            if packet.id != id || packet.kind != SERVERDATA_RESPONSE_VALUE {
+                return Err(RconError::Protocol(format!(
+                    "unexpected packet id {} type {}",
+                    packet.id, packet.kind
+                )));
+            }
+            reply.push_str(&packet.body);
+        }
+
+        Ok(reply)
+    }
+
+    async fn connect(&mut self) -> Result<(), RconError> {
+        let mut stream = match timeout(IO_TIMEOUT, TcpStream::connect(&self.address)).await {
+            Ok(s) => s?,
+            Err(_) => return Err(RconError::Timeout),
+        };
+
+        let id = self.take_id();
+        write_packet(&mut stream, id, SERVERDATA_AUTH, &self.password).await?;
+        loop {
+            let packet = read_packet(&mut stream).await?;
+            if packet.kind != SERVERDATA_AUTH_RESPONSE {
+                continue;
+            }
+            if packet.id == -1 {
+                return Err(RconError::AuthFailed);
+            }
+            if packet.id == id {
+                break;
+            }
+        }
+
+        tracing::info!(address = %self.address, "rcon connected");
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn take_id(&mut self) -> i32 {
+        let id = self.next_id;
+        self.next_id = if self.next_id == i32::MAX {
+            1
+        } else {
+            self.next_id + 1
+        };
+        id
+    }
+}
+
+/// The length, id and type, then the body ended by two nulls.
+fn encode(id: i32, kind: i32, body: &str) -> Vec<u8> {
+    let body = body.as_bytes();
+    let mut buf = Vec::with_capacity(body.len() + 14);
+    buf.extend_from_slice(&(body.len() as i32 + 10).to_le_bytes());
+    buf.extend_from_slice(&id.to_le_bytes());
+    buf.extend_from_slice(&kind.to_le_bytes());
+    buf.extend_from_slice(body);
+    buf.extend_from_slice(&[0, 0]);
+    buf
+}
+
+async fn write_packet<W: AsyncWrite + Unpin>(
+    stream: &mut W,
+    id: i32,
+    kind: i32,
+    body: &str,
+) -> Result<(), RconError> {
+    match timeout(IO_TIMEOUT, stream.write_all(&encode(id, kind, body))).await {
+        Ok(r) => Ok(r?),
+        Err(_) => Err(RconError::Timeout),
+    }
+}
+
+async fn read_packet<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Packet, RconError> {
+    let read = async {
+        let length = stream.read_i32_le().await?;
+        if !(10..=MAX_PACKET_LENGTH).contains(&length) {
+            return Err(RconError::Protocol(format!(
+                "invalid packet length {}",
+                length
+            )));
+        }
+        let mut buf = vec![0u8; length as usize];
+        stream.read_exact(&mut buf).await?;
+
+        let id = i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let kind = i32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+        let body = String::from_utf8_lossy(&buf[8..buf.len() - 2]).into_owned();
+        Ok::<Packet, RconError>(Packet { id, kind, body })
+    };
+
+    match timeout(IO_TIMEOUT, read).await {
+        Ok(r) => r,
+        Err(_) => Err(RconError::Timeout),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(bytes: &[u8]) -> Result<Packet, RconError> {
+        let mut reader = bytes;
+        read_packet(&mut reader).await
+    }
+
+    #[tokio::test]
+    async fn packets_round_trip() {
+        let mut buf = Vec::new();
+        write_packet(&mut buf, 7, SERVERDATA_EXECCOMMAND, "list")
+            .await
+            .unwrap();
+        assert_eq!(buf, encode(7, SERVERDATA_EXECCOMMAND, "list"));
+        assert_eq!(
+            read(&buf).await.unwrap(),
+            Packet {
+                id: 7,
+                kind: SERVERDATA_EXECCOMMAND,
+                body: String::from("list"),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_body() {
+        let buf = encode(-1, SERVERDATA_AUTH_RESPONSE, "");
+        assert_eq!(&buf[..4], &10i32.to_le_bytes());
+        let packet = read(&buf).await.unwrap();
+        assert_eq!(packet.id, -1);
+        assert_eq!(packet.body, "");
+    }
+
+    #[tokio::test]
+    async fn replies_in_sequence() {
+        let mut buf = encode(1, SERVERDATA_RESPONSE_VALUE, "There are 0 of a max of 20");
+        buf.extend(encode(2, SERVERDATA_RESPONSE_VALUE, ""));
+        let mut reader = buf.as_slice();
+        assert_eq!(read_packet(&mut reader).await.unwrap().id, 1);
+        assert_eq!(read_packet(&mut reader).await.unwrap().id, 2);
+        assert!(reader.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_lengths() {
+        for length in [-1i32, 0, 9, MAX_PACKET_LENGTH + 1, i32::MAX] {
+            let mut buf = length.to_le_bytes().to_vec();
+            buf.extend_from_slice(&[0; 16]);
+            assert!(
+                matches!(read(&buf).await, Err(RconError::Protocol(_))),
+                "length {}",
+                length
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_packets() {
+        let buf = encode(3, SERVERDATA_RESPONSE_VALUE, "hello");
+        assert!(matches!(
+            read(&buf[..buf.len() - 1]).await,
+            Err(RconError::Io(_))
+        ));
+        assert!(matches!(read(&buf[..2]).await, Err(RconError::Io(_))));
+        assert!(matches!(read(&[]).await, Err(RconError::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8_is_replaced() {
+        let mut buf = 12i32.to_le_bytes().to_vec();
+        buf.extend_from_slice(&1i32.to_le_bytes());
+        buf.extend_from_slice(&0i32.to_le_bytes());
+        buf.extend_from_slice(&[0xff, 0xfe, 0, 0]);
+        assert_eq!(read(&buf).await.unwrap().body, "\u{fffd}\u{fffd}");
+    }
+}
@@ -0,0 +1,339 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher};
+use serde::Serialize;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, Mutex};
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::auth::{Auth, ReloadError};
+use crate::check;
+use crate::cli;
+use crate::events::EventBus;
+use crate::logging::{self, FilterHandle};
+use crate::minecraft::MinecraftControl;
+use crate::notifications::{self, NotificationsConfig};
+use crate::scheduler::Task;
+use crate::util::now;
+
+// Editors write a file in a few steps, so changes are only read once it's
+// been quiet for this long.
+const SETTLE: Duration = Duration::from_millis(500);
+// What's applied without a restart. Tokens and users are read again on
+// every reload, since the files they're in may have changed.
+const AUTH_KEYS: &[&str] = &["tokens", "tokens_file", "users", "users_file"];
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct ReloadStatus {
+    // Seconds since the epoch.
+    pub at: u64,
+    // "sighup" or "file".
+    #[schema(value_type = String)]
+    pub trigger: &'static str,
+    // Why the config couldn't be read, in which case nothing was applied.
+    pub error: Option<String>,
+    // e.g. "auth", "notifications", "logging.filter" or
+    // "minecraft.survival.schedules".
+    pub applied: Vec<String>,
+    // Changes that couldn't be applied, and why.
+    pub failed: Vec<String>,
+    // Changes that only take effect once the panel is restarted.
+    pub restart_required: Vec<String>,
+}
+
+fn table(value: Option<&toml::Value>) -> toml::Table {
+    match value {
+        Some(toml::Value::Table(t)) => t.clone(),
+        _ => toml::Table::new(),
+    }
+}
+
+/// Keys of two tables that differ, leaving out `skip`.
+fn changed(old: &toml::Table, new: &toml::Table, skip: &[&str]) -> Vec<String> {
+    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter(|k| !skip.contains(&k.as_str()) && old.get(*k) != new.get(*k))
+        .cloned()
+        .collect()
+}
+
+/// Every server's table by name, whether it's one `[minecraft]` or a list
+/// of `[[minecraft]]`.
+fn servers(config: &toml::Table) -> Vec<(String, toml::Table)> {
+    let tables = match config.get("minecraft") {
+        Some(toml::Value::Array(a)) => a.iter().map(|s| table(Some(s))).collect(),
+        Some(s) => vec![table(Some(s))],
+        None => vec![toml::Table::new()],
+    };
+    tables
+        .into_iter()
+        .map(|t| {
+            let name = match t.get("name").and_then(|n| n.as_str()) {
+                Some(n) => n.to_owned(),
+                None => String::from("default"),
+            };
+            (name, t)
+        })
+        .collect()
+}
+
+/// Applies what it can of the config when the panel gets SIGHUP or the
+/// file changes: API tokens and users, notification destinations, the
+/// logging filter and scheduled tasks. Everything else is reported as
+/// needing a restart.
+#[derive(Clone)]
+pub struct Reloader {
+    path: PathBuf,
+    auth: Auth,
+    events: EventBus,
+    servers: Arc<Vec<MinecraftControl>>,
+    filter: FilterHandle,
+    shutdown: CancellationToken,
+    // Stops the notification senders started from the current config.
+    notifications: Arc<Mutex<CancellationToken>>,
+    // The config as it was last applied.
+    current: Arc<Mutex<toml::Table>>,
+    status: Arc<Mutex<Option<ReloadStatus>>>,
+}
+
+impl Reloader {
+    /// Starts the notification senders, which are restarted whenever the
+    /// [notifications] section changes.
+    pub fn new(
+        path: PathBuf,
+        raw: toml::Table,
+        notifications: Option<NotificationsConfig>,
+        auth: Auth,
+        events: EventBus,
+        servers: Arc<Vec<MinecraftControl>>,
+        filter: FilterHandle,
+        shutdown: CancellationToken,
+    ) -> Reloader {
+        let senders = shutdown.child_token();
+        notifications::init(notifications, &events, &servers, senders.clone());
+        Reloader {
+            path,
+            auth,
+            events,
+            servers,
+            filter,
+            shutdown,
+            notifications: Arc::new(Mutex::new(senders)),
+            current: Arc::new(Mutex::new(raw)),
+            status: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The outcome of the last reload, if there's been one.
+    pub async fn status(&self) -> Option<ReloadStatus> {
+        self.status.lock().await.clone()
+    }
+
+    async fn reload(&self, trigger: &'static str) {
+        let mut status = ReloadStatus {
+            at: now(),
+            trigger,
+            error: None,
+            applied: Vec::new(),
+            failed: Vec::new(),
+            restart_required: Vec::new(),
+        };
+        match self.apply(&mut status).await {
+            Ok(()) => tracing::info!(
+                "reloaded {}, applied {:?}, {} change(s) need a restart",
+                self.path.display(),
+                status.applied,
+                status.restart_required.len()
+            ),
+            Err(e) => {
+                tracing::warn!("could not reload {}: {}", self.path.display(), e);
+                status.error = Some(e);
+            }
+        }
+        for failure in &status.failed {
+            tracing::warn!("could not apply {}", failure);
+        }
+        *self.status.lock().await = Some(status);
+    }
+
+    async fn apply(&self, status: &mut ReloadStatus) -> Result<(), String> {
+        let new = cli::read_config(&self.path).await?;
+        // Nothing is applied from a config the panel wouldn't start with.
+        let config = match check::parse(&new) {
+            Ok(c) => c,
+            Err(problems) => return Err(problems.join("; ")),
+        };
+        let mut current = self.current.lock().await;
+
+        match self.auth.reload(config.auth.clone()).await {
+            Ok(()) => status.applied.push(String::from("auth")),
+            // Nothing else is applied either, so the whole file is tried
+            // again once it's been fixed.
+            Err(e @ ReloadError::Disables) => return Err(format!("auth: {}", e)),
+            Err(e) => status.failed.push(format!("auth: {}", e)),
+        }
+        let (old_auth, new_auth) = (table(current.get("auth")), table(new.get("auth")));
+        for key in changed(&old_auth, &new_auth, AUTH_KEYS) {
+            status.restart_required.push(format!("auth.{}", key));
+        }
+
+        if current.get("notifications") != new.get("notifications") {
+            let mut senders = self.notifications.lock().await;
+            senders.cancel();
+            *senders = self.shutdown.child_token();
+            notifications::init(
+                config.notifications.clone(),
+                &self.events,
+                &self.servers,
+                senders.clone(),
+            );
+            status.applied.push(String::from("notifications"));
+        }
+
+        let (old_logging, new_logging) = (table(current.get("logging")), table(new.get("logging")));
+        if old_logging.get("filter") != new_logging.get("filter") {
+            match logging::reload(&self.filter, config.logging.as_ref()) {
+                Ok(true) => status.applied.push(String::from("logging.filter")),
+                Ok(false) => status.failed.push(String::from(
+                    "logging.filter: RUST_LOG is set and takes precedence",
+                )),
+                Err(e) => status.failed.push(format!("logging.filter: {}", e)),
+            }
+        }
+        for key in changed(&old_logging, &new_logging, &["filter"]) {
+            status.restart_required.push(format!("logging.{}", key));
+        }
+
+        let (old_servers, new_servers) = (servers(&current), servers(&new));
+        let names = |s: &[(String, toml::Table)]| -> Vec<String> {
+            s.iter().map(|(n, _)| n.clone()).collect()
+        };
+        if names(&old_servers) != names(&new_servers) {
+            status.restart_required.push(String::from("minecraft"));
+        } else {
+            for ((name, old), (_, new)) in old_servers.iter().zip(new_servers.iter()) {
+                self.apply_server(name, old, new, status).await;
+            }
+        }
+
+        for key in changed(
+            &current,
+            &new,
+            &["auth", "notifications", "logging", "minecraft"],
+        ) {
+            status.restart_required.push(key);
+        }
+        *current = new;
+        Ok(())
+    }
+
+    async fn apply_server(
+        &self,
+        name: &str,
+        old: &toml::Table,
+        new: &toml::Table,
+        status: &mut ReloadStatus,
+    ) {
+        for key in changed(old, new, &["schedules"]) {
+            status
+                .restart_required
+                .push(format!("minecraft.{}.{}", name, key));
+        }
+        if old.get("schedules") == new.get("schedules") {
+            return;
+        }
+        let control = match self.servers.iter().find(|s| s.name() == name) {
+            Some(c) => c,
+            None => return,
+        };
+        let section = format!("minecraft.{}.schedules", name);
+        let tasks: Vec<Task> = match new.get("schedules").cloned() {
+            Some(s) => match cli::deserialize(s) {
+                Ok(t) => t,
+                Err(e) => {
+                    status.failed.push(format!("{}: {}", section, e));
+                    return;
+                }
+            },
+            None => Vec::new(),
+        };
+        match control.scheduler().reload(control.clone(), tasks).await {
+            Ok(()) => status.applied.push(section),
+            Err(e) => status.failed.push(format!("{}: {}", section, e)),
+        }
+    }
+
+    /// Reloads on SIGHUP, and when the file changes, until shutdown.
+    pub fn watch(&self) {
+        let reloader = self.clone();
+        tokio::spawn(async move {
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(s) => s,
+                Err(e) => {
+                    tracing::error!(error = %e, "could not listen for SIGHUP, the config won't be reloaded");
+                    return;
+                }
+            };
+            let (tx, mut changes) = mpsc::unbounded_channel();
+            // The watcher stops when dropped, so it's kept for as long as
+            // this runs.
+            let _watcher = match watch(&reloader.path, tx) {
+                Ok(w) => Some(w),
+                Err(e) => {
+                    tracing::warn!(
+                        "not watching {} for changes, reload it with SIGHUP: {}",
+                        reloader.path.display(),
+                        e
+                    );
+                    None
+                }
+            };
+            loop {
+                let trigger = tokio::select! {
+                    _ = hangup.recv() => "sighup",
+                    Some(()) = changes.recv() => {
+                        tokio::time::sleep(SETTLE).await;
+                        while changes.try_recv().is_ok() {}
+                        "file"
+                    },
+                    _ = reloader.shutdown.cancelled() => return,
+                };
+                reloader.reload(trigger).await;
+            }
+        });
+    }
+}
+
+/// Watches the directory rather than the file, since editors often save
+/// by replacing it.
+fn watch(
+    path: &Path,
+    tx: mpsc::UnboundedSender<()>,
+) -> Result<notify::RecommendedWatcher, notify::Error> {
+    let name = path.file_name().map(|n| n.to_owned());
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let event = match event {
+            Ok(e) => e,
+            Err(_) => return,
+        };
+        let ours = event.paths.iter().any(|p| p.file_name() == name.as_deref());
+        if ours && (event.kind.is_modify() || event.kind.is_create()) {
+            let _ = tx.send(());
+        }
+    })?;
+    let dir = match path.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
@@ -0,0 +1,147 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::minecraft::MinecraftControl;
+use crate::util::now;
+
+// What /proc/<pid>/stat counts CPU time in, which is 100 a second on every
+// Linux the panel runs on.
+const TICKS_PER_SECOND: f64 = 100.0;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ResourcesConfig {
+    // How often to sample, 15 seconds by default.
+    interval_secs: Option<u64>,
+    // How many samples to keep, 240 by default, an hour at the default
+    // interval.
+    window: Option<usize>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ProcessSample {
+    // Seconds since the epoch.
+    pub at: u64,
+    pub pid: u32,
+    // Of one core, so a busy server can go over 100. None for the first
+    // sample of a process, which has nothing to compare with.
+    pub cpu_percent: Option<f64>,
+    pub rss_bytes: u64,
+    pub threads: u32,
+    // None when the panel isn't allowed to look.
+    pub open_fds: Option<u32>,
+}
+
+/// Time the process has spent on the CPU, in ticks. The fields are counted
+/// from after the command name, which can have spaces and parentheses.
+async fn cpu_ticks(pid: u32) -> Option<u64> {
+    let stat = tokio::fs::read_to_string(format!("/proc/{}/stat", pid))
+        .await
+        .ok()?;
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 2..].split(' ').collect();
+    // utime and stime, the 14th and 15th fields of the whole line.
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+fn status_field(status: &str, key: &str) -> Option<u64> {
+    let line = status.lines().find(|l| l.starts_with(key))?;
+    line[key.len()..].split_whitespace().next()?.parse().ok()
+}
+
+async fn open_fds(pid: u32) -> Option<u32> {
+    let mut entries = tokio::fs::read_dir(format!("/proc/{}/fd", pid))
+        .await
+        .ok()?;
+    let mut count = 0;
+    while let Ok(Some(_)) = entries.next_entry().await {
+        count += 1;
+    }
+    Some(count)
+}
+
+/// Samples the server process's CPU, memory, threads and file descriptors
+/// from /proc, keeping the latest in a rolling window.
+#[derive(Clone)]
+pub struct Resources {
+    config: Option<ResourcesConfig>,
+    samples: Arc<Mutex<VecDeque<ProcessSample>>>,
+}
+
+impl Resources {
+    pub fn new(config: Option<ResourcesConfig>) -> Resources {
+        Resources {
+            config,
+            samples: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// Oldest first.
+    pub async fn samples(&self) -> Vec<ProcessSample> {
+        self.samples.lock().await.iter().cloned().collect()
+    }
+
+    pub async fn latest(&self) -> Option<ProcessSample> {
+        self.samples.lock().await.back().cloned()
+    }
+
+    /// Samples the process the backend says is the server's, while there
+    /// is one, until shutdown.
+    pub fn start(&self, control: MinecraftControl, shutdown: CancellationToken) {
+        let resources = self.clone();
+        let (interval, window) = match &self.config {
+            Some(c) => (c.interval_secs.unwrap_or(15), c.window.unwrap_or(240)),
+            None => (15, 240),
+        };
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval));
+            // The last CPU time seen, and of which process.
+            let mut last: Option<(u32, u64, Instant)> = None;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {},
+                    _ = shutdown.cancelled() => return,
+                }
+                let pid = match control.status().await.ok().and_then(|s| s.pid) {
+                    Some(p) if p > 0 => p,
+                    _ => continue,
+                };
+                let status = match tokio::fs::read_to_string(format!("/proc/{}/status", pid)).await
+                {
+                    Ok(s) => s,
+                    // In a container, or gone already.
+                    Err(_) => continue,
+                };
+                let ticks = cpu_ticks(pid).await;
+                let taken = Instant::now();
+                let cpu_percent = match (last, ticks) {
+                    (Some((last_pid, last_ticks, at)), Some(t)) if last_pid == pid => {
+                        let seconds = taken.duration_since(at).as_secs_f64();
+                        let used = t.saturating_sub(last_ticks) as f64 / TICKS_PER_SECOND;
+                        Some(used / seconds * 100.0)
+                    }
+                    _ => None,
+                };
+                last = ticks.map(|t| (pid, t, taken));
+                let sample = ProcessSample {
+                    at: now(),
+                    pid,
+                    cpu_percent,
+                    rss_bytes: status_field(&status, "VmRSS:").unwrap_or(0) * 1024,
+                    threads: status_field(&status, "Threads:").unwrap_or(0) as u32,
+                    open_fds: open_fds(pid).await,
+                };
+                let mut samples = resources.samples.lock().await;
+                samples.push_back(sample);
+                while samples.len() > window {
+                    samples.pop_front();
+                }
+            }
+        });
+    }
+}
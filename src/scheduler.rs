@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::commands::Command;
+use crate::minecraft::MinecraftControl;
+use crate::util::now;
+
+pub mod announcements;
+pub mod restart;
+
+// Task ids end up in URLs.
+const MAX_ID: usize = 64;
+
+/// What a task does when it comes round.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Action {
+    // Anything that can be typed on the console.
+    Command { command: String },
+    // With warnings in game counting down to it.
+    Restart(restart::Countdown),
+    Backup,
+    // Said to everyone in game, with `&` codes and [label](url) links.
+    Announce { message: String },
+}
+
+fn check_id(id: &str) -> Result<(), ScheduleError> {
+    let valid = id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if id.is_empty() || id.len() > MAX_ID || !valid {
+        return Err(ScheduleError::Invalid(format!(
+            "{:?} is not a valid id, use letters, numbers, - and _",
+            id
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Task {
+    pub id: String,
+    // A cron expression with seconds, e.g. "0 0 4 * * *" for 4am every day.
+    pub schedule: String,
+    #[serde(flatten)]
+    pub action: Action,
+    // Kept but not run when false. Enabled if unset.
+    pub enabled: Option<bool>,
+}
+
+impl Task {
+    fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    fn validate(&self) -> Result<cron::Schedule, ScheduleError> {
+        check_id(&self.id)?;
+        match &self.action {
+            Action::Command { command } if command.trim().is_empty() || command.contains('\n') => {
+                return Err(ScheduleError::Invalid(String::from(
+                    "the command has to be a single line",
+                )))
+            }
+            Action::Announce { message } if message.trim().is_empty() => {
+                return Err(ScheduleError::Invalid(String::from(
+                    "the announcement is empty",
+                )))
+            }
+            _ => {}
+        }
+        match cron::Schedule::from_str(&self.schedule) {
+            Ok(s) => Ok(s),
+            Err(e) => Err(ScheduleError::Invalid(format!(
+                "schedule {:?} is not valid: {}",
+                self.schedule, e
+            ))),
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct Run {
+    // Seconds since the epoch.
+    pub at: u64,
+    // Why it failed, if it did.
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ScheduledTask {
+    #[serde(flatten)]
+    pub task: Task,
+    // "config" for tasks from config.toml, which can't be changed through
+    // the API, or "api".
+    pub source: &'static str,
+    // Seconds since the epoch.
+    pub next_run: Option<u64>,
+    // Since the panel started.
+    pub last_run: Option<Run>,
+}
+
+#[derive(Debug)]
+pub enum ScheduleError {
+    Io(std::io::Error),
+    Invalid(String),
+    NotFound,
+    Exists,
+    // Defined in config.toml.
+    Configured,
+}
+
+impl From<std::io::Error> for ScheduleError {
+    fn from(e: std::io::Error) -> Self {
+        ScheduleError::Io(e)
+    }
+}
+
+impl std::fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScheduleError::Io(e) => write!(f, "could not save schedules: {}", e),
+            ScheduleError::Invalid(e) => write!(f, "{}", e),
+            ScheduleError::NotFound => write!(f, "there's nothing scheduled with that id"),
+            ScheduleError::Exists => write!(f, "there's already something scheduled with that id"),
+            ScheduleError::Configured => {
+                write!(f, "the task is set in the config file, change it there")
+            }
+        }
+    }
+}
+
+fn next_run(task: &Task) -> Option<u64> {
+    if !task.is_enabled() {
+        return None;
+    }
+    let schedule = cron::Schedule::from_str(&task.schedule).ok()?;
+    schedule
+        .upcoming(Local)
+        .next()
+        .map(|t| t.timestamp().max(0) as u64)
+}
+
+/// Runs a task that's due at `at`. Restarts start their countdown early
+/// and only restart at `at`.
+async fn run(control: &MinecraftControl, task: &Task, at: DateTime<Local>) -> Result<(), String> {
+    match &task.action {
+        Action::Command { command } => match control.command(command.clone()).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        },
+        Action::Restart(countdown) => {
+            let remaining = (at - Local::now()).to_std().unwrap_or_default();
+            countdown.run(control, Instant::now() + remaining).await
+        }
+        Action::Backup => match control.backups() {
+            Some(b) => match b.start(control.clone(), format!("schedule {}", task.id)) {
+                Ok(()) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            },
+            None => Err(String::from("backups aren't set up for this server")),
+        },
+        Action::Announce { message } => {
+            let tellraw = Command::Tellraw {
+                message: message.clone(),
+            };
+            match control.command(tellraw.render()).await {
+                Ok(_) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            }
+        }
+    }
+}
+
+/// Runs tasks on cron schedules: those in the config file, and those made
+/// through the API, which are persisted as JSON.
+#[derive(Clone)]
+pub struct Scheduler {
+    path: PathBuf,
+    // From config.toml, and replaced when it's reloaded.
+    configured: Arc<Mutex<Vec<Task>>>,
+    tasks: Arc<Mutex<Vec<Task>>>,
+    runs: Arc<Mutex<HashMap<String, Run>>>,
+    // Stops each task's loop when it's changed or deleted.
+    loops: Arc<Mutex<HashMap<String, CancellationToken>>>,
+    shutdown: CancellationToken,
+}
+
+/// Whether the tasks from the config can all be run, checked by
+/// `check::parse` before the config is used.
+pub fn check_configured(configured: &[Task]) -> Result<(), ScheduleError> {
+    for (i, task) in configured.iter().enumerate() {
+        if let Err(e) = task.validate() {
+            return Err(ScheduleError::Invalid(format!(
+                "scheduled task {:?} is not valid: {}",
+                task.id, e
+            )));
+        }
+        if configured[..i].iter().any(|t| t.id == task.id) {
+            return Err(ScheduleError::Invalid(format!(
+                "there's more than one scheduled task {:?}",
+                task.id
+            )));
+        }
+    }
+    Ok(())
+}
+
+impl Scheduler {
+    pub fn load(
+        path: PathBuf,
+        configured: Vec<Task>,
+        shutdown: CancellationToken,
+    ) -> Result<Scheduler, String> {
+        if let Err(e) = check_configured(&configured) {
+            return Err(e.to_string());
+        }
+        let tasks: Vec<Task> = match std::fs::read_to_string(&path) {
+            Ok(file) => match serde_json::from_str(&file) {
+                Ok(t) => t,
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            },
+            Err(_) => Vec::new(),
+        };
+        Ok(Scheduler {
+            path,
+            configured: Arc::new(Mutex::new(configured)),
+            tasks: Arc::new(Mutex::new(tasks)),
+            runs: Arc::new(Mutex::new(HashMap::new())),
+            loops: Arc::new(Mutex::new(HashMap::new())),
+            shutdown,
+        })
+    }
+
+    async fn save(&self, tasks: &[Task]) -> Result<(), std::io::Error> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(tasks)?).await?;
+        fs::rename(&tmp, &self.path).await
+    }
+
+    /// Runs every enabled task on its schedule, until shutdown.
+    pub fn start(&self, control: MinecraftControl) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            let configured = scheduler.configured.lock().await.clone();
+            let tasks = scheduler.tasks.lock().await.clone();
+            for task in configured.into_iter().chain(tasks) {
+                scheduler.spawn(control.clone(), task).await;
+            }
+        });
+    }
+
+    async fn spawn(&self, control: MinecraftControl, task: Task) {
+        let schedule = match (task.is_enabled(), task.validate()) {
+            (true, Ok(s)) => s,
+            (true, Err(e)) => {
+                tracing::warn!(task = %task.id, error = %e, "not running the scheduled task");
+                return;
+            }
+            (false, _) => return,
+        };
+        let stop = self.shutdown.child_token();
+        if let Some(old) = self
+            .loops
+            .lock()
+            .await
+            .insert(task.id.clone(), stop.clone())
+        {
+            old.cancel();
+        }
+        let lead = match &task.action {
+            Action::Restart(countdown) => countdown.lead(),
+            _ => Duration::ZERO,
+        };
+        let runs = self.runs.clone();
+        tokio::spawn(async move {
+            loop {
+                let next = match schedule.upcoming(Local).next() {
+                    Some(n) => n,
+                    None => return,
+                };
+                let wait = (next - Local::now()).to_std().unwrap_or_default();
+                tokio::select! {
+                    _ = tokio::time::sleep(wait.saturating_sub(lead)) => {},
+                    _ = stop.cancelled() => return,
+                }
+                // Deleting a task calls off a countdown that's under way.
+                let error = tokio::select! {
+                    result = run(&control, &task, next) => result.err(),
+                    _ = stop.cancelled() => return,
+                };
+                if let Some(e) = &error {
+                    tracing::warn!(task = %task.id, server = %control.name(), error = %e, "scheduled task failed");
+                }
+                runs.lock()
+                    .await
+                    .insert(task.id.clone(), Run { at: now(), error });
+            }
+        });
+    }
+
+    async fn stop(&self, id: &str) {
+        if let Some(stop) = self.loops.lock().await.remove(id) {
+            stop.cancel();
+        }
+        self.runs.lock().await.remove(id);
+    }
+
+    async fn is_configured(&self, id: &str) -> bool {
+        self.configured.lock().await.iter().any(|t| t.id == id)
+    }
+
+    /// Replaces the tasks from config.toml with those from a reloaded
+    /// one. Tasks that haven't changed carry on as they were.
+    pub async fn reload(
+        &self,
+        control: MinecraftControl,
+        configured: Vec<Task>,
+    ) -> Result<(), ScheduleError> {
+        check_configured(&configured)?;
+        let mut current = self.configured.lock().await;
+        let tasks = self.tasks.lock().await;
+        if let Some(t) = configured
+            .iter()
+            .find(|c| tasks.iter().any(|t| t.id == c.id))
+        {
+            return Err(ScheduleError::Invalid(format!(
+                "scheduled task {:?} is also one made through the API",
+                t.id
+            )));
+        }
+        drop(tasks);
+        let old = std::mem::replace(&mut *current, configured.clone());
+        drop(current);
+        let same = |a: &Task, b: &Task| {
+            a.id == b.id && serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+        };
+        for task in old
+            .iter()
+            .filter(|o| !configured.iter().any(|c| same(o, c)))
+        {
+            self.stop(&task.id).await;
+        }
+        for task in configured {
+            if !old.iter().any(|o| same(o, &task)) {
+                self.spawn(control.clone(), task).await;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn list(&self) -> Vec<ScheduledTask> {
+        let runs = self.runs.lock().await;
+        let configured = self.configured.lock().await;
+        let tasks = self.tasks.lock().await;
+        let configured = configured.iter().map(|t| (t, "config"));
+        configured
+            .chain(tasks.iter().map(|t| (t, "api")))
+            .map(|(task, source)| ScheduledTask {
+                task: task.clone(),
+                source,
+                next_run: next_run(task),
+                last_run: runs.get(&task.id).cloned(),
+            })
+            .collect()
+    }
+
+    pub async fn create(&self, control: MinecraftControl, task: Task) -> Result<(), ScheduleError> {
+        task.validate()?;
+        let configured = self.configured.lock().await;
+        let mut tasks = self.tasks.lock().await;
+        if configured
+            .iter()
+            .chain(tasks.iter())
+            .any(|t| t.id == task.id)
+        {
+            return Err(ScheduleError::Exists);
+        }
+        tasks.push(task.clone());
+        self.save(&tasks).await?;
+        drop(tasks);
+        drop(configured);
+        self.spawn(control, task).await;
+        Ok(())
+    }
+
+    /// Replaces a task made through the API, which starts over on its new
+    /// schedule.
+    pub async fn update(&self, control: MinecraftControl, task: Task) -> Result<(), ScheduleError> {
+        if self.is_configured(&task.id).await {
+            return Err(ScheduleError::Configured);
+        }
+        task.validate()?;
+        let mut tasks = self.tasks.lock().await;
+        match tasks.iter_mut().find(|t| t.id == task.id) {
+            Some(t) => *t = task.clone(),
+            None => return Err(ScheduleError::NotFound),
+        }
+        self.save(&tasks).await?;
+        drop(tasks);
+        self.stop(&task.id).await;
+        self.spawn(control, task).await;
+        Ok(())
+    }
+
+    pub async fn delete(&self, id: &str) -> Result<(), ScheduleError> {
+        if self.is_configured(id).await {
+            return Err(ScheduleError::Configured);
+        }
+        let mut tasks = self.tasks.lock().await;
+        let before = tasks.len();
+        tasks.retain(|t| t.id != id);
+        if tasks.len() == before {
+            return Err(ScheduleError::NotFound);
+        }
+        self.save(&tasks).await?;
+        drop(tasks);
+        self.stop(id).await;
+        Ok(())
+    }
+}
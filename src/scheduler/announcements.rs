@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use super::{check_id, ScheduleError};
+use crate::commands::Command;
+use crate::minecraft::MinecraftControl;
+use crate::players::parse_list;
+
+/// Messages sent to everyone in game every so often, one at a time.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Announcement {
+    pub id: String,
+    // With `&` colour codes and [label](https://...) links.
+    pub messages: Vec<String>,
+    pub interval_minutes: u64,
+    // Pick each message at random rather than going through them in turn.
+    pub random: Option<bool>,
+    // Kept but not sent when false. Enabled if unset.
+    pub enabled: Option<bool>,
+}
+
+impl Announcement {
+    fn validate(&self) -> Result<(), ScheduleError> {
+        check_id(&self.id)?;
+        if self.messages.is_empty() || self.messages.iter().any(|m| m.trim().is_empty()) {
+            return Err(ScheduleError::Invalid(String::from(
+                "announcements need at least one message, and none of them empty",
+            )));
+        }
+        if self.interval_minutes == 0 {
+            return Err(ScheduleError::Invalid(String::from(
+                "the interval has to be at least a minute",
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Whether there's anyone to announce to. Taken to be yes when the server
+/// doesn't say.
+async fn anyone_online(control: &MinecraftControl) -> bool {
+    match control.execute(String::from("list")).await {
+        Ok(Some(reply)) => parse_list(&reply).map_or(true, |l| l.online > 0),
+        _ => true,
+    }
+}
+
+/// Recurring announcements made through the API, persisted as JSON.
+#[derive(Clone)]
+pub struct Announcements {
+    path: PathBuf,
+    announcements: Arc<Mutex<Vec<Announcement>>>,
+    // Stops each announcement's loop when it's changed or deleted.
+    loops: Arc<Mutex<HashMap<String, CancellationToken>>>,
+    shutdown: CancellationToken,
+}
+
+impl Announcements {
+    pub fn load(path: PathBuf, shutdown: CancellationToken) -> Result<Announcements, String> {
+        let announcements: Vec<Announcement> = match std::fs::read_to_string(&path) {
+            Ok(file) => match serde_json::from_str(&file) {
+                Ok(a) => a,
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            },
+            Err(_) => Vec::new(),
+        };
+        Ok(Announcements {
+            path,
+            announcements: Arc::new(Mutex::new(announcements)),
+            loops: Arc::new(Mutex::new(HashMap::new())),
+            shutdown,
+        })
+    }
+
+    async fn save(&self, announcements: &[Announcement]) -> Result<(), std::io::Error> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(announcements)?).await?;
+        fs::rename(&tmp, &self.path).await
+    }
+
+    /// Sends every enabled announcement on its interval, until shutdown.
+    pub fn start(&self, control: MinecraftControl) {
+        let announcements = self.clone();
+        tokio::spawn(async move {
+            let list = announcements.announcements.lock().await.clone();
+            for announcement in list {
+                announcements.spawn(control.clone(), announcement).await;
+            }
+        });
+    }
+
+    async fn spawn(&self, control: MinecraftControl, announcement: Announcement) {
+        if !announcement.enabled.unwrap_or(true) {
+            return;
+        }
+        let stop = self.shutdown.child_token();
+        let mut loops = self.loops.lock().await;
+        if let Some(old) = loops.insert(announcement.id.clone(), stop.clone()) {
+            old.cancel();
+        }
+        tokio::spawn(async move {
+            let period = Duration::from_secs(announcement.interval_minutes * 60);
+            let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+            let mut next = 0;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {},
+                    _ = stop.cancelled() => return,
+                }
+                if !anyone_online(&control).await {
+                    continue;
+                }
+                let count = announcement.messages.len();
+                let i = match announcement.random {
+                    Some(true) => rand::thread_rng().gen_range(0..count),
+                    _ => next % count,
+                };
+                next = i + 1;
+                let tellraw = Command::Tellraw {
+                    message: announcement.messages[i].clone(),
+                };
+                if control.command(tellraw.render()).await.is_err() {
+                    tracing::warn!(server = %control.name(), id = %announcement.id, "could not send an announcement");
+                }
+            }
+        });
+    }
+
+    async fn stop(&self, id: &str) {
+        if let Some(stop) = self.loops.lock().await.remove(id) {
+            stop.cancel();
+        }
+    }
+
+    pub async fn list(&self) -> Vec<Announcement> {
+        self.announcements.lock().await.clone()
+    }
+
+    pub async fn create(
+        &self,
+        control: MinecraftControl,
+        announcement: Announcement,
+    ) -> Result<(), ScheduleError> {
+        announcement.validate()?;
+        let mut announcements = self.announcements.lock().await;
+        if announcements.iter().any(|a| a.id == announcement.id) {
+            return Err(ScheduleError::Exists);
+        }
+        announcements.push(announcement.clone());
+        self.save(&announcements).await?;
+        drop(announcements);
+        self.spawn(control, announcement).await;
+        Ok(())
+    }
+
+    pub async fn update(
+        &self,
+        control: MinecraftControl,
+        announcement: Announcement,
+    ) -> Result<(), ScheduleError> {
+        announcement.validate()?;
+        let mut announcements = self.announcements.lock().await;
+        match announcements.iter_mut().find(|a| a.id == announcement.id) {
+            Some(a) => *a = announcement.clone(),
+            None => return Err(ScheduleError::NotFound),
+        }
+        self.save(&announcements).await?;
+        drop(announcements);
+        self.stop(&announcement.id).await;
+        self.spawn(control, announcement).await;
+        Ok(())
+    }
+
+    pub async fn delete(&self, id: &str) -> Result<(), ScheduleError> {
+        let mut announcements = self.announcements.lock().await;
+        let before = announcements.len();
+        announcements.retain(|a| a.id != id);
+        if announcements.len() == before {
+            return Err(ScheduleError::NotFound);
+        }
+        self.save(&announcements).await?;
+        drop(announcements);
+        self.stop(id).await;
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::commands::Command;
+use crate::events::ServerEvent;
+use crate::minecraft::MinecraftControl;
+use crate::players::parse_list;
+use crate::util::now;
+
+// Minutes before the restart that players are warned, if not set.
+const WARNINGS: &[u64] = &[10, 5, 1];
+const KICK_MESSAGE: &str = "The server is restarting, come back in a minute";
+
+/// How a scheduled restart warns the players on the server. The restart
+/// happens at the scheduled time, with the warnings before it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Countdown {
+    // Minutes before the restart, 10, 5 and 1 if unset. An empty list
+    // restarts without warning.
+    pub warnings: Option<Vec<u64>>,
+    // Said in chat and shown as a title, with {minutes} filled in.
+    pub message: Option<String>,
+    // What players are kicked with just before the restart.
+    pub kick_message: Option<String>,
+}
+
+impl Countdown {
+    /// Longest first.
+    fn warnings(&self) -> Vec<u64> {
+        let mut warnings = match &self.warnings {
+            Some(w) => w.clone(),
+            None => WARNINGS.to_vec(),
+        };
+        warnings.retain(|m| *m > 0);
+        warnings.sort_unstable_by(|a, b| b.cmp(a));
+        warnings.dedup();
+        warnings
+    }
+
+    /// How long before the restart the first warning goes out.
+    pub fn lead(&self) -> Duration {
+        match self.warnings().first() {
+            Some(m) => Duration::from_secs(m * 60),
+            None => Duration::ZERO,
+        }
+    }
+
+    fn message(&self, minutes: u64) -> String {
+        match &self.message {
+            Some(m) => m.replace("{minutes}", &minutes.to_string()),
+            None if minutes == 1 => String::from("Restarting in 1 minute"),
+            None => format!("Restarting in {} minutes", minutes),
+        }
+    }
+
+    async fn announce(&self, control: &MinecraftControl, minutes: u64, restart_at: u64) {
+        let message = self.message(minutes);
+        let commands = [
+            Command::Say {
+                message: message.clone(),
+            },
+            Command::Title { message },
+        ];
+        for command in commands {
+            if control.command(command.render()).await.is_err() {
+                tracing::warn!(server = %control.name(), "could not warn players of the restart");
+            }
+        }
+        control.publish(ServerEvent::RestartWarning {
+            minutes,
+            at: restart_at,
+        });
+    }
+
+    async fn kick_everyone(&self, control: &MinecraftControl) {
+        let players = match control.execute(String::from("list")).await {
+            Ok(Some(reply)) => parse_list(&reply).map(|l| l.players).unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        let reason = match &self.kick_message {
+            Some(m) => m.clone(),
+            None => String::from(KICK_MESSAGE),
+        };
+        for player in players {
+            let kick = Command::Kick {
+                player,
+                reason: Some(reason.clone()),
+            };
+            if kick.validate().is_ok() {
+                let _ = control.command(kick.render()).await;
+            }
+        }
+    }
+
+    /// Warns players at each of the warnings still to come, then kicks
+    /// them and restarts the server at `at`.
+    pub async fn run(&self, control: &MinecraftControl, at: Instant) -> Result<(), String> {
+        let remaining = at.saturating_duration_since(Instant::now());
+        let restart_at = now() + remaining.as_secs();
+        for minutes in self.warnings() {
+            let before = Duration::from_secs(minutes * 60);
+            // Waking up a moment late shouldn't lose the first warning.
+            if at.saturating_duration_since(Instant::now()) + Duration::from_secs(1) < before {
+                continue;
+            }
+            tokio::time::sleep_until(at.checked_sub(before).unwrap_or(at)).await;
+            self.announce(control, minutes, restart_at).await;
+        }
+        tokio::time::sleep_until(at).await;
+        self.kick_everyone(control).await;
+        match control.restart().await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
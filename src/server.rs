@@ -0,0 +1,1049 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::io;
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{self, Event, Sse},
+        IntoResponse,
+    },
+    routing::{any, delete, get, post, put},
+    Extension, Json, Router,
+};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::auth::{self, Principal, Role};
+use crate::backend::{BackendError, LogStream, ServerStatus};
+use crate::bedrock;
+use crate::commands::{Command, Refusal};
+use crate::console;
+use crate::error::{ApiError, ErrorBody};
+use crate::logsource::{
+    archive::{self, ArchivedLog},
+    journal::{self, JournalPage},
+    search::{self, SearchResults, SearchTerms},
+};
+use crate::minecraft::{MinecraftControl, MinecraftError};
+use crate::mojang::{self, Mojang, MojangError, Profile};
+use crate::ping;
+use crate::players::PlayerList;
+use crate::policy;
+use crate::query;
+use crate::sessions::{PlayerSessions, Playtime};
+use crate::watchdog::Incident;
+
+pub mod announcements;
+pub mod backups;
+pub mod bans;
+pub mod commands;
+pub mod gamerules;
+pub mod jvm;
+pub mod maintenance;
+pub mod metrics;
+pub mod mods;
+pub mod ops;
+pub mod players;
+pub mod properties;
+pub mod schedules;
+pub mod updater;
+pub mod worlds;
+
+/// Where a server's routes are mounted, which decides their paths.
+#[derive(Clone, Copy)]
+pub enum Layout {
+    // Under `/servers/{name}` for every server, and at the root for the
+    // first one, as they were before the API was versioned.
+    Legacy,
+    // Under `/api/v1/servers/{name}`, where the `/api` the legacy paths
+    // start with would be repeated, so it's left off.
+    V1,
+}
+
+impl Layout {
+    pub fn path(self, path: &str) -> &str {
+        match self {
+            Layout::Legacy => path,
+            Layout::V1 => path.strip_prefix("/api").unwrap_or(path),
+        }
+    }
+}
+
+/// Routes for controlling a single server.
+pub fn routes(layout: Layout) -> Router<MinecraftControl> {
+    let viewer_routes: Router<MinecraftControl> = Router::new()
+        .route(layout.path("/ws"), any(console::ws_handler))
+        .route(layout.path("/events"), get(events_handler))
+        .route(layout.path("/ws/metrics"), any(metrics::ws_handler))
+        .route(layout.path("/events/logs"), get(console::events_handler))
+        .route(layout.path("/log"), get(log_handler))
+        .route(layout.path("/api/logs"), get(history_handler))
+        .route(layout.path("/api/logs/search"), get(search_handler))
+        .route(layout.path("/api/logs/archive"), get(archive_list_handler))
+        .route(
+            layout.path("/api/logs/archive/{name}"),
+            get(archive_handler),
+        )
+        .route(layout.path("/server/status"), get(status_handler))
+        .route(layout.path("/api/status"), get(ping_handler))
+        .route(layout.path("/api/metrics/tps"), get(metrics::tps_handler))
+        .route(
+            layout.path("/api/metrics/process"),
+            get(metrics::process_handler),
+        )
+        .route(layout.path("/api/metrics/disk"), get(metrics::disk_handler))
+        .route(layout.path("/api/metrics/gc"), get(metrics::gc_handler))
+        .route(
+            layout.path("/api/watchdog/incidents"),
+            get(incidents_handler),
+        )
+        .route(layout.path("/api/mods"), get(mods::list_handler))
+        .route(
+            layout.path("/api/mods/modrinth/search"),
+            get(mods::search_handler),
+        )
+        .route(
+            layout.path("/api/mods/modrinth/updates"),
+            get(mods::updates_handler),
+        )
+        .route(layout.path("/api/motd"), get(properties::motd_handler))
+        .route(layout.path("/api/worlds"), get(worlds::list_handler))
+        .route(
+            layout.path("/api/worlds/{name}/level"),
+            get(worlds::level_handler),
+        )
+        .route(
+            layout.path("/api/worlds/{name}/regions"),
+            get(worlds::regions_handler),
+        )
+        .route(layout.path("/api/players"), get(players_handler))
+        .route(layout.path("/api/players/playtime"), get(playtime_handler))
+        .route(
+            layout.path("/api/players/{name}/sessions"),
+            get(sessions_handler),
+        )
+        .route(
+            layout.path("/api/players/{name}/profile"),
+            get(profile_handler),
+        )
+        .route(
+            layout.path("/api/players/{name}/head.png"),
+            get(head_handler),
+        )
+        .route(
+            layout.path("/api/players/{name}/stats"),
+            get(players::stats_handler),
+        );
+
+    let operator_routes: Router<MinecraftControl> = Router::new()
+        .route(layout.path("/command"), post(command_writer))
+        .route(layout.path("/api/commands"), post(commands::run_handler))
+        .route(layout.path("/api/players/{name}/kick"), post(kick_handler))
+        .route(
+            layout.path("/api/players/{name}/data"),
+            get(players::data_handler),
+        )
+        .route(layout.path("/api/bans"), get(bans::list_handler))
+        .route(
+            layout.path("/api/bans/players"),
+            post(bans::ban_player_handler),
+        )
+        .route(
+            layout.path("/api/bans/players/{player}"),
+            delete(bans::pardon_player_handler),
+        )
+        .route(layout.path("/api/bans/ips"), post(bans::ban_ip_handler))
+        .route(
+            layout.path("/api/bans/ips/{ip}"),
+            delete(bans::pardon_ip_handler),
+        )
+        .route(layout.path("/api/ops"), get(ops::list_handler))
+        .route(layout.path("/api/backups"), get(backups::list_handler))
+        .route(layout.path("/api/schedules"), get(schedules::list_handler))
+        .route(
+            layout.path("/api/announcements"),
+            get(announcements::list_handler).post(announcements::create_handler),
+        )
+        .route(
+            layout.path("/api/announcements/{id}"),
+            put(announcements::update_handler).delete(announcements::delete_handler),
+        )
+        .route(
+            layout.path("/api/gamerules"),
+            get(gamerules::get_handler).put(gamerules::put_handler),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            Role::Operator,
+            auth::require_role,
+        ));
+
+    let admin_routes: Router<MinecraftControl> = Router::new()
+        .route(layout.path("/server/start"), post(start_handler))
+        .route(layout.path("/server/stop"), post(stop_handler))
+        .route(layout.path("/server/restart"), post(restart_handler))
+        .route(
+            layout.path("/api/ops/{player}"),
+            post(ops::op_handler).delete(ops::deop_handler),
+        )
+        .route(
+            layout.path("/api/server.properties"),
+            get(properties::get_handler).patch(properties::patch_handler),
+        )
+        .route(layout.path("/api/motd"), put(properties::set_motd_handler))
+        .route(
+            layout.path("/api/maintenance"),
+            get(maintenance::get_handler).post(maintenance::set_handler),
+        )
+        .route(
+            layout.path("/api/mods/modrinth/install"),
+            post(mods::install_handler),
+        )
+        .route(
+            layout.path("/api/server-icon"),
+            put(properties::icon_handler),
+        )
+        .route(layout.path("/api/server-jar"), get(updater::status_handler))
+        .route(
+            layout.path("/api/server-jar/versions"),
+            get(updater::versions_handler),
+        )
+        .route(
+            layout.path("/api/server-jar/update"),
+            post(updater::update_handler),
+        )
+        .route(
+            layout.path("/api/server-jar/rollback"),
+            post(updater::rollback_handler),
+        )
+        .route(layout.path("/api/eula"), get(properties::eula_handler))
+        .route(
+            layout.path("/api/worlds/{name}"),
+            post(worlds::upload_handler),
+        )
+        .route(layout.path("/api/backups"), post(backups::create_handler))
+        .route(
+            layout.path("/api/backups/{id}/restore"),
+            post(backups::restore_handler),
+        )
+        .route(
+            layout.path("/api/worlds/{name}/download"),
+            get(worlds::download_handler),
+        )
+        .route(
+            layout.path("/api/worlds/{name}/prune"),
+            post(worlds::prune_handler),
+        )
+        .route(
+            layout.path("/api/jvm/thread-dump"),
+            post(jvm::thread_dump_handler),
+        )
+        .route(
+            layout.path("/api/jvm/heap-summary"),
+            post(jvm::heap_summary_handler),
+        )
+        .route(
+            layout.path("/api/eula/accept"),
+            post(properties::accept_eula_handler),
+        )
+        .route(
+            layout.path("/api/schedules"),
+            post(schedules::create_handler),
+        )
+        .route(
+            layout.path("/api/schedules/{id}"),
+            put(schedules::update_handler).delete(schedules::delete_handler),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            Role::Admin,
+            auth::require_role,
+        ));
+
+    Router::new()
+        .merge(viewer_routes)
+        .merge(operator_routes)
+        .merge(admin_routes)
+}
+
+/// The routes described in the OpenAPI document, relative to
+/// `/api/v1/servers/{server}`.
+#[derive(OpenApi)]
+#[openapi(paths(
+    log_handler,
+    command_writer,
+    commands::run_handler,
+    status_handler,
+    start_handler,
+    stop_handler,
+    restart_handler,
+    players_handler,
+    kick_handler,
+    bans::list_handler,
+    bans::ban_player_handler,
+    bans::ban_ip_handler,
+    bans::pardon_player_handler,
+    bans::pardon_ip_handler,
+    ops::list_handler,
+    ops::op_handler,
+    ops::deop_handler,
+))]
+pub struct ServerApi;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LogQuery {
+    // Only the last this many lines.
+    lines: Option<usize>,
+    // Keep the response open and add lines as they're logged.
+    follow: Option<bool>,
+}
+
+/// The server's latest.log, or its tail, optionally followed as it grows.
+#[utoipa::path(
+    get,
+    path = "/log",
+    tag = "console",
+    params(LogQuery),
+    responses((status = 200, description = "Log lines", body = String, content_type = "text/plain"))
+)]
+async fn log_handler(
+    State(control): State<MinecraftControl>,
+    Query(query): Query<LogQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    // Subscribed to before reading so nothing logged in between is missed,
+    // though a line or two may then show up twice.
+    let live = if query.follow.unwrap_or(false) {
+        Some(control.subscribe().1)
+    } else {
+        None
+    };
+
+    let logstream = match control.log().await {
+        Ok(s) => s,
+        Err(e) => return Err(minecraft_error(e)),
+    };
+    let mut logstream: LogStream = match query.lines {
+        Some(n) => {
+            let tail = last_lines(logstream, n.clamp(1, MAX_TAIL_LINES)).await;
+            futures::stream::once(async move { tail }).boxed()
+        }
+        None => logstream,
+    };
+    if let Some(rx) = live {
+        let lines = futures::stream::unfold(rx, |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(entry) => return Some((Ok(Bytes::from(entry.message + "\n")), rx)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        logstream = logstream.chain(lines).boxed();
+    }
+    // A followed log would otherwise hold up shutting down.
+    let body = Body::from_stream(logstream.take_until(control.shutdown().cancelled_owned()));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        "text/plain; charset=utf-8".parse().unwrap(),
+    );
+
+    Ok((headers, body))
+}
+
+const MAX_TAIL_LINES: usize = 10000;
+
+/// Reads a log through to the end, keeping only its last `n` lines.
+async fn last_lines(mut log: LogStream, n: usize) -> Result<Bytes, io::Error> {
+    let mut lines: VecDeque<Vec<u8>> = VecDeque::with_capacity(n);
+    let mut partial = Vec::new();
+    while let Some(chunk) = log.next().await {
+        for byte in chunk?.iter() {
+            partial.push(*byte);
+            if *byte != b'\n' {
+                continue;
+            }
+            if lines.len() == n {
+                lines.pop_front();
+            }
+            lines.push_back(std::mem::take(&mut partial));
+        }
+    }
+    // The last line may not be finished yet.
+    if !partial.is_empty() {
+        if lines.len() == n {
+            lines.pop_front();
+        }
+        lines.push_back(partial);
+    }
+    Ok(Bytes::from(
+        lines.into_iter().flatten().collect::<Vec<u8>>(),
+    ))
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+async fn history_handler(
+    State(control): State<MinecraftControl>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<JournalPage>, ApiError> {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let filter = match control.journal_filter() {
+        Some(f) => f,
+        None => return saved_history(control, query.cursor, limit).await,
+    };
+
+    let page = tokio::task::spawn_blocking(move || {
+        journal::history(&filter, query.cursor.as_deref(), limit)
+    })
+    .await;
+    match page {
+        Ok(Ok(page)) => Ok(Json(page)),
+        Ok(Err(e)) => {
+            tracing::warn!(error = %e, "could not read journal history");
+            Err(ApiError::new(StatusCode::BAD_REQUEST, e.to_string()))
+        }
+        Err(e) => Err(ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+fn log_dir(control: &MinecraftControl) -> Result<std::path::PathBuf, ApiError> {
+    match control.log_dir() {
+        Some(d) => Ok(d),
+        None => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            String::from("this server's log files aren't available to the panel"),
+        )),
+    }
+}
+
+pub fn server_dir(control: &MinecraftControl) -> Result<std::path::PathBuf, ApiError> {
+    match control.server_dir() {
+        Some(d) => Ok(d),
+        None => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            String::from("this server's files aren't available to the panel"),
+        )),
+    }
+}
+
+async fn archive_list_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<Vec<ArchivedLog>>, ApiError> {
+    let dir = log_dir(&control)?;
+    match archive::list(&dir).await {
+        Ok(logs) => Ok(Json(logs)),
+        Err(e) => {
+            tracing::warn!(path = %dir.display(), error = %e, "could not list archived logs");
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+async fn archive_handler(
+    State(control): State<MinecraftControl>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let dir = log_dir(&control)?;
+    let logstream = match archive::open(&dir, &name).await {
+        Ok(s) => s,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                format!("no archived log {}", name),
+            ))
+        }
+        Err(e) => {
+            return Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        "text/plain; charset=utf-8".parse().unwrap(),
+    );
+    Ok((headers, Body::from_stream(logstream)))
+}
+
+/// History from the SQLite database, for servers not logging to the
+/// journal.
+async fn saved_history(
+    control: MinecraftControl,
+    cursor: Option<String>,
+    limit: usize,
+) -> Result<Json<JournalPage>, ApiError> {
+    let history = match control.history() {
+        Some(h) => h.clone(),
+        None => {
+            return Err(ApiError::new(
+                StatusCode::NOT_FOUND,
+                String::from("this server's log is not in the journal or the history"),
+            ))
+        }
+    };
+    let name = control.name().to_owned();
+    let page =
+        tokio::task::spawn_blocking(move || history.page(&name, cursor.as_deref(), limit)).await;
+    match page {
+        Ok(Ok(page)) => Ok(Json(page)),
+        Ok(Err(e)) => {
+            tracing::warn!(error = %e, "could not read log history");
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+        Err(e) => Err(ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: Option<String>,
+    regex: Option<String>,
+    // Milliseconds since the epoch.
+    from: Option<u64>,
+    to: Option<u64>,
+    limit: Option<usize>,
+}
+
+/// Looks through the saved history, or else the log files and the journal,
+/// whichever this server has, oldest first.
+async fn search_handler(
+    State(control): State<MinecraftControl>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResults>, ApiError> {
+    let terms = match SearchTerms::new(
+        query.q.as_deref(),
+        query.regex.as_deref(),
+        query.from,
+        query.to,
+    ) {
+        Ok(t) => t,
+        Err(e) => return Err(ApiError::new(StatusCode::BAD_REQUEST, e)),
+    };
+    let limit = query.limit.unwrap_or(500).clamp(1, 5000);
+    // One more than asked for, to tell whether there are more.
+    let wanted = limit + 1;
+
+    // Everything the panel has seen is in the history, so that's all there
+    // is to look through when it's kept.
+    if let Some(history) = control.history() {
+        let history = history.clone();
+        let name = control.name().to_owned();
+        let found =
+            tokio::task::spawn_blocking(move || history.search(&name, &terms, limit + 1)).await;
+        return match found {
+            Ok(Ok(mut matches)) => {
+                let truncated = matches.len() > limit;
+                matches.truncate(limit);
+                Ok(Json(SearchResults { matches, truncated }))
+            }
+            Ok(Err(e)) => Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            )),
+            Err(e) => Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            )),
+        };
+    }
+
+    let mut matches = Vec::new();
+    if let Some(dir) = control.log_dir() {
+        let files = match search::log_files(&dir, &terms).await {
+            Ok(f) => f,
+            Err(e) => {
+                tracing::warn!(path = %dir.display(), error = %e, "could not list log files");
+                Vec::new()
+            }
+        };
+        for file in files {
+            if matches.len() >= wanted {
+                break;
+            }
+            if let Err(e) = search::search_file(&file, &terms, &mut matches, wanted).await {
+                tracing::warn!(path = %file.display(), error = %e, "could not search a log file");
+            }
+        }
+    }
+    if let Some(filter) = control.journal_filter() {
+        if matches.len() < wanted {
+            let remaining = wanted - matches.len();
+            let found =
+                tokio::task::spawn_blocking(move || journal::search(&filter, &terms, remaining))
+                    .await;
+            match found {
+                Ok(Ok(found)) => matches.extend(found),
+                Ok(Err(e)) => tracing::warn!(error = %e, "could not search the journal"),
+                Err(e) => {
+                    return Err(ApiError::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        e.to_string(),
+                    ))
+                }
+            }
+        }
+    }
+
+    let truncated = matches.len() > limit;
+    matches.truncate(limit);
+    Ok(Json(SearchResults { matches, truncated }))
+}
+
+/// This server's events as they happen, as server-sent events named after
+/// their type.
+async fn events_handler(
+    State(control): State<MinecraftControl>,
+    Extension(keepalive): Extension<console::KeepAlive>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let name = control.name().to_owned();
+    let events = futures::stream::unfold(control.events().subscribe(), |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((event, rx)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+    .take_until(control.shutdown().cancelled_owned())
+    .filter(move |event| futures::future::ready(event.server == name))
+    .map(|event| {
+        let kind = event.event.name();
+        Ok(match Event::default().event(kind).json_data(&event) {
+            Ok(e) => e,
+            Err(_) => Event::default().comment("unserializable event"),
+        })
+    });
+    Sse::new(events).keep_alive(sse::KeepAlive::new().interval(keepalive.interval))
+}
+
+/// Sends a command to the console and answers with the server's reply.
+#[utoipa::path(
+    post,
+    path = "/command",
+    tag = "console",
+    request_body(content = String, content_type = "text/plain"),
+    responses(
+        (status = 200, description = "The reply, empty if the server didn't give one in time", body = String),
+        (status = 403, description = "The caller's command policy denied it", body = ErrorBody),
+        (status = 502, description = "The console or RCON connection failed", body = ErrorBody),
+        (status = 503, description = "The console socket doesn't exist", body = ErrorBody),
+        (status = 504, description = "The server didn't reply in time", body = ErrorBody),
+    )
+)]
+async fn command_writer(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    body: String,
+) -> Result<String, ApiError> {
+    run_command(&control, &principal, body).await
+}
+
+/// Checks a command against the caller's policy and runs it, returning the
+/// server's reply if it gives one.
+pub async fn run_command(
+    control: &MinecraftControl,
+    principal: &Principal,
+    body: String,
+) -> Result<String, ApiError> {
+    // Backends write the body to the console as it is, where a line break
+    // would start a second command that the policy never saw. Only the one
+    // many clients end the body with is let through.
+    let body = policy::strip_line_end(&body).to_owned();
+    if body.chars().any(char::is_control) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "a command has to be a single line without control characters",
+        )
+        .code("invalid_command"));
+    }
+    if let Err(rule) = principal.policy.check(&body) {
+        tracing::warn!(user = %principal.name, command = ?body, %rule, "command denied by policy");
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            format!("command denied by rule: {}", rule),
+        )
+        .code("command_denied")
+        .details(serde_json::json!({ "rule": rule })));
+    }
+
+    if let Err(e) = control.rate_limit(&principal.id).await {
+        tracing::warn!("{} was rate limited: {}", principal.name, e);
+        return Err(minecraft_error(e));
+    }
+
+    match control.execute(body).await {
+        Ok(Some(reply)) => Ok(reply),
+        Ok(None) => Ok(String::new()),
+        Err(e) => Err(minecraft_error(e)),
+    }
+}
+
+/// A status for why a command couldn't be sent, so callers can tell a
+/// stopped server from a broken one.
+pub fn minecraft_error(e: MinecraftError) -> ApiError {
+    let (status, code) = match &e {
+        MinecraftError::CommandTimeout => (StatusCode::GATEWAY_TIMEOUT, "command_timeout"),
+        MinecraftError::SocketMissing(_) => (StatusCode::SERVICE_UNAVAILABLE, "socket_missing"),
+        MinecraftError::BrokenPipe(_) => (StatusCode::BAD_GATEWAY, "socket_closed"),
+        MinecraftError::RconError(_) => (StatusCode::BAD_GATEWAY, "rcon_failed"),
+        MinecraftError::PermissionDenied(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "socket_permission_denied",
+        ),
+        MinecraftError::LogError(_) | MinecraftError::BackendError(_) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, "backend_failed")
+        }
+        MinecraftError::QueueFull => (StatusCode::SERVICE_UNAVAILABLE, "command_queue_full"),
+        MinecraftError::RateLimited(secs) => {
+            return ApiError::new(StatusCode::TOO_MANY_REQUESTS, e.to_string())
+                .code("rate_limited")
+                .details(serde_json::json!({ "retry_after_secs": secs }));
+        }
+        MinecraftError::CommandDenied(rule) => {
+            return ApiError::new(StatusCode::FORBIDDEN, e.to_string())
+                .code("command_denied")
+                .details(serde_json::json!({ "rule": rule }));
+        }
+    };
+    ApiError::new(status, e.to_string()).code(code)
+}
+
+/// Runs a command the panel built, turning the server's refusals into
+/// errors.
+pub async fn run_typed_command(
+    control: &MinecraftControl,
+    principal: &Principal,
+    command: Command,
+) -> Result<String, ApiError> {
+    if let Err(e) = command.validate() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, e).code("invalid_command"));
+    }
+    let reply = run_command(control, principal, command.render()).await?;
+    match command.check_reply(&reply) {
+        Ok(()) => Ok(reply),
+        Err(Refusal::NoPlayer(r)) => Err(ApiError::new(StatusCode::NOT_FOUND, r).code("no_player")),
+        Err(Refusal::NothingChanged(r)) => {
+            Err(ApiError::new(StatusCode::CONFLICT, r).code("nothing_changed"))
+        }
+        Err(Refusal::Invalid(r)) => {
+            Err(ApiError::new(StatusCode::BAD_REQUEST, r).code("refused_by_server"))
+        }
+    }
+}
+
+/// Takes a player as either a name or a UUID and gives back the name that
+/// commands need. Names are passed through as they are, so offline-mode
+/// servers don't depend on Mojang.
+pub async fn player_name(mojang: &Mojang, player: String) -> Result<String, ApiError> {
+    if mojang::parse_uuid(&player).is_none() {
+        return Ok(player);
+    }
+    match mojang.by_uuid(&player).await {
+        Ok(Some(p)) => Ok(p.name),
+        Ok(None) => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("no player has the UUID {}", player),
+        )),
+        Err(e) => Err(mojang_error(e)),
+    }
+}
+
+pub fn mojang_error(e: MojangError) -> ApiError {
+    match e {
+        MojangError::RateLimited => ApiError::new(StatusCode::TOO_MANY_REQUESTS, e.to_string()),
+        MojangError::Http(_) => ApiError::new(StatusCode::BAD_GATEWAY, e.to_string()),
+    }
+}
+
+/// Who's online, from the reply to `list`. This is run on the caller's
+/// behalf whatever their command policy, since it only reads.
+#[utoipa::path(
+    get,
+    path = "/players",
+    tag = "players",
+    responses(
+        (status = 200, description = "Who's online", body = PlayerList),
+        (status = 504, description = "The server didn't reply to list", body = ErrorBody),
+    )
+)]
+async fn players_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<PlayerList>, ApiError> {
+    let reply = match control.execute(String::from("list")).await {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            return Err(ApiError::new(
+                StatusCode::GATEWAY_TIMEOUT,
+                "the server didn't reply to list",
+            ))
+        }
+        Err(e) => return Err(minecraft_error(e)),
+    };
+    match crate::players::parse_list(&reply) {
+        Some(list) => Ok(Json(list)),
+        None => Err(ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            format!("could not read the reply to list: {}", reply),
+        )),
+    }
+}
+
+async fn playtime_handler(State(control): State<MinecraftControl>) -> Json<Vec<Playtime>> {
+    Json(control.sessions().playtime().await)
+}
+
+async fn sessions_handler(
+    State(control): State<MinecraftControl>,
+    Path(name): Path<String>,
+) -> Result<Json<PlayerSessions>, ApiError> {
+    match control.sessions().sessions(&name).await {
+        Some(s) => Ok(Json(s)),
+        None => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("{} hasn't played here", name),
+        )),
+    }
+}
+
+#[derive(Deserialize, Default, ToSchema)]
+struct KickRequest {
+    reason: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/players/{name}/kick",
+    tag = "players",
+    params(("name" = String, Path, description = "A player name or UUID")),
+    request_body(content = Option<KickRequest>),
+    responses(
+        (status = 200, description = "The server's reply", body = String),
+        (status = 404, description = "They aren't online", body = ErrorBody),
+    )
+)]
+async fn kick_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Extension(mojang): Extension<Mojang>,
+    Path(name): Path<String>,
+    request: Option<Json<KickRequest>>,
+) -> Result<String, ApiError> {
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    let command = Command::Kick {
+        player: player_name(&mojang, name).await?,
+        reason: request.reason,
+    };
+    run_typed_command(&control, &principal, command).await
+}
+
+/// The Mojang account behind a name or UUID.
+async fn profile_handler(
+    Extension(mojang): Extension<Mojang>,
+    Path(name): Path<String>,
+) -> Result<Json<Profile>, ApiError> {
+    match mojang.resolve(&name).await {
+        Ok(Some(p)) => Ok(Json(p)),
+        Ok(None) => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("there's no Minecraft account called {}", name),
+        )),
+        Err(e) => Err(mojang_error(e)),
+    }
+}
+
+/// The player's head as a PNG, served from the panel so browsers don't
+/// each ask a skin renderer for it.
+async fn head_handler(
+    Extension(mojang): Extension<Mojang>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let uuid = match mojang::parse_uuid(&name) {
+        Some(u) => u,
+        None => match mojang.by_name(&name).await {
+            Ok(Some(p)) => p.uuid,
+            Ok(None) => return Err(ApiError::new(StatusCode::NOT_FOUND, "no such player")),
+            Err(e) => return Err(mojang_error(e)),
+        },
+    };
+    match mojang.head(&uuid).await {
+        Ok(Some(png)) => Ok((
+            [
+                (header::CONTENT_TYPE, "image/png"),
+                (header::CACHE_CONTROL, "public, max-age=86400"),
+            ],
+            png,
+        )),
+        Ok(None) => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "the player has no skin",
+        )),
+        Err(e) => Err(mojang_error(e)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/server/status",
+    tag = "lifecycle",
+    responses(
+        (status = 200, description = "What the backend says about the server", body = ServerStatus),
+        (status = 403, description = "The panel isn't allowed to ask", body = ErrorBody),
+        (status = 404, description = "The unit or container doesn't exist", body = ErrorBody),
+    )
+)]
+async fn status_handler(State(control): State<MinecraftControl>) -> impl IntoResponse {
+    match control.status().await {
+        Ok(status) => Ok(Json(status)),
+        Err(e) => Err(lifecycle_response(Err(e))),
+    }
+}
+
+#[derive(Serialize)]
+struct LiveStatus {
+    #[serde(flatten)]
+    status: ping::Status,
+    // Everyone online and the plugins, when enable-query is on.
+    query: Option<query::FullStat>,
+    // Why the query failed, if it did.
+    query_error: Option<String>,
+    // The Bedrock side, when bedrock_address is set.
+    bedrock: Option<bedrock::BedrockStatus>,
+    bedrock_error: Option<String>,
+}
+
+/// What the server shows in the server list, straight from the server.
+async fn ping_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<LiveStatus>, ApiError> {
+    let address = control.status_address().await;
+    let status = match ping::ping(&address, Duration::from_secs(5)).await {
+        Ok(s) => s,
+        Err(e) => {
+            return Err(ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("could not ping {}: {}", address, e),
+            ))
+        }
+    };
+    let (query, query_error) = match control.query_address().await {
+        Some(a) => match query::full_stat(&a, Duration::from_secs(5)).await {
+            Ok(q) => (Some(q), None),
+            Err(e) => (None, Some(e.to_string())),
+        },
+        None => (None, None),
+    };
+    let (bedrock, bedrock_error) = match control.bedrock_address() {
+        Some(a) => match bedrock::ping(a, Duration::from_secs(5)).await {
+            Ok(b) => (Some(b), None),
+            Err(e) => (None, Some(e.to_string())),
+        },
+        None => (None, None),
+    };
+    Ok(Json(LiveStatus {
+        status,
+        query,
+        query_error,
+        bedrock,
+        bedrock_error,
+    }))
+}
+
+/// Times the watchdog restarted the server, newest first.
+async fn incidents_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<Vec<Incident>>, ApiError> {
+    match control.watchdog() {
+        Some(w) => Ok(Json(w.incidents().await)),
+        None => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            String::from("the watchdog isn't set up for this server"),
+        )),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/server/start",
+    tag = "lifecycle",
+    responses(
+        (status = 202, description = "The job queued", body = String),
+        (status = 403, description = "The panel isn't allowed to start it", body = ErrorBody),
+    )
+)]
+async fn start_handler(State(control): State<MinecraftControl>) -> impl IntoResponse {
+    lifecycle_response(control.start().await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/server/stop",
+    tag = "lifecycle",
+    responses(
+        (status = 202, description = "The job queued", body = String),
+        (status = 403, description = "The panel isn't allowed to stop it", body = ErrorBody),
+    )
+)]
+async fn stop_handler(State(control): State<MinecraftControl>) -> impl IntoResponse {
+    lifecycle_response(control.stop().await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/server/restart",
+    tag = "lifecycle",
+    responses(
+        (status = 202, description = "The job queued", body = String),
+        (status = 403, description = "The panel isn't allowed to restart it", body = ErrorBody),
+    )
+)]
+async fn restart_handler(State(control): State<MinecraftControl>) -> impl IntoResponse {
+    lifecycle_response(control.restart().await)
+}
+
+fn lifecycle_response(
+    result: Result<String, BackendError>,
+) -> Result<(StatusCode, String), ApiError> {
+    match result {
+        Ok(job) => Ok((StatusCode::ACCEPTED, job)),
+        Err(e) => {
+            tracing::warn!(error = %e, "lifecycle request failed");
+            let status = match e {
+                BackendError::PermissionDenied(_) => StatusCode::FORBIDDEN,
+                BackendError::NotFound(_) => StatusCode::NOT_FOUND,
+                BackendError::Io(_) | BackendError::Console(..) | BackendError::Other(_) => {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            };
+            Err(ApiError::new(status, e.to_string()))
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+
+use crate::auth::Principal;
+use crate::error::ApiError;
+use crate::minecraft::MinecraftControl;
+use crate::scheduler::announcements::Announcement;
+
+pub async fn list_handler(State(control): State<MinecraftControl>) -> Json<Vec<Announcement>> {
+    Json(control.announcements().list().await)
+}
+
+/// Announcements go out with tellraw, so the caller has to be allowed it.
+fn check_policy(principal: &Principal) -> Result<(), ApiError> {
+    match principal.policy.check("tellraw") {
+        Ok(()) => Ok(()),
+        Err(rule) => Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            format!("command denied by rule: {}", rule),
+        )),
+    }
+}
+
+pub async fn create_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Json(announcement): Json<Announcement>,
+) -> Result<(StatusCode, Json<Announcement>), ApiError> {
+    check_policy(&principal)?;
+    match control
+        .announcements()
+        .create(control.clone(), announcement.clone())
+        .await
+    {
+        Ok(()) => {
+            tracing::info!(user = %principal.name, server = %control.name(), id = %announcement.id, "added an announcement");
+            Ok((StatusCode::CREATED, Json(announcement)))
+        }
+        Err(e) => Err(super::schedules::schedule_error(e)),
+    }
+}
+
+pub async fn update_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Json(mut announcement): Json<Announcement>,
+) -> Result<Json<Announcement>, ApiError> {
+    check_policy(&principal)?;
+    announcement.id = id;
+    match control
+        .announcements()
+        .update(control.clone(), announcement.clone())
+        .await
+    {
+        Ok(()) => Ok(Json(announcement)),
+        Err(e) => Err(super::schedules::schedule_error(e)),
+    }
+}
+
+pub async fn delete_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    match control.announcements().delete(&id).await {
+        Ok(()) => {
+            tracing::info!(user = %principal.name, server = %control.name(), %id, "deleted an announcement");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Err(e) => Err(super::schedules::schedule_error(e)),
+    }
+}
@@ -0,0 +1,124 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Principal;
+use crate::backup::{retention::Retention, BackupError, BackupRecord, Backups};
+use crate::error::ApiError;
+use crate::minecraft::MinecraftControl;
+
+#[derive(Serialize)]
+pub struct BackupList {
+    backups: Vec<BackupRecord>,
+    running: bool,
+    // Seconds since the epoch.
+    next_run: Option<u64>,
+    retention: Option<Retention>,
+    // Pruning follows each backup, so it's next with the next scheduled
+    // one.
+    next_prune: Option<u64>,
+}
+
+fn backups(control: &MinecraftControl) -> Result<&Backups, ApiError> {
+    match control.backups() {
+        Some(b) => Ok(b),
+        None => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            String::from("backups aren't set up for this server"),
+        )),
+    }
+}
+
+pub async fn list_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<BackupList>, ApiError> {
+    let backups = backups(&control)?;
+    Ok(Json(BackupList {
+        backups: backups.list().await,
+        running: backups.is_running(),
+        next_run: backups.next_run(),
+        retention: backups.retention().cloned(),
+        next_prune: backups.retention().and(backups.next_run()),
+    }))
+}
+
+/// Starts a backup. It runs in the background and publishes an event
+/// when it's done.
+pub async fn create_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+) -> Result<(StatusCode, String), ApiError> {
+    match backups(&control)?.start(control.clone(), principal.name.clone()) {
+        Ok(()) => {
+            tracing::info!(user = %principal.name, server = %control.name(), "started a backup");
+            Ok((StatusCode::ACCEPTED, String::from("backup started")))
+        }
+        Err(e) => Err(ApiError::new(error_status(&e), e.to_string())),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RestoreRequest {
+    // From a first request without one.
+    token: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct RestoreConfirmation {
+    token: String,
+    // Seconds since the epoch.
+    expires: u64,
+    message: String,
+}
+
+fn error_status(e: &BackupError) -> StatusCode {
+    match e {
+        BackupError::NotFound => StatusCode::NOT_FOUND,
+        BackupError::Unconfirmed => StatusCode::FORBIDDEN,
+        BackupError::Busy => StatusCode::CONFLICT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Restores a backup over the server's files. Without a token this only
+/// returns one, to be sent back to go ahead; with it the restore starts
+/// in the background, publishing its progress as events.
+pub async fn restore_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    request: Option<Json<RestoreRequest>>,
+) -> Result<(StatusCode, Json<serde_json::Value>), ApiError> {
+    let backups = backups(&control)?;
+    let token = match request.and_then(|Json(r)| r.token) {
+        Some(t) => t,
+        None => {
+            let (token, expires) = match backups.confirmation(&id).await {
+                Ok(c) => c,
+                Err(e) => return Err(ApiError::new(error_status(&e), e.to_string())),
+            };
+            let confirmation = RestoreConfirmation {
+                token,
+                expires,
+                message: format!(
+                    "send this token back to restore {}, which stops the server and moves its current files aside",
+                    id
+                ),
+            };
+            return Ok((StatusCode::OK, Json(serde_json::json!(confirmation))));
+        }
+    };
+    match backups.restore(control.clone(), &id, &token).await {
+        Ok(()) => {
+            tracing::info!(user = %principal.name, server = %control.name(), backup = %id, "started restoring a backup");
+            Ok((
+                StatusCode::ACCEPTED,
+                Json(serde_json::json!({ "restoring": id })),
+            ))
+        }
+        Err(e) => Err(ApiError::new(error_status(&e), e.to_string())),
+    }
+}
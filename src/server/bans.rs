@@ -0,0 +1,169 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::bans::{BanKind, BanList};
+use crate::commands::Command;
+use crate::error::{ApiError, ErrorBody};
+use crate::minecraft::MinecraftControl;
+use crate::mojang::Mojang;
+
+#[derive(Deserialize, ToSchema)]
+pub struct BanRequest {
+    // A player name or UUID, or for IP bans an address or an online
+    // player whose address is banned.
+    target: String,
+    reason: Option<String>,
+    // Seconds since the epoch to lift the ban at. Permanent if unset.
+    expires: Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/bans",
+    tag = "bans",
+    responses(
+        (status = 200, description = "Banned players and addresses", body = BanList),
+        (status = 404, description = "The server directory isn't known", body = ErrorBody),
+    )
+)]
+pub async fn list_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<BanList>, ApiError> {
+    let dir = super::server_dir(&control)?;
+    match control.bans().list(&dir).await {
+        Ok(list) => Ok(Json(list)),
+        Err(e) => {
+            tracing::warn!(error = %e, "could not read bans");
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/bans/players",
+    tag = "bans",
+    request_body = BanRequest,
+    responses(
+        (status = 200, description = "The server's reply", body = String),
+        (status = 404, description = "There's no such player", body = ErrorBody),
+        (status = 409, description = "They're banned already", body = ErrorBody),
+    )
+)]
+pub async fn ban_player_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Extension(mojang): Extension<Mojang>,
+    Json(mut request): Json<BanRequest>,
+) -> Result<String, ApiError> {
+    request.target = super::player_name(&mojang, request.target).await?;
+    ban(control, principal, BanKind::Player, request).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/bans/ips",
+    tag = "bans",
+    request_body = BanRequest,
+    responses(
+        (status = 200, description = "The server's reply", body = String),
+        (status = 409, description = "The address is banned already", body = ErrorBody),
+    )
+)]
+pub async fn ban_ip_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Extension(mojang): Extension<Mojang>,
+    Json(mut request): Json<BanRequest>,
+) -> Result<String, ApiError> {
+    request.target = super::player_name(&mojang, request.target).await?;
+    ban(control, principal, BanKind::Ip, request).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/bans/players/{player}",
+    tag = "bans",
+    params(("player" = String, Path, description = "A player name or UUID")),
+    responses(
+        (status = 200, description = "The server's reply", body = String),
+        (status = 409, description = "They weren't banned", body = ErrorBody),
+    )
+)]
+pub async fn pardon_player_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Extension(mojang): Extension<Mojang>,
+    Path(player): Path<String>,
+) -> Result<String, ApiError> {
+    let player = super::player_name(&mojang, player).await?;
+    pardon(control, principal, BanKind::Player, player).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/bans/ips/{ip}",
+    tag = "bans",
+    params(("ip" = String, Path)),
+    responses(
+        (status = 200, description = "The server's reply", body = String),
+        (status = 409, description = "The address wasn't banned", body = ErrorBody),
+    )
+)]
+pub async fn pardon_ip_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Path(ip): Path<String>,
+) -> Result<String, ApiError> {
+    pardon(control, principal, BanKind::Ip, ip).await
+}
+
+async fn ban(
+    control: MinecraftControl,
+    principal: Principal,
+    kind: BanKind,
+    request: BanRequest,
+) -> Result<String, ApiError> {
+    let command = match kind {
+        BanKind::Player => Command::Ban {
+            player: request.target.clone(),
+            reason: request.reason,
+        },
+        BanKind::Ip => Command::BanIp {
+            target: request.target.clone(),
+            reason: request.reason,
+        },
+    };
+    let reply = super::run_typed_command(&control, &principal, command).await?;
+
+    if let Err(e) = control
+        .bans()
+        .banned(kind, &request.target, request.expires, &principal.name)
+        .await
+    {
+        tracing::warn!(error = %e, "could not save bans");
+    }
+    Ok(reply)
+}
+
+async fn pardon(
+    control: MinecraftControl,
+    principal: Principal,
+    kind: BanKind,
+    target: String,
+) -> Result<String, ApiError> {
+    let reply = super::run_typed_command(&control, &principal, kind.pardon(&target)).await?;
+    if let Err(e) = control.bans().pardoned(kind, &target).await {
+        tracing::warn!(error = %e, "could not save bans");
+    }
+    Ok(reply)
+}
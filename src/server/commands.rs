@@ -0,0 +1,46 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::commands::Command;
+use crate::error::{ApiError, ErrorBody};
+use crate::minecraft::MinecraftControl;
+
+#[derive(Serialize, ToSchema)]
+pub struct CommandReply {
+    // As it was sent to the console.
+    command: String,
+    reply: String,
+}
+
+/// Runs a command described as JSON, which the panel checks and writes out
+/// itself, so nothing in it can become a second command.
+#[utoipa::path(
+    post,
+    path = "/commands",
+    tag = "console",
+    request_body = Command,
+    responses(
+        (status = 200, description = "The command sent and the server's reply", body = CommandReply),
+        (status = 400, description = "An argument isn't valid, or the server said so", body = ErrorBody),
+        (status = 403, description = "The caller's role or command policy doesn't allow it", body = ErrorBody),
+        (status = 404, description = "The player doesn't exist or isn't online", body = ErrorBody),
+        (status = 409, description = "The server says nothing changed", body = ErrorBody),
+    )
+)]
+pub async fn run_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Json(command): Json<Command>,
+) -> Result<Json<CommandReply>, ApiError> {
+    if principal.role < command.role() {
+        return Err(ApiError::new(StatusCode::FORBIDDEN, "insufficient role"));
+    }
+    let rendered = command.render();
+    let reply = super::run_typed_command(&control, &principal, command).await?;
+    Ok(Json(CommandReply {
+        command: rendered,
+        reply,
+    }))
+}
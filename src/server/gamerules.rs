@@ -0,0 +1,56 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use serde_json::{Map, Value};
+
+use crate::auth::Principal;
+use crate::commands::Command;
+use crate::error::ApiError;
+use crate::gamerules;
+use crate::minecraft::MinecraftControl;
+
+/// Every rule the server has, asked for one at a time. Like `list`, this
+/// only reads, so it doesn't go through the caller's command policy.
+pub async fn get_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<Map<String, Value>>, ApiError> {
+    let mut rules = Map::new();
+    for rule in gamerules::names() {
+        let reply = match control.execute(format!("gamerule {}", rule)).await {
+            Ok(Some(r)) => r,
+            Ok(None) => {
+                return Err(ApiError::new(
+                    StatusCode::GATEWAY_TIMEOUT,
+                    "the server didn't reply to gamerule",
+                ))
+            }
+            Err(e) => return Err(super::minecraft_error(e)),
+        };
+        // Rules this version doesn't have are left out.
+        if let Some(value) = gamerules::parse_reply(rule, &reply) {
+            rules.insert(rule.to_owned(), value);
+        }
+    }
+    Ok(Json(rules))
+}
+
+/// Sets the rules given. They're all checked before any are changed, but
+/// a rule the server turns down stops the rest.
+pub async fn put_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Json(changes): Json<Map<String, Value>>,
+) -> Result<String, ApiError> {
+    let commands: Vec<Command> = changes
+        .into_iter()
+        .map(|(rule, value)| Command::Gamerule { rule, value })
+        .collect();
+    for command in &commands {
+        if let Err(e) = command.validate() {
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, e).code("invalid_command"));
+        }
+    }
+    let mut replies = Vec::new();
+    for command in commands {
+        replies.push(super::run_typed_command(&control, &principal, command).await?);
+    }
+    Ok(replies.join("\n"))
+}
@@ -0,0 +1,49 @@
+use axum::{extract::State, http::StatusCode, Json};
+
+use crate::error::ApiError;
+use crate::jvm::{Diagnostic, JvmError};
+use crate::minecraft::MinecraftControl;
+
+fn jvm_error(e: JvmError) -> ApiError {
+    let status = match e {
+        JvmError::NotRunning => StatusCode::CONFLICT,
+        JvmError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        JvmError::Failed(_) => StatusCode::BAD_GATEWAY,
+        JvmError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    ApiError::new(status, e.to_string())
+}
+
+async fn pid(control: &MinecraftControl) -> Result<u32, ApiError> {
+    match control.status().await {
+        Ok(s) => match s.pid {
+            Some(p) if p > 0 => Ok(p),
+            _ => Err(jvm_error(JvmError::NotRunning)),
+        },
+        Err(e) => Err(ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+/// Every thread's stack, for working out why a server froze.
+pub async fn thread_dump_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<Diagnostic>, ApiError> {
+    let pid = pid(&control).await?;
+    match control.jvm().thread_dump(pid).await {
+        Ok(d) => Ok(Json(d)),
+        Err(e) => Err(jvm_error(e)),
+    }
+}
+
+pub async fn heap_summary_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<Diagnostic>, ApiError> {
+    let pid = pid(&control).await?;
+    match control.jvm().heap_summary(pid).await {
+        Ok(d) => Ok(Json(d)),
+        Err(e) => Err(jvm_error(e)),
+    }
+}
@@ -0,0 +1,93 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Principal;
+use crate::error::ApiError;
+use crate::maintenance::{MaintenanceError, MaintenanceState};
+use crate::minecraft::MinecraftControl;
+
+fn maintenance_error(e: MaintenanceError) -> ApiError {
+    let status = match e {
+        MaintenanceError::Invalid(_) => StatusCode::BAD_REQUEST,
+        MaintenanceError::Unchanged => StatusCode::CONFLICT,
+        MaintenanceError::Command => StatusCode::BAD_GATEWAY,
+        MaintenanceError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    ApiError::new(status, e.to_string())
+}
+
+fn check_policy(principal: &Principal, command: &str) -> Result<(), ApiError> {
+    match principal.policy.check(command) {
+        Ok(()) => Ok(()),
+        Err(rule) => Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            format!("command denied by rule: {}", rule),
+        )),
+    }
+}
+
+/// Whether maintenance is on, since when and who turned it on.
+pub async fn get_handler(
+    State(control): State<MinecraftControl>,
+) -> Json<Option<MaintenanceState>> {
+    Json(control.maintenance().state().await)
+}
+
+#[derive(Deserialize)]
+pub struct MaintenanceRequest {
+    enabled: bool,
+    // Shown in the server list while it lasts, with `&` codes.
+    motd: Option<String>,
+    // Kick everyone online who isn't an op.
+    kick_non_ops: Option<bool>,
+    kick_message: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct MaintenanceResponse {
+    state: Option<MaintenanceState>,
+    // Players kicked when it was turned on.
+    kicked: Vec<String>,
+}
+
+/// Turns maintenance on or off. Turning it off puts the whitelist and MOTD
+/// back the way they were before.
+pub async fn set_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<MaintenanceRequest>,
+) -> Result<Json<MaintenanceResponse>, ApiError> {
+    let dir = super::server_dir(&control)?;
+    check_policy(&principal, "whitelist")?;
+    let kick = request.kick_non_ops.unwrap_or(false);
+    if kick {
+        check_policy(&principal, "kick")?;
+    }
+    let maintenance = control.maintenance();
+    let kicked = if request.enabled {
+        maintenance
+            .enable(
+                &control,
+                &dir,
+                &principal.name,
+                request.motd.as_deref(),
+                kick,
+                request.kick_message.as_deref(),
+            )
+            .await
+    } else {
+        maintenance
+            .disable(&control, &dir)
+            .await
+            .map(|()| Vec::new())
+    };
+    let kicked = match kicked {
+        Ok(k) => k,
+        Err(e) => return Err(maintenance_error(e)),
+    };
+    tracing::info!(user = %principal.name, server = %control.name(), enabled = request.enabled, "turned maintenance mode on or off");
+    Ok(Json(MaintenanceResponse {
+        state: maintenance.state().await,
+        kicked,
+    }))
+}
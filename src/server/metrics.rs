@@ -0,0 +1,131 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        State, WebSocketUpgrade,
+    },
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use crate::console;
+use crate::disk::{self, DiskUsage};
+use crate::error::ApiError;
+use crate::gc::GcSummary;
+use crate::minecraft::MinecraftControl;
+use crate::resources::ProcessSample;
+use crate::tps::{Sample, Tps};
+
+fn tps(control: &MinecraftControl) -> Result<&Tps, ApiError> {
+    match control.tps() {
+        Some(t) => Ok(t),
+        None => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            String::from("TPS sampling isn't set up for this server"),
+        )),
+    }
+}
+
+#[derive(Serialize)]
+pub struct TpsWindow {
+    pub latest: Option<Sample>,
+    // Oldest first.
+    pub samples: Vec<Sample>,
+}
+
+pub async fn tps_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<TpsWindow>, ApiError> {
+    let tps = tps(&control)?;
+    Ok(Json(TpsWindow {
+        latest: tps.latest().await,
+        samples: tps.samples().await,
+    }))
+}
+
+#[derive(Serialize)]
+pub struct ProcessWindow {
+    pub latest: Option<ProcessSample>,
+    // Oldest first.
+    pub samples: Vec<ProcessSample>,
+}
+
+/// CPU, memory, threads and file descriptors of the server process.
+pub async fn process_handler(State(control): State<MinecraftControl>) -> Json<ProcessWindow> {
+    let resources = control.resources();
+    Json(ProcessWindow {
+        latest: resources.latest().await,
+        samples: resources.samples().await,
+    })
+}
+
+/// Sizes of the world, logs and backups, and the space left for them.
+pub async fn disk_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<DiskUsage>, ApiError> {
+    let dir = super::server_dir(&control)?;
+    match disk::usage(&control, &dir).await {
+        Ok(u) => Ok(Json(u)),
+        Err(e) => Err(ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+/// GC pause percentiles and how the heap left after collections is
+/// trending.
+pub async fn gc_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<GcSummary>, ApiError> {
+    match control.gc() {
+        Some(g) => Ok(Json(g.summary().await)),
+        None => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            String::from("GC logs aren't read for this server"),
+        )),
+    }
+}
+
+/// Sends each TPS sample as JSON as it's taken.
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(control): State<MinecraftControl>,
+) -> Result<impl IntoResponse, ApiError> {
+    let rx = tps(&control)?.subscribe();
+    let shutdown = control.shutdown();
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, rx, shutdown)))
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    mut rx: broadcast::Receiver<Sample>,
+    shutdown: CancellationToken,
+) {
+    let (mut sender, mut receiver) = socket.split();
+    loop {
+        let sample = tokio::select! {
+            _ = shutdown.cancelled() => {
+                let _ = sender.send(Message::Close(Some(console::going_away()))).await;
+                return;
+            },
+            sample = rx.recv() => match sample {
+                Ok(s) => s,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            message = receiver.next() => match message {
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => return,
+                Some(Ok(_)) => continue,
+            },
+        };
+        let text = serde_json::to_string(&sample).unwrap();
+        if sender.send(Message::Text(text)).await.is_err() {
+            return;
+        }
+    }
+}
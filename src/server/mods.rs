@@ -0,0 +1,167 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::Deserialize;
+
+use crate::auth::Principal;
+use crate::error::ApiError;
+use crate::level;
+use crate::minecraft::MinecraftControl;
+use crate::modrinth::{Installed, Modrinth, ModrinthError, Platform, SearchHit, Update};
+use crate::mods::{self, Mod};
+use crate::properties;
+
+/// The jars in the server's mods and plugins folders.
+pub async fn list_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<Vec<Mod>>, ApiError> {
+    let dir = super::server_dir(&control)?;
+    match tokio::task::spawn_blocking(move || mods::list(&dir)).await {
+        Ok(m) => Ok(Json(m)),
+        Err(e) => Err(ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+fn modrinth_error(e: ModrinthError) -> ApiError {
+    let status = match e {
+        ModrinthError::NotFound => StatusCode::NOT_FOUND,
+        ModrinthError::Incompatible => StatusCode::CONFLICT,
+        ModrinthError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        ModrinthError::Http(_) | ModrinthError::Corrupt => StatusCode::BAD_GATEWAY,
+    };
+    ApiError::new(status, e.to_string())
+}
+
+/// The server's loader and Minecraft version, as configured or going by
+/// its mods and the version that last saved its world.
+async fn platform(control: &MinecraftControl) -> Result<Platform, ApiError> {
+    let dir = super::server_dir(control)?;
+    let loader = match control.loader() {
+        Some(l) => Some(l.to_owned()),
+        None => {
+            let dir = dir.clone();
+            let found = tokio::task::spawn_blocking(move || mods::list(&dir)).await;
+            found
+                .ok()
+                .and_then(|m| mods::loader(&m))
+                .map(|l| l.to_owned())
+        }
+    };
+    let game_version = match control.game_version() {
+        Some(v) => Some(v.to_owned()),
+        None => match level::read(&properties::world_dir(&dir).await).await {
+            Ok(Some(l)) => l.version,
+            _ => None,
+        },
+    };
+    match (loader, game_version) {
+        (Some(loader), Some(game_version)) => Ok(Platform {
+            loader,
+            game_version,
+        }),
+        _ => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            String::from("set loader and game_version for this server, they can't be worked out"),
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    query: String,
+    limit: Option<u32>,
+}
+
+/// Modrinth projects that have a version for this server.
+pub async fn search_handler(
+    State(control): State<MinecraftControl>,
+    Extension(modrinth): Extension<Modrinth>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchHit>>, ApiError> {
+    let platform = platform(&control).await?;
+    match modrinth
+        .search(&query.query, &platform, query.limit.unwrap_or(20))
+        .await
+    {
+        Ok(hits) => Ok(Json(hits)),
+        Err(e) => Err(modrinth_error(e)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct InstallRequest {
+    // A Modrinth project id or slug.
+    project: String,
+    // A version id. The newest for this server if unset.
+    version: Option<String>,
+    // A jar to delete once the new one is in, for updates, e.g.
+    // "mods/sodium-0.5.8.jar".
+    replace: Option<String>,
+}
+
+/// Downloads a mod or plugin from Modrinth. It's loaded the next time the
+/// server starts.
+pub async fn install_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Extension(modrinth): Extension<Modrinth>,
+    Json(request): Json<InstallRequest>,
+) -> Result<Json<Installed>, ApiError> {
+    let dir = super::server_dir(&control)?;
+    let platform = platform(&control).await?;
+    // Checked first so a bad name doesn't leave two copies installed.
+    let replace = match &request.replace {
+        Some(r) => match r.strip_prefix(&format!("{}/", platform.folder())) {
+            Some(name) if name.ends_with(".jar") && !name.contains(['/', '\\']) => {
+                Some(dir.join(platform.folder()).join(name))
+            }
+            _ => {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    format!("replace has to be a jar in {}/", platform.folder()),
+                ))
+            }
+        },
+        None => None,
+    };
+
+    let installed = match modrinth
+        .install(
+            &dir,
+            &request.project,
+            request.version.as_deref(),
+            &platform,
+        )
+        .await
+    {
+        Ok(i) => i,
+        Err(e) => return Err(modrinth_error(e)),
+    };
+    tracing::info!(user = %principal.name, file = %installed.file, "installed a mod from Modrinth");
+    if let Some(old) = replace {
+        if old != dir.join(&installed.file) {
+            if let Err(e) = tokio::fs::remove_file(&old).await {
+                tracing::warn!(path = %old.display(), error = %e, "could not remove the replaced mod");
+            }
+        }
+    }
+    Ok(Json(installed))
+}
+
+/// Installed jars from Modrinth with a newer version for this server.
+pub async fn updates_handler(
+    State(control): State<MinecraftControl>,
+    Extension(modrinth): Extension<Modrinth>,
+) -> Result<Json<Vec<Update>>, ApiError> {
+    let dir = super::server_dir(&control)?;
+    let platform = platform(&control).await?;
+    match modrinth.updates(&dir, &platform).await {
+        Ok(u) => Ok(Json(u)),
+        Err(e) => Err(modrinth_error(e)),
+    }
+}
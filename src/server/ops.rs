@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::auth::Principal;
+use crate::commands::Command;
+use crate::error::{ApiError, ErrorBody};
+use crate::minecraft::MinecraftControl;
+use crate::mojang::Mojang;
+use crate::players::{self, Op};
+
+#[derive(Deserialize, Default, ToSchema)]
+pub struct OpRequest {
+    // 1 to 4. The server's op-permission-level if unset.
+    level: Option<u8>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/ops",
+    tag = "ops",
+    responses(
+        (status = 200, description = "Everyone in ops.json", body = Vec<Op>),
+        (status = 404, description = "The server directory isn't known", body = ErrorBody),
+    )
+)]
+pub async fn list_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<Vec<Op>>, ApiError> {
+    let dir = super::server_dir(&control)?;
+    match players::read_ops(&dir).await {
+        Ok(ops) => Ok(Json(ops)),
+        Err(e) => {
+            tracing::warn!(error = %e, "could not read ops");
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/ops/{player}",
+    tag = "ops",
+    params(("player" = String, Path, description = "A player name or UUID")),
+    request_body(content = Option<OpRequest>),
+    responses(
+        (status = 200, description = "The server's reply", body = String),
+        (status = 400, description = "The level isn't from 1 to 4", body = ErrorBody),
+        (status = 409, description = "They're an op already and no level was given", body = ErrorBody),
+    )
+)]
+pub async fn op_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Extension(mojang): Extension<Mojang>,
+    Path(player): Path<String>,
+    request: Option<Json<OpRequest>>,
+) -> Result<String, ApiError> {
+    let player = super::player_name(&mojang, player).await?;
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    if let Some(level) = request.level {
+        if !(1..=4).contains(&level) {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "level has to be from 1 to 4",
+            ));
+        }
+    }
+
+    let command = Command::Op {
+        player: player.clone(),
+    };
+    let reply = super::run_typed_command(&control, &principal, command).await;
+    let (reply, level) = match (reply, request.level) {
+        (Ok(r), Some(l)) => (r, l),
+        // Already an op, but their level can still change.
+        (Err(e), Some(l)) if e.status() == StatusCode::CONFLICT => (e.message().to_owned(), l),
+        (reply, None) => return reply,
+        (Err(e), Some(_)) => return Err(e),
+    };
+
+    let dir = super::server_dir(&control)?;
+    // The server writes ops.json when it handles the command, which may
+    // not have happened yet without RCON.
+    for _ in 0..10 {
+        match players::set_op_level(&dir, &player, level).await {
+            Ok(true) => {
+                return Ok(format!(
+                    "{}\nlevel {} applies once the server restarts",
+                    reply, level
+                ))
+            }
+            Ok(false) => tokio::time::sleep(Duration::from_millis(200)).await,
+            Err(e) => {
+                return Err(ApiError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    e.to_string(),
+                ))
+            }
+        }
+    }
+    Err(ApiError::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("{} was not added to ops.json", player),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/ops/{player}",
+    tag = "ops",
+    params(("player" = String, Path, description = "A player name or UUID")),
+    responses(
+        (status = 200, description = "The server's reply", body = String),
+        (status = 409, description = "They weren't an op", body = ErrorBody),
+    )
+)]
+pub async fn deop_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Extension(mojang): Extension<Mojang>,
+    Path(player): Path<String>,
+) -> Result<String, ApiError> {
+    let player = super::player_name(&mojang, player).await?;
+    super::run_typed_command(&control, &principal, Command::Deop { player }).await
+}
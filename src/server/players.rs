@@ -0,0 +1,82 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+
+use crate::error::ApiError;
+use crate::minecraft::MinecraftControl;
+use crate::mojang::{self, Mojang};
+use crate::playerdata::{self, PlayerData};
+use crate::players;
+use crate::properties;
+use crate::stats::{self, PlayerStats};
+
+pub async fn stats_handler(
+    State(control): State<MinecraftControl>,
+    Extension(mojang): Extension<Mojang>,
+    Path(name): Path<String>,
+) -> Result<Json<PlayerStats>, ApiError> {
+    let dir = super::server_dir(&control)?;
+    let uuid = player_uuid(&dir, &mojang, &name).await?;
+    match stats::read(&properties::world_dir(&dir).await, &uuid).await {
+        Ok(Some(s)) => Ok(Json(s)),
+        Ok(None) => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("{} hasn't played here", name),
+        )),
+        Err(e) => {
+            tracing::warn!(%uuid, error = %e, "could not read player stats");
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+pub async fn data_handler(
+    State(control): State<MinecraftControl>,
+    Extension(mojang): Extension<Mojang>,
+    Path(name): Path<String>,
+) -> Result<Json<PlayerData>, ApiError> {
+    let dir = super::server_dir(&control)?;
+    let uuid = player_uuid(&dir, &mojang, &name).await?;
+    match playerdata::read(&properties::world_dir(&dir).await, &uuid).await {
+        Ok(Some(d)) => Ok(Json(d)),
+        Ok(None) => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("{} hasn't played here", name),
+        )),
+        Err(e) => {
+            tracing::warn!(%uuid, error = %e, "could not read player data");
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+/// The UUID the server files a player under, from a name or UUID. The
+/// server's own cache is asked before Mojang.
+async fn player_uuid(
+    server_dir: &std::path::Path,
+    mojang: &Mojang,
+    player: &str,
+) -> Result<String, ApiError> {
+    if let Some(uuid) = mojang::parse_uuid(player) {
+        return Ok(uuid);
+    }
+    if let Some(uuid) = players::cached_uuid(server_dir, player).await {
+        return Ok(uuid);
+    }
+    match mojang.by_name(player).await {
+        Ok(Some(p)) => Ok(p.uuid),
+        Ok(None) => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("there's no player called {}", player),
+        )),
+        Err(e) => Err(super::mojang_error(e)),
+    }
+}
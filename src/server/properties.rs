@@ -0,0 +1,270 @@
+use std::io::Cursor;
+
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use bytes::Bytes;
+use image::{imageops::FilterType, ImageFormat};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::auth::Principal;
+use crate::error::ApiError;
+use crate::minecraft::MinecraftControl;
+use crate::motd;
+use crate::properties::{self, Properties};
+
+#[derive(Serialize)]
+pub struct PropertiesUpdate {
+    properties: Map<String, Value>,
+    // Changed keys the server was told about straight away.
+    applied: Vec<String>,
+    // Changed keys that only take effect once the server restarts.
+    restart_required: Vec<String>,
+}
+
+async fn load(control: &MinecraftControl) -> Result<Properties, ApiError> {
+    let dir = super::server_dir(control)?;
+    match properties::read(&dir).await {
+        Ok(p) => Ok(p),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            String::from("the server hasn't written server.properties yet"),
+        )),
+        Err(e) => Err(ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+pub async fn get_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<Map<String, Value>>, ApiError> {
+    Ok(Json(load(&control).await?.to_json()))
+}
+
+/// Changes the properties given and leaves the rest of the file alone.
+/// Nothing is written unless every value is valid.
+pub async fn patch_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Json(changes): Json<Map<String, Value>>,
+) -> Result<Json<PropertiesUpdate>, ApiError> {
+    let mut props = load(&control).await?;
+    let mut changed = Vec::new();
+    for (key, value) in &changes {
+        let value = match props.check(key, value) {
+            Ok(v) => v,
+            Err(e) => return Err(ApiError::new(StatusCode::BAD_REQUEST, e)),
+        };
+        if props.get(key).as_deref() != Some(value.as_str()) {
+            changed.push((key.clone(), value));
+        }
+    }
+    for (key, value) in &changed {
+        props.set(key, value);
+    }
+    if !changed.is_empty() {
+        let dir = super::server_dir(&control)?;
+        if let Err(e) = props.write(&dir).await {
+            return Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ));
+        }
+        tracing::info!(
+            user = %principal.name,
+            keys = %changed
+                .iter()
+                .map(|(k, _)| k.as_str())
+                .collect::<Vec<&str>>()
+                .join(", "),
+            "changed server.properties"
+        );
+    }
+
+    let mut applied = Vec::new();
+    let mut restart_required = Vec::new();
+    for (key, value) in changed {
+        let live = match properties::live_command(&key, &value) {
+            Some(command) => super::run_command(&control, &principal, command)
+                .await
+                .is_ok(),
+            None => false,
+        };
+        if live {
+            applied.push(key);
+        } else {
+            restart_required.push(key);
+        }
+    }
+    Ok(Json(PropertiesUpdate {
+        properties: props.to_json(),
+        applied,
+        restart_required,
+    }))
+}
+
+#[derive(Serialize)]
+pub struct Motd {
+    // With `§` codes, as it's saved.
+    motd: String,
+    // HTML for showing it as the server list would.
+    preview: String,
+}
+
+#[derive(Deserialize)]
+pub struct MotdRequest {
+    // Text with `&` or `§` codes, or JSON text.
+    motd: Value,
+}
+
+pub async fn motd_handler(State(control): State<MinecraftControl>) -> Result<Json<Motd>, ApiError> {
+    let motd = match load(&control).await?.get("motd") {
+        Some(m) => m,
+        None => String::from("A Minecraft Server"),
+    };
+    Ok(Json(Motd {
+        preview: motd::preview(&motd),
+        motd,
+    }))
+}
+
+/// Saves a new MOTD, which the server shows from its next restart.
+pub async fn set_motd_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<MotdRequest>,
+) -> Result<Json<Motd>, ApiError> {
+    let motd = match motd::normalize(&request.motd) {
+        Ok(m) => m,
+        Err(e) => return Err(ApiError::new(StatusCode::BAD_REQUEST, e)),
+    };
+    let mut props = load(&control).await?;
+    props.set("motd", &motd);
+    if let Err(e) = props.write(&super::server_dir(&control)?).await {
+        return Err(ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        ));
+    }
+    tracing::info!(user = %principal.name, "changed the motd");
+    Ok(Json(Motd {
+        preview: motd::preview(&motd),
+        motd,
+    }))
+}
+
+/// Saves a PNG as server-icon.png, scaled to the 64x64 the server needs.
+/// The server only loads it when it starts.
+pub async fn icon_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    body: Bytes,
+) -> Result<String, ApiError> {
+    let dir = super::server_dir(&control)?;
+    let icon = tokio::task::spawn_blocking(move || {
+        let image = image::load_from_memory_with_format(&body, ImageFormat::Png)?;
+        let image = if image.width() == 64 && image.height() == 64 {
+            image
+        } else {
+            image.resize_exact(64, 64, FilterType::Lanczos3)
+        };
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        Ok::<Vec<u8>, image::ImageError>(png)
+    })
+    .await;
+    let icon = match icon {
+        Ok(Ok(png)) => png,
+        Ok(Err(e)) => {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("not a usable PNG: {}", e),
+            ))
+        }
+        Err(e) => {
+            return Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+    };
+
+    let path = dir.join("server-icon.png");
+    let tmp = path.with_extension("tmp");
+    let written = async {
+        tokio::fs::write(&tmp, icon).await?;
+        tokio::fs::rename(&tmp, &path).await
+    };
+    if let Err(e) = written.await {
+        return Err(ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        ));
+    }
+    tracing::info!(user = %principal.name, "changed the server icon");
+    Ok(String::from("icon saved, and shown from the next restart"))
+}
+
+#[derive(Serialize)]
+pub struct Eula {
+    accepted: bool,
+}
+
+#[derive(Deserialize)]
+pub struct EulaRequest {
+    // Has to be true, so accepting can't happen by accident.
+    #[serde(default)]
+    confirm: bool,
+}
+
+async fn eula_accepted(dir: &std::path::Path) -> bool {
+    match tokio::fs::read_to_string(dir.join("eula.txt")).await {
+        Ok(file) => file
+            .lines()
+            .any(|l| l.trim().eq_ignore_ascii_case("eula=true")),
+        Err(_) => false,
+    }
+}
+
+pub async fn eula_handler(State(control): State<MinecraftControl>) -> Result<Json<Eula>, ApiError> {
+    let dir = super::server_dir(&control)?;
+    Ok(Json(Eula {
+        accepted: eula_accepted(&dir).await,
+    }))
+}
+
+/// Accepts the Minecraft EULA on the caller's behalf, which a new server
+/// won't start without.
+pub async fn accept_eula_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<EulaRequest>,
+) -> Result<Json<Eula>, ApiError> {
+    if !request.confirm {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            String::from(
+                "set confirm to true to agree to the EULA at https://aka.ms/MinecraftEULA",
+            ),
+        ));
+    }
+    let dir = super::server_dir(&control)?;
+    let file = format!(
+        "#By changing the setting below to TRUE you are indicating your agreement to our EULA (https://aka.ms/MinecraftEULA).\n#Accepted by {} through the panel on {}\neula=true\n",
+        principal.name,
+        chrono::Local::now().to_rfc2822()
+    );
+    let written = async {
+        tokio::fs::create_dir_all(&dir).await?;
+        tokio::fs::write(dir.join("eula.txt"), file).await
+    };
+    if let Err(e) = written.await {
+        return Err(ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        ));
+    }
+    tracing::info!(user = %principal.name, "accepted the EULA");
+    Ok(Json(Eula { accepted: true }))
+}
@@ -0,0 +1,132 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use data_encoding::HEXLOWER;
+use rand::RngCore;
+use serde::Deserialize;
+
+use crate::auth::Principal;
+use crate::commands::Command;
+use crate::error::ApiError;
+use crate::minecraft::MinecraftControl;
+use crate::scheduler::{Action, ScheduleError, ScheduledTask, Task};
+
+#[derive(Deserialize)]
+pub struct TaskRequest {
+    // Made up if unset. Taken from the path when replacing a task.
+    id: Option<String>,
+    schedule: String,
+    #[serde(flatten)]
+    action: Action,
+    enabled: Option<bool>,
+}
+
+pub fn schedule_error(e: ScheduleError) -> ApiError {
+    let status = match e {
+        ScheduleError::Invalid(_) => StatusCode::BAD_REQUEST,
+        ScheduleError::NotFound => StatusCode::NOT_FOUND,
+        ScheduleError::Exists | ScheduleError::Configured => StatusCode::CONFLICT,
+        ScheduleError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    ApiError::new(status, e.to_string())
+}
+
+/// Tasks run the console commands they're given, so those have to be ones
+/// the caller could run themselves.
+fn check_policy(principal: &Principal, action: &Action) -> Result<(), ApiError> {
+    let command = match action {
+        Action::Command { command } => command.clone(),
+        Action::Announce { message } => Command::Tellraw {
+            message: message.clone(),
+        }
+        .render(),
+        Action::Restart(_) | Action::Backup => return Ok(()),
+    };
+    match principal.policy.check(&command) {
+        Ok(()) => Ok(()),
+        Err(rule) => Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            format!("command denied by rule: {}", rule),
+        )),
+    }
+}
+
+fn random_id() -> String {
+    let mut bytes = [0u8; 6];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    HEXLOWER.encode(&bytes)
+}
+
+pub async fn list_handler(State(control): State<MinecraftControl>) -> Json<Vec<ScheduledTask>> {
+    Json(control.scheduler().list().await)
+}
+
+pub async fn create_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<TaskRequest>,
+) -> Result<(StatusCode, Json<Task>), ApiError> {
+    check_policy(&principal, &request.action)?;
+    let task = Task {
+        id: match request.id {
+            Some(id) => id,
+            None => random_id(),
+        },
+        schedule: request.schedule,
+        action: request.action,
+        enabled: request.enabled,
+    };
+    match control
+        .scheduler()
+        .create(control.clone(), task.clone())
+        .await
+    {
+        Ok(()) => {
+            tracing::info!(user = %principal.name, server = %control.name(), task = %task.id, "scheduled a task");
+            Ok((StatusCode::CREATED, Json(task)))
+        }
+        Err(e) => Err(schedule_error(e)),
+    }
+}
+
+pub async fn update_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Json(request): Json<TaskRequest>,
+) -> Result<Json<Task>, ApiError> {
+    check_policy(&principal, &request.action)?;
+    let task = Task {
+        id,
+        schedule: request.schedule,
+        action: request.action,
+        enabled: request.enabled,
+    };
+    match control
+        .scheduler()
+        .update(control.clone(), task.clone())
+        .await
+    {
+        Ok(()) => {
+            tracing::info!(user = %principal.name, server = %control.name(), task = %task.id, "changed a task");
+            Ok(Json(task))
+        }
+        Err(e) => Err(schedule_error(e)),
+    }
+}
+
+pub async fn delete_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    match control.scheduler().delete(&id).await {
+        Ok(()) => {
+            tracing::info!(user = %principal.name, server = %control.name(), task = %id, "deleted a task");
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Err(e) => Err(schedule_error(e)),
+    }
+}
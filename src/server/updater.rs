@@ -0,0 +1,105 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::Deserialize;
+
+use crate::auth::Principal;
+use crate::error::ApiError;
+use crate::minecraft::MinecraftControl;
+use crate::updater::{Build, JarStatus, UpdateError, Updater};
+
+fn updater(control: &MinecraftControl) -> Result<&Updater, ApiError> {
+    match control.updater() {
+        Some(u) => Ok(u),
+        None => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            String::from("the updater isn't set up for this server"),
+        )),
+    }
+}
+
+fn update_error(e: UpdateError) -> ApiError {
+    let status = match e {
+        UpdateError::NoVersion => StatusCode::CONFLICT,
+        UpdateError::NoBuild | UpdateError::NoRollback => StatusCode::NOT_FOUND,
+        UpdateError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        UpdateError::Http(_) | UpdateError::Corrupt => StatusCode::BAD_GATEWAY,
+    };
+    ApiError::new(status, e.to_string())
+}
+
+#[derive(Deserialize)]
+pub struct VersionQuery {
+    // Another Minecraft version than the one the server is on.
+    version: Option<String>,
+}
+
+/// The latest build for the server and whether it's the one on disk.
+pub async fn status_handler(
+    State(control): State<MinecraftControl>,
+    Query(query): Query<VersionQuery>,
+) -> Result<Json<JarStatus>, ApiError> {
+    let dir = super::server_dir(&control)?;
+    let updater = updater(&control)?;
+    match updater
+        .status(&control, &dir, query.version.as_deref())
+        .await
+    {
+        Ok(s) => Ok(Json(s)),
+        Err(e) => Err(update_error(e)),
+    }
+}
+
+/// The Minecraft versions the server can be moved to.
+pub async fn versions_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    match updater(&control)?.versions().await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => Err(update_error(e)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdateRequest {
+    // Move the server to this Minecraft version. Worlds can't be opened by
+    // older versions once they've been upgraded, so take a backup first.
+    version: Option<String>,
+}
+
+/// Swaps in the latest build. Returns it, or 204 if the jar was already
+/// up to date.
+pub async fn update_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    request: Option<Json<UpdateRequest>>,
+) -> Result<(StatusCode, Json<Option<Build>>), ApiError> {
+    let dir = super::server_dir(&control)?;
+    let version = request.and_then(|Json(r)| r.version);
+    let updater = updater(&control)?;
+    match updater.update(&control, &dir, version.as_deref()).await {
+        Ok(Some(build)) => {
+            tracing::info!(user = %principal.name, server = %control.name(), version = %build.version, build = %build.build, "updated the server jar");
+            Ok((StatusCode::OK, Json(Some(build))))
+        }
+        Ok(None) => Ok((StatusCode::NO_CONTENT, Json(None))),
+        Err(e) => Err(update_error(e)),
+    }
+}
+
+/// Puts the previous jar back, keeping the current one as <jar>.old.
+pub async fn rollback_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<Option<Build>>, ApiError> {
+    let dir = super::server_dir(&control)?;
+    match updater(&control)?.rollback(&control, &dir).await {
+        Ok(build) => {
+            tracing::info!(user = %principal.name, server = %control.name(), "rolled back the server jar");
+            Ok(Json(build))
+        }
+        Err(e) => Err(update_error(e)),
+    }
+}
@@ -0,0 +1,260 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+use serde::Deserialize;
+use tokio_util::io::ReaderStream;
+
+use crate::auth::Principal;
+use crate::error::ApiError;
+use crate::level::{self, Level};
+use crate::minecraft::{MinecraftControl, MinecraftError};
+use crate::worlds::{
+    self,
+    import::ImportError,
+    prune::{Criteria, PruneReport},
+    regions::DimensionRegions,
+    World,
+};
+
+pub async fn list_handler(
+    State(control): State<MinecraftControl>,
+) -> Result<Json<Vec<World>>, ApiError> {
+    let dir = super::server_dir(&control)?;
+    match worlds::list(&dir).await {
+        Ok(w) => Ok(Json(w)),
+        Err(e) => {
+            tracing::warn!(error = %e, "could not list worlds");
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+fn world_dir(control: &MinecraftControl, name: &str) -> Result<std::path::PathBuf, ApiError> {
+    let dir = super::server_dir(control)?;
+    match worlds::path(&dir, name) {
+        Some(p) if p.join("level.dat").exists() => Ok(p),
+        _ => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("there's no world called {:?}", name),
+        )),
+    }
+}
+
+/// The world's seed, spawn, time, weather and datapacks, as last saved.
+pub async fn level_handler(
+    State(control): State<MinecraftControl>,
+    Path(name): Path<String>,
+) -> Result<Json<Level>, ApiError> {
+    let dir = world_dir(&control, &name)?;
+    match level::read(&dir).await {
+        Ok(Some(l)) => Ok(Json(l)),
+        Ok(None) => Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("{} has no level.dat", name),
+        )),
+        Err(e) => {
+            tracing::warn!(world = %name, error = %e, "could not read level.dat");
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+/// Chunk counts, sizes and save times from the world's region files, to
+/// find out where it's grown.
+pub async fn regions_handler(
+    State(control): State<MinecraftControl>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<DimensionRegions>>, ApiError> {
+    let dir = world_dir(&control, &name)?;
+    match worlds::regions::scan(&dir).await {
+        Ok(r) => Ok(Json(r)),
+        Err(e) => {
+            tracing::warn!(world = %name, error = %e, "could not read the regions");
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DownloadQuery {
+    // Flush the world and stop the server saving while it's read.
+    save: Option<bool>,
+}
+
+pub async fn download_handler(
+    State(control): State<MinecraftControl>,
+    Path(name): Path<String>,
+    Query(query): Query<DownloadQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let dir = world_dir(&control, &name)?;
+    // Streaming the world anyway would hand out one that may be half
+    // written, looking just like a good one.
+    let paused = if query.save.unwrap_or(false) {
+        match control.pause_saving().await {
+            Ok(p) => Some(p),
+            // A server that isn't running isn't writing either.
+            Err(_) if !control.is_running().await => None,
+            Err(MinecraftError::CommandTimeout) => {
+                return Err(ApiError::new(
+                    StatusCode::GATEWAY_TIMEOUT,
+                    String::from("the server didn't confirm it had saved the world"),
+                ))
+            }
+            Err(e) => {
+                return Err(ApiError::new(
+                    StatusCode::CONFLICT,
+                    format!("could not pause saving: {}", e),
+                ))
+            }
+        }
+    } else {
+        None
+    };
+
+    let (archive, task) = worlds::archive(name.clone(), dir);
+    let world = name.clone();
+    tokio::spawn(async move {
+        match task.await {
+            Ok(Ok(())) => tracing::info!(%world, "sent a world download"),
+            Ok(Err(e)) => tracing::warn!(%world, error = %e, "world download failed"),
+            Err(e) => tracing::warn!(%world, error = %e, "world download failed"),
+        }
+        if let Some(p) = paused {
+            if p.resume().await.is_err() {
+                tracing::warn!(%world, "could not turn saving back on after a download");
+            }
+        }
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, String::from("application/gzip")),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.tar.gz\"", name),
+            ),
+        ],
+        Body::from_stream(ReaderStream::new(archive)),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct UploadQuery {
+    // Move an existing world of the same name aside instead of refusing.
+    replace: Option<bool>,
+}
+
+/// Unpacks an uploaded tar.gz or zip as a world. The server has to be
+/// stopped so it can't save over it.
+pub async fn upload_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Path(name): Path<String>,
+    Query(query): Query<UploadQuery>,
+    body: Body,
+) -> Result<String, ApiError> {
+    let dir = super::server_dir(&control)?;
+    let target = match worlds::path(&dir, &name) {
+        Some(p) => p,
+        None => {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("{:?} can't be a world name", name),
+            ))
+        }
+    };
+    if control.is_running().await {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            String::from("stop the server before uploading a world"),
+        ));
+    }
+
+    let imported = worlds::import::import(
+        body.into_data_stream(),
+        target,
+        control.world_upload_limit(),
+        query.replace.unwrap_or(false),
+    )
+    .await;
+    match imported {
+        Ok(moved) => {
+            tracing::info!(user = %principal.name, world = %name, "uploaded a world");
+            Ok(match moved {
+                Some(old) => format!("imported {}, the old world is now {}", name, old.display()),
+                None => format!("imported {}", name),
+            })
+        }
+        Err(e) => {
+            tracing::warn!(world = %name, error = %e, "could not import a world");
+            let status = match e {
+                ImportError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                ImportError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+                ImportError::Exists => StatusCode::CONFLICT,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            Err(ApiError::new(status, e.to_string()))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PruneRequest {
+    #[serde(flatten)]
+    criteria: Criteria,
+    // Only report what would be pruned. On unless it's set to false.
+    dry_run: Option<bool>,
+}
+
+/// Deletes chunks outside a radius or not saved since a cutoff, to trim a
+/// world down. The server has to be stopped so it can't save them again.
+pub async fn prune_handler(
+    State(control): State<MinecraftControl>,
+    Extension(principal): Extension<Principal>,
+    Path(name): Path<String>,
+    Json(request): Json<PruneRequest>,
+) -> Result<Json<PruneReport>, ApiError> {
+    let dir = world_dir(&control, &name)?;
+    if request.criteria.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            String::from("give a radius, a before time or both"),
+        ));
+    }
+    let dry_run = request.dry_run.unwrap_or(true);
+    if !dry_run && control.is_running().await {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            String::from("stop the server before pruning a world"),
+        ));
+    }
+
+    match worlds::prune::prune(&dir, request.criteria, dry_run).await {
+        Ok(report) => {
+            if !dry_run {
+                tracing::info!(user = %principal.name, world = %name, chunks = report.chunks, bytes = report.reclaimed, "pruned chunks");
+            }
+            Ok(Json(report))
+        }
+        Err(e) => {
+            tracing::warn!(world = %name, error = %e, "could not prune chunks");
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+    }
+}
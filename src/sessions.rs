@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::{broadcast, Mutex};
+use tokio_util::sync::CancellationToken;
+
+use crate::events::{EventBus, ServerEvent};
+use crate::util::now;
+
+// Older sessions are forgotten, though they still count towards playtime.
+const MAX_SESSIONS: usize = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    // Seconds since the epoch.
+    pub joined: u64,
+    // Unset while the player is still online.
+    pub left: Option<u64>,
+}
+
+impl Session {
+    fn seconds(&self, now: u64) -> u64 {
+        self.left.unwrap_or(now).saturating_sub(self.joined)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct PlayerRecord {
+    sessions: Vec<Session>,
+    // Playtime of the sessions that were dropped.
+    #[serde(default)]
+    forgotten_seconds: u64,
+}
+
+#[derive(Serialize)]
+pub struct PlayerSessions {
+    pub player: String,
+    pub online: bool,
+    pub playtime_seconds: u64,
+    // Oldest first.
+    pub sessions: Vec<Session>,
+}
+
+#[derive(Serialize)]
+pub struct Playtime {
+    pub player: String,
+    pub online: bool,
+    pub playtime_seconds: u64,
+    pub last_seen: u64,
+}
+
+/// When each player was on a server, worked out from join and leave
+/// messages and persisted as JSON.
+#[derive(Clone)]
+pub struct SessionStore {
+    path: PathBuf,
+    players: Arc<Mutex<HashMap<String, PlayerRecord>>>,
+}
+
+impl SessionStore {
+    pub fn load(path: PathBuf) -> Result<SessionStore, String> {
+        let players = match std::fs::read_to_string(&path) {
+            Ok(file) => match serde_json::from_str(&file) {
+                Ok(p) => p,
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            },
+            Err(_) => HashMap::new(),
+        };
+        Ok(SessionStore {
+            path,
+            players: Arc::new(Mutex::new(players)),
+        })
+    }
+
+    async fn save(&self, players: &HashMap<String, PlayerRecord>) -> Result<(), std::io::Error> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(players)?).await?;
+        fs::rename(&tmp, &self.path).await
+    }
+
+    /// Follows `server`'s events until `shutdown` is cancelled.
+    pub fn track(&self, server: String, events: &EventBus, shutdown: CancellationToken) {
+        let store = self.clone();
+        let mut rx = events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    event = rx.recv() => match event {
+                        Ok(e) => e,
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return,
+                    },
+                    _ = shutdown.cancelled() => return,
+                };
+                if event.server != server {
+                    continue;
+                }
+                let time = event.timestamp / 1000;
+                let mut players = store.players.lock().await;
+                match event.event {
+                    ServerEvent::PlayerJoined { player } => {
+                        let record = players.entry(player).or_default();
+                        // The leave was missed, so the best guess is that
+                        // they were on until now.
+                        close(record, time);
+                        record.sessions.push(Session {
+                            joined: time,
+                            left: None,
+                        });
+                        if record.sessions.len() > MAX_SESSIONS {
+                            let dropped = record.sessions.remove(0);
+                            record.forgotten_seconds += dropped.seconds(time);
+                        }
+                    }
+                    ServerEvent::PlayerLeft { player } => match players.get_mut(&player) {
+                        Some(record) => close(record, time),
+                        None => continue,
+                    },
+                    // Nobody gets a leave message when the server goes
+                    // down.
+                    ServerEvent::ServerStopping
+                    | ServerEvent::ServerStarted { .. }
+                    | ServerEvent::Crash { .. } => {
+                        for record in players.values_mut() {
+                            close(record, time);
+                        }
+                    }
+                    _ => continue,
+                }
+                if let Err(e) = store.save(&players).await {
+                    tracing::warn!(error = %e, "could not save sessions");
+                }
+            }
+        });
+    }
+
+    pub async fn sessions(&self, player: &str) -> Option<PlayerSessions> {
+        let players = self.players.lock().await;
+        let record = players.get(player)?;
+        Some(PlayerSessions {
+            player: player.to_owned(),
+            online: online(record),
+            playtime_seconds: playtime(record, now()),
+            sessions: record.sessions.clone(),
+        })
+    }
+
+    /// Everyone who has played, most playtime first.
+    pub async fn playtime(&self) -> Vec<Playtime> {
+        let now = now();
+        let players = self.players.lock().await;
+        let mut playtimes: Vec<Playtime> = players
+            .iter()
+            .map(|(player, record)| Playtime {
+                player: player.clone(),
+                online: online(record),
+                playtime_seconds: playtime(record, now),
+                last_seen: match record.sessions.last() {
+                    Some(s) if s.left.is_none() => now,
+                    Some(s) => s.left.unwrap_or(s.joined),
+                    None => 0,
+                },
+            })
+            .collect();
+        playtimes.sort_by(|a, b| b.playtime_seconds.cmp(&a.playtime_seconds));
+        playtimes
+    }
+}
+
+fn close(record: &mut PlayerRecord, time: u64) {
+    if let Some(session) = record.sessions.last_mut() {
+        if session.left.is_none() {
+            session.left = Some(time.max(session.joined));
+        }
+    }
+}
+
+fn online(record: &PlayerRecord) -> bool {
+    matches!(record.sessions.last(), Some(s) if s.left.is_none())
+}
+
+fn playtime(record: &PlayerRecord, now: u64) -> u64 {
+    record.forgotten_seconds + record.sessions.iter().map(|s| s.seconds(now)).sum::<u64>()
+}
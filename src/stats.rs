@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+#[derive(Deserialize)]
+struct StatsFile {
+    // Only written since 1.13, which changed the format.
+    #[serde(default)]
+    stats: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+#[derive(Serialize)]
+pub struct PlayerStats {
+    pub uuid: String,
+    pub play_time_seconds: u64,
+    pub deaths: u64,
+    pub mob_kills: u64,
+    pub player_kills: u64,
+    pub blocks_mined: u64,
+    // Centimetres, as the server counts it, over every way of moving.
+    pub distance_travelled: u64,
+    // Everything, by category and then statistic, without the
+    // "minecraft:" prefixes. e.g. "mined" -> "stone" -> 120.
+    pub categories: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+fn strip(key: String) -> String {
+    match key.strip_prefix("minecraft:") {
+        Some(k) => k.to_owned(),
+        None => key,
+    }
+}
+
+/// Reads `world/stats/<uuid>.json`. None if the player has never been on
+/// the server.
+pub async fn read(world_dir: &Path, uuid: &str) -> Result<Option<PlayerStats>, std::io::Error> {
+    let file =
+        match fs::read_to_string(world_dir.join("stats").join(format!("{}.json", uuid))).await {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+    let file: StatsFile = serde_json::from_str(&file)?;
+    let categories: BTreeMap<String, BTreeMap<String, u64>> = file
+        .stats
+        .into_iter()
+        .map(|(category, stats)| {
+            (
+                strip(category),
+                stats.into_iter().map(|(k, v)| (strip(k), v)).collect(),
+            )
+        })
+        .collect();
+
+    let custom = |key: &str| -> u64 {
+        categories
+            .get("custom")
+            .and_then(|c| c.get(key))
+            .copied()
+            .unwrap_or(0)
+    };
+    let distance_travelled = match categories.get("custom") {
+        Some(c) => c
+            .iter()
+            .filter(|(k, _)| k.ends_with("_one_cm"))
+            .map(|(_, v)| v)
+            .sum(),
+        None => 0,
+    };
+    Ok(Some(PlayerStats {
+        uuid: uuid.to_owned(),
+        // Ticks, which were called play_one_minute before 1.17.
+        play_time_seconds: custom("play_time").max(custom("play_one_minute")) / 20,
+        deaths: custom("deaths"),
+        mob_kills: custom("mob_kills"),
+        player_kills: custom("player_kills"),
+        blocks_mined: match categories.get("mined") {
+            Some(m) => m.values().sum(),
+            None => 0,
+        },
+        distance_travelled,
+        categories,
+    }))
+}
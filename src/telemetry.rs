@@ -0,0 +1,84 @@
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{trace, Resource};
+use serde::Deserialize;
+use tracing_subscriber::{filter::LevelFilter, Layer, Registry};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TelemetryConfig {
+    // An OTLP gRPC collector, e.g. "http://localhost:4317".
+    otlp_endpoint: String,
+    // "minecraft-control" by default.
+    service_name: Option<String>,
+    // The least severe spans exported: "error", "warn", "info", "debug" or
+    // "trace". "info" by default, which leaves out one span per log line.
+    level: Option<String>,
+    // The share of requests traced, from 0 to 1. All of them by default.
+    sample_ratio: Option<f64>,
+}
+
+fn level(config: &TelemetryConfig) -> Result<LevelFilter, String> {
+    match config.level.as_deref().unwrap_or("info").parse() {
+        Ok(l) => Ok(l),
+        Err(_) => Err(format!("telemetry level {:?} is not valid", config.level)),
+    }
+}
+
+/// Whether the level in the config is one `init` can use, for
+/// `check::parse`.
+pub fn check(config: Option<&TelemetryConfig>) -> Result<(), String> {
+    match config {
+        Some(c) => level(c).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+/// A layer exporting spans over OTLP, when telemetry is configured. The
+/// provider has to be shut down before exiting, to flush what's left.
+#[allow(clippy::type_complexity)]
+pub fn init(
+    config: Option<TelemetryConfig>,
+) -> Result<
+    Option<(
+        Box<dyn Layer<Registry> + Send + Sync>,
+        trace::TracerProvider,
+    )>,
+    String,
+> {
+    let config = match config {
+        Some(c) => c,
+        None => return Ok(None),
+    };
+    let level = level(&config)?;
+    let service_name = match config.service_name {
+        Some(n) => n,
+        None => String::from("minecraft-control"),
+    };
+    let exporter = opentelemetry_otlp::new_exporter()
+        .tonic()
+        .with_endpoint(config.otlp_endpoint);
+    let trace_config = trace::Config::default()
+        .with_sampler(trace::Sampler::ParentBased(Box::new(
+            trace::Sampler::TraceIdRatioBased(config.sample_ratio.unwrap_or(1.0)),
+        )))
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            service_name,
+        )]));
+    let provider = match opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(trace_config)
+        .install_batch(opentelemetry_sdk::runtime::Tokio)
+    {
+        Ok(p) => p,
+        Err(e) => return Err(format!("could not set up the OTLP exporter: {}", e)),
+    };
+    let tracer = provider.tracer("minecraft-control");
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(tracer)
+        .with_filter(level)
+        .boxed();
+    Ok(Some((layer, provider)))
+}
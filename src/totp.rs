@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use data_encoding::{BASE32_NOPAD, HEXLOWER};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use tokio::{fs, sync::Mutex};
+
+use crate::util::now;
+
+const STEP: u64 = 30;
+const DIGITS: u32 = 6;
+const RECOVERY_CODES: usize = 10;
+const MAX_FAILURES: u32 = 5;
+const LOCKOUT_SECONDS: u64 = 300;
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Enrollment {
+    secret: String,
+    #[serde(default)]
+    confirmed: bool,
+    // SHA-256 of each unused recovery code.
+    #[serde(default)]
+    recovery_codes: Vec<String>,
+    // Last accepted time step, so a code can't be replayed.
+    #[serde(default)]
+    last_step: u64,
+}
+
+/// TOTP enrollments for local users, persisted as JSON.
+#[derive(Clone)]
+pub struct TwoFactorStore {
+    path: PathBuf,
+    users: Arc<Mutex<HashMap<String, Enrollment>>>,
+    // Failed attempts per user and when the first of them happened.
+    failures: Arc<Mutex<HashMap<String, (u32, u64)>>>,
+}
+
+impl TwoFactorStore {
+    pub async fn load(path: PathBuf) -> Result<TwoFactorStore, std::io::Error> {
+        let users = match fs::read_to_string(&path).await {
+            Ok(file) => match serde_json::from_str(&file) {
+                Ok(u) => u,
+                Err(e) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("{}: {}", path.display(), e),
+                    ))
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(TwoFactorStore {
+            path,
+            users: Arc::new(Mutex::new(users)),
+            failures: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    async fn save(&self, users: &HashMap<String, Enrollment>) -> Result<(), std::io::Error> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(users)?).await?;
+        fs::rename(&tmp, &self.path).await
+    }
+
+    pub async fn enabled(&self, username: &str) -> bool {
+        match self.users.lock().await.get(username) {
+            Some(e) => e.confirmed,
+            None => false,
+        }
+    }
+
+    /// Starts an enrollment and returns the new base32 secret, or `None` when
+    /// the user already has two-factor enabled.
+    pub async fn begin(&self, username: &str) -> Result<Option<String>, std::io::Error> {
+        let mut users = self.users.lock().await;
+        if let Some(e) = users.get(username) {
+            if e.confirmed {
+                return Ok(None);
+            }
+        }
+
+        let mut secret = [0u8; 20];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let secret = BASE32_NOPAD.encode(&secret);
+        users.insert(
+            username.to_owned(),
+            Enrollment {
+                secret: secret.clone(),
+                confirmed: false,
+                recovery_codes: Vec::new(),
+                last_step: 0,
+            },
+        );
+        self.save(&users).await?;
+        Ok(Some(secret))
+    }
+
+    /// Finishes an enrollment with a first valid code and returns the
+    /// recovery codes, which are only ever shown this once.
+    pub async fn confirm(
+        &self,
+        username: &str,
+        code: &str,
+    ) -> Result<Option<Vec<String>>, std::io::Error> {
+        let mut users = self.users.lock().await;
+        let enrollment = match users.get_mut(username) {
+            Some(e) if !e.confirmed => e,
+            _ => return Ok(None),
+        };
+        let step = match check_code(&enrollment.secret, code, enrollment.last_step) {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+
+        let codes: Vec<String> = (0..RECOVERY_CODES).map(|_| recovery_code()).collect();
+        enrollment.confirmed = true;
+        enrollment.last_step = step;
+        enrollment.recovery_codes = codes.iter().map(|c| hash_recovery_code(c)).collect();
+        self.save(&users).await?;
+        Ok(Some(codes))
+    }
+
+    /// Checks a TOTP or recovery code for a user with two-factor enabled.
+    /// Recovery codes are consumed on use.
+    pub async fn verify(&self, username: &str, code: &str) -> Result<bool, std::io::Error> {
+        if self.locked_out(username).await {
+            return Ok(false);
+        }
+
+        let mut users = self.users.lock().await;
+        let enrollment = match users.get_mut(username) {
+            Some(e) if e.confirmed => e,
+            _ => return Ok(false),
+        };
+
+        let accepted =
+            if let Some(step) = check_code(&enrollment.secret, code, enrollment.last_step) {
+                enrollment.last_step = step;
+                true
+            } else {
+                let hashed = hash_recovery_code(code);
+                let before = enrollment.recovery_codes.len();
+                enrollment.recovery_codes.retain(|c| c != &hashed);
+                before != enrollment.recovery_codes.len()
+            };
+
+        if accepted {
+            self.save(&users).await?;
+            self.failures.lock().await.remove(username);
+        } else {
+            self.record_failure(username).await;
+        }
+        Ok(accepted)
+    }
+
+    pub async fn disable(&self, username: &str, code: &str) -> Result<bool, std::io::Error> {
+        if !self.verify(username, code).await? {
+            return Ok(false);
+        }
+        let mut users = self.users.lock().await;
+        users.remove(username);
+        self.save(&users).await?;
+        Ok(true)
+    }
+
+    async fn locked_out(&self, username: &str) -> bool {
+        match self.failures.lock().await.get(username) {
+            Some((count, since)) => *count >= MAX_FAILURES && now() < since + LOCKOUT_SECONDS,
+            None => false,
+        }
+    }
+
+    async fn record_failure(&self, username: &str) {
+        let mut failures = self.failures.lock().await;
+        let now = now();
+        let entry = failures.entry(username.to_owned()).or_insert((0, now));
+        if now >= entry.1 + LOCKOUT_SECONDS {
+            *entry = (0, now);
+        }
+        entry.0 += 1;
+    }
+}
+
+pub fn otpauth_uri(issuer: &str, username: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&digits={}&period={}",
+        percent_encode(issuer),
+        percent_encode(username),
+        secret,
+        percent_encode(issuer),
+        DIGITS,
+        STEP
+    )
+}
+
+/// Accepts codes one step either side of now to allow for clock drift,
+/// returning the matched step if it's newer than `last_step`.
+fn check_code(secret: &str, code: &str, last_step: u64) -> Option<u64> {
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code: u32 = code.trim().parse().ok()?;
+    let current = now() / STEP;
+    (current.saturating_sub(1)..=current + 1)
+        .filter(|step| *step > last_step)
+        .find(|step| totp(&secret, *step) == code)
+}
+
+fn totp(secret: &[u8], step: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).unwrap();
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation from RFC 4226.
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    value % 10u32.pow(DIGITS)
+}
+
+fn recovery_code() -> String {
+    let mut bytes = [0u8; 5];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let code = BASE32_NOPAD.encode(&bytes).to_lowercase();
+    format!("{}-{}", &code[..4], &code[4..])
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter(|c| *c != '-')
+        .collect();
+    HEXLOWER.encode(&Sha256::digest(normalized.as_bytes()))
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn store(name: &str) -> (PathBuf, TwoFactorStore) {
+        let path =
+            std::env::temp_dir().join(format!("mcctl-totp-{}-{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path).await;
+        (path.clone(), TwoFactorStore::load(path).await.unwrap())
+    }
+
+    fn code(secret: &str, step: u64) -> String {
+        let secret = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+        format!("{:06}", totp(&secret, step))
+    }
+
+    #[test]
+    fn rfc_6238_vectors() {
+        // The SHA-1 vectors from RFC 6238 appendix B, cut to six digits.
+        let secret = b"12345678901234567890";
+        for (time, expected) in [
+            (59, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+            (20000000000, 353130),
+        ] {
+            assert_eq!(totp(secret, time / STEP), expected, "{}", time);
+        }
+    }
+
+    #[test]
+    fn clock_drift_and_bad_codes() {
+        let secret = BASE32_NOPAD.encode(b"12345678901234567890");
+        let current = now() / STEP;
+        assert_eq!(
+            check_code(&secret, &code(&secret, current), 0),
+            Some(current)
+        );
+        assert!(check_code(&secret, &code(&secret, current + 1), 0).is_some());
+        assert!(check_code(&secret, &code(&secret, current + 5), 0).is_none());
+        assert!(check_code(&secret, &code(&secret, current - 5), 0).is_none());
+        assert!(check_code(&secret, "abcdef", 0).is_none());
+        assert!(check_code("not base32!", "123456", 0).is_none());
+    }
+
+    #[tokio::test]
+    async fn codes_cannot_be_replayed() {
+        let (path, store) = store("replay").await;
+        let secret = store.begin("alice").await.unwrap().unwrap();
+        assert!(!store.enabled("alice").await);
+        let current = now() / STEP;
+        assert!(store
+            .confirm("alice", &code(&secret, current))
+            .await
+            .unwrap()
+            .is_some());
+        assert!(store.enabled("alice").await);
+        assert!(store.begin("alice").await.unwrap().is_none());
+
+        assert!(!store
+            .verify("alice", &code(&secret, current))
+            .await
+            .unwrap());
+        assert!(store
+            .verify("alice", &code(&secret, current + 1))
+            .await
+            .unwrap());
+        assert!(!store
+            .verify("alice", &code(&secret, current + 1))
+            .await
+            .unwrap());
+        assert!(!store
+            .verify("alice", &code(&secret, current))
+            .await
+            .unwrap());
+        let _ = fs::remove_file(&path).await;
+    }
+
+    #[tokio::test]
+    async fn recovery_codes_work_once() {
+        let (path, store) = store("recovery").await;
+        let secret = store.begin("alice").await.unwrap().unwrap();
+        let codes = store
+            .confirm("alice", &code(&secret, now() / STEP))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        assert!(store.verify("alice", &codes[0]).await.unwrap());
+        assert!(!store.verify("alice", &codes[0]).await.unwrap());
+        let typed = codes[1].to_uppercase().replace('-', "");
+        assert!(store.verify("alice", &typed).await.unwrap());
+
+        // The codes were saved hashed, and still work after a reload.
+        let file = fs::read_to_string(&path).await.unwrap();
+        assert!(!file.contains(&codes[2]));
+        let reloaded = TwoFactorStore::load(path.clone()).await.unwrap();
+        assert!(reloaded.verify("alice", &codes[2]).await.unwrap());
+        let _ = fs::remove_file(&path).await;
+    }
+
+    #[tokio::test]
+    async fn locked_out_after_failures() {
+        let (path, store) = store("lockout").await;
+        let secret = store.begin("alice").await.unwrap().unwrap();
+        let codes = store
+            .confirm("alice", &code(&secret, now() / STEP))
+            .await
+            .unwrap()
+            .unwrap();
+        for _ in 0..MAX_FAILURES {
+            assert!(!store.verify("alice", "wrong").await.unwrap());
+        }
+        // Even a good code is turned down until the lockout is over.
+        assert!(!store.verify("alice", &codes[0]).await.unwrap());
+        assert!(!store
+            .verify("alice", &code(&secret, now() / STEP + 1))
+            .await
+            .unwrap());
+        assert!(store.failures.lock().await.contains_key("alice"));
+
+        // Another user isn't affected.
+        assert!(!store.verify("bob", "wrong").await.unwrap());
+        assert!(!store.locked_out("bob").await);
+        let _ = fs::remove_file(&path).await;
+    }
+
+    #[test]
+    fn uri() {
+        assert_eq!(
+            otpauth_uri("My Server", "alice@example.com", "ABC"),
+            "otpauth://totp/My%20Server:alice%40example.com?secret=ABC&issuer=My%20Server&digits=6&period=30"
+        );
+    }
+}
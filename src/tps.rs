@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, Mutex};
+use tokio_util::sync::CancellationToken;
+
+use crate::minecraft::MinecraftControl;
+use crate::util::now;
+
+// Tried in turn until one gets a reply that can be read: vanilla since
+// 1.20.3, Paper and its forks, and Forge.
+const COMMANDS: &[&str] = &["tick query", "tps", "forge tps"];
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TpsConfig {
+    // How often to sample, 30 seconds by default.
+    interval_secs: Option<u64>,
+    // How many samples to keep, 120 by default, an hour at the default
+    // interval.
+    window: Option<usize>,
+    // The command to sample with. Worked out from the replies if unset.
+    command: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Sample {
+    // Seconds since the epoch.
+    pub at: u64,
+    pub tps: f64,
+    // Milliseconds per tick, for servers that say.
+    pub mspt: Option<f64>,
+}
+
+fn strip_codes(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            chars.next();
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// The first number in `text` after `label`.
+fn number_after(text: &str, label: &str) -> Option<f64> {
+    let rest = &text[text.find(label)? + label.len()..];
+    let start = rest.find(|c: char| c.is_ascii_digit())?;
+    let number: String = rest[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    number.parse().ok()
+}
+
+/// Reads the reply to one of COMMANDS:
+/// `Target tick rate: 20.0 per second. Average time per tick: 1.2ms`,
+/// `TPS from last 1m, 5m, 15m: 20.0, 20.0, 20.0` or
+/// `Overall: Mean tick time: 1.234 ms. Mean TPS: 20.000`.
+fn parse(reply: &str) -> Option<(f64, Option<f64>)> {
+    let reply = strip_codes(reply);
+    if let Some(mspt) = number_after(&reply, "Average time per tick:") {
+        let target = number_after(&reply, "Target tick rate:").unwrap_or(20.0);
+        let tps = if mspt > 0.0 {
+            (1000.0 / mspt).min(target)
+        } else {
+            target
+        };
+        return Some((tps, Some(mspt)));
+    }
+    if let Some(tps) = number_after(&reply, "15m:") {
+        // Paper caps what it shows at 20 with a * for more.
+        return Some((tps.min(20.0), None));
+    }
+    if let Some(tps) = number_after(&reply, "Overall:").and(number_after(&reply, "Mean TPS:")) {
+        let mspt = number_after(&reply, "Mean tick time:");
+        return Some((tps, mspt));
+    }
+    None
+}
+
+/// Samples the server's ticks per second on an interval and keeps the
+/// latest in a rolling window. New samples are also sent to subscribers.
+#[derive(Clone)]
+pub struct Tps {
+    config: TpsConfig,
+    samples: Arc<Mutex<VecDeque<Sample>>>,
+    tx: broadcast::Sender<Sample>,
+}
+
+impl Tps {
+    pub fn new(config: TpsConfig) -> Tps {
+        let (tx, _) = broadcast::channel(16);
+        Tps {
+            config,
+            samples: Arc::new(Mutex::new(VecDeque::new())),
+            tx,
+        }
+    }
+
+    /// Oldest first.
+    pub async fn samples(&self) -> Vec<Sample> {
+        self.samples.lock().await.iter().cloned().collect()
+    }
+
+    pub async fn latest(&self) -> Option<Sample> {
+        self.samples.lock().await.back().cloned()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Sample> {
+        self.tx.subscribe()
+    }
+
+    /// Tries each command until one gives a reply that can be read.
+    async fn sample(
+        &self,
+        control: &MinecraftControl,
+        command: &mut Option<String>,
+    ) -> Option<Sample> {
+        let candidates: Vec<String> = match command {
+            Some(c) => vec![c.clone()],
+            None => COMMANDS.iter().map(|c| c.to_string()).collect(),
+        };
+        for candidate in candidates {
+            let reply = match control.execute(candidate.clone()).await {
+                Ok(Some(r)) => r,
+                _ => continue,
+            };
+            if let Some((tps, mspt)) = parse(&reply) {
+                *command = Some(candidate);
+                return Some(Sample {
+                    at: now(),
+                    tps,
+                    mspt,
+                });
+            }
+        }
+        None
+    }
+
+    /// Samples every `interval_secs` while the server is running, until
+    /// shutdown.
+    pub fn start(&self, control: MinecraftControl, shutdown: CancellationToken) {
+        let tps = self.clone();
+        let interval = Duration::from_secs(self.config.interval_secs.unwrap_or(30));
+        let window = self.config.window.unwrap_or(120);
+        tokio::spawn(async move {
+            let mut command = tps.config.command.clone();
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {},
+                    _ = shutdown.cancelled() => return,
+                }
+                if !control.is_running().await {
+                    continue;
+                }
+                let sample = match tps.sample(&control, &mut command).await {
+                    Some(s) => s,
+                    None => continue,
+                };
+                let mut samples = tps.samples.lock().await;
+                samples.push_back(sample.clone());
+                while samples.len() > window {
+                    samples.pop_front();
+                }
+                drop(samples);
+                let _ = tps.tx.send(sample);
+            }
+        });
+    }
+}
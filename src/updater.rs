@@ -0,0 +1,551 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use data_encoding::HEXLOWER;
+use md5::Md5;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use crate::events::ServerEvent;
+use crate::minecraft::MinecraftControl;
+
+const PAPER_API: &str = "https://api.papermc.io/v2/projects";
+const PURPUR_API: &str = "https://api.purpurmc.org/v2/purpur";
+const VERSION_MANIFEST: &str = "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json";
+const FABRIC_META: &str = "https://meta.fabricmc.net/v2/versions";
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Flavor {
+    Paper,
+    Folia,
+    Purpur,
+    Vanilla,
+    Fabric,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdaterConfig {
+    flavor: Flavor,
+    // The Minecraft version to follow builds of. The server's game_version
+    // if unset. Once the panel has installed a jar, the version it's for is
+    // followed instead.
+    version: Option<String>,
+    // The jar the server runs, relative to its directory.
+    jar: Option<String>,
+    // Only take builds from Paper's default channel and stable Fabric
+    // loaders, not experimental ones.
+    stable_only: Option<bool>,
+    // Restart the server this long after an update, if it's running.
+    restart_after_secs: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum UpdateError {
+    Http(reqwest::Error),
+    Io(std::io::Error),
+    NoVersion,
+    NoBuild,
+    // The download didn't match the checksum the API gave for it.
+    Corrupt,
+    // There's no <jar>.old to go back to.
+    NoRollback,
+}
+
+impl From<reqwest::Error> for UpdateError {
+    fn from(e: reqwest::Error) -> Self {
+        UpdateError::Http(e)
+    }
+}
+
+impl From<std::io::Error> for UpdateError {
+    fn from(e: std::io::Error) -> Self {
+        UpdateError::Io(e)
+    }
+}
+
+impl std::fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateError::Http(e) => write!(f, "could not fetch the build: {}", e),
+            UpdateError::Io(e) => write!(f, "{}", e),
+            UpdateError::NoVersion => {
+                write!(
+                    f,
+                    "set a version for the updater or game_version for the server"
+                )
+            }
+            UpdateError::NoBuild => write!(f, "there's no build for that version"),
+            UpdateError::Corrupt => write!(f, "the download didn't match its checksum"),
+            UpdateError::NoRollback => write!(f, "there's no previous jar to roll back to"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "algorithm", content = "hash", rename_all = "lowercase")]
+pub enum Checksum {
+    Sha256(String),
+    // What Mojang gives.
+    Sha1(String),
+    // All Purpur gives.
+    Md5(String),
+}
+
+impl Checksum {
+    fn matches(&self, bytes: &[u8]) -> bool {
+        match self {
+            Checksum::Sha256(h) => HEXLOWER.encode(&Sha256::digest(bytes)) == *h,
+            Checksum::Sha1(h) => HEXLOWER.encode(&Sha1::digest(bytes)) == *h,
+            Checksum::Md5(h) => HEXLOWER.encode(&Md5::digest(bytes)) == *h,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Build {
+    pub version: String,
+    // The Paper or Purpur build number, the Fabric loader version, or the
+    // Minecraft version again for vanilla.
+    pub build: String,
+    #[serde(skip)]
+    url: String,
+    // None for Fabric, whose launcher jars are built on request.
+    pub checksum: Option<Checksum>,
+}
+
+#[derive(Serialize)]
+pub struct JarStatus {
+    pub flavor: Flavor,
+    // What the panel last installed, if it's still in place.
+    pub installed: Option<Build>,
+    pub latest: Build,
+    // Whether the jar on disk is the latest build.
+    pub up_to_date: bool,
+    // Whether there's a <jar>.old to roll back to.
+    pub rollback: bool,
+}
+
+#[derive(Deserialize)]
+struct PaperProject {
+    // Oldest first.
+    versions: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct PaperBuilds {
+    builds: Vec<PaperBuild>,
+}
+
+#[derive(Deserialize)]
+struct PaperBuild {
+    build: u32,
+    channel: String,
+    downloads: PaperDownloads,
+}
+
+#[derive(Deserialize)]
+struct PaperDownloads {
+    application: PaperDownload,
+}
+
+#[derive(Deserialize)]
+struct PaperDownload {
+    name: String,
+    sha256: String,
+}
+
+#[derive(Deserialize)]
+struct PurpurBuild {
+    build: String,
+    md5: String,
+}
+
+#[derive(Deserialize)]
+struct VersionManifest {
+    // Newest first.
+    versions: Vec<ManifestVersion>,
+}
+
+#[derive(Deserialize)]
+struct ManifestVersion {
+    id: String,
+    // "release", "snapshot", "old_beta" or "old_alpha".
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct VersionDetails {
+    downloads: VersionDownloads,
+}
+
+#[derive(Deserialize)]
+struct VersionDownloads {
+    // Versions older than 1.2.5 don't have one.
+    server: Option<VersionDownload>,
+}
+
+#[derive(Deserialize)]
+struct VersionDownload {
+    sha1: String,
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct FabricVersion {
+    version: String,
+    stable: bool,
+}
+
+#[derive(Deserialize)]
+struct FabricLoader {
+    loader: FabricVersion,
+}
+
+/// Renames `from` to `to`, or if there's no `from`, makes sure there's no
+/// `to` either.
+async fn move_record(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    match fs::rename(from, to).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => match fs::remove_file(to).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Keeps a server's jar on the latest Paper, Folia, Purpur, vanilla or
+/// Fabric build for its Minecraft version, and moves it to new versions.
+#[derive(Clone)]
+pub struct Updater {
+    config: UpdaterConfig,
+    http: reqwest::Client,
+}
+
+impl Updater {
+    pub fn new(config: UpdaterConfig) -> Updater {
+        Updater {
+            config,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    fn jar(&self, server_dir: &Path) -> PathBuf {
+        server_dir.join(match &self.config.jar {
+            Some(j) => j.as_str(),
+            None => "server.jar",
+        })
+    }
+
+    fn stable_only(&self) -> bool {
+        self.config.stable_only.unwrap_or(true)
+    }
+
+    /// What was installed is kept next to the jar as <jar>.json, since
+    /// Fabric's launcher jars can't be told apart by hash.
+    async fn installed(&self, jar: &Path) -> Option<Build> {
+        let bytes = fs::read(jar.with_extension("jar.json")).await.ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    fn version(
+        &self,
+        control: &MinecraftControl,
+        installed: &Option<Build>,
+    ) -> Result<String, UpdateError> {
+        if let Some(b) = installed {
+            return Ok(b.version.clone());
+        }
+        match (&self.config.version, control.game_version()) {
+            (Some(v), _) => Ok(v.clone()),
+            (None, Some(v)) => Ok(v.to_owned()),
+            (None, None) => Err(UpdateError::NoVersion),
+        }
+    }
+
+    /// GETs JSON, with a 404 taken to mean there's no such version.
+    async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<T, UpdateError> {
+        let response = self.http.get(url).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(UpdateError::NoBuild);
+        }
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    fn paper_project(&self) -> &'static str {
+        match self.config.flavor {
+            Flavor::Folia => "folia",
+            _ => "paper",
+        }
+    }
+
+    async fn latest_paper(&self, version: &str) -> Result<Build, UpdateError> {
+        let project = self.paper_project();
+        let builds: PaperBuilds = self
+            .get(&format!(
+                "{}/{}/versions/{}/builds",
+                PAPER_API, project, version
+            ))
+            .await?;
+        let stable_only = self.stable_only();
+        // Oldest first.
+        let build = builds
+            .builds
+            .into_iter()
+            .rev()
+            .find(|b| !stable_only || b.channel == "default");
+        match build {
+            Some(b) => Ok(Build {
+                version: version.to_owned(),
+                build: b.build.to_string(),
+                url: format!(
+                    "{}/{}/versions/{}/builds/{}/downloads/{}",
+                    PAPER_API, project, version, b.build, b.downloads.application.name
+                ),
+                checksum: Some(Checksum::Sha256(b.downloads.application.sha256)),
+            }),
+            None => Err(UpdateError::NoBuild),
+        }
+    }
+
+    async fn latest_purpur(&self, version: &str) -> Result<Build, UpdateError> {
+        let b: PurpurBuild = self
+            .get(&format!("{}/{}/latest", PURPUR_API, version))
+            .await?;
+        Ok(Build {
+            version: version.to_owned(),
+            url: format!("{}/{}/{}/download", PURPUR_API, version, b.build),
+            build: b.build,
+            checksum: Some(Checksum::Md5(b.md5)),
+        })
+    }
+
+    async fn latest_vanilla(&self, version: &str) -> Result<Build, UpdateError> {
+        let manifest: VersionManifest = self.get(VERSION_MANIFEST).await?;
+        let entry = match manifest.versions.into_iter().find(|v| v.id == version) {
+            Some(v) => v,
+            None => return Err(UpdateError::NoBuild),
+        };
+        let details: VersionDetails = self.get(&entry.url).await?;
+        match details.downloads.server {
+            Some(server) => Ok(Build {
+                version: version.to_owned(),
+                build: version.to_owned(),
+                url: server.url,
+                checksum: Some(Checksum::Sha1(server.sha1)),
+            }),
+            None => Err(UpdateError::NoBuild),
+        }
+    }
+
+    /// The launcher jar for the newest loader and installer, which
+    /// downloads the vanilla server the first time it's run.
+    async fn latest_fabric(&self, version: &str) -> Result<Build, UpdateError> {
+        let stable_only = self.stable_only();
+        let loaders: Vec<FabricLoader> = self
+            .get(&format!("{}/loader/{}", FABRIC_META, version))
+            .await?;
+        let loader = loaders
+            .into_iter()
+            .map(|l| l.loader)
+            .find(|l| !stable_only || l.stable);
+        let installers: Vec<FabricVersion> =
+            self.get(&format!("{}/installer", FABRIC_META)).await?;
+        let installer = installers.into_iter().find(|i| i.stable);
+        match (loader, installer) {
+            (Some(loader), Some(installer)) => Ok(Build {
+                version: version.to_owned(),
+                url: format!(
+                    "{}/loader/{}/{}/{}/server/jar",
+                    FABRIC_META, version, loader.version, installer.version
+                ),
+                build: loader.version,
+                checksum: None,
+            }),
+            _ => Err(UpdateError::NoBuild),
+        }
+    }
+
+    async fn latest(&self, version: &str) -> Result<Build, UpdateError> {
+        match self.config.flavor {
+            Flavor::Paper | Flavor::Folia => self.latest_paper(version).await,
+            Flavor::Purpur => self.latest_purpur(version).await,
+            Flavor::Vanilla => self.latest_vanilla(version).await,
+            Flavor::Fabric => self.latest_fabric(version).await,
+        }
+    }
+
+    /// The Minecraft versions there are builds for, newest first. Only
+    /// releases, not snapshots.
+    pub async fn versions(&self) -> Result<Vec<String>, UpdateError> {
+        match self.config.flavor {
+            Flavor::Paper | Flavor::Folia => {
+                let project: PaperProject = self
+                    .get(&format!("{}/{}", PAPER_API, self.paper_project()))
+                    .await?;
+                Ok(project.versions.into_iter().rev().collect())
+            }
+            Flavor::Purpur => {
+                let project: PaperProject = self.get(PURPUR_API).await?;
+                Ok(project.versions.into_iter().rev().collect())
+            }
+            Flavor::Vanilla => {
+                let manifest: VersionManifest = self.get(VERSION_MANIFEST).await?;
+                Ok(manifest
+                    .versions
+                    .into_iter()
+                    .filter(|v| v.kind == "release")
+                    .map(|v| v.id)
+                    .collect())
+            }
+            Flavor::Fabric => {
+                let versions: Vec<FabricVersion> =
+                    self.get(&format!("{}/game", FABRIC_META)).await?;
+                Ok(versions
+                    .into_iter()
+                    .filter(|v| v.stable)
+                    .map(|v| v.version)
+                    .collect())
+            }
+        }
+    }
+
+    /// The latest build, for `version` if given, and whether the server
+    /// already runs it.
+    pub async fn status(
+        &self,
+        control: &MinecraftControl,
+        server_dir: &Path,
+        version: Option<&str>,
+    ) -> Result<JarStatus, UpdateError> {
+        let jar = self.jar(server_dir);
+        let installed = self.installed(&jar).await;
+        let version = match version {
+            Some(v) => v.to_owned(),
+            None => self.version(control, &installed)?,
+        };
+        let latest = self.latest(&version).await?;
+        let up_to_date = match &latest.checksum {
+            Some(c) => match fs::read(&jar).await {
+                Ok(bytes) => c.matches(&bytes),
+                Err(_) => false,
+            },
+            None => installed
+                .as_ref()
+                .is_some_and(|b| b.version == latest.version && b.build == latest.build),
+        };
+        Ok(JarStatus {
+            flavor: self.config.flavor,
+            installed,
+            latest,
+            up_to_date,
+            rollback: fs::try_exists(jar.with_extension("jar.old"))
+                .await
+                .unwrap_or(false),
+        })
+    }
+
+    /// Downloads the latest build, for `version` if given, and puts it in
+    /// place of the jar, which is kept next to it as <jar>.old. Returns
+    /// None if it was already the latest. The server picks it up when it
+    /// next starts, which happens after restart_after_secs if that's set.
+    pub async fn update(
+        &self,
+        control: &MinecraftControl,
+        server_dir: &Path,
+        version: Option<&str>,
+    ) -> Result<Option<Build>, UpdateError> {
+        let status = self.status(control, server_dir, version).await?;
+        if status.up_to_date {
+            return Ok(None);
+        }
+        let build = status.latest;
+        let bytes = self
+            .http
+            .get(&build.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        if let Some(c) = &build.checksum {
+            if !c.matches(&bytes) {
+                return Err(UpdateError::Corrupt);
+            }
+        }
+
+        let jar = self.jar(server_dir);
+        let record = jar.with_extension("jar.json");
+        let tmp = jar.with_extension("jar.tmp");
+        fs::write(&tmp, &bytes).await?;
+        if fs::try_exists(&jar).await? {
+            fs::rename(&jar, jar.with_extension("jar.old")).await?;
+            // So it goes back with the old jar on a rollback.
+            move_record(&record, &jar.with_extension("jar.old.json")).await?;
+        }
+        fs::rename(&tmp, &jar).await?;
+        fs::write(&record, serde_json::to_vec(&build).unwrap()).await?;
+        control.publish(ServerEvent::ServerUpdated {
+            version: build.version.clone(),
+            build: build.build.clone(),
+        });
+        self.schedule_restart(control);
+        Ok(Some(build))
+    }
+
+    /// Swaps the jar with <jar>.old, so rolling back twice undoes it.
+    /// Returns what's now in place, if the panel installed it.
+    pub async fn rollback(
+        &self,
+        control: &MinecraftControl,
+        server_dir: &Path,
+    ) -> Result<Option<Build>, UpdateError> {
+        let jar = self.jar(server_dir);
+        let old = jar.with_extension("jar.old");
+        if !fs::try_exists(&old).await? {
+            return Err(UpdateError::NoRollback);
+        }
+        let tmp = jar.with_extension("jar.tmp");
+        fs::rename(&jar, &tmp).await?;
+        fs::rename(&old, &jar).await?;
+        fs::rename(&tmp, &old).await?;
+
+        let record = jar.with_extension("jar.json");
+        let old_record = jar.with_extension("jar.old.json");
+        let tmp_record = jar.with_extension("jar.json.tmp");
+        move_record(&record, &tmp_record).await?;
+        move_record(&old_record, &record).await?;
+        move_record(&tmp_record, &old_record).await?;
+
+        let installed = self.installed(&jar).await;
+        if let Some(b) = &installed {
+            control.publish(ServerEvent::ServerUpdated {
+                version: b.version.clone(),
+                build: b.build.clone(),
+            });
+        }
+        self.schedule_restart(control);
+        Ok(installed)
+    }
+
+    fn schedule_restart(&self, control: &MinecraftControl) {
+        if let Some(delay) = self.config.restart_after_secs {
+            let control = control.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(delay)).await;
+                if !control.is_running().await {
+                    return;
+                }
+                if let Err(e) = control.restart().await {
+                    tracing::warn!(server = %control.name(), error = %e, "could not restart after updating");
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::json;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::sync::CancellationToken;
+
+use crate::events::ServerEvent;
+use crate::minecraft::MinecraftControl;
+use crate::motd;
+use crate::parser::is_player_name;
+use crate::protocol::{read_packet, write_packet, write_string, Handshake, Reader};
+
+// How often the server is checked on, to know when to take the port.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+// How long the server gets to start and take the port before it's taken
+// to have stopped again.
+const START_GRACE: Duration = Duration::from_secs(60);
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+const MOTD: &str = "&7Sleeping &8- &7join to start the server";
+const KICK_MESSAGE: &str = "The server is starting up, join again in a minute";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WakeConfig {
+    // The server's address, which is listened on while it's stopped.
+    // 0.0.0.0:25565 if unset.
+    address: Option<String>,
+    // Shown in the server list while it's stopped, with & codes.
+    motd: Option<String>,
+    // Shown to the player whose join starts the server.
+    kick_message: Option<String>,
+}
+
+/// Answers one connection. Returns the name of the player if it was an
+/// attempt to join.
+async fn handle(mut stream: TcpStream, config: &WakeConfig) -> Option<String> {
+    let (id, body) = read_packet(&mut stream).await.ok()?;
+    let handshake = match id {
+        0 => Handshake::parse(&body)?,
+        _ => return None,
+    };
+    match handshake.next_state {
+        1 => {
+            let motd = config.motd.as_deref().unwrap_or(MOTD);
+            let status = json!({
+                "version": { "name": "Sleeping", "protocol": handshake.protocol },
+                "players": { "max": 0, "online": 0 },
+                "description": motd::to_json(motd),
+            });
+            let (id, _) = read_packet(&mut stream).await.ok()?;
+            if id != 0 {
+                return None;
+            }
+            let mut response = Vec::new();
+            write_string(&mut response, &status.to_string());
+            write_packet(&mut stream, 0, &response).await.ok()?;
+            // The client times the round trip with a ping it wants back.
+            let (id, ping) = read_packet(&mut stream).await.ok()?;
+            if id == 1 {
+                let _ = write_packet(&mut stream, 1, &ping).await;
+            }
+            None
+        }
+        2 | 3 => {
+            let (_, login) = read_packet(&mut stream).await.ok()?;
+            let player = match Reader::new(&login).string() {
+                Some(p) if is_player_name(&p) => p,
+                _ => String::from("someone"),
+            };
+            let message = config.kick_message.as_deref().unwrap_or(KICK_MESSAGE);
+            let mut disconnect = Vec::new();
+            write_string(&mut disconnect, &json!({ "text": message }).to_string());
+            let _ = write_packet(&mut stream, 0, &disconnect).await;
+            Some(player)
+        }
+        _ => None,
+    }
+}
+
+/// Listens on the port until someone tries to join, or the server is
+/// started some other way. Returns who joined, if anyone did.
+async fn listen(
+    listener: TcpListener,
+    control: &MinecraftControl,
+    config: &WakeConfig,
+    shutdown: &CancellationToken,
+) -> Option<Option<String>> {
+    let (tx, mut rx) = mpsc::channel(1);
+    let mut events = control.events().subscribe();
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((s, _)) => s,
+                    Err(_) => continue,
+                };
+                let (tx, config) = (tx.clone(), config.clone());
+                tokio::spawn(async move {
+                    let joined = tokio::time::timeout(IO_TIMEOUT, handle(stream, &config)).await;
+                    if let Ok(Some(player)) = joined {
+                        let _ = tx.try_send(player);
+                    }
+                });
+            }
+            player = rx.recv() => return Some(player),
+            event = events.recv() => match event {
+                // The server needs its port back.
+                Ok(e) if e.server == control.name()
+                    && matches!(e.event, ServerEvent::LifecycleRequested { action: "start" | "restart" }) => {
+                    return Some(None);
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return None,
+            },
+            _ = poll.tick() => {
+                if control.is_running().await {
+                    return Some(None);
+                }
+            }
+            _ = shutdown.cancelled() => return None,
+        }
+    }
+}
+
+/// Holds the server's port while it's stopped, showing it as asleep in
+/// the server list, and starts it when someone tries to join.
+pub fn watch(config: WakeConfig, control: MinecraftControl, shutdown: CancellationToken) {
+    let address = match &config.address {
+        Some(a) => a.clone(),
+        None => String::from("0.0.0.0:25565"),
+    };
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(POLL_INTERVAL) => {},
+                _ = shutdown.cancelled() => return,
+            }
+            if control.is_running().await {
+                continue;
+            }
+            // Most likely the server still has it.
+            let listener = match TcpListener::bind(&address).await {
+                Ok(l) => l,
+                Err(_) => continue,
+            };
+            let joined = match listen(listener, &control, &config, &shutdown).await {
+                Some(j) => j,
+                None => return,
+            };
+            let player = match joined {
+                Some(p) => p,
+                None => continue,
+            };
+            tracing::info!(%player, server = %control.name(), "a player tried to join, starting the server");
+            if let Err(e) = control.start().await {
+                tracing::warn!(server = %control.name(), error = %e, "could not start the server");
+                continue;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(START_GRACE) => {},
+                _ = shutdown.cancelled() => return,
+            }
+        }
+    });
+}
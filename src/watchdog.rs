@@ -0,0 +1,148 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::events::ServerEvent;
+use crate::minecraft::MinecraftControl;
+use crate::ping;
+use crate::util::now;
+
+// Only the latest are kept.
+const MAX_INCIDENTS: usize = 100;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WatchdogConfig {
+    // Where the server is pinged, 127.0.0.1 on its server-port if unset.
+    address: Option<String>,
+    // 30 seconds by default.
+    interval_secs: Option<u64>,
+    // How long a ping gets, 5 seconds by default.
+    timeout_secs: Option<u64>,
+    // Failed pings in a row before the server is restarted, 3 by default.
+    failures: Option<u32>,
+    // Servers aren't pinged until they've been up this long, as they don't
+    // answer until they've loaded. 5 minutes by default.
+    startup_grace_secs: Option<u64>,
+    // Where incidents are kept, watchdog-<name>.json by default.
+    incidents_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Incident {
+    // Seconds since the epoch.
+    pub at: u64,
+    pub failures: u32,
+    // Why the last ping failed.
+    pub error: String,
+    // Why the restart failed, if it did.
+    pub restart_error: Option<String>,
+}
+
+/// Pings the server the way the server list does, and restarts it when
+/// it's up as far as its backend knows but stops answering. Each restart
+/// is kept as an incident, persisted as JSON.
+#[derive(Clone)]
+pub struct Watchdog {
+    config: WatchdogConfig,
+    path: PathBuf,
+    incidents: Arc<Mutex<Vec<Incident>>>,
+}
+
+impl Watchdog {
+    pub fn load(config: WatchdogConfig, default_path: String) -> Result<Watchdog, String> {
+        let path = PathBuf::from(config.incidents_path.clone().unwrap_or(default_path));
+        let incidents = match std::fs::read_to_string(&path) {
+            Ok(file) => match serde_json::from_str(&file) {
+                Ok(i) => i,
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            },
+            Err(_) => Vec::new(),
+        };
+        Ok(Watchdog {
+            config,
+            path,
+            incidents: Arc::new(Mutex::new(incidents)),
+        })
+    }
+
+    /// Newest first.
+    pub async fn incidents(&self) -> Vec<Incident> {
+        let mut incidents = self.incidents.lock().await.clone();
+        incidents.reverse();
+        incidents
+    }
+
+    async fn record(&self, incident: Incident) -> Result<(), std::io::Error> {
+        let mut incidents = self.incidents.lock().await;
+        incidents.push(incident);
+        let over = incidents.len().saturating_sub(MAX_INCIDENTS);
+        incidents.drain(..over);
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&*incidents)?).await?;
+        fs::rename(&tmp, &self.path).await
+    }
+
+    async fn address(&self, control: &MinecraftControl) -> String {
+        match &self.config.address {
+            Some(a) => a.clone(),
+            None => ping::local_address(control).await,
+        }
+    }
+
+    /// Pings the server every `interval_secs` until shutdown.
+    pub fn watch(&self, control: MinecraftControl, shutdown: CancellationToken) {
+        let watchdog = self.clone();
+        let interval = Duration::from_secs(self.config.interval_secs.unwrap_or(30));
+        let timeout = Duration::from_secs(self.config.timeout_secs.unwrap_or(5));
+        let limit = self.config.failures.unwrap_or(3);
+        let grace = self.config.startup_grace_secs.unwrap_or(300);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            let mut failures = 0;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {},
+                    _ = shutdown.cancelled() => return,
+                }
+                let up = match control.status().await {
+                    Ok(s) => s.active_state == "active" && s.uptime_seconds.unwrap_or(0) >= grace,
+                    Err(_) => false,
+                };
+                if !up {
+                    failures = 0;
+                    continue;
+                }
+                let address = watchdog.address(&control).await;
+                let error = match ping::ping(&address, timeout).await {
+                    Ok(_) => {
+                        failures = 0;
+                        continue;
+                    }
+                    Err(e) => e.to_string(),
+                };
+                failures += 1;
+                if failures < limit {
+                    continue;
+                }
+                tracing::warn!(server = %control.name(), failures, %error, "server is up but isn't answering pings, restarting it");
+                let restart_error = control.restart().await.err().map(|e| e.to_string());
+                control.publish(ServerEvent::Unresponsive { failures });
+                let incident = Incident {
+                    at: now(),
+                    failures,
+                    error,
+                    restart_error,
+                };
+                if let Err(e) = watchdog.record(incident).await {
+                    tracing::warn!(error = %e, "could not save watchdog incidents");
+                }
+                failures = 0;
+            }
+        });
+    }
+}
@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use async_compression::tokio::write::GzipEncoder;
+use serde::Serialize;
+use tokio::fs;
+use tokio::io::{AsyncWriteExt, DuplexStream};
+use tokio::task::JoinHandle;
+
+use crate::level;
+
+pub mod import;
+pub mod prune;
+pub mod regions;
+
+#[derive(Serialize)]
+pub struct World {
+    // The directory's name, which is what the API takes.
+    pub name: String,
+    pub level_name: Option<String>,
+    // Bytes on disk.
+    pub size: u64,
+    // Seconds since the epoch that anything in it last changed.
+    pub modified: u64,
+    // A string since seeds don't fit in a JavaScript number.
+    pub seed: Option<String>,
+    pub version: Option<String>,
+    // e.g. "minecraft:overworld", "minecraft:the_nether" or a datapack's.
+    pub dimensions: Vec<String>,
+}
+
+/// Checks a world name from a request and finds its directory, which has
+/// to be right inside the server's.
+pub fn path(server_dir: &Path, name: &str) -> Option<PathBuf> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' || c == ' ')
+    {
+        return None;
+    }
+    Some(server_dir.join(name))
+}
+
+/// Streams a world as a gzipped tarball, writing it on a task of its own
+/// so only a little of it is ever in memory. The archive's paths start
+/// with the world's name.
+pub fn archive(
+    name: String,
+    dir: PathBuf,
+) -> (DuplexStream, JoinHandle<Result<(), std::io::Error>>) {
+    let (reader, writer) = tokio::io::duplex(64 * 1024);
+    let task = tokio::spawn(async move {
+        let mut tar = tokio_tar::Builder::new(GzipEncoder::new(writer));
+        tar.follow_symlinks(false);
+        tar.append_dir_all(&name, &dir).await?;
+        let mut gzip = tar.into_inner().await?;
+        gzip.shutdown().await
+    });
+    (reader, task)
+}
+
+/// Every directory in the server's with a level.dat. Vanilla keeps the
+/// nether and end inside the world, Bukkit and its forks next to it.
+pub async fn list(server_dir: &Path) -> Result<Vec<World>, std::io::Error> {
+    let mut worlds = Vec::new();
+    let mut dir = fs::read_dir(server_dir).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let path = entry.path();
+        if !fs::try_exists(path.join("level.dat"))
+            .await
+            .unwrap_or(false)
+        {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        worlds.push(describe(name, path).await);
+    }
+    worlds.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(worlds)
+}
+
+async fn describe(name: String, path: PathBuf) -> World {
+    let level = match level::read(&path).await {
+        Ok(l) => l,
+        Err(e) => {
+            tracing::warn!(world = %name, error = %e, "could not read level.dat");
+            None
+        }
+    };
+    let dimensions = dimensions(&path).await;
+    let walked = path.clone();
+    let (size, modified) = match tokio::task::spawn_blocking(move || disk_usage(&walked)).await {
+        Ok(u) => u,
+        Err(_) => (0, 0),
+    };
+    let (level_name, seed, version) = match level {
+        Some(l) => (l.name, l.seed, l.version),
+        None => (None, None, None),
+    };
+    World {
+        name,
+        level_name,
+        size,
+        modified,
+        seed,
+        version,
+        dimensions,
+    }
+}
+
+async fn dimensions(world: &Path) -> Vec<String> {
+    dimension_dirs(world)
+        .await
+        .into_iter()
+        .map(|(d, _)| d)
+        .collect()
+}
+
+/// Each dimension in a world and the directory with its region folder.
+pub async fn dimension_dirs(world: &Path) -> Vec<(String, PathBuf)> {
+    let mut found = Vec::new();
+    for (dir, dimension) in [
+        ("", "minecraft:overworld"),
+        ("DIM-1", "minecraft:the_nether"),
+        ("DIM1", "minecraft:the_end"),
+    ] {
+        let dir = world.join(dir);
+        if fs::try_exists(dir.join("region")).await.unwrap_or(false) {
+            found.push((dimension.to_owned(), dir));
+        }
+    }
+    // Datapack dimensions are under dimensions/<namespace>/<name>.
+    if let Ok(mut namespaces) = fs::read_dir(world.join("dimensions")).await {
+        while let Ok(Some(namespace)) = namespaces.next_entry().await {
+            let mut names = match fs::read_dir(namespace.path()).await {
+                Ok(n) => n,
+                Err(_) => continue,
+            };
+            while let Ok(Some(name)) = names.next_entry().await {
+                let dimension = format!(
+                    "{}:{}",
+                    namespace.file_name().to_string_lossy(),
+                    name.file_name().to_string_lossy()
+                );
+                found.push((dimension, name.path()));
+            }
+        }
+    }
+    found
+}
+
+/// The total size of everything under `dir` and when the newest of it was
+/// modified. This blocks, so call it from `spawn_blocking`.
+pub fn disk_usage(dir: &Path) -> (u64, u64) {
+    let mut size = 0;
+    let mut modified = 0;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(e) => e,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let metadata = match entry.metadata() {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+                continue;
+            }
+            size += metadata.len();
+            if let Ok(Ok(m)) = metadata.modified().map(|m| m.duration_since(UNIX_EPOCH)) {
+                modified = modified.max(m.as_secs());
+            }
+        }
+    }
+    (size, modified)
+}
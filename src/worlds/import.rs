@@ -0,0 +1,353 @@
+use std::io::Read as _;
+use std::path::{Component, Path, PathBuf};
+
+use async_compression::tokio::bufread::GzipDecoder;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio_tar::EntryType;
+
+use crate::util::now;
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    Upload(String),
+    TooLarge(u64),
+    // An entry that would land outside the world, or a link.
+    Unsafe(String),
+    NotAWorld,
+    NotAnArchive,
+    Exists,
+    Zip(zip::result::ZipError),
+}
+
+impl From<std::io::Error> for ImportError {
+    fn from(e: std::io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+impl From<zip::result::ZipError> for ImportError {
+    fn from(e: zip::result::ZipError) -> Self {
+        ImportError::Zip(e)
+    }
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "{}", e),
+            ImportError::Upload(e) => write!(f, "upload failed: {}", e),
+            ImportError::TooLarge(limit) => {
+                write!(f, "the world is larger than the limit of {} bytes", limit)
+            }
+            ImportError::Unsafe(path) => write!(f, "the archive has an unsafe entry: {}", path),
+            ImportError::NotAWorld => write!(f, "there's no level.dat in the archive"),
+            ImportError::NotAnArchive => write!(f, "the upload isn't a tar.gz or zip"),
+            ImportError::Exists => write!(f, "a world with that name already exists"),
+            ImportError::Zip(e) => write!(f, "could not read zip: {}", e),
+        }
+    }
+}
+
+/// Only plain relative paths are let through, so nothing can be written
+/// outside the staging directory.
+fn safe_path(path: &Path) -> Result<PathBuf, ImportError> {
+    let mut safe = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(c) => safe.push(c),
+            Component::CurDir => {}
+            _ => return Err(ImportError::Unsafe(path.display().to_string())),
+        }
+    }
+    Ok(safe)
+}
+
+/// Unpacks an uploaded tar.gz or zip as the world `target`, both of which
+/// may have the world at their root or in a single directory. Everything
+/// is unpacked next to it first, so a bad upload leaves the server
+/// directory as it was. With `replace`, an existing world is renamed
+/// rather than deleted, and its new name returned.
+pub async fn import<S, E>(
+    body: S,
+    target: PathBuf,
+    limit: u64,
+    replace: bool,
+) -> Result<Option<PathBuf>, ImportError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    if !replace && fs::try_exists(&target).await? {
+        return Err(ImportError::Exists);
+    }
+    let name = match target.file_name() {
+        Some(n) => n.to_string_lossy().into_owned(),
+        None => return Err(ImportError::NotAWorld),
+    };
+    let staging = target.with_file_name(format!(".import-{}", name));
+    let upload = target.with_file_name(format!(".import-{}.upload", name));
+
+    let imported = stage(body, &upload, &staging, limit).await;
+    let _ = fs::remove_file(&upload).await;
+    let result = match imported {
+        Ok(root) => swap(&root, &target, replace).await,
+        Err(e) => Err(e),
+    };
+    let _ = fs::remove_dir_all(&staging).await;
+    result
+}
+
+async fn stage<S, E>(
+    mut body: S,
+    upload: &Path,
+    staging: &Path,
+    limit: u64,
+) -> Result<PathBuf, ImportError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    let mut file = fs::File::create(upload).await?;
+    let mut written = 0;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| ImportError::Upload(e.to_string()))?;
+        written += chunk.len() as u64;
+        if written > limit {
+            return Err(ImportError::TooLarge(limit));
+        }
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    drop(file);
+
+    let _ = fs::remove_dir_all(staging).await;
+    fs::create_dir_all(staging).await?;
+    let mut magic = [0; 4];
+    let read = fs::File::open(upload).await?.read(&mut magic).await?;
+    match &magic[..read] {
+        [0x1f, 0x8b, ..] => untar(upload, staging, limit).await?,
+        [b'P', b'K', 3, 4] => {
+            let (upload, staging) = (upload.to_path_buf(), staging.to_path_buf());
+            match tokio::task::spawn_blocking(move || unzip(&upload, &staging, limit)).await {
+                Ok(r) => r?,
+                Err(e) => return Err(ImportError::Upload(e.to_string())),
+            }
+        }
+        _ => return Err(ImportError::NotAnArchive),
+    }
+    world_root(staging).await
+}
+
+async fn untar(upload: &Path, staging: &Path, limit: u64) -> Result<(), ImportError> {
+    let file = fs::File::open(upload).await?;
+    let mut archive = tokio_tar::Archive::new(GzipDecoder::new(BufReader::new(file)));
+    let mut entries = archive.entries()?;
+    let mut total = 0;
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry?;
+        let path = safe_path(&entry.path()?)?;
+        let out = staging.join(&path);
+        match entry.header().entry_type() {
+            EntryType::Directory => {
+                fs::create_dir_all(&out).await?;
+                continue;
+            }
+            EntryType::Regular | EntryType::Continuous => {}
+            // PAX and GNU long name headers are handled by tokio-tar.
+            EntryType::XHeader | EntryType::XGlobalHeader => continue,
+            _ => return Err(ImportError::Unsafe(path.display().to_string())),
+        }
+        total += entry.header().size()?;
+        if total > limit {
+            return Err(ImportError::TooLarge(limit));
+        }
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let mut file = fs::File::create(&out).await?;
+        tokio::io::copy(&mut entry, &mut file).await?;
+    }
+    Ok(())
+}
+
+/// This blocks, so call it from `spawn_blocking`.
+fn unzip(upload: &Path, staging: &Path, limit: u64) -> Result<(), ImportError> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(upload)?)?;
+    let mut total = 0;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let path = match entry.enclosed_name() {
+            Some(p) => safe_path(&p)?,
+            None => return Err(ImportError::Unsafe(entry.name().to_owned())),
+        };
+        // Symlinks are files whose mode says so.
+        if entry.unix_mode().is_some_and(|m| m & 0o170000 == 0o120000) {
+            return Err(ImportError::Unsafe(path.display().to_string()));
+        }
+        let out = staging.join(&path);
+        if entry.is_dir() {
+            std::fs::create_dir_all(&out)?;
+            continue;
+        }
+        let size = entry.size();
+        total += size;
+        if total > limit {
+            return Err(ImportError::TooLarge(limit));
+        }
+        if let Some(parent) = out.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // The size is only what the archive claims, so the copy is capped
+        // as well.
+        let mut file = std::fs::File::create(&out)?;
+        let copied = std::io::copy(&mut (&mut entry).take(size + 1), &mut file)?;
+        if copied > size {
+            return Err(ImportError::TooLarge(limit));
+        }
+    }
+    Ok(())
+}
+
+async fn world_root(staging: &Path) -> Result<PathBuf, ImportError> {
+    if fs::try_exists(staging.join("level.dat")).await? {
+        return Ok(staging.to_path_buf());
+    }
+    let mut dirs = Vec::new();
+    let mut entries = fs::read_dir(staging).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    match dirs.as_slice() {
+        [dir] if fs::try_exists(dir.join("level.dat")).await? => Ok(dir.clone()),
+        _ => Err(ImportError::NotAWorld),
+    }
+}
+
+async fn swap(root: &Path, target: &Path, replace: bool) -> Result<Option<PathBuf>, ImportError> {
+    let mut moved = None;
+    if fs::try_exists(target).await? {
+        if !replace {
+            return Err(ImportError::Exists);
+        }
+        let stamp = now();
+        let name = target.file_name().unwrap_or_default().to_string_lossy();
+        let aside = target.with_file_name(format!("{}.old-{}", name, stamp));
+        fs::rename(target, &aside).await?;
+        moved = Some(aside);
+    }
+    fs::rename(root, target).await?;
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write as _;
+    use zip::write::SimpleFileOptions;
+
+    // Stored, so the upload is as large as what's in it.
+    fn zipped(entries: &[(&str, &str)]) -> Bytes {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, data) in entries {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(data.as_bytes()).unwrap();
+        }
+        Bytes::from(zip.finish().unwrap().into_inner())
+    }
+
+    async fn import_bytes(
+        name: &str,
+        upload: Bytes,
+        limit: u64,
+    ) -> (PathBuf, Result<Option<PathBuf>, ImportError>) {
+        let dir =
+            std::env::temp_dir().join(format!("mcctl-import-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir).await;
+        fs::create_dir_all(&dir).await.unwrap();
+        let body = futures::stream::iter(vec![Ok::<_, std::io::Error>(upload)]);
+        let result = import(body, dir.join("world"), limit, false).await;
+        (dir, result)
+    }
+
+    #[test]
+    fn safe_paths() {
+        assert_eq!(
+            safe_path(Path::new("region/r.0.0.mca")).unwrap(),
+            Path::new("region/r.0.0.mca")
+        );
+        assert_eq!(
+            safe_path(Path::new("./level.dat")).unwrap(),
+            Path::new("level.dat")
+        );
+        for path in ["../level.dat", "world/../../level.dat", "/etc/passwd"] {
+            assert!(
+                matches!(safe_path(Path::new(path)), Err(ImportError::Unsafe(_))),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn world_in_a_directory() {
+        let upload = zipped(&[
+            ("survival/level.dat", "level"),
+            ("survival/region/r.0.0.mca", ""),
+        ]);
+        let (dir, result) = import_bytes("ok", upload, 1024).await;
+        assert!(matches!(result, Ok(None)));
+        assert!(dir.join("world/level.dat").exists());
+        assert!(dir.join("world/region/r.0.0.mca").exists());
+        assert!(!dir.join(".import-world").exists());
+        let _ = fs::remove_dir_all(&dir).await;
+    }
+
+    #[tokio::test]
+    async fn entries_outside_the_world() {
+        for (name, entry) in [("up", "../level.dat"), ("abs", "/tmp/level.dat")] {
+            let upload = zipped(&[("level.dat", "level"), (entry, "level")]);
+            let (dir, result) = import_bytes(name, upload, 1024).await;
+            assert!(matches!(result, Err(ImportError::Unsafe(_))), "{}", entry);
+            assert!(!dir.join("world").exists());
+            assert!(!dir.join("level.dat").exists());
+            let _ = fs::remove_dir_all(&dir).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn too_large() {
+        // The upload alone is over the limit.
+        let upload = zipped(&[("level.dat", "a".repeat(4096).as_str())]);
+        let (dir, result) = import_bytes("upload", upload, 1024).await;
+        assert!(matches!(result, Err(ImportError::TooLarge(1024))));
+        let _ = fs::remove_dir_all(&dir).await;
+
+        // The upload is small but unpacks to more than the limit.
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        zip.start_file("level.dat", options).unwrap();
+        zip.write_all(&[0; 64 * 1024]).unwrap();
+        let upload = Bytes::from(zip.finish().unwrap().into_inner());
+        assert!(upload.len() < 1024);
+        let (dir, result) = import_bytes("unpacked", upload, 1024).await;
+        assert!(matches!(result, Err(ImportError::TooLarge(1024))));
+        assert!(!dir.join("world").exists());
+        let _ = fs::remove_dir_all(&dir).await;
+    }
+
+    #[tokio::test]
+    async fn not_an_archive() {
+        let (dir, result) = import_bytes("text", Bytes::from_static(b"hello"), 1024).await;
+        assert!(matches!(result, Err(ImportError::NotAnArchive)));
+        let _ = fs::remove_dir_all(&dir).await;
+    }
+}
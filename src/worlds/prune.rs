@@ -0,0 +1,225 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::dimension_dirs;
+use super::regions::{coordinates, read_header, CHUNKS, SECTOR};
+
+// Folders in a dimension laid out as region files. Entities and points of
+// interest are pruned along with the chunks they're in.
+const FOLDERS: &[&str] = &["region", "entities", "poi"];
+
+/// Which chunks to delete. With both set, only chunks matching both go.
+#[derive(Deserialize, Clone, Copy)]
+pub struct Criteria {
+    // Blocks from the centre, as a square like the world border. Chunks
+    // entirely outside it are pruned.
+    pub radius: Option<u32>,
+    // Block coordinates, 0, 0 if unset.
+    #[serde(default)]
+    pub center: [i32; 2],
+    // Seconds since the epoch. Chunks last saved before it are pruned.
+    pub before: Option<u32>,
+}
+
+impl Criteria {
+    pub fn is_empty(&self) -> bool {
+        self.radius.is_none() && self.before.is_none()
+    }
+
+    fn prunes(&self, x: i32, z: i32, saved: u32) -> bool {
+        let outside = match self.radius {
+            Some(r) => {
+                let r = r as i64;
+                let (cx, cz) = (self.center[0] as i64, self.center[1] as i64);
+                let (x, z) = (x as i64 * 16, z as i64 * 16);
+                x + 15 < cx - r || x > cx + r || z + 15 < cz - r || z > cz + r
+            }
+            None => true,
+        };
+        let old = match self.before {
+            Some(b) => saved < b,
+            None => true,
+        };
+        outside && old
+    }
+}
+
+#[derive(Serialize, Default)]
+pub struct PruneReport {
+    pub dry_run: bool,
+    pub chunks: u64,
+    // Region files left with no chunks, which are deleted.
+    pub files_deleted: u64,
+    // Region files rewritten without the pruned chunks.
+    pub files_rewritten: u64,
+    // Bytes freed, or that would be.
+    pub reclaimed: u64,
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// Copies the chunks not removed into a fresh region file, packed
+/// together, and puts it in place of the old one.
+fn rewrite(
+    path: &Path,
+    file: &mut std::fs::File,
+    locations: &[u32; CHUNKS],
+    timestamps: &[u32; CHUNKS],
+    keep: &[bool; CHUNKS],
+) -> Result<(), std::io::Error> {
+    let tmp = path.with_extension("mca.tmp");
+    let mut out = std::fs::File::create(&tmp)?;
+    let mut header = vec![0u8; 2 * SECTOR as usize];
+    out.write_all(&header)?;
+    let mut next = 2;
+    for i in (0..CHUNKS).filter(|i| keep[*i]) {
+        let (offset, count) = (locations[i] >> 8, locations[i] & 0xff);
+        let mut sectors = vec![0u8; (count as u64 * SECTOR) as usize];
+        file.seek(SeekFrom::Start(offset as u64 * SECTOR))?;
+        file.read_exact(&mut sectors)?;
+        out.write_all(&sectors)?;
+        header[i * 4..i * 4 + 4].copy_from_slice(&((next << 8) | count).to_be_bytes());
+        let t = SECTOR as usize + i * 4;
+        header[t..t + 4].copy_from_slice(&timestamps[i].to_be_bytes());
+        next += count;
+    }
+    out.seek(SeekFrom::Start(0))?;
+    out.write_all(&header)?;
+    out.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Removes the chunks marked in `remove` from one region file and returns
+/// how many it had.
+fn prune_file(
+    path: &Path,
+    (rx, rz): (i32, i32),
+    remove: &[bool; CHUNKS],
+    dry_run: bool,
+    report: &mut PruneReport,
+) -> Result<u64, std::io::Error> {
+    let mut file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let header = match read_header(&mut file)? {
+        Some(h) => h,
+        None => return Ok(0),
+    };
+    let mut keep = [false; CHUNKS];
+    let mut removed = 0;
+    let mut kept_sectors = 0;
+    for i in (0..CHUNKS).filter(|i| header.has_chunk(*i)) {
+        if !remove[i] {
+            keep[i] = true;
+            kept_sectors += (header.locations[i] & 0xff) as u64;
+            continue;
+        }
+        removed += 1;
+        // Chunks too big for the region file are kept next to it.
+        let (x, z) = (rx * 32 + (i % 32) as i32, rz * 32 + (i / 32) as i32);
+        let external = path.with_file_name(format!("c.{}.{}.mcc", x, z));
+        if external.exists() {
+            report.reclaimed += file_size(&external);
+            if !dry_run {
+                std::fs::remove_file(&external)?;
+            }
+        }
+    }
+    if removed == 0 {
+        return Ok(0);
+    }
+
+    let size = file_size(path);
+    if kept_sectors == 0 {
+        report.files_deleted += 1;
+        report.reclaimed += size;
+        if !dry_run {
+            std::fs::remove_file(path)?;
+        }
+    } else {
+        report.files_rewritten += 1;
+        report.reclaimed += size.saturating_sub((2 + kept_sectors) * SECTOR);
+        if !dry_run {
+            rewrite(
+                path,
+                &mut file,
+                &header.locations,
+                &header.timestamps,
+                &keep,
+            )?;
+        }
+    }
+    Ok(removed)
+}
+
+/// Prunes one dimension. Which chunks go is decided from the region
+/// folder, since that's where their save times are. This blocks, so call
+/// it from `spawn_blocking`.
+fn prune_dimension(
+    dir: &Path,
+    criteria: Criteria,
+    dry_run: bool,
+    report: &mut PruneReport,
+) -> Result<(), std::io::Error> {
+    for entry in std::fs::read_dir(dir.join("region"))?.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let (rx, rz) = match coordinates(&name) {
+            Some(c) => c,
+            None => continue,
+        };
+        let header = match read_header(&mut std::fs::File::open(entry.path())?)? {
+            Some(h) => h,
+            None => continue,
+        };
+        let mut remove = [false; CHUNKS];
+        for (i, r) in remove.iter_mut().enumerate() {
+            let (x, z) = (rx * 32 + (i % 32) as i32, rz * 32 + (i / 32) as i32);
+            *r = header.has_chunk(i) && criteria.prunes(x, z, header.timestamps[i]);
+        }
+        for folder in FOLDERS {
+            let removed = prune_file(
+                &dir.join(folder).join(&name),
+                (rx, rz),
+                &remove,
+                dry_run,
+                report,
+            )?;
+            if *folder == "region" {
+                report.chunks += removed;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the chunks matching `criteria` from every dimension of a
+/// world, or with `dry_run` only works out what would go. The server
+/// mustn't be running, or it'll write the chunks back.
+pub async fn prune(
+    world: &Path,
+    criteria: Criteria,
+    dry_run: bool,
+) -> Result<PruneReport, std::io::Error> {
+    let dirs = dimension_dirs(world).await;
+    let pruned = tokio::task::spawn_blocking(move || {
+        let mut report = PruneReport {
+            dry_run,
+            ..Default::default()
+        };
+        for (_, dir) in dirs {
+            prune_dimension(&dir, criteria, dry_run, &mut report)?;
+        }
+        Ok(report)
+    })
+    .await;
+    match pruned {
+        Ok(r) => r,
+        Err(e) => Err(std::io::Error::other(e)),
+    }
+}
@@ -0,0 +1,151 @@
+use std::io::Read;
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::dimension_dirs;
+
+// Chunks in a region file: 32 by 32.
+pub const CHUNKS: usize = 1024;
+// Region files are laid out in sectors of this many bytes, the first two
+// of which are the header.
+pub const SECTOR: u64 = 4096;
+// How many of the biggest region files are listed for each dimension.
+const LARGEST: usize = 10;
+
+/// Where each chunk is in a region file and when it was last saved.
+pub struct Header {
+    // The chunk's first sector in the top three bytes and how many it
+    // takes in the last. 0 for chunks that haven't been generated.
+    pub locations: [u32; CHUNKS],
+    // Seconds since the epoch.
+    pub timestamps: [u32; CHUNKS],
+}
+
+impl Header {
+    pub fn has_chunk(&self, i: usize) -> bool {
+        self.locations[i] != 0
+    }
+}
+
+/// Parses "r.<x>.<z>.mca" into the region's coordinates.
+pub fn coordinates(file: &str) -> Option<(i32, i32)> {
+    let mut parts = file.strip_prefix("r.")?.strip_suffix(".mca")?.split('.');
+    let x = parts.next()?.parse().ok()?;
+    let z = parts.next()?.parse().ok()?;
+    match parts.next() {
+        Some(_) => None,
+        None => Some((x, z)),
+    }
+}
+
+/// None for files too short to have a header, which the server leaves
+/// behind when it's stopped mid-write.
+pub fn read_header(file: &mut std::fs::File) -> Result<Option<Header>, std::io::Error> {
+    let mut bytes = vec![0; 2 * SECTOR as usize];
+    match file.read_exact(&mut bytes) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let word = |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+    let mut header = Header {
+        locations: [0; CHUNKS],
+        timestamps: [0; CHUNKS],
+    };
+    for i in 0..CHUNKS {
+        header.locations[i] = word(i * 4);
+        header.timestamps[i] = word(SECTOR as usize + i * 4);
+    }
+    Ok(Some(header))
+}
+
+#[derive(Serialize, Clone)]
+pub struct RegionFile {
+    pub file: String,
+    pub x: i32,
+    pub z: i32,
+    pub chunks: u32,
+    // Bytes on disk.
+    pub size: u64,
+    // Seconds since the epoch that chunks in it were saved.
+    pub oldest: Option<u32>,
+    pub newest: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct DimensionRegions {
+    pub dimension: String,
+    pub files: usize,
+    pub chunks: u64,
+    pub size: u64,
+    pub oldest: Option<u32>,
+    pub newest: Option<u32>,
+    // The biggest region files, biggest first.
+    pub largest: Vec<RegionFile>,
+}
+
+fn scan_file(path: &Path, name: String, x: i32, z: i32) -> Result<RegionFile, std::io::Error> {
+    let mut file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut region = RegionFile {
+        file: name,
+        x,
+        z,
+        chunks: 0,
+        size,
+        oldest: None,
+        newest: None,
+    };
+    let header = match read_header(&mut file)? {
+        Some(h) => h,
+        None => return Ok(region),
+    };
+    for i in (0..CHUNKS).filter(|i| header.has_chunk(*i)) {
+        region.chunks += 1;
+        let saved = header.timestamps[i];
+        if saved == 0 {
+            continue;
+        }
+        region.oldest = Some(region.oldest.map_or(saved, |o| o.min(saved)));
+        region.newest = Some(region.newest.map_or(saved, |n| n.max(saved)));
+    }
+    Ok(region)
+}
+
+/// Reads the header of every region file in a dimension. This blocks, so
+/// call it from `spawn_blocking`.
+fn scan_dimension(dimension: String, dir: &Path) -> Result<DimensionRegions, std::io::Error> {
+    let mut regions = Vec::new();
+    for entry in std::fs::read_dir(dir.join("region"))?.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let (x, z) = match coordinates(&name) {
+            Some(c) => c,
+            None => continue,
+        };
+        regions.push(scan_file(&entry.path(), name, x, z)?);
+    }
+    regions.sort_by(|a, b| b.size.cmp(&a.size));
+    Ok(DimensionRegions {
+        dimension,
+        files: regions.len(),
+        chunks: regions.iter().map(|r| r.chunks as u64).sum(),
+        size: regions.iter().map(|r| r.size).sum(),
+        oldest: regions.iter().filter_map(|r| r.oldest).min(),
+        newest: regions.iter().filter_map(|r| r.newest).max(),
+        largest: regions.into_iter().take(LARGEST).collect(),
+    })
+}
+
+/// A summary of the region files of each of a world's dimensions.
+pub async fn scan(world: &Path) -> Result<Vec<DimensionRegions>, std::io::Error> {
+    let mut scanned = Vec::new();
+    for (dimension, dir) in dimension_dirs(world).await {
+        let result = tokio::task::spawn_blocking(move || scan_dimension(dimension, &dir)).await;
+        match result {
+            Ok(r) => scanned.push(r?),
+            Err(e) => return Err(std::io::Error::other(e)),
+        }
+    }
+    Ok(scanned)
+}